@@ -1,4 +1,10 @@
-use std::{path::PathBuf, sync::Arc, thread, time::Duration};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
 
 use anyhow::{anyhow, bail, Context, Result};
 use napi::{
@@ -25,7 +31,9 @@ use tracing::Instrument;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Registry};
 use turbo_rcstr::RcStr;
 use turbo_tasks::{
-    get_effects, Completion, Effects, ReadRef, ResolvedVc, TransientInstance, UpdateInfo, Vc,
+    function_statistics::FunctionStatistics, get_effects,
+    invalidation_provenance::InvalidationProvenance, Completion, Effects, ReadRef, ResolvedVc,
+    TransientInstance, UpdateInfo, Vc,
 };
 use turbo_tasks_fs::{
     util::uri_from_file, DiskFileSystem, FileContent, FileSystem, FileSystemPath,
@@ -286,6 +294,9 @@ pub struct ProjectInstance {
     turbo_tasks: NextTurboTasks,
     container: Vc<ProjectContainer>,
     exit_receiver: tokio::sync::Mutex<Option<ExitReceiver>>,
+    /// The invalidation provenance of the last HMR update by HMR identifier. Only recorded when
+    /// the backend tracks invalidation provenance.
+    hmr_invalidation_provenance: Arc<Mutex<HashMap<RcStr, InvalidationProvenance>>>,
 }
 
 #[napi(ts_return_type = "Promise<{ __napiType: \"Project\" }>")]
@@ -398,6 +409,7 @@ pub async fn project_new(
             turbo_tasks,
             container,
             exit_receiver: tokio::sync::Mutex::new(Some(exit_receiver)),
+            hmr_invalidation_provenance: Default::default(),
         },
         100,
     ))
@@ -755,6 +767,7 @@ pub fn project_hmr_events(
     func: JsFunction,
 ) -> napi::Result<External<RootTask>> {
     let turbo_tasks = project.turbo_tasks.clone();
+    let hmr_invalidation_provenance = project.hmr_invalidation_provenance.clone();
     let project = project.container;
     let session = TransientInstance::new(());
     subscribe(
@@ -766,6 +779,7 @@ pub fn project_hmr_events(
             move || {
                 let identifier: RcStr = outer_identifier.clone().into();
                 let session = session.clone();
+                let hmr_invalidation_provenance = hmr_invalidation_provenance.clone();
                 async move {
                     let project = project.project().resolve().await?;
                     let state = project.hmr_version_state(identifier.clone(), session);
@@ -779,6 +793,17 @@ pub fn project_hmr_events(
                         effects,
                     } = &*update;
                     effects.apply().await?;
+                    if matches!(&**update, Update::Total(_) | Update::Partial(_)) {
+                        if let Some(provenance) = project
+                            .hmr_invalidation_provenance(identifier.clone(), state)
+                            .await?
+                        {
+                            hmr_invalidation_provenance
+                                .lock()
+                                .unwrap()
+                                .insert(identifier.clone(), provenance);
+                        }
+                    }
                     match &**update {
                         Update::Missing | Update::None => {}
                         Update::Total(TotalUpdate { to }) => {
@@ -950,6 +975,81 @@ impl From<UpdateInfo> for NapiUpdateInfo {
     }
 }
 
+#[napi(object)]
+struct NapiFunctionStatistics {
+    pub name: String,
+    pub executions: f64,
+    pub cache_hits: f64,
+    pub total_duration_ms: f64,
+    pub peak_duration_ms: f64,
+    pub cells_written: f64,
+    pub invalidations: f64,
+}
+
+impl From<FunctionStatistics> for NapiFunctionStatistics {
+    fn from(statistics: FunctionStatistics) -> Self {
+        Self {
+            name: statistics.name.to_string(),
+            executions: statistics.executions as f64,
+            cache_hits: statistics.cache_hits as f64,
+            total_duration_ms: statistics.total_duration.as_secs_f64() * 1000.0,
+            peak_duration_ms: statistics.peak_duration.as_secs_f64() * 1000.0,
+            cells_written: statistics.cells_written as f64,
+            invalidations: statistics.invalidations as f64,
+        }
+    }
+}
+
+/// Returns execution statistics per turbo-tasks function, accumulated since the project was
+/// created and sorted by total execution time. Only the persistent caching backend collects them,
+/// with the memory backend the list is empty.
+#[napi]
+pub fn project_function_statistics(
+    #[napi(ts_arg_type = "{ __napiType: \"Project\" }")] project: External<ProjectInstance>,
+) -> Vec<NapiFunctionStatistics> {
+    project
+        .turbo_tasks
+        .function_statistics()
+        .into_iter()
+        .map(NapiFunctionStatistics::from)
+        .collect()
+}
+
+#[napi(object)]
+struct NapiInvalidationStep {
+    /// The task that was invalidated.
+    pub description: String,
+    /// Why the task was invalidated, e.g. which cell changed or which file was modified.
+    pub cause: String,
+}
+
+/// Explains why the last HMR update of `identifier` was recomputed. The first step is the update
+/// itself, every following step is the task whose change invalidated the previous one, the last
+/// step holds the root cause, e.g. a file write. Only recorded when the
+/// `NEXT_TURBOPACK_INVALIDATION_PROVENANCE` env var is set and persistent caching is enabled.
+#[napi]
+pub fn project_hmr_invalidation_provenance(
+    #[napi(ts_arg_type = "{ __napiType: \"Project\" }")] project: External<ProjectInstance>,
+    identifier: String,
+) -> Option<Vec<NapiInvalidationStep>> {
+    let provenance = project
+        .hmr_invalidation_provenance
+        .lock()
+        .unwrap()
+        .get(&*identifier)
+        .cloned()?;
+    Some(
+        provenance
+            .steps
+            .into_iter()
+            .map(|step| NapiInvalidationStep {
+                description: step.description,
+                cause: step.cause.to_string(),
+            })
+            .collect(),
+    )
+}
+
 /// Subscribes to lifecycle events of the compilation.
 ///
 /// Emits an [UpdateMessage::Start] event when any computation starts.
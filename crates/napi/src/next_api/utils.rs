@@ -10,7 +10,8 @@ use napi::{
 };
 use serde::Serialize;
 use turbo_tasks::{
-    trace::TraceRawVcs, ReadRef, TaskId, TryJoinIterExt, TurboTasks, UpdateInfo, Vc,
+    function_statistics::FunctionStatistics, run_once_with_priority, trace::TraceRawVcs, ReadRef,
+    TaskId, TaskPriority, TryJoinIterExt, TurboTasks, UpdateInfo, Vc,
 };
 use turbo_tasks_backend::{default_backing_storage, DefaultBackingStorage};
 use turbo_tasks_fs::FileContent;
@@ -61,6 +62,23 @@ impl NextTurboTasks {
         }
     }
 
+    /// Like [`Self::run_once`], but the future and all tasks it schedules are executed with the
+    /// given [`TaskPriority`].
+    pub async fn run_once_with_priority<T: Send + 'static>(
+        &self,
+        priority: TaskPriority,
+        future: impl Future<Output = Result<T>> + Send + 'static,
+    ) -> Result<T> {
+        match self {
+            NextTurboTasks::Memory(turbo_tasks) => {
+                run_once_with_priority(turbo_tasks.clone(), priority, future).await
+            }
+            NextTurboTasks::PersistentCaching(turbo_tasks) => {
+                run_once_with_priority(turbo_tasks.clone(), priority, future).await
+            }
+        }
+    }
+
     pub fn spawn_once_task<T, Fut>(&self, future: Fut) -> TaskId
     where
         T: Send,
@@ -106,6 +124,13 @@ impl NextTurboTasks {
         }
     }
 
+    pub fn function_statistics(&self) -> Vec<FunctionStatistics> {
+        match self {
+            NextTurboTasks::Memory(turbo_tasks) => turbo_tasks.function_statistics(),
+            NextTurboTasks::PersistentCaching(turbo_tasks) => turbo_tasks.function_statistics(),
+        }
+    }
+
     pub fn memory_backend(&self) -> Option<&turbo_tasks_memory::MemoryBackend> {
         match self {
             NextTurboTasks::Memory(turbo_tasks) => Some(turbo_tasks.backend()),
@@ -135,6 +160,9 @@ pub fn create_turbo_tasks(
                     } else {
                         turbo_tasks_backend::StorageMode::ReadWrite
                     }),
+                    invalidation_provenance: env::var_os("NEXT_TURBOPACK_INVALIDATION_PROVENANCE")
+                        .is_some(),
+                    memory_limit: (memory_limit != usize::MAX).then_some(memory_limit),
                     ..Default::default()
                 },
                 default_backing_storage(&output_path.join("cache/turbopack"))?,
@@ -36,6 +36,7 @@ use turbopack_core::{
     module::{Module, Modules},
     reference::primary_chunkable_referenced_modules,
 };
+use turbopack_resolve::dedupe::{find_duplicate_packages, DuplicatePackages};
 
 use crate::{
     client_references::{map_client_references, ClientReferenceMapType, ClientReferencesSet},
@@ -1046,6 +1047,31 @@ impl ReducedGraphs {
     }
 }
 
+/// Emits an issue for each package that is bundled more than once into the client modules of
+/// `graphs`. Server modules are not sent to the browser, so they don't add to the bundle size.
+#[turbo_tasks::function]
+async fn report_duplicate_packages(
+    graphs: Vc<SingleModuleGraphs>,
+) -> Result<Vc<DuplicatePackages>> {
+    let mut modules = FxIndexSet::default();
+    for graph in graphs.await?.iter() {
+        modules.extend(
+            graph
+                .await?
+                .iter_nodes()
+                .filter(|node| {
+                    node.layer
+                        .as_ref()
+                        .is_some_and(|layer| &**layer == "app-client" || &**layer == "client")
+                })
+                .map(|node| node.module),
+        );
+    }
+    Ok(find_duplicate_packages(Vc::cell(
+        modules.into_iter().collect(),
+    )))
+}
+
 #[turbo_tasks::function]
 async fn get_reduced_graphs_for_endpoint_inner(
     project: Vc<Project>,
@@ -1075,6 +1101,9 @@ async fn get_reduced_graphs_for_endpoint_inner(
         ),
     };
 
+    // Endpoints that bundle a package in the same way share its issue
+    report_duplicate_packages(Vc::cell(graphs.clone())).await?;
+
     let next_dynamic = async {
         graphs
             .iter()
@@ -27,12 +27,15 @@ use turbo_tasks::{
     debug::ValueDebugFormat,
     fxindexmap,
     graph::{AdjacencyMap, GraphTraversal},
+    invalidation_provenance::InvalidationProvenance,
     trace::TraceRawVcs,
-    Completion, Completions, FxIndexMap, IntoTraitRef, NonLocalValue, ReadRef, ResolvedVc, State,
-    TaskInput, TransientInstance, TryFlatJoinIterExt, Value, Vc,
+    turbo_tasks, Completion, Completions, FxIndexMap, IntoTraitRef, NonLocalValue, ReadRef,
+    ResolvedVc, State, TaskInput, TransientInstance, TryFlatJoinIterExt, Value, Vc,
 };
 use turbo_tasks_env::{EnvMap, ProcessEnv};
-use turbo_tasks_fs::{DiskFileSystem, FileSystem, FileSystemPath, VirtualFileSystem};
+use turbo_tasks_fs::{
+    DiskFileSystem, FileSystem, FileSystemPath, VirtualFileSystem, WatcherDegradationKind,
+};
 use turbopack::{
     evaluate_context::node_build_environment, transition::TransitionOptions, ModuleAssetContext,
 };
@@ -546,6 +549,63 @@ impl Issue for ConflictIssue {
     }
 }
 
+#[turbo_tasks::value(shared)]
+struct FileWatcherIssue {
+    /// The first affected path.
+    path: ResolvedVc<FileSystemPath>,
+    kind: WatcherDegradationKind,
+    /// The number of affected paths.
+    count: usize,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for FileWatcherIssue {
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Misc.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Warning.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.path
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Text(match self.kind {
+            WatcherDegradationKind::WatchLimit => {
+                "The file watch limit of the OS was reached".into()
+            }
+            WatcherDegradationKind::EventOverflow => "The OS dropped file change events".into(),
+        })
+        .cell()
+    }
+
+    #[turbo_tasks::function]
+    fn description(&self) -> Vc<OptionStyledString> {
+        let description = match self.kind {
+            WatcherDegradationKind::WatchLimit => format!(
+                "{} directories can't be watched natively and are polled for changes instead, \
+                 which is slower and uses more CPU. On Linux the limit can be raised with `sysctl \
+                 fs.inotify.max_user_watches=524288`.",
+                self.count
+            ),
+            WatcherDegradationKind::EventOverflow => {
+                "Files changed faster than the OS could report them, e. g. during a git checkout. \
+                 The affected directories were rescanned to pick up the missed changes. On Linux \
+                 the event queue can be enlarged with `sysctl fs.inotify.max_queued_events=65536`."
+                    .to_string()
+            }
+        };
+        Vc::cell(Some(StyledString::Text(description.into()).resolved_cell()))
+    }
+}
+
 #[turbo_tasks::value_impl]
 impl Project {
     #[turbo_tasks::function]
@@ -947,11 +1007,44 @@ impl Project {
         Ok(Default::default())
     }
 
+    /// Emits an issue when the file watcher can't observe all changes natively, e. g. because
+    /// the OS limit of file watches was reached.
+    #[turbo_tasks::function]
+    async fn emit_file_watcher_issues(self: Vc<Self>) -> Result<Vc<()>> {
+        let degradations = self.project_fs().watcher_degradations().await?;
+        for kind in [
+            WatcherDegradationKind::WatchLimit,
+            WatcherDegradationKind::EventOverflow,
+        ] {
+            let mut paths = degradations
+                .iter()
+                .filter(|degradation| degradation.kind == kind)
+                .map(|degradation| &degradation.path);
+            let Some(first_path) = paths.next() else {
+                continue;
+            };
+            FileWatcherIssue {
+                path: self
+                    .project_root_path()
+                    .join(first_path.clone())
+                    .to_resolved()
+                    .await?,
+                kind,
+                count: 1 + paths.count(),
+            }
+            .resolved_cell()
+            .emit();
+        }
+
+        Ok(Default::default())
+    }
+
     /// Scans the app/pages directories for entry points files (matching the
     /// provided page_extensions).
     #[turbo_tasks::function]
     pub async fn entrypoints(self: Vc<Self>) -> Result<Vc<Entrypoints>> {
         self.collect_project_feature_telemetry().await?;
+        self.emit_file_watcher_issues().await?;
 
         let mut routes = FxIndexMap::default();
         let app_project = self.app_project();
@@ -1426,6 +1519,29 @@ impl Project {
     }
 }
 
+impl Project {
+    /// Explains why the last HMR update of the chunk group internally known as `identifier` was
+    /// recomputed, by following the chain of invalidations down to the root cause, e.g. a file
+    /// write. `from` is the version state of the HMR session, see [Project::hmr_version_state].
+    ///
+    /// Returns `None` when the backend doesn't record invalidation provenance or the update was
+    /// never recomputed. Must be called before the version state is updated, since that
+    /// invalidates the update again.
+    pub async fn hmr_invalidation_provenance(
+        self: Vc<Self>,
+        identifier: RcStr,
+        from: Vc<VersionState>,
+    ) -> Result<Option<InvalidationProvenance>> {
+        // The arguments need to be resolved to look up the same task as the HMR subscription. The
+        // update must not be resolved, since that would return the task that owns its cell.
+        let this = self.resolve().await?;
+        let from = from.resolve().await?;
+        let update = this.hmr_update(identifier, from);
+        let task_id = Vc::into_raw(update).get_task_id();
+        Ok(turbo_tasks().invalidation_provenance(task_id))
+    }
+}
+
 #[turbo_tasks::function]
 async fn any_output_changed(
     roots: Vc<OutputAssets>,
@@ -247,6 +247,39 @@ export interface NapiUpdateInfo {
   duration: number
   tasks: number
 }
+export interface NapiFunctionStatistics {
+  name: string
+  executions: number
+  cacheHits: number
+  totalDurationMs: number
+  peakDurationMs: number
+  cellsWritten: number
+  invalidations: number
+}
+/**
+ * Returns execution statistics per turbo-tasks function, accumulated since the project was
+ * created and sorted by total execution time. Only the persistent caching backend collects them,
+ * with the memory backend the list is empty.
+ */
+export declare function projectFunctionStatistics(project: {
+  __napiType: 'Project'
+}): Array<NapiFunctionStatistics>
+export interface NapiInvalidationStep {
+  /** The task that was invalidated. */
+  description: string
+  /** Why the task was invalidated, e.g. which cell changed or which file was modified. */
+  cause: string
+}
+/**
+ * Explains why the last HMR update of `identifier` was recomputed. The first step is the update
+ * itself, every following step is the task whose change invalidated the previous one, the last
+ * step holds the root cause, e.g. a file write. Only recorded when the
+ * `NEXT_TURBOPACK_INVALIDATION_PROVENANCE` env var is set and persistent caching is enabled.
+ */
+export declare function projectHmrInvalidationProvenance(
+  project: { __napiType: 'Project' },
+  identifier: string
+): Array<NapiInvalidationStep> | null
 /**
  * Subscribes to lifecycle events of the compilation.
  *
@@ -0,0 +1,258 @@
+//! Prints statistics about the on-disk structure of a turbo-persistence database.
+//!
+//! Usage: `turbo-persistence-inspect <path> [--sst | --verify | --repair]`
+//!
+//! The database is opened in read-only mode, so it's safe to run this while no other process is
+//! writing to the database. `--verify` checks the consistency of all files and `--repair` rolls the
+//! database back to the last consistent commit. `--repair` modifies the database and must not run
+//! while another process has it opened.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use turbo_persistence::{
+    codec_name, BlobFileStatistics, BlockStatistics, EntryStatistics, FamilyStatistics, Snapshot,
+    TurboPersistence,
+};
+
+fn main() -> Result<()> {
+    let mut path = None;
+    let mut print_sst_files = false;
+    let mut verify = false;
+    let mut repair = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--sst" => print_sst_files = true,
+            "--verify" => verify = true,
+            "--repair" => repair = true,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => bail!("Unexpected argument: {arg}"),
+        }
+    }
+    let path = path.context("missing argument: database path")?;
+
+    if repair {
+        let report = TurboPersistence::repair(&path)?;
+        match report.previous_sequence_number {
+            Some(previous) if previous == report.sequence_number => {
+                println!("Sequence number: {} (unchanged)", report.sequence_number)
+            }
+            Some(previous) => println!(
+                "Sequence number: {} (rolled back from {previous})",
+                report.sequence_number
+            ),
+            None => println!(
+                "Sequence number: {} (CURRENT file was invalid)",
+                report.sequence_number
+            ),
+        }
+        for file in report.removed_files.iter() {
+            println!("Removed {}", file.display());
+        }
+        for file in report.unexpected_files.iter() {
+            println!("Skipped unexpected file {}", file.display());
+        }
+        return Ok(());
+    }
+
+    if verify {
+        let report = TurboPersistence::verify(&path)?;
+        if let Some(sequence_number) = report.sequence_number {
+            println!("Sequence number:   {sequence_number}");
+        }
+        println!("SST files:         {}", report.sst_files);
+        println!("Blob files:        {}", report.blob_files);
+        println!("Uncommitted files: {}", report.uncommitted_files);
+        if !report.has_commit_log {
+            println!("Commit log:        missing (written by an older version)");
+        }
+        for issue in report.issues.iter() {
+            println!("{issue}");
+        }
+        if !report.is_ok() {
+            bail!(
+                "Found {} issues, run with --repair to fix them",
+                report.issues.len()
+            );
+        }
+        println!("No issues found");
+        return Ok(());
+    }
+
+    let db = TurboPersistence::open_read_only(path.clone())
+        .with_context(|| format!("Unable to open database at {}", path.display()))?;
+    print_database(&path, &db.snapshot(), print_sst_files)?;
+
+    #[cfg(feature = "stats")]
+    println!("\n{:#?}", db.statistics());
+
+    Ok(())
+}
+
+fn print_database(path: &Path, snapshot: &Snapshot<'_>, print_sst_files: bool) -> Result<()> {
+    let families = snapshot.family_statistics()?;
+    let blob_files = snapshot.blob_file_statistics(&families)?;
+
+    println!("Database:        {}", path.display());
+    println!("Sequence number: {}", snapshot.sequence_number());
+
+    for family in families.iter() {
+        println!();
+        print_family(family, print_sst_files);
+    }
+
+    let (referenced, unreferenced): (Vec<_>, Vec<_>) =
+        blob_files.iter().partition(|b| b.referenced);
+    let file_size = |blobs: &[&BlobFileStatistics]| blobs.iter().map(|b| b.file_size).sum::<u64>();
+    let uncompressed_size = blob_files.iter().map(|b| b.uncompressed_size).sum::<u64>();
+    println!();
+    println!("Blob files");
+    println!(
+        "  Files:             {} ({} on disk, {} uncompressed)",
+        blob_files.len(),
+        format_bytes(file_size(&referenced) + file_size(&unreferenced)),
+        format_bytes(uncompressed_size)
+    );
+    println!(
+        "  Referenced:        {} ({})",
+        referenced.len(),
+        format_bytes(file_size(&referenced))
+    );
+    println!(
+        "  Unreferenced:      {} ({})",
+        unreferenced.len(),
+        format_bytes(file_size(&unreferenced))
+    );
+    Ok(())
+}
+
+fn print_family(family: &FamilyStatistics, print_sst_files: bool) {
+    let mut entries = EntryStatistics::default();
+    let mut index_blocks = BlockStatistics::default();
+    let mut key_blocks = BlockStatistics::default();
+    let mut value_blocks = BlockStatistics::default();
+    let mut file_size = 0;
+    let mut aqmf_size = 0;
+    let mut key_compression_dictionary_size = 0;
+    let mut value_compression_dictionary_size = 0;
+    for sst in family.sst_files.iter() {
+        entries.small += sst.entries.small;
+        entries.medium += sst.entries.medium;
+        entries.blob += sst.entries.blob;
+        entries.deleted += sst.entries.deleted;
+        add_blocks(&mut index_blocks, &sst.index_blocks);
+        add_blocks(&mut key_blocks, &sst.key_blocks);
+        add_blocks(&mut value_blocks, &sst.value_blocks);
+        file_size += sst.file_size;
+        aqmf_size += sst.aqmf_size;
+        key_compression_dictionary_size += sst.key_compression_dictionary_size;
+        value_compression_dictionary_size += sst.value_compression_dictionary_size;
+    }
+
+    let mut codecs = family
+        .sst_files
+        .iter()
+        .map(|sst| codec_name(sst.codec))
+        .collect::<Vec<_>>();
+    codecs.sort_unstable();
+    codecs.dedup();
+
+    println!("Family {}", family.family);
+    println!(
+        "  SST files:         {} ({})",
+        family.sst_files.len(),
+        format_bytes(file_size)
+    );
+    println!("  Codecs:            {}", codecs.join(", "));
+    println!(
+        "  Entries:           {} (small {}, medium {}, blob {}, deleted {})",
+        entries.total(),
+        entries.small,
+        entries.medium,
+        entries.blob,
+        entries.deleted
+    );
+    println!("  Live keys:         {}", family.live_keys);
+    println!(
+        "  Tombstones:        {:.1}%",
+        percent(entries.deleted as u64, entries.total() as u64)
+    );
+    println!(
+        "  Shadowed entries:  {:.1}%",
+        percent(
+            (entries.total() - entries.deleted).saturating_sub(family.live_keys) as u64,
+            entries.total() as u64
+        )
+    );
+    println!("  Coverage:          {:.2}", family.coverage);
+    println!("  AQMF filters:      {}", format_bytes(aqmf_size as u64));
+    println!(
+        "  Dictionaries:      key {}, value {}",
+        format_bytes(key_compression_dictionary_size as u64),
+        format_bytes(value_compression_dictionary_size as u64)
+    );
+    println!("  Index blocks:      {}", format_blocks(&index_blocks));
+    println!("  Key blocks:        {}", format_blocks(&key_blocks));
+    println!("  Value blocks:      {}", format_blocks(&value_blocks));
+
+    if print_sst_files {
+        for sst in family.sst_files.iter() {
+            println!(
+                "  SST {:08}  {:016x} - {:016x}  {:<4}  {:>10}  {:>8} entries  {:>4} key blocks  \
+                 {:>4} value blocks",
+                sst.sequence_number,
+                sst.min_hash,
+                sst.max_hash,
+                codec_name(sst.codec),
+                format_bytes(sst.file_size),
+                sst.entries.total(),
+                sst.key_blocks.count,
+                sst.value_blocks.count
+            );
+        }
+    }
+}
+
+fn add_blocks(total: &mut BlockStatistics, blocks: &BlockStatistics) {
+    total.count += blocks.count;
+    total.compressed_size += blocks.compressed_size;
+    total.uncompressed_size += blocks.uncompressed_size;
+}
+
+fn format_blocks(blocks: &BlockStatistics) -> String {
+    let ratio = if blocks.compressed_size == 0 {
+        0.0
+    } else {
+        blocks.uncompressed_size as f64 / blocks.compressed_size as f64
+    };
+    format!(
+        "{} ({} compressed, {} uncompressed, ratio {:.2}x)",
+        blocks.count,
+        format_bytes(blocks.compressed_size),
+        format_bytes(blocks.uncompressed_size),
+        ratio
+    )
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.2} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,5 @@
+/// Computes the checksum that is stored next to compressed blocks and blob values. It's used to
+/// detect truncated or corrupted files.
+pub(crate) fn checksum(data: &[u8]) -> u32 {
+    twox_hash::XxHash64::oneshot(0, data) as u32
+}
@@ -0,0 +1,105 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+
+/// The name of the commit log file in the database directory.
+const COMMIT_LOG_FILE: &str = "LOG";
+
+/// The size of a single entry in the commit log: sequence number (4 bytes) and flags (1 byte).
+const ENTRY_SIZE: usize = 5;
+
+/// Flag for commits that deleted files.
+const FLAG_DELETES_FILES: u8 = 1;
+
+/// An entry in the commit log. Every commit appends an entry before the CURRENT file is updated,
+/// so the log contains all commit boundaries. It's used to roll back to a consistent state during
+/// repair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CommitLogEntry {
+    /// The sequence number of the database after the commit.
+    pub(crate) sequence_number: u32,
+    /// The commit deleted files (e. g. a compaction). Such commits can only be rolled back while
+    /// its del file and the files listed in it still exist.
+    pub(crate) deletes_files: bool,
+}
+
+/// Reads all complete entries of the commit log. A missing log is treated as empty. An incomplete
+/// entry at the end is ignored, it's from a commit that never finished.
+pub(crate) fn read_commit_log(path: &Path) -> Result<Vec<CommitLogEntry>> {
+    let content = match fs::read(path.join(COMMIT_LOG_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read LOG file"),
+    };
+    content
+        .chunks_exact(ENTRY_SIZE)
+        .map(|mut entry| {
+            Ok(CommitLogEntry {
+                sequence_number: entry.read_u32::<BE>()?,
+                deletes_files: entry.read_u8()? & FLAG_DELETES_FILES != 0,
+            })
+        })
+        .collect()
+}
+
+/// Returns true if the database has a commit log. Databases written before the commit log was
+/// introduced don't have one.
+pub(crate) fn has_commit_log(path: &Path) -> bool {
+    path.join(COMMIT_LOG_FILE).is_file()
+}
+
+/// Appends an entry to the commit log and fsyncs it.
+pub(crate) fn append_commit_log(path: &Path, entry: CommitLogEntry) -> Result<()> {
+    let mut buf = Vec::with_capacity(ENTRY_SIZE);
+    buf.write_u32::<BE>(entry.sequence_number)?;
+    buf.write_u8(if entry.deletes_files {
+        FLAG_DELETES_FILES
+    } else {
+        0
+    })?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path.join(COMMIT_LOG_FILE))
+        .context("Failed to open LOG file")?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Removes all entries after the given sequence number and incomplete entries from the commit log.
+/// These are left over from commits that never finished and would otherwise be mistaken for commit
+/// boundaries later.
+pub(crate) fn truncate_commit_log(path: &Path, sequence_number: u32) -> Result<()> {
+    let entries = read_commit_log(path)?;
+    let valid_entries = entries
+        .iter()
+        .take_while(|entry| entry.sequence_number <= sequence_number)
+        .count();
+    let valid_len = (valid_entries * ENTRY_SIZE) as u64;
+    let log_path = path.join(COMMIT_LOG_FILE);
+    match fs::metadata(&log_path) {
+        Ok(metadata) if metadata.len() > valid_len => {
+            let file = OpenOptions::new()
+                .write(true)
+                .open(&log_path)
+                .context("Failed to open LOG file")?;
+            file.set_len(valid_len)?;
+            file.sync_all()?;
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context("Failed to read LOG file metadata"),
+    }
+    Ok(())
+}
+
+/// Returns true if the file name is the commit log.
+pub(crate) fn is_commit_log(name: &str) -> bool {
+    name == COMMIT_LOG_FILE
+}
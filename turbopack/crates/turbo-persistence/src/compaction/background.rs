@@ -0,0 +1,224 @@
+#[cfg(test)]
+use std::sync::mpsc;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use parking_lot::{Condvar, Mutex};
+
+use crate::TurboPersistence;
+
+/// The longest time the throttle sleeps before checking for cancellation again.
+const MAX_THROTTLE_SLEEP: Duration = Duration::from_millis(50);
+
+/// Configuration for the background compaction. See
+/// [`TurboPersistence::start_background_compaction`].
+#[derive(Debug, Clone)]
+pub struct BackgroundCompactionConfig {
+    /// A key family is only compacted when its coverage exceeds this value.
+    pub max_coverage: f32,
+    /// The maximum number of SST files that are merged into a new sequence of SST files. Smaller
+    /// values lead to shorter, more incremental compactions.
+    pub max_merge_sequence: usize,
+    /// The time to wait between two compactions.
+    pub interval: Duration,
+    /// The maximum number of bytes per second that are merged by a compaction. Reading and writing
+    /// is roughly proportional to that. `None` disables the limit.
+    pub io_budget: Option<u64>,
+    /// The number of threads that are used for compaction.
+    pub threads: usize,
+}
+
+impl Default for BackgroundCompactionConfig {
+    fn default() -> Self {
+        Self {
+            max_coverage: 20.0,
+            max_merge_sequence: 8,
+            interval: Duration::from_secs(10),
+            io_budget: Some(64 * 1024 * 1024),
+            threads: 2,
+        }
+    }
+}
+
+/// The state that is shared between the handle and the background thread.
+pub(crate) struct BackgroundCompactionState {
+    /// Set when the background thread should exit. This also cancels a running compaction.
+    pub(crate) stopped: AtomicBool,
+    /// Used to wait for `stopped` between compactions.
+    mutex: Mutex<()>,
+    /// Notified when `stopped` is set.
+    stopped_changed: Condvar,
+    /// Receives a message whenever a compaction has started its write operation.
+    #[cfg(test)]
+    started: Mutex<mpsc::Sender<()>>,
+}
+
+impl BackgroundCompactionState {
+    /// Called by a compaction once it holds the write operation, so writes started afterwards
+    /// cancel it.
+    pub(crate) fn compaction_started(&self) {
+        #[cfg(test)]
+        let _ = self.started.lock().send(());
+    }
+}
+
+/// A handle to the background compaction thread. The thread is stopped when the handle is dropped.
+pub struct BackgroundCompaction {
+    shared: Arc<BackgroundCompactionState>,
+    join_handle: Option<JoinHandle<Result<()>>>,
+    #[cfg(test)]
+    started: mpsc::Receiver<()>,
+}
+
+impl BackgroundCompaction {
+    /// Starts the background compaction thread. It holds only a weak reference to the database and
+    /// exits when the database is dropped.
+    pub(crate) fn start(
+        db: Weak<TurboPersistence>,
+        config: BackgroundCompactionConfig,
+    ) -> Result<Self> {
+        #[cfg(test)]
+        let (started_sender, started) = mpsc::channel();
+        let shared = Arc::new(BackgroundCompactionState {
+            stopped: AtomicBool::new(false),
+            mutex: Mutex::new(()),
+            stopped_changed: Condvar::new(),
+            #[cfg(test)]
+            started: Mutex::new(started_sender),
+        });
+        let thread_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(config.threads.max(1))
+            .thread_name(|i| format!("turbo-persistence compaction {i}"))
+            .build()
+            .context("Unable to create compaction thread pool")?;
+        let join_handle = thread::Builder::new()
+            .name("turbo-persistence compaction".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || loop {
+                    {
+                        let mut guard = shared.mutex.lock();
+                        if !shared.stopped.load(Ordering::Acquire) {
+                            shared.stopped_changed.wait_for(&mut guard, config.interval);
+                        }
+                    }
+                    if shared.stopped.load(Ordering::Acquire) {
+                        return Ok(());
+                    }
+                    let Some(db) = db.upgrade() else {
+                        return Ok(());
+                    };
+                    thread_pool.install(|| db.background_compact(&config, &shared))?;
+                }
+            })
+            .context("Unable to spawn compaction thread")?;
+        Ok(Self {
+            shared,
+            join_handle: Some(join_handle),
+            #[cfg(test)]
+            started,
+        })
+    }
+
+    /// Blocks until the next compaction has started. Fails when the background thread has exited.
+    #[cfg(test)]
+    pub(crate) fn wait_for_compaction(&self) -> Result<()> {
+        self.started
+            .recv()
+            .context("Background compaction thread has exited")
+    }
+
+    /// Stops the background thread, cancels a running compaction and waits for the thread to
+    /// exit. Returns the error that stopped the thread, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop_internal()
+    }
+
+    fn stop_internal(&mut self) -> Result<()> {
+        self.shared.stopped.store(true, Ordering::Release);
+        {
+            let _guard = self.shared.mutex.lock();
+            self.shared.stopped_changed.notify_all();
+        }
+        match self.join_handle.take() {
+            Some(join_handle) => join_handle
+                .join()
+                .unwrap_or_else(|_| Err(anyhow!("Background compaction thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BackgroundCompaction {
+    fn drop(&mut self) {
+        // Errors can't be returned from drop, use `stop` to handle them
+        if let Err(err) = self.stop_internal() {
+            eprintln!("Background compaction failed: {err:?}");
+        }
+    }
+}
+
+/// Limits the throughput of a compaction and allows to cancel it. It's checked regularly while
+/// merging.
+pub(crate) struct CompactionThrottle<'a> {
+    /// The maximum number of bytes per second.
+    bytes_per_second: Option<u64>,
+    /// Set when the compaction should be cancelled, e. g. because a write batch is started.
+    cancelled: &'a AtomicBool,
+    /// Set when the background compaction is stopped.
+    stopped: &'a AtomicBool,
+    /// The start of the compaction.
+    start: Instant,
+    /// The number of bytes processed so far.
+    processed: AtomicU64,
+}
+
+impl<'a> CompactionThrottle<'a> {
+    pub(crate) fn new(
+        bytes_per_second: Option<u64>,
+        cancelled: &'a AtomicBool,
+        stopped: &'a AtomicBool,
+    ) -> Self {
+        Self {
+            bytes_per_second,
+            cancelled,
+            stopped,
+            start: Instant::now(),
+            processed: AtomicU64::new(0),
+        }
+    }
+
+    /// Accounts for processed bytes. Sleeps when the compaction is ahead of the budget. Fails when
+    /// the compaction is cancelled.
+    pub(crate) fn consume(&self, bytes: u64) -> Result<()> {
+        let processed = self.processed.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let target = self
+            .bytes_per_second
+            .map(|rate| Duration::from_secs_f64(processed as f64 / rate.max(1) as f64));
+        loop {
+            if self.is_cancelled() {
+                bail!("Compaction cancelled");
+            }
+            let Some(target) = target else {
+                return Ok(());
+            };
+            let elapsed = self.start.elapsed();
+            if elapsed >= target {
+                return Ok(());
+            }
+            thread::sleep((target - elapsed).min(MAX_THROTTLE_SLEEP));
+        }
+    }
+
+    /// Returns true if the compaction was cancelled or the background compaction was stopped.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire) || self.stopped.load(Ordering::Acquire)
+    }
+}
@@ -1 +1,2 @@
+pub mod background;
 pub mod selector;
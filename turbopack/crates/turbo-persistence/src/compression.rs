@@ -0,0 +1,266 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use lzzzz::lz4::{self, max_compressed_size, ACC_LEVEL_DEFAULT};
+
+/// The id of the LZ4 codec in file headers.
+const CODEC_LZ4: u8 = 0;
+/// The id of the zstd codec in file headers.
+const CODEC_ZSTD: u8 = 1;
+
+/// The default zstd compression level.
+const ZSTD_DEFAULT_LEVEL: i32 = 3;
+
+/// The compression codec that is used to compress key blocks, value blocks and blob files.
+///
+/// The codec is recorded in the header of each file, so files written with different codecs can be
+/// mixed in a single database. Compaction rewrites files with the configured codec.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionCodec {
+    /// LZ4 with a compression dictionary. Very fast compression and decompression.
+    #[default]
+    Lz4,
+    /// zstd with a trained compression dictionary. Slower, but leads to much smaller files.
+    Zstd {
+        /// The compression level (1-22).
+        level: i32,
+    },
+}
+
+impl CompressionCodec {
+    /// The id of the codec that is stored in file headers.
+    pub(crate) fn id(&self) -> u8 {
+        match self {
+            CompressionCodec::Lz4 => CODEC_LZ4,
+            CompressionCodec::Zstd { .. } => CODEC_ZSTD,
+        }
+    }
+
+    /// Prepares a compression dictionary for this codec. An empty dictionary is allowed.
+    pub(crate) fn encoder_dictionary(&self, dict: Vec<u8>) -> EncoderDictionary {
+        let zstd = match *self {
+            CompressionCodec::Zstd { level } if !dict.is_empty() => {
+                Some(zstd::dict::EncoderDictionary::copy(&dict, level))
+            }
+            _ => None,
+        };
+        EncoderDictionary { raw: dict, zstd }
+    }
+
+    /// Compresses a block with a compression dictionary that was prepared for this codec.
+    pub(crate) fn compress_with_dict(
+        &self,
+        block: &[u8],
+        dict: &EncoderDictionary,
+    ) -> Result<Vec<u8>> {
+        let mut compressed = match *self {
+            CompressionCodec::Lz4 => {
+                let mut compressor = lz4::Compressor::with_dict(&dict.raw)
+                    .context("LZ4 compressor creation failed")?;
+                let mut compressed = Vec::with_capacity(max_compressed_size(block.len()));
+                compressor.next_to_vec(block, &mut compressed, ACC_LEVEL_DEFAULT)?;
+                compressed
+            }
+            CompressionCodec::Zstd { level } => {
+                let mut compressor = match &dict.zstd {
+                    Some(prepared) => zstd::bulk::Compressor::with_prepared_dictionary(prepared),
+                    None => zstd::bulk::Compressor::new(level),
+                }
+                .context("zstd compressor creation failed")?;
+                compressor.compress(block)?
+            }
+        };
+        if compressed.capacity() > compressed.len() * 2 {
+            compressed.shrink_to_fit();
+        }
+        Ok(compressed)
+    }
+
+    /// Compresses a value without a compression dictionary and appends it to the buffer.
+    pub(crate) fn compress_to_vec(&self, value: &[u8], buffer: &mut Vec<u8>) -> Result<()> {
+        match *self {
+            CompressionCodec::Lz4 => {
+                lz4::compress_to_vec(value, buffer, ACC_LEVEL_DEFAULT)?;
+            }
+            CompressionCodec::Zstd { level } => {
+                buffer.extend_from_slice(&zstd::bulk::compress(value, level)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A compression dictionary that is prepared once and reused for all blocks of an SST file. zstd
+/// would otherwise parse the dictionary again for every block.
+#[derive(Default)]
+pub(crate) struct EncoderDictionary {
+    /// The dictionary as it is stored in the file. LZ4 uses it directly.
+    raw: Vec<u8>,
+    /// The prepared zstd dictionary, if the codec is zstd and the dictionary is not empty.
+    zstd: Option<zstd::dict::EncoderDictionary<'static>>,
+}
+
+impl fmt::Debug for EncoderDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncoderDictionary")
+            .field("size", &self.raw.len())
+            .finish()
+    }
+}
+
+impl EncoderDictionary {
+    /// The dictionary as it is stored in the file.
+    pub(crate) fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+/// A decompression dictionary that is prepared once and reused for all blocks of an SST file.
+pub(crate) struct DecoderDictionary {
+    /// The codec id from the file header.
+    codec: u8,
+    /// The raw dictionary. Only kept for LZ4, which uses it directly.
+    raw: Vec<u8>,
+    /// The prepared zstd dictionary, if the codec is zstd and the dictionary is not empty.
+    zstd: Option<zstd::dict::DecoderDictionary<'static>>,
+}
+
+impl DecoderDictionary {
+    /// Prepares a dictionary for the codec with the given id. An empty dictionary is allowed.
+    pub(crate) fn new(codec: u8, dict: &[u8]) -> Self {
+        Self {
+            codec,
+            raw: if codec == CODEC_LZ4 {
+                dict.to_vec()
+            } else {
+                Vec::new()
+            },
+            zstd: (codec == CODEC_ZSTD && !dict.is_empty())
+                .then(|| zstd::dict::DecoderDictionary::copy(dict)),
+        }
+    }
+
+    /// Decompresses data that was compressed with this dictionary. The destination buffer needs
+    /// to have the exact uncompressed size.
+    pub(crate) fn decompress(&self, compressed: &[u8], decompressed: &mut [u8]) -> Result<()> {
+        match self.codec {
+            CODEC_LZ4 => {
+                lz4::decompress_with_dict(compressed, decompressed, &self.raw)?;
+            }
+            CODEC_ZSTD => {
+                let mut decompressor = match &self.zstd {
+                    Some(prepared) => zstd::bulk::Decompressor::with_prepared_dictionary(prepared),
+                    None => zstd::bulk::Decompressor::new(),
+                }
+                .context("zstd decompressor creation failed")?;
+                let len = decompressor.decompress_to_buffer(compressed, decompressed)?;
+                if len != decompressed.len() {
+                    bail!(
+                        "Decompressed size {len} doesn't match expected size {}",
+                        decompressed.len()
+                    );
+                }
+            }
+            codec => bail!("Unknown compression codec {codec}"),
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses data that was compressed without a dictionary with the codec with the given id.
+/// The destination buffer needs to have the exact uncompressed size.
+pub(crate) fn decompress(codec: u8, compressed: &[u8], decompressed: &mut [u8]) -> Result<()> {
+    DecoderDictionary::new(codec, &[]).decompress(compressed, decompressed)
+}
+
+/// Returns a human readable name for a codec id.
+pub fn codec_name(codec: u8) -> &'static str {
+    match codec {
+        CODEC_LZ4 => "lz4",
+        CODEC_ZSTD => "zstd",
+        _ => "unknown",
+    }
+}
+
+/// Checks that the codec id is known.
+pub(crate) fn validate_codec(codec: u8) -> Result<()> {
+    if codec != CODEC_LZ4 && codec != CODEC_ZSTD {
+        bail!("Unknown compression codec {codec}");
+    }
+    Ok(())
+}
+
+impl fmt::Display for CompressionCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressionCodec::Lz4 => write!(f, "lz4"),
+            CompressionCodec::Zstd { level } => write!(f, "zstd:{level}"),
+        }
+    }
+}
+
+/// Parses `lz4`, `zstd` or `zstd:<level>`.
+impl FromStr for CompressionCodec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once(':') {
+            None if s == "lz4" => Ok(CompressionCodec::Lz4),
+            None if s == "zstd" => Ok(CompressionCodec::Zstd {
+                level: ZSTD_DEFAULT_LEVEL,
+            }),
+            Some(("zstd", level)) => {
+                let level = level
+                    .parse()
+                    .with_context(|| format!("Invalid zstd compression level {level}"))?;
+                if !zstd::compression_level_range().contains(&level) {
+                    bail!("zstd compression level {level} is out of range");
+                }
+                Ok(CompressionCodec::Zstd { level })
+            }
+            _ => bail!("Unknown compression codec {s} (expected lz4, zstd or zstd:<level>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressionCodec, DecoderDictionary};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "lz4".parse::<CompressionCodec>().unwrap(),
+            CompressionCodec::Lz4
+        );
+        assert_eq!(
+            "zstd:19".parse::<CompressionCodec>().unwrap(),
+            CompressionCodec::Zstd { level: 19 }
+        );
+        assert!("zstd:100".parse::<CompressionCodec>().is_err());
+        assert!("gzip".parse::<CompressionCodec>().is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let data = (0..10000u32)
+            .flat_map(|i| (i % 100).to_be_bytes())
+            .collect::<Vec<_>>();
+        let dict = (0..100u32)
+            .flat_map(|i| i.to_be_bytes())
+            .collect::<Vec<_>>();
+        for codec in [CompressionCodec::Lz4, CompressionCodec::Zstd { level: 3 }] {
+            for dict in [&[][..], &dict[..]] {
+                let encoder = codec.encoder_dictionary(dict.to_vec());
+                let decoder = DecoderDictionary::new(codec.id(), dict);
+                // The prepared dictionaries are reused for multiple blocks
+                for block in data.chunks(7000) {
+                    let compressed = codec.compress_with_dict(block, &encoder).unwrap();
+                    let mut decompressed = vec![0; block.len()];
+                    decoder.decompress(&compressed, &mut decompressed).unwrap();
+                    assert_eq!(decompressed, block);
+                }
+            }
+        }
+    }
+}
@@ -28,3 +28,10 @@ pub const KEY_BLOCK_AVG_SIZE: usize = 16 * 1024;
 /// Maximum RAM bytes for value block cache
 pub const VALUE_BLOCK_CACHE_SIZE: u64 = 300 * 1024 * 1024;
 pub const VALUE_BLOCK_AVG_SIZE: usize = 132000;
+
+/// Size of the blob file header: codec (1 byte), uncompressed length (4 bytes) and checksum (4
+/// bytes)
+pub const BLOB_HEADER_SIZE: usize = 9;
+
+/// A background compaction checks its I/O budget and cancellation after merging this many bytes
+pub const COMPACTION_THROTTLE_GRANULARITY: usize = 1024 * 1024;
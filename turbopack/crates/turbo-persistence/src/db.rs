@@ -3,7 +3,7 @@ use std::{
     collections::HashSet,
     fs::{self, File, OpenOptions, ReadDir},
     io::Write,
-    mem::{swap, transmute, MaybeUninit},
+    mem::{replace, swap, transmute, MaybeUninit},
     path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicU32, Ordering},
@@ -13,26 +13,34 @@ use std::{
 
 use anyhow::{bail, Context, Result};
 use byteorder::{ReadBytesExt, WriteBytesExt, BE};
-use lzzzz::lz4::decompress;
 use memmap2::Mmap;
-use parking_lot::{Mutex, RwLock};
+use parking_lot::{Condvar, Mutex, RwLock, RwLockReadGuard};
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 
 use crate::{
     arc_slice::ArcSlice,
-    compaction::selector::{
-        get_compaction_jobs, total_coverage, CompactConfig, Compactable, CompactionJobs,
+    commit_log::{append_commit_log, is_commit_log, truncate_commit_log, CommitLogEntry},
+    compaction::{
+        background::{
+            BackgroundCompaction, BackgroundCompactionConfig, BackgroundCompactionState,
+            CompactionThrottle,
+        },
+        selector::{
+            get_compaction_jobs, total_coverage, CompactConfig, Compactable, CompactionJobs,
+        },
     },
+    compression::{decompress, CompressionCodec},
     constants::{
-        AQMF_AVG_SIZE, AQMF_CACHE_SIZE, DATA_THRESHOLD_PER_COMPACTED_FILE, KEY_BLOCK_AVG_SIZE,
-        KEY_BLOCK_CACHE_SIZE, MAX_ENTRIES_PER_COMPACTED_FILE, VALUE_BLOCK_AVG_SIZE,
-        VALUE_BLOCK_CACHE_SIZE,
+        AQMF_AVG_SIZE, AQMF_CACHE_SIZE, COMPACTION_THROTTLE_GRANULARITY,
+        DATA_THRESHOLD_PER_COMPACTED_FILE, KEY_BLOCK_AVG_SIZE, KEY_BLOCK_CACHE_SIZE,
+        MAX_ENTRIES_PER_COMPACTED_FILE, VALUE_BLOCK_AVG_SIZE, VALUE_BLOCK_CACHE_SIZE,
     },
     key::{hash_key, StoreKey},
-    lookup_entry::LookupEntry,
+    lookup_entry::{LookupEntry, LookupValue},
     merge_iter::MergeIter,
     static_sorted_file::{
-        AqmfCache, BlockCache, LookupResult, StaticSortedFile, StaticSortedFileRange,
+        AqmfCache, BlockCache, LookupResult, StaticSortedFile, StaticSortedFileIter,
+        StaticSortedFileRange, StaticSortedFileStatistics,
     },
     static_sorted_file_builder::StaticSortedFileBuilder,
     write_batch::{FinishResult, WriteBatch},
@@ -100,19 +108,68 @@ struct TrackedStats {
     miss_global: std::sync::atomic::AtomicU64,
 }
 
+/// Statistics about the on-disk structure of a key family.
+#[derive(Debug)]
+pub struct FamilyStatistics {
+    /// The key family.
+    pub family: u32,
+    /// Statistics for all SST files of the family in order.
+    pub sst_files: Vec<StaticSortedFileStatistics>,
+    /// The number of keys that are visible to readers.
+    pub live_keys: usize,
+    /// The average number of SST files that need to be touched to figure out that a key is
+    /// missing. Compaction reduces that.
+    pub coverage: f32,
+}
+
+/// Statistics about a blob file.
+#[derive(Debug)]
+pub struct BlobFileStatistics {
+    /// The sequence number of the blob file.
+    pub sequence_number: u32,
+    /// The compression codec of the blob file. See [`crate::codec_name`].
+    pub codec: u8,
+    /// The size of the file on disk.
+    pub file_size: u64,
+    /// The size of the value after decompression.
+    pub uncompressed_size: u64,
+    /// True if any SST file references this blob file.
+    pub referenced: bool,
+}
+
+/// Configuration for a TurboPersistence database.
+#[derive(Debug, Default, Clone)]
+pub struct DbConfig {
+    /// The compression codec for new SST and blob files. Existing files keep their codec until
+    /// they are compacted.
+    pub compression: CompressionCodec,
+}
+
 /// TurboPersistence is a persistent key-value store. It is limited to a single writer at a time
 /// using a single write batch. It allows for concurrent reads.
 pub struct TurboPersistence {
     /// The path to the directory where the database is stored
     path: PathBuf,
+    /// If true, the database directory is never modified.
+    read_only: bool,
+    /// The configuration of the database.
+    config: DbConfig,
     /// The inner state of the database. Writing will update that.
     inner: RwLock<Inner>,
     /// A cache for the last WriteBatch. It is used to avoid reallocation of buffers for the
     /// WriteBatch.
     idle_write_batch: Mutex<Option<(TypeId, Box<dyn Any + Send + Sync>)>>,
-    /// A flag to indicate if a write operation is currently active. Prevents multiple concurrent
-    /// write operations.
-    active_write_operation: AtomicBool,
+    /// The state of the active write operation. Prevents multiple concurrent write operations.
+    write_operation: Mutex<WriteOperationState>,
+    /// Notified when a write operation finishes, wakes up write operations that wait for a
+    /// cancelled background compaction.
+    write_operation_finished: Condvar,
+    /// SST files that were deleted by the last commit. They are removed from disk by the next
+    /// commit, so that a repair can still roll back the last commit.
+    deleted_sst_files: Mutex<Vec<u32>>,
+    /// Set to cancel a running background compaction, e. g. when a write batch is started. Only
+    /// modified while holding the `write_operation` lock.
+    background_compaction_cancelled: AtomicBool,
     /// A cache for deserialized AQMF filters.
     aqmf_cache: AqmfCache,
     /// A cache for decompressed key blocks.
@@ -124,6 +181,18 @@ pub struct TurboPersistence {
     stats: TrackedStats,
 }
 
+/// The state of write operations of the database.
+#[derive(Default)]
+struct WriteOperationState {
+    /// A write batch, a compaction or the background compaction is active.
+    active: bool,
+    /// The active write operation is the background compaction, which can be cancelled.
+    background_compaction: bool,
+    /// The number of write operations that wait for the background compaction to finish. The
+    /// background compaction doesn't start while they are waiting.
+    waiting: usize,
+}
+
 /// The inner state of the database.
 struct Inner {
     /// The list of SST files in the database in order.
@@ -138,14 +207,40 @@ impl TurboPersistence {
     /// properly. Cleanup only requires to read a few bytes from a few files and to delete
     /// files, so it's fast.
     pub fn open(path: PathBuf) -> Result<Self> {
-        let mut db = Self {
+        Self::open_with_config(path, DbConfig::default())
+    }
+
+    /// Open a TurboPersistence database at the given path with a custom configuration.
+    /// See [`TurboPersistence::open`].
+    pub fn open_with_config(path: PathBuf, config: DbConfig) -> Result<Self> {
+        let mut db = Self::new(path, false, config);
+        db.open_directory()?;
+        Ok(db)
+    }
+
+    /// Open a TurboPersistence database at the given path in read-only mode.
+    /// This will never modify the directory, so no cleanup is performed. Left-over uncommitted
+    /// files are ignored. Write batches and compactions are not allowed.
+    pub fn open_read_only(path: PathBuf) -> Result<Self> {
+        let mut db = Self::new(path, true, DbConfig::default());
+        db.open_directory()?;
+        Ok(db)
+    }
+
+    fn new(path: PathBuf, read_only: bool, config: DbConfig) -> Self {
+        Self {
             path,
+            read_only,
+            config,
             inner: RwLock::new(Inner {
                 static_sorted_files: Vec::new(),
                 current_sequence_number: 0,
             }),
             idle_write_batch: Mutex::new(None),
-            active_write_operation: AtomicBool::new(false),
+            write_operation: Mutex::new(WriteOperationState::default()),
+            write_operation_finished: Condvar::new(),
+            deleted_sst_files: Mutex::new(Vec::new()),
+            background_compaction_cancelled: AtomicBool::new(false),
             aqmf_cache: AqmfCache::with(
                 AQMF_CACHE_SIZE as usize / AQMF_AVG_SIZE,
                 AQMF_CACHE_SIZE,
@@ -169,9 +264,7 @@ impl TurboPersistence {
             ),
             #[cfg(feature = "stats")]
             stats: TrackedStats::default(),
-        };
-        db.open_directory()?;
-        Ok(db)
+        }
     }
 
     /// Performas the initial check on the database directory.
@@ -182,13 +275,16 @@ impl TurboPersistence {
                     .load_directory(entries)
                     .context("Loading persistence directory failed")?
                 {
+                    if self.read_only {
+                        bail!("Database has no CURRENT file");
+                    }
                     self.init_directory()
                         .context("Initializing persistence directory failed")?;
                 }
                 Ok(())
             }
             Err(e) => {
-                if e.kind() == std::io::ErrorKind::NotFound {
+                if e.kind() == std::io::ErrorKind::NotFound && !self.read_only {
                     self.create_and_init_directory()
                         .context("Creating and initializing persistence directory failed")?;
                     Ok(())
@@ -229,6 +325,10 @@ impl TurboPersistence {
         let current = current_file.read_u32::<BE>()?;
         drop(current_file);
 
+        if !self.read_only {
+            truncate_commit_log(&self.path, current)?;
+        }
+
         let mut deleted_files = HashSet::new();
         for entry in entries {
             let entry = entry?;
@@ -244,7 +344,9 @@ impl TurboPersistence {
                     continue;
                 }
                 if seq > current {
-                    fs::remove_file(&path)?;
+                    if !self.read_only {
+                        fs::remove_file(&path)?;
+                    }
                 } else {
                     match ext {
                         "sst" => {
@@ -258,6 +360,9 @@ impl TurboPersistence {
                                 deleted_files.insert(seq);
                                 let sst_file = self.path.join(format!("{:08}.sst", seq));
                                 let blob_file = self.path.join(format!("{:08}.blob", seq));
+                                if self.read_only {
+                                    continue;
+                                }
                                 for path in [sst_file, blob_file] {
                                     if fs::exists(&path)? {
                                         fs::remove_file(path)?;
@@ -265,7 +370,7 @@ impl TurboPersistence {
                                     }
                                 }
                             }
-                            if no_existing_files {
+                            if no_existing_files && !self.read_only {
                                 fs::remove_file(&path)?;
                             }
                         }
@@ -282,6 +387,9 @@ impl TurboPersistence {
                     Some("CURRENT") => {
                         // Already read
                     }
+                    Some(name) if is_commit_log(name) => {
+                        // Only used for repair
+                    }
                     _ => {
                         bail!("Unexpected file in persistence directory: {:?}", path);
                     }
@@ -339,17 +447,60 @@ impl TurboPersistence {
         #[cfg(target_os = "linux")]
         mmap.advise(memmap2::Advice::Unmergeable)?;
         let mut compressed = &mmap[..];
+        let codec = compressed.read_u8()?;
         let uncompressed_length = compressed.read_u32::<BE>()? as usize;
+        #[cfg(feature = "strict_checks")]
+        if compressed.len() < 4
+            || crate::checksum::checksum(&compressed[4..]) != (&compressed[..4]).read_u32::<BE>()?
+        {
+            bail!("Corrupted blob file {:08}.blob: checksum mismatch", seq);
+        }
+        // Skip the checksum
+        let compressed = compressed.get(4..).context("Blob file is truncated")?;
 
         let buffer = Arc::new_zeroed_slice(uncompressed_length);
         // Safety: MaybeUninit<u8> can be safely transmuted to u8.
         let mut buffer = unsafe { transmute::<Arc<[MaybeUninit<u8>]>, Arc<[u8]>>(buffer) };
         // Safety: We know that the buffer is not shared yet.
         let decompressed = unsafe { Arc::get_mut_unchecked(&mut buffer) };
-        decompress(compressed, decompressed)?;
+        decompress(codec, compressed, decompressed)
+            .with_context(|| format!("Unable to decompress blob file {:08}.blob", seq))?;
         Ok(ArcSlice::from(buffer))
     }
 
+    /// Marks the start of a write operation. Write batches and explicit compactions have priority
+    /// over the background compaction, so a running background compaction is cancelled. Fails when
+    /// another write batch or compaction is active.
+    fn start_write_operation(&self) -> Result<()> {
+        let mut state = self.write_operation.lock();
+        loop {
+            if !state.active {
+                state.active = true;
+                return Ok(());
+            }
+            if !state.background_compaction {
+                bail!(
+                    "Another write batch or compaction is already active (Only a single write \
+                     operations is allowed at a time)"
+                );
+            }
+            self.background_compaction_cancelled
+                .store(true, Ordering::Release);
+            state.waiting += 1;
+            self.write_operation_finished.wait(&mut state);
+            state.waiting -= 1;
+        }
+    }
+
+    /// Marks the end of a write operation and wakes up write operations that wait for it.
+    fn finish_write_operation(&self) {
+        let mut state = self.write_operation.lock();
+        state.active = false;
+        state.background_compaction = false;
+        drop(state);
+        self.write_operation_finished.notify_all();
+    }
+
     /// Returns true if the database is empty.
     pub fn is_empty(&self) -> bool {
         self.inner.read().static_sorted_files.is_empty()
@@ -362,16 +513,10 @@ impl TurboPersistence {
     pub fn write_batch<K: StoreKey + Send + Sync + 'static, const FAMILIES: usize>(
         &self,
     ) -> Result<WriteBatch<K, FAMILIES>> {
-        if self
-            .active_write_operation
-            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
-            .is_err()
-        {
-            bail!(
-                "Another write batch or compaction is already active (Only a single write \
-                 operations is allowed at a time)"
-            );
+        if self.read_only {
+            bail!("Database is opened in read-only mode");
         }
+        self.start_write_operation()?;
         let current = self.inner.read().current_sequence_number;
         if let Some((ty, any)) = self.idle_write_batch.lock().take() {
             if ty == TypeId::of::<WriteBatch<K, FAMILIES>>() {
@@ -380,7 +525,11 @@ impl TurboPersistence {
                 return Ok(write_batch);
             }
         }
-        Ok(WriteBatch::new(self.path.clone(), current))
+        Ok(WriteBatch::new(
+            self.path.clone(),
+            self.config.compression,
+            current,
+        ))
     }
 
     /// Commits a WriteBatch to the database. This will finish writing the data to disk and make it
@@ -395,7 +544,7 @@ impl TurboPersistence {
             new_blob_files,
         } = write_batch.finish()?;
         self.commit(new_sst_files, new_blob_files, vec![], sequence_number)?;
-        self.active_write_operation.store(false, Ordering::Release);
+        self.finish_write_operation();
         self.idle_write_batch.lock().replace((
             TypeId::of::<WriteBatch<K, FAMILIES>>(),
             Box::new(write_batch),
@@ -457,6 +606,14 @@ impl TurboPersistence {
             file.sync_all()?;
         }
 
+        append_commit_log(
+            &self.path,
+            CommitLogEntry {
+                sequence_number: seq,
+                deletes_files: !removed_ssts.is_empty(),
+            },
+        )?;
+
         let mut current_file = OpenOptions::new()
             .write(true)
             .truncate(false)
@@ -465,7 +622,8 @@ impl TurboPersistence {
         current_file.write_u32::<BE>(seq)?;
         current_file.sync_all()?;
 
-        for seq in removed_ssts {
+        let deleted_sst_files = replace(&mut *self.deleted_sst_files.lock(), removed_ssts);
+        for seq in deleted_sst_files {
             fs::remove_file(self.path.join(format!("{seq:08}.sst")))?;
         }
 
@@ -484,17 +642,72 @@ impl TurboPersistence {
     /// need to be read to find a key. It also limits the maximum number of SST files that are
     /// merged at once, which is the main factor for the runtime of the compaction.
     pub fn compact(&self, max_coverage: f32, max_merge_sequence: usize) -> Result<()> {
-        if self
-            .active_write_operation
-            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
-            .is_err()
+        if self.read_only {
+            bail!("Database is opened in read-only mode");
+        }
+        self.start_write_operation()?;
+        let result = self.compact_with_throttle(max_coverage, max_merge_sequence, None);
+        self.finish_write_operation();
+        result
+    }
+
+    /// Starts a background thread that compacts the database incrementally while it's used. Write
+    /// batches and explicit compactions cancel a running background compaction. The thread is
+    /// stopped when the returned handle is dropped.
+    pub fn start_background_compaction(
+        self: &Arc<Self>,
+        config: BackgroundCompactionConfig,
+    ) -> Result<BackgroundCompaction> {
+        if self.read_only {
+            bail!("Database is opened in read-only mode");
+        }
+        BackgroundCompaction::start(Arc::downgrade(self), config)
+    }
+
+    /// Runs a single background compaction. Does nothing when another write operation is active.
+    pub(crate) fn background_compact(
+        &self,
+        config: &BackgroundCompactionConfig,
+        state: &BackgroundCompactionState,
+    ) -> Result<()> {
         {
-            bail!(
-                "Another write batch or compaction is already active (Only a single write \
-                 operations is allowed at a time)"
-            );
+            let mut state = self.write_operation.lock();
+            if state.active || state.waiting > 0 {
+                return Ok(());
+            }
+            state.active = true;
+            state.background_compaction = true;
+            // Reset while holding the lock, so the cancellation by a write operation that starts
+            // during this compaction can't be lost
+            self.background_compaction_cancelled
+                .store(false, Ordering::Release);
         }
+        state.compaction_started();
+        let throttle = CompactionThrottle::new(
+            config.io_budget,
+            &self.background_compaction_cancelled,
+            &state.stopped,
+        );
+        let result = self.compact_with_throttle(
+            config.max_coverage,
+            config.max_merge_sequence,
+            Some(&throttle),
+        );
+        self.finish_write_operation();
+        match result {
+            Err(_) if throttle.is_cancelled() => Ok(()),
+            result => result,
+        }
+    }
 
+    /// Runs a compaction. The caller must have started a write operation. Files written by a
+    /// failed or cancelled compaction are removed.
+    fn compact_with_throttle(
+        &self,
+        max_coverage: f32,
+        max_merge_sequence: usize,
+        throttle: Option<&CompactionThrottle>,
+    ) -> Result<()> {
         let mut sequence_number;
         let mut new_sst_files = Vec::new();
         let mut indicies_to_delete = Vec::new();
@@ -502,14 +715,23 @@ impl TurboPersistence {
         {
             let inner = self.inner.read();
             sequence_number = AtomicU32::new(inner.current_sequence_number);
-            self.compact_internal(
+            if let Err(err) = self.compact_internal(
                 &inner.static_sorted_files,
                 &sequence_number,
                 &mut new_sst_files,
                 &mut indicies_to_delete,
                 max_coverage,
                 max_merge_sequence,
-            )?;
+                throttle,
+            ) {
+                for seq in inner.current_sequence_number + 1..=*sequence_number.get_mut() {
+                    let path = self.path.join(format!("{:08}.sst", seq));
+                    if fs::exists(&path)? {
+                        fs::remove_file(path)?;
+                    }
+                }
+                return Err(err);
+            }
         }
 
         self.commit(
@@ -519,8 +741,6 @@ impl TurboPersistence {
             *sequence_number.get_mut(),
         )?;
 
-        self.active_write_operation.store(false, Ordering::Release);
-
         Ok(())
     }
 
@@ -533,6 +753,7 @@ impl TurboPersistence {
         indicies_to_delete: &mut Vec<usize>,
         max_coverage: f32,
         max_merge_sequence: usize,
+        throttle: Option<&CompactionThrottle>,
     ) -> Result<bool> {
         if static_sorted_files.is_empty() {
             return Ok(false);
@@ -572,6 +793,7 @@ impl TurboPersistence {
         let key_block_cache = &self.key_block_cache;
         let value_block_cache = &self.value_block_cache;
         let path = &self.path;
+        let codec = self.config.compression;
 
         let result = sst_by_family
             .into_par_iter()
@@ -609,6 +831,7 @@ impl TurboPersistence {
                     .map(|indicies| {
                         fn create_sst_file(
                             family: u32,
+                            codec: CompressionCodec,
                             entries: &[LookupEntry],
                             total_key_size: usize,
                             total_value_size: usize,
@@ -617,6 +840,7 @@ impl TurboPersistence {
                         ) -> Result<(u32, File)> {
                             let builder = StaticSortedFileBuilder::new(
                                 family,
+                                codec,
                                 entries,
                                 total_key_size,
                                 total_value_size,
@@ -644,9 +868,18 @@ impl TurboPersistence {
                         let mut entries = Vec::new();
                         let mut last_entries = Vec::new();
                         let mut last_entries_total_sizes = (0, 0);
+                        let mut unthrottled_size = 0;
                         for entry in iter {
                             let entry = entry?;
 
+                            if let Some(throttle) = throttle {
+                                unthrottled_size += entry.key.len() + entry.value.size_in_sst();
+                                if unthrottled_size >= COMPACTION_THROTTLE_GRANULARITY {
+                                    throttle.consume(unthrottled_size as u64)?;
+                                    unthrottled_size = 0;
+                                }
+                            }
+
                             // Remove duplicates
                             if let Some(current) = current.take() {
                                 if current.key != entry.key {
@@ -675,6 +908,7 @@ impl TurboPersistence {
 
                                             new_sst_files.push(create_sst_file(
                                                 family as u32,
+                                                codec,
                                                 &entries,
                                                 selected_total_key_size,
                                                 selected_total_value_size,
@@ -705,6 +939,7 @@ impl TurboPersistence {
 
                             new_sst_files.push(create_sst_file(
                                 family as u32,
+                                codec,
                                 &entries,
                                 total_key_size,
                                 total_value_size,
@@ -728,6 +963,7 @@ impl TurboPersistence {
 
                             new_sst_files.push(create_sst_file(
                                 family as u32,
+                                codec,
                                 part1,
                                 // We don't know the exact sizes so we estimate them
                                 last_entries_total_sizes.0 / 2,
@@ -738,6 +974,7 @@ impl TurboPersistence {
 
                             new_sst_files.push(create_sst_file(
                                 family as u32,
+                                codec,
                                 part2,
                                 last_entries_total_sizes.0 / 2,
                                 last_entries_total_sizes.1 / 2,
@@ -835,6 +1072,16 @@ impl TurboPersistence {
         Ok(None)
     }
 
+    /// Creates a consistent read-only view of the database. The snapshot keeps the current set of
+    /// SST files alive, so commits and compactions will wait until the snapshot is dropped. It
+    /// should not be held long-term.
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot {
+            db: self,
+            inner: self.inner.read(),
+        }
+    }
+
     /// Returns database statistics.
     #[cfg(feature = "stats")]
     pub fn statistics(&self) -> Statistics {
@@ -862,6 +1109,223 @@ impl TurboPersistence {
     }
 }
 
+/// A consistent read-only view of the database at a certain sequence number.
+pub struct Snapshot<'l> {
+    db: &'l TurboPersistence,
+    inner: RwLockReadGuard<'l, Inner>,
+}
+
+impl Snapshot<'_> {
+    /// The sequence number of the database at the time of the snapshot.
+    pub fn sequence_number(&self) -> u32 {
+        self.inner.current_sequence_number
+    }
+
+    /// Iterates over all live entries of a key family. Entries are yielded in key hash order.
+    /// When a key is stored in multiple SST files, only the most recent value is yielded and
+    /// deleted keys are skipped.
+    pub fn iter_family(&self, family: usize) -> Result<FamilyIter<'_>> {
+        let mut iters = Vec::new();
+        for sst in self.inner.static_sorted_files.iter() {
+            if sst.range()?.family as usize != family {
+                continue;
+            }
+            iters.push(sst.iter(&self.db.key_block_cache, &self.db.value_block_cache)?);
+        }
+        Ok(FamilyIter {
+            db: self.db,
+            iter: MergeIter::new(iters.into_iter())?,
+            current: None,
+        })
+    }
+
+    /// Counts the live entries of a key family. This doesn't read blob files.
+    pub fn count_family(&self, family: usize) -> Result<usize> {
+        let mut iter = self.iter_family(family)?;
+        let mut count = 0;
+        while iter.next_entry()?.is_some() {
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Returns the size of all committed SST and blob files on disk. This only reads file
+    /// metadata, so it's cheap compared to [`Snapshot::family_statistics`]. Uncommitted files and
+    /// files that are not yet removed after a compaction are not included.
+    pub fn disk_size(&self) -> Result<u64> {
+        let mut size = self
+            .inner
+            .static_sorted_files
+            .iter()
+            .map(|sst| sst.file_size())
+            .sum();
+        for entry in fs::read_dir(&self.db.path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("blob") {
+                continue;
+            }
+            let sequence_number: u32 = path
+                .file_stem()
+                .context("File has no file stem")?
+                .to_str()
+                .context("File stem is not valid utf-8")?
+                .parse()?;
+            if sequence_number > self.inner.current_sequence_number {
+                // Uncommitted blob file
+                continue;
+            }
+            size += entry.metadata()?.len();
+        }
+        Ok(size)
+    }
+
+    /// Computes statistics about the on-disk structure of all key families. This reads all key
+    /// blocks of all SST files, so it's slow for large databases.
+    pub fn family_statistics(&self) -> Result<Vec<FamilyStatistics>> {
+        struct SstWithRange<'l>(&'l StaticSortedFileStatistics);
+
+        impl Compactable for SstWithRange<'_> {
+            fn range(&self) -> (u64, u64) {
+                (self.0.min_hash, self.0.max_hash)
+            }
+        }
+
+        let mut families: Vec<FamilyStatistics> = Vec::new();
+        for sst in self.inner.static_sorted_files.iter() {
+            let stats = sst.statistics().with_context(|| {
+                format!(
+                    "Unable to inspect sst file {:08}.sst",
+                    sst.sequence_number()
+                )
+            })?;
+            let family = stats.family;
+            match families.iter_mut().find(|f| f.family == family) {
+                Some(family) => family.sst_files.push(stats),
+                None => families.push(FamilyStatistics {
+                    family,
+                    sst_files: vec![stats],
+                    live_keys: 0,
+                    coverage: 0.0,
+                }),
+            }
+        }
+        families.sort_unstable_by_key(|f| f.family);
+        for family in families.iter_mut() {
+            let ssts_with_ranges = family
+                .sst_files
+                .iter()
+                .map(SstWithRange)
+                .collect::<Vec<_>>();
+            family.coverage = total_coverage(&ssts_with_ranges, (0, u64::MAX));
+            family.live_keys = self.count_family(family.family as usize)?;
+        }
+        Ok(families)
+    }
+
+    /// Computes statistics about all committed blob files. A blob file is referenced when one of
+    /// the SST files in `families` points to it.
+    pub fn blob_file_statistics(
+        &self,
+        families: &[FamilyStatistics],
+    ) -> Result<Vec<BlobFileStatistics>> {
+        let referenced_blobs = families
+            .iter()
+            .flat_map(|f| f.sst_files.iter())
+            .flat_map(|sst| sst.blob_references.iter().copied())
+            .collect::<HashSet<_>>();
+        let mut blob_files = Vec::new();
+        for entry in fs::read_dir(&self.db.path)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("blob") {
+                continue;
+            }
+            let sequence_number: u32 = path
+                .file_stem()
+                .context("File has no file stem")?
+                .to_str()
+                .context("File stem is not valid utf-8")?
+                .parse()?;
+            if sequence_number > self.inner.current_sequence_number {
+                // Uncommitted blob file
+                continue;
+            }
+            let mut file = File::open(&path)?;
+            let file_size = file.metadata()?.len();
+            let codec = file.read_u8()?;
+            let uncompressed_size = file.read_u32::<BE>()? as u64;
+            blob_files.push(BlobFileStatistics {
+                sequence_number,
+                codec,
+                file_size,
+                uncompressed_size,
+                referenced: referenced_blobs.contains(&sequence_number),
+            });
+        }
+        blob_files.sort_unstable_by_key(|b| b.sequence_number);
+        Ok(blob_files)
+    }
+}
+
+/// An iterator over all live entries of a key family. See [`Snapshot::iter_family`].
+pub struct FamilyIter<'l> {
+    db: &'l TurboPersistence,
+    iter: MergeIter<StaticSortedFileIter<'l>>,
+    /// The latest entry for the current key. It might be overridden by a following entry with the
+    /// same key from a newer SST file.
+    current: Option<LookupEntry>,
+}
+
+impl Iterator for FamilyIter<'_> {
+    type Item = Result<(ArcSlice<u8>, ArcSlice<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_internal().transpose()
+    }
+}
+
+impl FamilyIter<'_> {
+    /// Gets the next live entry and moves the cursor.
+    fn next_internal(&mut self) -> Result<Option<(ArcSlice<u8>, ArcSlice<u8>)>> {
+        let Some(LookupEntry { key, value, .. }) = self.next_entry()? else {
+            return Ok(None);
+        };
+        Ok(Some(match value {
+            LookupValue::Slice { value } => (key, value),
+            LookupValue::Blob { sequence_number } => (key, self.db.read_blob(sequence_number)?),
+            LookupValue::Deleted => unreachable!(),
+        }))
+    }
+
+    /// Gets the next entry that is not deleted without reading blob files and moves the cursor.
+    fn next_entry(&mut self) -> Result<Option<LookupEntry>> {
+        loop {
+            let Some(entry) = self.iter.next().transpose()? else {
+                return Ok(self
+                    .current
+                    .take()
+                    .filter(|entry| !matches!(entry.value, LookupValue::Deleted)));
+            };
+            // The MergeIter yields entries with the same key in SST file order, so the last one
+            // wins.
+            let Some(current) = self.current.replace(entry) else {
+                continue;
+            };
+            if self
+                .current
+                .as_ref()
+                .is_some_and(|entry| entry.key == current.key)
+            {
+                // Override value
+                continue;
+            }
+            if !matches!(current.value, LookupValue::Deleted) {
+                return Ok(Some(current));
+            }
+        }
+    }
+}
+
 /// Helper method to remove certain indicies from a list while keeping the order.
 /// This is similar to the `remove` method on Vec, but it allows to remove multiple indicies at
 /// once. It returns the removed elements in unspecified order.
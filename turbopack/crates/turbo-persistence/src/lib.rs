@@ -3,9 +3,12 @@
 #![feature(get_mut_unchecked)]
 
 mod arc_slice;
+mod checksum;
 mod collector;
 mod collector_entry;
+mod commit_log;
 mod compaction;
+mod compression;
 mod constants;
 mod db;
 mod key;
@@ -13,12 +16,19 @@ mod lookup_entry;
 mod merge_iter;
 mod static_sorted_file;
 mod static_sorted_file_builder;
+mod verify;
 mod write_batch;
 
 #[cfg(test)]
 mod tests;
 
 pub use arc_slice::ArcSlice;
-pub use db::TurboPersistence;
+pub use compaction::background::{BackgroundCompaction, BackgroundCompactionConfig};
+pub use compression::{codec_name, CompressionCodec};
+pub use db::{
+    BlobFileStatistics, DbConfig, FamilyIter, FamilyStatistics, Snapshot, TurboPersistence,
+};
 pub use key::{QueryKey, StoreKey};
+pub use static_sorted_file::{BlockStatistics, EntryStatistics, StaticSortedFileStatistics};
+pub use verify::{RepairReport, VerifyIssue, VerifyReport};
 pub use write_batch::WriteBatch;
@@ -9,17 +9,24 @@ use std::{
 
 use anyhow::{bail, Result};
 use byteorder::{ReadBytesExt, BE};
-use lzzzz::lz4::decompress_with_dict;
 use memmap2::Mmap;
 use quick_cache::sync::GuardResult;
 use rustc_hash::FxHasher;
 
 use crate::{
     arc_slice::ArcSlice,
+    checksum::checksum,
+    compression::{validate_codec, DecoderDictionary},
     lookup_entry::{LookupEntry, LookupValue},
     QueryKey,
 };
 
+/// The magic number and version of SST files.
+pub const SST_MAGIC: u32 = 0x53535403;
+
+/// The size of the block prefix that stores the uncompressed length and the checksum.
+const BLOCK_PREFIX_SIZE: usize = 8;
+
 /// The block header for an index block.
 pub const BLOCK_TYPE_INDEX: u8 = 0;
 /// The block header for a key block.
@@ -60,6 +67,78 @@ impl From<LookupValue> for LookupResult {
     }
 }
 
+/// Size statistics for a group of blocks in an SST file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockStatistics {
+    /// The number of blocks.
+    pub count: usize,
+    /// The size of the blocks on disk, excluding the uncompressed length and checksum prefix.
+    pub compressed_size: u64,
+    /// The size of the blocks after decompression.
+    pub uncompressed_size: u64,
+}
+
+impl BlockStatistics {
+    fn add(&mut self, compressed_size: usize, uncompressed_size: usize) {
+        self.count += 1;
+        self.compressed_size += compressed_size as u64;
+        self.uncompressed_size += uncompressed_size as u64;
+    }
+}
+
+/// The number of entries in an SST file by value type.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EntryStatistics {
+    /// Entries with a value stored in a shared value block.
+    pub small: usize,
+    /// Entries with a value stored in a dedicated value block.
+    pub medium: usize,
+    /// Entries with a value stored in a blob file.
+    pub blob: usize,
+    /// Tombstones.
+    pub deleted: usize,
+}
+
+impl EntryStatistics {
+    /// The total number of entries.
+    pub fn total(&self) -> usize {
+        self.small + self.medium + self.blob + self.deleted
+    }
+}
+
+/// Statistics about the on-disk structure of a single SST file.
+#[derive(Debug)]
+pub struct StaticSortedFileStatistics {
+    /// The sequence number of the file.
+    pub sequence_number: u32,
+    /// The key family stored in the file.
+    pub family: u32,
+    /// The compression codec of the blocks. See [`crate::codec_name`].
+    pub codec: u8,
+    /// The minimum hash value in the file.
+    pub min_hash: u64,
+    /// The maximum hash value in the file.
+    pub max_hash: u64,
+    /// The total size of the file.
+    pub file_size: u64,
+    /// The size of the serialized AQMF filter.
+    pub aqmf_size: usize,
+    /// The size of the key compression dictionary.
+    pub key_compression_dictionary_size: usize,
+    /// The size of the value compression dictionary.
+    pub value_compression_dictionary_size: usize,
+    /// Statistics for the index blocks.
+    pub index_blocks: BlockStatistics,
+    /// Statistics for the key blocks.
+    pub key_blocks: BlockStatistics,
+    /// Statistics for the value blocks.
+    pub value_blocks: BlockStatistics,
+    /// The number of entries by value type.
+    pub entries: EntryStatistics,
+    /// The sequence numbers of all blob files referenced by this file.
+    pub blob_references: Vec<u32>,
+}
+
 /// A byte range in the SST file.
 struct LocationInFile {
     start: usize,
@@ -68,6 +147,8 @@ struct LocationInFile {
 
 /// The read and parsed header of an SST file.
 struct Header {
+    /// The compression codec of the blocks.
+    codec: u8,
     /// The key family stored in this file.
     family: u32,
     /// The minimum hash value in this file.
@@ -130,6 +211,10 @@ pub struct StaticSortedFile {
     /// The AQMF filter of this file. This is only used if the range is very large. Smaller ranges
     /// use the AQMF cache instead.
     aqmf: OnceLock<qfilter::Filter>,
+    /// The prepared key compression dictionary of this file.
+    key_compression_dictionary: OnceLock<DecoderDictionary>,
+    /// The prepared value compression dictionary of this file.
+    value_compression_dictionary: OnceLock<DecoderDictionary>,
 }
 
 impl StaticSortedFile {
@@ -138,6 +223,11 @@ impl StaticSortedFile {
         self.sequence_number
     }
 
+    /// The size of this file on disk.
+    pub fn file_size(&self) -> u64 {
+        self.mmap.len() as u64
+    }
+
     /// Opens an SST file at the given path. This memory maps the file, but does not read it yet.
     /// It's lazy read on demand.
     pub fn open(sequence_number: u32, path: PathBuf) -> Result<Self> {
@@ -147,6 +237,8 @@ impl StaticSortedFile {
             mmap,
             header: OnceLock::new(),
             aqmf: OnceLock::new(),
+            key_compression_dictionary: OnceLock::new(),
+            value_compression_dictionary: OnceLock::new(),
         };
         Ok(file)
     }
@@ -156,9 +248,11 @@ impl StaticSortedFile {
         self.header.get_or_try_init(|| {
             let mut file = &*self.mmap;
             let magic = file.read_u32::<BE>()?;
-            if magic != 0x53535401 {
+            if magic != SST_MAGIC {
                 bail!("Invalid magic number or version");
             }
+            let codec = file.read_u8()?;
+            validate_codec(codec)?;
             let family = file.read_u32::<BE>()?;
             let min_hash = file.read_u64::<BE>()?;
             let max_hash = file.read_u64::<BE>()?;
@@ -166,7 +260,7 @@ impl StaticSortedFile {
             let key_compression_dictionary_length = file.read_u16::<BE>()? as usize;
             let value_compression_dictionary_length = file.read_u16::<BE>()? as usize;
             let block_count = file.read_u16::<BE>()?;
-            const HEADER_SIZE: usize = 33;
+            const HEADER_SIZE: usize = 34;
             let mut current_offset = HEADER_SIZE;
             let aqmf = LocationInFile {
                 start: current_offset,
@@ -187,6 +281,7 @@ impl StaticSortedFile {
             let blocks_start = block_offsets_start + block_count as usize * 4;
 
             Ok(Header {
+                codec,
                 family,
                 min_hash,
                 max_hash,
@@ -210,6 +305,164 @@ impl StaticSortedFile {
         })
     }
 
+    /// Computes statistics about the on-disk structure of this file. This reads and decompresses
+    /// all index and key blocks, but not the value blocks. It doesn't use the block caches.
+    pub fn statistics(&self) -> Result<StaticSortedFileStatistics> {
+        let header = self.header()?;
+        let mut stats = StaticSortedFileStatistics {
+            sequence_number: self.sequence_number,
+            family: header.family,
+            codec: header.codec,
+            min_hash: header.min_hash,
+            max_hash: header.max_hash,
+            file_size: self.mmap.len() as u64,
+            aqmf_size: header.aqmf.end - header.aqmf.start,
+            key_compression_dictionary_size: header.key_compression_dictionary.end
+                - header.key_compression_dictionary.start,
+            value_compression_dictionary_size: header.value_compression_dictionary.end
+                - header.value_compression_dictionary.start,
+            index_blocks: BlockStatistics::default(),
+            key_blocks: BlockStatistics::default(),
+            value_blocks: BlockStatistics::default(),
+            entries: EntryStatistics::default(),
+            blob_references: Vec::new(),
+        };
+
+        // Walk the index tree to find all index and key blocks. All remaining blocks are value
+        // blocks.
+        let mut is_key_or_index_block = vec![false; header.block_count as usize];
+        let mut queue = vec![header.block_count - 1];
+        while let Some(block_index) = queue.pop() {
+            if is_key_or_index_block[block_index as usize] {
+                bail!("Block {block_index} is referenced multiple times");
+            }
+            is_key_or_index_block[block_index as usize] = true;
+            let compressed_size = self.compressed_block_size(header, block_index)?;
+            let block = self.read_key_block(header, block_index)?;
+            let mut block = &block[..];
+            let uncompressed_size = block.len();
+            match block.read_u8()? {
+                BLOCK_TYPE_INDEX => {
+                    stats.index_blocks.add(compressed_size, uncompressed_size);
+                    for entry in block.chunks(10) {
+                        let child = (&entry[..2]).read_u16::<BE>()?;
+                        if child >= header.block_count {
+                            bail!("Index block {block_index} references invalid block {child}");
+                        }
+                        queue.push(child);
+                    }
+                }
+                BLOCK_TYPE_KEY => {
+                    stats.key_blocks.add(compressed_size, uncompressed_size);
+                    let entry_count = block.read_u24::<BE>()? as usize;
+                    if entry_count * 4 > block.len() {
+                        bail!("Key block {block_index} is too small for {entry_count} entries");
+                    }
+                    let offsets = &block[..entry_count * 4];
+                    let entries = &block[entry_count * 4..];
+                    for index in 0..entry_count {
+                        let GetKeyEntryResult { ty, mut val, .. } =
+                            get_key_entry(offsets, entries, entry_count, index)?;
+                        match ty {
+                            KEY_BLOCK_ENTRY_TYPE_SMALL => stats.entries.small += 1,
+                            KEY_BLOCK_ENTRY_TYPE_MEDIUM => stats.entries.medium += 1,
+                            KEY_BLOCK_ENTRY_TYPE_BLOB => {
+                                stats.entries.blob += 1;
+                                stats.blob_references.push(val.read_u32::<BE>()?);
+                            }
+                            KEY_BLOCK_ENTRY_TYPE_DELETED => stats.entries.deleted += 1,
+                            _ => bail!("Invalid key entry type {ty}"),
+                        }
+                    }
+                }
+                _ => {
+                    bail!("Invalid block type");
+                }
+            }
+        }
+        for block_index in 0..header.block_count {
+            if is_key_or_index_block[block_index as usize] {
+                continue;
+            }
+            let compressed_size = self.compressed_block_size(header, block_index)?;
+            let uncompressed_size = self.uncompressed_block_size(header, block_index)?;
+            stats.value_blocks.add(compressed_size, uncompressed_size);
+        }
+        stats.blob_references.sort_unstable();
+        stats.blob_references.dedup();
+        Ok(stats)
+    }
+
+    /// Checks the integrity of this file: the header, the location and checksum of every block and
+    /// the structure of the index tree. This reads the whole file. Returns the statistics of the
+    /// file, which include the referenced blob files.
+    pub fn verify(&self) -> Result<StaticSortedFileStatistics> {
+        let header = self.header()?;
+        if header.blocks_start > self.mmap.len() {
+            bail!(
+                "File is truncated: header expects at least {} bytes, but the file has only {} \
+                 bytes",
+                header.blocks_start,
+                self.mmap.len()
+            );
+        }
+        if header.block_count == 0 {
+            bail!("File has no blocks");
+        }
+        let mut blocks_end = header.blocks_start;
+        for block_index in 0..header.block_count {
+            let LocationInFile { start, end } = self.block_location(header, block_index)?;
+            let expected_checksum = (&self.mmap[start + 4..start + 8]).read_u32::<BE>()?;
+            if checksum(&self.mmap[start + BLOCK_PREFIX_SIZE..end]) != expected_checksum {
+                bail!("Checksum mismatch in block {block_index}");
+            }
+            blocks_end = end;
+        }
+        if blocks_end != self.mmap.len() {
+            bail!(
+                "File has {} unexpected bytes after the last block",
+                self.mmap.len() - blocks_end
+            );
+        }
+        pot::from_slice::<qfilter::Filter>(&self.mmap[header.aqmf.start..header.aqmf.end])?;
+        self.statistics()
+    }
+
+    /// Returns the byte range of a block in the file, including the uncompressed length and
+    /// checksum prefix.
+    fn block_location(&self, header: &Header, block_index: u16) -> Result<LocationInFile> {
+        let offset = header.block_offsets_start + block_index as usize * 4;
+        let start = if block_index == 0 {
+            header.blocks_start
+        } else {
+            header.blocks_start + (&self.mmap[offset - 4..offset]).read_u32::<BE>()? as usize
+        };
+        let end = header.blocks_start + (&self.mmap[offset..offset + 4]).read_u32::<BE>()? as usize;
+        if start + BLOCK_PREFIX_SIZE > end || end > self.mmap.len() {
+            bail!(
+                "Corrupted file seq:{} block:{} block {} - {} > file end {}",
+                self.sequence_number,
+                block_index,
+                start,
+                end,
+                self.mmap.len()
+            );
+        }
+        Ok(LocationInFile { start, end })
+    }
+
+    /// Returns the compressed size of a block.
+    fn compressed_block_size(&self, header: &Header, block_index: u16) -> Result<usize> {
+        let LocationInFile { start, end } = self.block_location(header, block_index)?;
+        Ok(end - start - BLOCK_PREFIX_SIZE)
+    }
+
+    /// Returns the uncompressed size of a block without decompressing it.
+    fn uncompressed_block_size(&self, header: &Header, block_index: u16) -> Result<usize> {
+        let LocationInFile { start, .. } = self.block_location(header, block_index)?;
+        Ok((&self.mmap[start..start + 4]).read_u32::<BE>()? as usize)
+    }
+
     /// Iterate over all entries in this file in sorted order.
     pub fn iter<'l>(
         &'l self,
@@ -450,22 +703,26 @@ impl StaticSortedFile {
 
     /// Reads a key block from the file.
     fn read_key_block(&self, header: &Header, block_index: u16) -> Result<ArcSlice<u8>> {
-        self.read_block(
-            header,
-            block_index,
-            &self.mmap
-                [header.key_compression_dictionary.start..header.key_compression_dictionary.end],
-        )
+        let dictionary = self.key_compression_dictionary.get_or_init(|| {
+            DecoderDictionary::new(
+                header.codec,
+                &self.mmap[header.key_compression_dictionary.start
+                    ..header.key_compression_dictionary.end],
+            )
+        });
+        self.read_block(header, block_index, dictionary)
     }
 
     /// Reads a value block from the file.
     fn read_value_block(&self, header: &Header, block_index: u16) -> Result<ArcSlice<u8>> {
-        self.read_block(
-            header,
-            block_index,
-            &self.mmap[header.value_compression_dictionary.start
-                ..header.value_compression_dictionary.end],
-        )
+        let dictionary = self.value_compression_dictionary.get_or_init(|| {
+            DecoderDictionary::new(
+                header.codec,
+                &self.mmap[header.value_compression_dictionary.start
+                    ..header.value_compression_dictionary.end],
+            )
+        });
+        self.read_block(header, block_index, dictionary)
     }
 
     /// Reads a block from the file.
@@ -473,7 +730,7 @@ impl StaticSortedFile {
         &self,
         header: &Header,
         block_index: u16,
-        compression_dictionary: &[u8],
+        compression_dictionary: &DecoderDictionary,
     ) -> Result<ArcSlice<u8>> {
         #[cfg(feature = "strict_checks")]
         if block_index >= header.block_count {
@@ -524,14 +781,26 @@ impl StaticSortedFile {
         }
         let uncompressed_length =
             (&self.mmap[block_start..block_start + 4]).read_u32::<BE>()? as usize;
-        let block = self.mmap[block_start + 4..block_end].to_vec();
+        let block = self.mmap[block_start + BLOCK_PREFIX_SIZE..block_end].to_vec();
+        #[cfg(feature = "strict_checks")]
+        {
+            let expected_checksum =
+                (&self.mmap[block_start + 4..block_start + 8]).read_u32::<BE>()?;
+            if checksum(&block) != expected_checksum {
+                bail!(
+                    "Corrupted file seq:{} block:{} checksum mismatch",
+                    self.sequence_number,
+                    block_index
+                );
+            }
+        }
 
         let buffer = Arc::new_zeroed_slice(uncompressed_length);
         // Safety: MaybeUninit<u8> can be safely transmuted to u8.
         let mut buffer = unsafe { transmute::<Arc<[MaybeUninit<u8>]>, Arc<[u8]>>(buffer) };
         // Safety: We know that the buffer is not shared yet.
         let decompressed = unsafe { Arc::get_mut_unchecked(&mut buffer) };
-        decompress_with_dict(&block, decompressed, compression_dictionary)?;
+        compression_dictionary.decompress(&block, decompressed)?;
         Ok(ArcSlice::from(buffer))
     }
 }
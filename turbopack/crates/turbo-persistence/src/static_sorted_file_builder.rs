@@ -7,11 +7,14 @@ use std::{
 
 use anyhow::{Context, Result};
 use byteorder::{ByteOrder, WriteBytesExt, BE};
-use lzzzz::lz4::{max_compressed_size, ACC_LEVEL_DEFAULT};
 
-use crate::static_sorted_file::{
-    BLOCK_TYPE_INDEX, BLOCK_TYPE_KEY, KEY_BLOCK_ENTRY_TYPE_BLOB, KEY_BLOCK_ENTRY_TYPE_DELETED,
-    KEY_BLOCK_ENTRY_TYPE_MEDIUM, KEY_BLOCK_ENTRY_TYPE_SMALL,
+use crate::{
+    checksum::checksum,
+    compression::{CompressionCodec, EncoderDictionary},
+    static_sorted_file::{
+        BLOCK_TYPE_INDEX, BLOCK_TYPE_KEY, KEY_BLOCK_ENTRY_TYPE_BLOB, KEY_BLOCK_ENTRY_TYPE_DELETED,
+        KEY_BLOCK_ENTRY_TYPE_MEDIUM, KEY_BLOCK_ENTRY_TYPE_SMALL, SST_MAGIC,
+    },
 };
 
 /// The maximum number of entries that should go into a single key block
@@ -74,9 +77,10 @@ pub enum EntryValue<'l> {
 #[derive(Debug, Default)]
 pub struct StaticSortedFileBuilder {
     family: u32,
+    codec: CompressionCodec,
     aqmf: Vec<u8>,
-    key_compression_dictionary: Vec<u8>,
-    value_compression_dictionary: Vec<u8>,
+    key_compression_dictionary: EncoderDictionary,
+    value_compression_dictionary: EncoderDictionary,
     blocks: Vec<(u32, Vec<u8>)>,
     min_hash: u64,
     max_hash: u64,
@@ -85,6 +89,7 @@ pub struct StaticSortedFileBuilder {
 impl StaticSortedFileBuilder {
     pub fn new<E: Entry>(
         family: u32,
+        codec: CompressionCodec,
         entries: &[E],
         total_key_size: usize,
         total_value_size: usize,
@@ -92,13 +97,14 @@ impl StaticSortedFileBuilder {
         debug_assert!(entries.iter().map(|e| e.key_hash()).is_sorted());
         let mut builder = Self {
             family,
+            codec,
             min_hash: entries.first().map(|e| e.key_hash()).unwrap_or(u64::MAX),
             max_hash: entries.last().map(|e| e.key_hash()).unwrap_or(0),
             ..Default::default()
         };
         builder.compute_aqmf(entries);
         builder.compute_compression_dictionary(entries, total_key_size, total_value_size)?;
-        builder.compute_blocks(entries);
+        builder.compute_blocks(entries)?;
         Ok(builder)
     }
 
@@ -182,27 +188,31 @@ impl StaticSortedFileBuilder {
         assert!(key_samples.len() == key_sample_sizes.iter().sum::<usize>());
         assert!(value_samples.len() == value_sample_sizes.iter().sum::<usize>());
         if key_samples.len() > MIN_KEY_COMPRESSION_SAMPLES_SIZE && key_sample_sizes.len() > 5 {
-            self.key_compression_dictionary = zstd::dict::from_continuous(
-                &key_samples,
-                &key_sample_sizes,
-                KEY_COMPRESSION_DICTIONARY_SIZE,
-            )
-            .context("Key dictionary creation failed")?;
+            self.key_compression_dictionary = self.codec.encoder_dictionary(
+                zstd::dict::from_continuous(
+                    &key_samples,
+                    &key_sample_sizes,
+                    KEY_COMPRESSION_DICTIONARY_SIZE,
+                )
+                .context("Key dictionary creation failed")?,
+            );
         }
         if value_samples.len() > MIN_VALUE_COMPRESSION_SAMPLES_SIZE && value_sample_sizes.len() > 5
         {
-            self.value_compression_dictionary = zstd::dict::from_continuous(
-                &value_samples,
-                &value_sample_sizes,
-                VALUE_COMPRESSION_DICTIONARY_SIZE,
-            )
-            .context("Value dictionary creation failed")?;
+            self.value_compression_dictionary = self.codec.encoder_dictionary(
+                zstd::dict::from_continuous(
+                    &value_samples,
+                    &value_sample_sizes,
+                    VALUE_COMPRESSION_DICTIONARY_SIZE,
+                )
+                .context("Value dictionary creation failed")?,
+            );
         }
         Ok(())
     }
 
     /// Compute index, key and value blocks.
-    fn compute_blocks<E: Entry>(&mut self, entries: &[E]) {
+    fn compute_blocks<E: Entry>(&mut self, entries: &[E]) -> Result<()> {
         // TODO implement multi level index
         // TODO place key and value block near to each other
 
@@ -232,7 +242,7 @@ impl StaticSortedFileBuilder {
                                 value_locations[j].0 = block_index;
                             }
                         }
-                        self.blocks.push(self.compress_value_block(&block));
+                        self.blocks.push(self.compress_value_block(&block)?);
                         current_block_start = i;
                         current_block_size = 0;
                         current_block_count = 0;
@@ -243,7 +253,7 @@ impl StaticSortedFileBuilder {
                 }
                 EntryValue::Medium { value } => {
                     value_locations.push((self.blocks.len(), value.len()));
-                    self.blocks.push(self.compress_value_block(value));
+                    self.blocks.push(self.compress_value_block(value)?);
                 }
                 _ => {
                     value_locations.push((0, 0));
@@ -259,7 +269,7 @@ impl StaticSortedFileBuilder {
                     value_locations[j].0 = block_index;
                 }
             }
-            self.blocks.push(self.compress_value_block(&block));
+            self.blocks.push(self.compress_value_block(&block)?);
         }
 
         let mut key_block_boundaries = Vec::new();
@@ -308,7 +318,7 @@ impl StaticSortedFileBuilder {
                 }
                 key_block_boundaries
                     .push((entries[current_block_start].key_hash(), self.blocks.len()));
-                self.blocks.push(self.compress_key_block(&block.finish()));
+                self.blocks.push(self.compress_key_block(&block.finish())?);
                 current_block_size = 0;
                 current_block_start = i;
             }
@@ -322,7 +332,7 @@ impl StaticSortedFileBuilder {
                 add_entry_to_block(entry, value_location, &mut block);
             }
             key_block_boundaries.push((entries[current_block_start].key_hash(), self.blocks.len()));
-            self.blocks.push(self.compress_key_block(&block.finish()));
+            self.blocks.push(self.compress_key_block(&block.finish())?);
         }
 
         // Compute the index
@@ -334,30 +344,26 @@ impl StaticSortedFileBuilder {
             index_block.put(*hash, *block as u16);
         }
         self.blocks
-            .push(self.compress_key_block(&index_block.finish()));
+            .push(self.compress_key_block(&index_block.finish())?);
+        Ok(())
     }
 
     /// Compresses a block with a compression dictionary.
-    fn compress_block(&self, block: &[u8], dict: &[u8]) -> (u32, Vec<u8>) {
-        let mut compressor =
-            lzzzz::lz4::Compressor::with_dict(dict).expect("LZ4 compressor creation failed");
-        let mut compressed = Vec::with_capacity(max_compressed_size(block.len()));
-        compressor
-            .next_to_vec(block, &mut compressed, ACC_LEVEL_DEFAULT)
-            .expect("Compression failed");
-        if compressed.capacity() > compressed.len() * 2 {
-            compressed.shrink_to_fit();
-        }
-        (block.len().try_into().unwrap(), compressed)
+    fn compress_block(&self, block: &[u8], dict: &EncoderDictionary) -> Result<(u32, Vec<u8>)> {
+        let compressed = self
+            .codec
+            .compress_with_dict(block, dict)
+            .context("Compression failed")?;
+        Ok((block.len().try_into().unwrap(), compressed))
     }
 
     /// Compresses an index or key block.
-    fn compress_key_block(&self, block: &[u8]) -> (u32, Vec<u8>) {
+    fn compress_key_block(&self, block: &[u8]) -> Result<(u32, Vec<u8>)> {
         self.compress_block(block, &self.key_compression_dictionary)
     }
 
     /// Compresses a value block.
-    fn compress_value_block(&self, block: &[u8]) -> (u32, Vec<u8>) {
+    fn compress_value_block(&self, block: &[u8]) -> Result<(u32, Vec<u8>)> {
         self.compress_block(block, &self.value_compression_dictionary)
     }
 
@@ -365,7 +371,9 @@ impl StaticSortedFileBuilder {
     pub fn write(&self, file: &Path) -> io::Result<File> {
         let mut file = BufWriter::new(File::create(file)?);
         // magic number and version
-        file.write_u32::<BE>(0x53535401)?;
+        file.write_u32::<BE>(SST_MAGIC)?;
+        // compression codec
+        file.write_u8(self.codec.id())?;
         // family
         file.write_u32::<BE>(self.family)?;
         // min hash
@@ -375,30 +383,44 @@ impl StaticSortedFileBuilder {
         // AQMF length
         file.write_u24::<BE>(self.aqmf.len().try_into().unwrap())?;
         // Key compression dictionary length
-        file.write_u16::<BE>(self.key_compression_dictionary.len().try_into().unwrap())?;
+        file.write_u16::<BE>(
+            self.key_compression_dictionary
+                .raw()
+                .len()
+                .try_into()
+                .unwrap(),
+        )?;
         // Value compression dictionary length
-        file.write_u16::<BE>(self.value_compression_dictionary.len().try_into().unwrap())?;
+        file.write_u16::<BE>(
+            self.value_compression_dictionary
+                .raw()
+                .len()
+                .try_into()
+                .unwrap(),
+        )?;
         // Number of blocks
         file.write_u16::<BE>(self.blocks.len().try_into().unwrap())?;
 
         // Write the AQMF
         file.write_all(&self.aqmf)?;
         // Write the key compression dictionary
-        file.write_all(&self.key_compression_dictionary)?;
+        file.write_all(self.key_compression_dictionary.raw())?;
         // Write the value compression dictionary
-        file.write_all(&self.value_compression_dictionary)?;
+        file.write_all(self.value_compression_dictionary.raw())?;
 
         // Write the blocks
         let mut offset = 0;
         for (_, block) in &self.blocks {
-            // Block length (including the uncompressed length field)
-            let len = block.len() + 4;
+            // Block length (including the uncompressed length and checksum fields)
+            let len = block.len() + 8;
             offset += len;
             file.write_u32::<BE>(offset.try_into().unwrap())?;
         }
         for (uncompressed_size, block) in &self.blocks {
             // Uncompressed size
             file.write_u32::<BE>(*uncompressed_size)?;
+            // Checksum of the compressed block
+            file.write_u32::<BE>(checksum(block))?;
             // Compressed block
             file.write_all(block)?;
         }
@@ -1,9 +1,16 @@
-use std::time::Instant;
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
-use crate::{db::TurboPersistence, write_batch::WriteBatch};
+use crate::{
+    codec_name, db::TurboPersistence, write_batch::WriteBatch, BackgroundCompactionConfig,
+    CompressionCodec, DbConfig,
+};
 
 #[test]
 fn full_cycle() -> Result<()> {
@@ -448,3 +455,484 @@ fn persist_changes() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn iter_family() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    fn check(db: &TurboPersistence, expected: &BTreeMap<u32, Vec<u8>>) -> Result<()> {
+        let snapshot = db.snapshot();
+        let mut actual = BTreeMap::new();
+        for entry in snapshot.iter_family(0)? {
+            let (key, value) = entry?;
+            let key = u32::from_be_bytes((*key).try_into()?);
+            assert!(
+                actual.insert(key, value.to_vec()).is_none(),
+                "Key {key} yielded twice"
+            );
+        }
+        assert_eq!(&actual, expected);
+        assert_eq!(snapshot.iter_family(1)?.count(), 10);
+        Ok(())
+    }
+
+    let mut expected = BTreeMap::new();
+    {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        let b = db.write_batch::<_, 2>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), vec![1; (i % 10) as usize].into())?;
+            expected.insert(i, vec![1; (i % 10) as usize]);
+        }
+        for i in 0..10u32 {
+            b.put(1, i.to_be_bytes(), vec![2].into())?;
+        }
+        db.commit_write_batch(b)?;
+
+        let b = db.write_batch::<_, 2>()?;
+        for i in (0..1000u32).step_by(3) {
+            b.put(0, i.to_be_bytes(), vec![3].into())?;
+            expected.insert(i, vec![3]);
+        }
+        for i in (0..1000u32).step_by(7) {
+            b.delete(0, i.to_be_bytes())?;
+            expected.remove(&i);
+        }
+        b.put(0, 5000u32.to_be_bytes(), vec![4; 70 * 1024 * 1024].into())?;
+        expected.insert(5000, vec![4; 70 * 1024 * 1024]);
+        db.commit_write_batch(b)?;
+
+        check(&db, &expected)?;
+
+        db.full_compact()?;
+
+        check(&db, &expected)?;
+        db.shutdown()?;
+    }
+    {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        check(&db, &expected)?;
+        db.shutdown()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn inspect_read_only() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        let b = db.write_batch::<_, 2>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), vec![1; 100].into())?;
+        }
+        b.put(1, 0u32.to_be_bytes(), vec![2; 70 * 1024 * 1024].into())?;
+        db.commit_write_batch(b)?;
+
+        let b = db.write_batch::<_, 2>()?;
+        for i in 0..100u32 {
+            b.delete(0, i.to_be_bytes())?;
+        }
+        db.commit_write_batch(b)?;
+        db.shutdown()?;
+    }
+
+    let files_before = std::fs::read_dir(path)?.count();
+    let db = TurboPersistence::open_read_only(path.to_path_buf())?;
+    assert!(db.write_batch::<Vec<u8>, 2>().is_err());
+    assert!(db.full_compact().is_err());
+
+    let snapshot = db.snapshot();
+    let families = snapshot.family_statistics()?;
+    let blob_files = snapshot.blob_file_statistics(&families)?;
+    assert_eq!(families.len(), 2);
+    let family = &families[0];
+    assert_eq!(family.family, 0);
+    assert_eq!(family.sst_files.len(), 2);
+    assert_eq!(family.live_keys, 900);
+    let entries = family
+        .sst_files
+        .iter()
+        .map(|sst| sst.entries)
+        .fold((0, 0), |(total, deleted), e| {
+            (total + e.total(), deleted + e.deleted)
+        });
+    assert_eq!(entries, (1100, 100));
+    assert!(family.coverage > 0.0);
+    for sst in family.sst_files.iter() {
+        assert!(sst.key_blocks.count > 0);
+        assert!(sst.index_blocks.count > 0);
+        assert!(sst.aqmf_size > 0);
+    }
+
+    let family = &families[1];
+    assert_eq!(family.live_keys, 1);
+    assert_eq!(family.sst_files[0].blob_references.len(), 1);
+    assert_eq!(blob_files.len(), 1);
+    assert!(blob_files[0].referenced);
+    assert_eq!(blob_files[0].uncompressed_size, 70 * 1024 * 1024);
+
+    let sst_size: u64 = families
+        .iter()
+        .flat_map(|f| f.sst_files.iter())
+        .map(|sst| sst.file_size)
+        .sum();
+    assert_eq!(snapshot.disk_size()?, sst_size + blob_files[0].file_size);
+
+    drop(snapshot);
+    drop(db);
+    assert_eq!(std::fs::read_dir(path)?.count(), files_before);
+
+    assert!(TurboPersistence::open_read_only(path.join("missing")).is_err());
+    Ok(())
+}
+
+#[test]
+fn zstd_compression() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    fn check(db: &TurboPersistence) -> Result<()> {
+        for i in 0..100 * 1024u32 {
+            let Some(value) = db.get(0, &i.to_be_bytes())? else {
+                panic!("Value not found");
+            };
+            assert_eq!(&*value, format!("value {}", i % 1000).as_bytes());
+        }
+        for i in 0..10u8 {
+            let Some(value) = db.get(1, &[i; 4])? else {
+                panic!("Value not found");
+            };
+            assert_eq!(&*value, &vec![i; 100 * 1024]);
+        }
+        let Some(value) = db.get(1, &[255u8; 4])? else {
+            panic!("Value not found");
+        };
+        assert_eq!(&*value, &vec![255; 70 * 1024 * 1024]);
+        Ok(())
+    }
+
+    let zstd = DbConfig {
+        compression: CompressionCodec::Zstd { level: 3 },
+    };
+    {
+        let db = TurboPersistence::open_with_config(path.to_path_buf(), zstd.clone())?;
+        let b = db.write_batch::<_, 2>()?;
+        for i in 0..100 * 1024u32 {
+            b.put(
+                0,
+                i.to_be_bytes(),
+                format!("value {}", i % 1000).into_bytes().into(),
+            )?;
+        }
+        for i in 0..10u8 {
+            b.put(1, [i; 4], vec![i; 100 * 1024].into())?;
+        }
+        b.put(1, [255u8; 4], vec![255; 70 * 1024 * 1024].into())?;
+        db.commit_write_batch(b)?;
+        check(&db)?;
+
+        let snapshot = db.snapshot();
+        let families = snapshot.family_statistics()?;
+        for family in families.iter() {
+            for sst in family.sst_files.iter() {
+                assert_eq!(codec_name(sst.codec), "zstd");
+            }
+        }
+        let blob_files = snapshot.blob_file_statistics(&families)?;
+        assert_eq!(codec_name(blob_files[0].codec), "zstd");
+        db.shutdown()?;
+    }
+    {
+        // The codec is read from the files, so a different configuration can read them
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        check(&db)?;
+
+        // Files with different codecs can be mixed
+        let b = db.write_batch::<_, 2>()?;
+        for i in (0..100 * 1024u32).step_by(2) {
+            b.put(
+                0,
+                i.to_be_bytes(),
+                format!("value {}", i % 1000).into_bytes().into(),
+            )?;
+        }
+        b.put(1, [0u8; 4], vec![0; 100 * 1024].into())?;
+        db.commit_write_batch(b)?;
+        check(&db)?;
+
+        // Compaction rewrites the files with the configured codec
+        db.full_compact()?;
+        check(&db)?;
+        let snapshot = db.snapshot();
+        let families = snapshot.family_statistics()?;
+        for family in families.iter() {
+            for sst in family.sst_files.iter() {
+                assert_eq!(codec_name(sst.codec), "lz4");
+            }
+        }
+        db.shutdown()?;
+    }
+    Ok(())
+}
+
+#[test]
+fn verify_and_repair() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    fn sst_files(path: &std::path::Path) -> Result<Vec<u32>> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?.path();
+            if entry.extension().and_then(|s| s.to_str()) == Some("sst") {
+                files.push(entry.file_stem().unwrap().to_str().unwrap().parse()?);
+            }
+        }
+        files.sort_unstable();
+        Ok(files)
+    }
+
+    fn write(db: &TurboPersistence, value: u8) -> Result<u32> {
+        let b = db.write_batch::<_, 1>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), vec![value; 100].into())?;
+        }
+        db.commit_write_batch(b)?;
+        Ok(db.snapshot().sequence_number())
+    }
+
+    fn check(path: &std::path::Path, value: Option<u8>) -> Result<()> {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        for i in 0..1000u32 {
+            let result = db.get(0, &i.to_be_bytes())?;
+            assert_eq!(result.as_deref(), value.map(|v| vec![v; 100]).as_deref());
+        }
+        db.shutdown()
+    }
+
+    let (first_commit, second_commit) = {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        let first_commit = write(&db, 1)?;
+        let b = db.write_batch::<_, 2>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), vec![2; 100].into())?;
+        }
+        b.put(1, 0u32.to_be_bytes(), vec![2; 70 * 1024 * 1024].into())?;
+        db.commit_write_batch(b)?;
+        let second_commit = db.snapshot().sequence_number();
+        db.shutdown()?;
+        (first_commit, second_commit)
+    };
+
+    let report = TurboPersistence::verify(path)?;
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.sequence_number, Some(second_commit));
+    assert_eq!(report.sst_files, 3);
+    assert_eq!(report.blob_files, 1);
+
+    // Files from an unfinished commit are not an issue
+    std::fs::write(
+        path.join(format!("{:08}.sst", second_commit + 10)),
+        b"garbage",
+    )?;
+    let report = TurboPersistence::verify(path)?;
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert_eq!(report.uncommitted_files, 1);
+
+    // A truncated file of the second commit rolls back to the first commit
+    let last_sst = *sst_files(path)?.iter().rev().nth(1).unwrap();
+    assert!(last_sst > first_commit);
+    let sst_path = path.join(format!("{last_sst:08}.sst"));
+    let content = std::fs::read(&sst_path)?;
+    std::fs::write(&sst_path, &content[..content.len() / 2])?;
+    let report = TurboPersistence::verify(path)?;
+    assert!(matches!(
+        report.issues[..],
+        [crate::VerifyIssue::CorruptedSstFile { sequence_number, .. }]
+            if sequence_number == last_sst
+    ));
+    let report = TurboPersistence::repair(path)?;
+    assert_eq!(report.previous_sequence_number, Some(second_commit));
+    assert_eq!(report.sequence_number, first_commit);
+    assert_eq!(report.removed_files.len(), 4);
+    assert!(TurboPersistence::verify(path)?.is_ok());
+    check(path, Some(1))?;
+
+    // A corrupted block after a compaction rolls back to the compaction
+    let compaction_commit = {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        write(&db, 3)?;
+        db.full_compact()?;
+        let compaction_commit = db.snapshot().sequence_number();
+        write(&db, 4)?;
+        db.shutdown()?;
+        compaction_commit
+    };
+    let last_sst = *sst_files(path)?.last().unwrap();
+    let sst_path = path.join(format!("{last_sst:08}.sst"));
+    let mut content = std::fs::read(&sst_path)?;
+    let len = content.len();
+    content[len - 1] ^= 0xff;
+    std::fs::write(&sst_path, &content)?;
+    assert!(!TurboPersistence::verify(path)?.is_ok());
+    let report = TurboPersistence::repair(path)?;
+    assert_eq!(report.sequence_number, compaction_commit);
+    check(path, Some(3))?;
+
+    // Files written by a compaction can't be rolled back
+    let first_sst = *sst_files(path)?.first().unwrap();
+    std::fs::write(path.join(format!("{first_sst:08}.sst")), b"")?;
+    let report = TurboPersistence::repair(path)?;
+    assert_eq!(report.sequence_number, 0);
+    assert!(TurboPersistence::verify(path)?.is_ok());
+    check(path, None)?;
+
+    // Without a CURRENT file the last logged commit is used
+    write(&TurboPersistence::open(path.to_path_buf())?, 5)?;
+    std::fs::remove_file(path.join("CURRENT"))?;
+    assert!(!TurboPersistence::verify(path)?.is_ok());
+    let report = TurboPersistence::repair(path)?;
+    assert_eq!(report.previous_sequence_number, None);
+    assert!(report.removed_files.is_empty());
+    check(path, Some(5))?;
+
+    // Unexpected files are reported, but neither treated as corruption nor removed
+    std::fs::create_dir(path.join("unrelated"))?;
+    std::fs::write(path.join("unrelated").join("file"), b"keep")?;
+    let report = TurboPersistence::verify(path)?;
+    assert!(!report.is_ok());
+    assert!(!report.is_corrupted());
+    let report = TurboPersistence::repair(path)?;
+    assert!(report.removed_files.is_empty());
+    assert_eq!(report.unexpected_files, vec![path.join("unrelated")]);
+    assert_eq!(std::fs::read(path.join("unrelated").join("file"))?, b"keep");
+
+    Ok(())
+}
+
+#[test]
+fn repair_after_compaction() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    fn write(db: &TurboPersistence, value: u8) -> Result<u32> {
+        let b = db.write_batch::<_, 1>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), vec![value; 100].into())?;
+        }
+        db.commit_write_batch(b)?;
+        Ok(db.snapshot().sequence_number())
+    }
+
+    let second_commit = {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        write(&db, 1)?;
+        let second_commit = write(&db, 2)?;
+        db.full_compact()?;
+        db.shutdown()?;
+        second_commit
+    };
+
+    // The files deleted by the compaction are kept until the next commit, so a truncated file
+    // written by the compaction rolls back to the commit before the compaction
+    let last_sst = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("sst"))
+        .max()
+        .unwrap();
+    let content = std::fs::read(&last_sst)?;
+    std::fs::write(&last_sst, &content[..content.len() / 2])?;
+    assert!(TurboPersistence::verify(path)?.is_corrupted());
+    let report = TurboPersistence::repair(path)?;
+    assert_eq!(report.sequence_number, second_commit);
+    assert!(TurboPersistence::verify(path)?.is_ok());
+    {
+        let db = TurboPersistence::open(path.to_path_buf())?;
+        for i in 0..1000u32 {
+            let result = db.get(0, &i.to_be_bytes())?;
+            assert_eq!(result.as_deref(), Some(&[2; 100][..]));
+        }
+        db.shutdown()?;
+    }
+
+    // Databases written before the LOG file was introduced are not corrupted
+    std::fs::remove_file(path.join("LOG"))?;
+    let report = TurboPersistence::verify(path)?;
+    assert!(report.is_ok(), "{:?}", report.issues);
+    assert!(!report.has_commit_log);
+
+    Ok(())
+}
+
+#[test]
+fn background_compaction() -> Result<()> {
+    let tempdir = tempfile::tempdir()?;
+    let path = tempdir.path();
+
+    fn write(db: &TurboPersistence, round: u32) -> Result<()> {
+        let b = db.write_batch::<_, 1>()?;
+        for i in 0..1000u32 {
+            b.put(0, i.to_be_bytes(), round.to_be_bytes().repeat(256).into())?;
+        }
+        db.commit_write_batch(b)?;
+        Ok(())
+    }
+
+    fn check(db: &TurboPersistence, round: u32) -> Result<()> {
+        for i in 0..1000u32 {
+            let Some(value) = db.get(0, &i.to_be_bytes())? else {
+                panic!("Value not found");
+            };
+            assert_eq!(&*value, &round.to_be_bytes().repeat(256));
+        }
+        Ok(())
+    }
+
+    let db = Arc::new(TurboPersistence::open(path.to_path_buf())?);
+    for round in 0..20 {
+        write(&db, round)?;
+    }
+    assert_eq!(db.snapshot().family_statistics()?[0].sst_files.len(), 20);
+
+    // A compaction that exceeds its I/O budget is cancelled by write batches
+    let compaction = db.start_background_compaction(BackgroundCompactionConfig {
+        max_coverage: 2.0,
+        max_merge_sequence: 20,
+        interval: Duration::from_millis(1),
+        io_budget: Some(1),
+        threads: 1,
+    })?;
+    compaction.wait_for_compaction()?;
+    write(&db, 20)?;
+    compaction.stop()?;
+    check(&db, 20)?;
+    assert_eq!(db.snapshot().family_statistics()?[0].sst_files.len(), 21);
+    assert_eq!(TurboPersistence::verify(path)?.uncommitted_files, 0);
+
+    // Compaction runs incrementally while the database is read
+    let compaction = db.start_background_compaction(BackgroundCompactionConfig {
+        max_coverage: 2.0,
+        max_merge_sequence: 4,
+        interval: Duration::from_millis(1),
+        io_budget: None,
+        threads: 2,
+    })?;
+    while db.snapshot().family_statistics()?[0].coverage > 2.0 {
+        compaction.wait_for_compaction()?;
+        check(&db, 20)?;
+    }
+    compaction.stop()?;
+    check(&db, 20)?;
+    db.shutdown()?;
+    drop(db);
+
+    let db = TurboPersistence::open(path.to_path_buf())?;
+    check(&db, 20)?;
+    Ok(())
+}
@@ -0,0 +1,499 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE};
+
+use crate::{
+    checksum::checksum,
+    commit_log::{
+        append_commit_log, has_commit_log, is_commit_log, read_commit_log, truncate_commit_log,
+        CommitLogEntry,
+    },
+    compression::decompress,
+    constants::BLOB_HEADER_SIZE,
+    static_sorted_file::StaticSortedFile,
+    TurboPersistence,
+};
+
+/// A problem found by [`TurboPersistence::verify`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyIssue {
+    /// The CURRENT file is missing or invalid.
+    InvalidCurrentFile { error: String },
+    /// The LOG file exists, but doesn't contain the committed sequence number.
+    InvalidCommitLog { error: String },
+    /// A file that doesn't belong to the database.
+    UnexpectedFile { path: PathBuf },
+    /// A committed SST file is truncated or corrupted.
+    CorruptedSstFile { sequence_number: u32, error: String },
+    /// A committed blob file is truncated or corrupted.
+    CorruptedBlobFile { sequence_number: u32, error: String },
+    /// A committed del file is truncated.
+    CorruptedDelFile { sequence_number: u32, error: String },
+    /// An SST file references a blob file that doesn't exist.
+    MissingBlobFile {
+        sequence_number: u32,
+        referenced_by: u32,
+    },
+}
+
+impl VerifyIssue {
+    /// Returns true if the issue means that committed data is damaged. Unexpected files are not
+    /// data corruption, they might be unrelated files that were put into the directory.
+    pub fn is_corruption(&self) -> bool {
+        !matches!(self, VerifyIssue::UnexpectedFile { .. })
+    }
+
+    /// The lowest sequence number of the files that are affected by this issue.
+    fn sequence_number(&self) -> Option<u32> {
+        match *self {
+            VerifyIssue::InvalidCurrentFile { .. }
+            | VerifyIssue::InvalidCommitLog { .. }
+            | VerifyIssue::UnexpectedFile { .. } => None,
+            VerifyIssue::CorruptedSstFile {
+                sequence_number, ..
+            }
+            | VerifyIssue::CorruptedBlobFile {
+                sequence_number, ..
+            }
+            | VerifyIssue::CorruptedDelFile {
+                sequence_number, ..
+            } => Some(sequence_number),
+            VerifyIssue::MissingBlobFile {
+                sequence_number,
+                referenced_by,
+            } => Some(sequence_number.min(referenced_by)),
+        }
+    }
+}
+
+impl fmt::Display for VerifyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyIssue::InvalidCurrentFile { error } => write!(f, "Invalid CURRENT file: {error}"),
+            VerifyIssue::InvalidCommitLog { error } => write!(f, "Invalid LOG file: {error}"),
+            VerifyIssue::UnexpectedFile { path } => {
+                write!(f, "Unexpected file {}", path.display())
+            }
+            VerifyIssue::CorruptedSstFile {
+                sequence_number,
+                error,
+            } => write!(f, "Corrupted SST file {sequence_number:08}.sst: {error}"),
+            VerifyIssue::CorruptedBlobFile {
+                sequence_number,
+                error,
+            } => write!(f, "Corrupted blob file {sequence_number:08}.blob: {error}"),
+            VerifyIssue::CorruptedDelFile {
+                sequence_number,
+                error,
+            } => write!(f, "Corrupted del file {sequence_number:08}.del: {error}"),
+            VerifyIssue::MissingBlobFile {
+                sequence_number,
+                referenced_by,
+            } => write!(
+                f,
+                "Missing blob file {sequence_number:08}.blob (referenced by \
+                 {referenced_by:08}.sst)"
+            ),
+        }
+    }
+}
+
+/// The result of [`TurboPersistence::verify`].
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// The committed sequence number from the CURRENT file.
+    pub sequence_number: Option<u32>,
+    /// False for databases that were written before the LOG file was introduced. This is not an
+    /// issue, but such databases can only be repaired by resetting them to an empty state.
+    pub has_commit_log: bool,
+    /// The number of committed SST files that were checked.
+    pub sst_files: usize,
+    /// The number of committed blob files that were checked.
+    pub blob_files: usize,
+    /// The number of files from commits that never finished. These are removed when the database
+    /// is opened, so they are not an issue.
+    pub uncommitted_files: usize,
+    /// The problems that were found.
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl VerifyReport {
+    /// Returns true if no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns true if committed data is damaged, see [`VerifyIssue::is_corruption`]. Only then
+    /// [`TurboPersistence::repair`] can help.
+    pub fn is_corrupted(&self) -> bool {
+        self.issues.iter().any(VerifyIssue::is_corruption)
+    }
+}
+
+/// The result of [`TurboPersistence::repair`].
+#[derive(Debug)]
+pub struct RepairReport {
+    /// The committed sequence number before the repair, if the CURRENT file was valid.
+    pub previous_sequence_number: Option<u32>,
+    /// The committed sequence number after the repair. All commits up to this sequence number are
+    /// kept.
+    pub sequence_number: u32,
+    /// The files that were removed.
+    pub removed_files: Vec<PathBuf>,
+    /// Files and directories that don't belong to the database. They are left untouched.
+    pub unexpected_files: Vec<PathBuf>,
+}
+
+/// The kind of a database file, derived from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum FileKind {
+    Sst,
+    Blob,
+    Del,
+}
+
+/// The files in a database directory.
+#[derive(Default)]
+struct DirectoryListing {
+    /// All files with a sequence number, sorted by sequence number.
+    files: BTreeMap<(u32, FileKind), PathBuf>,
+    /// All files that don't belong to the database.
+    unexpected_files: Vec<PathBuf>,
+}
+
+/// The result of checking the files of a directory against a sequence number.
+struct FileCheck {
+    sst_files: usize,
+    blob_files: usize,
+    uncommitted_files: usize,
+    issues: Vec<VerifyIssue>,
+}
+
+impl TurboPersistence {
+    /// Checks the consistency of the database at the given path without opening it: the CURRENT
+    /// and LOG files, the header and the checksum of every block of all committed SST files, all
+    /// committed blob files and all blob references. This reads the whole database.
+    pub fn verify(path: &Path) -> Result<VerifyReport> {
+        let log = read_commit_log(path)?;
+        let has_commit_log = has_commit_log(path);
+        let mut issues = Vec::new();
+        let sequence_number = match read_current(path) {
+            Ok(sequence_number) => Some(sequence_number),
+            Err(err) => {
+                issues.push(VerifyIssue::InvalidCurrentFile {
+                    error: format!("{err:#}"),
+                });
+                None
+            }
+        };
+        let Some(current) = sequence_number else {
+            return Ok(VerifyReport {
+                sequence_number,
+                has_commit_log,
+                sst_files: 0,
+                blob_files: 0,
+                uncommitted_files: 0,
+                issues,
+            });
+        };
+        if current > 0
+            && has_commit_log
+            && !log.iter().any(|entry| entry.sequence_number == current)
+        {
+            issues.push(VerifyIssue::InvalidCommitLog {
+                error: format!("Missing entry for the committed sequence number {current}"),
+            });
+        }
+        let listing = list_directory(path)?;
+        let check = check_files(&listing, current)?;
+        issues.extend(check.issues);
+        Ok(VerifyReport {
+            sequence_number,
+            has_commit_log,
+            sst_files: check.sst_files,
+            blob_files: check.blob_files,
+            uncommitted_files: check.uncommitted_files,
+            issues,
+        })
+    }
+
+    /// Repairs the database at the given path, e. g. after a crash or when it fails to open.
+    ///
+    /// Uncommitted files are removed. Files and directories that don't belong to the database are
+    /// never removed, they are only reported. When committed files are truncated or
+    /// corrupted, the database is rolled back to the last commit before the first affected file
+    /// whose files are all still intact. Commits that deleted files (compactions) can only be
+    /// rolled back while the deleted files still exist, which is the case until the next commit or
+    /// until the database is opened again, whichever comes first.
+    /// When no such commit exists, the database is reset to an empty state instead.
+    ///
+    /// The database must not be opened while it's repaired.
+    pub fn repair(path: &Path) -> Result<RepairReport> {
+        let log = read_commit_log(path)?;
+        let previous_sequence_number = read_current(path).ok();
+        // Without a valid CURRENT file, fall back to the last logged commit. Its files were
+        // fsynced before it was logged.
+        let current = previous_sequence_number
+            .or_else(|| log.last().map(|entry| entry.sequence_number))
+            .unwrap_or(0);
+        let log = log
+            .into_iter()
+            .filter(|entry| entry.sequence_number <= current)
+            .collect::<Vec<_>>();
+
+        let listing = list_directory(path)?;
+        let check = check_files(&listing, current)?;
+        let sequence_number = match check
+            .issues
+            .iter()
+            .filter_map(|issue| issue.sequence_number())
+            .min()
+        {
+            Some(first_corrupted) => {
+                last_consistent_sequence_number(&listing, &log, first_corrupted)?
+            }
+            None => current,
+        };
+
+        // Update CURRENT first. This makes all later files uncommitted, so a crash during repair
+        // leaves a consistent database behind.
+        if previous_sequence_number != Some(sequence_number) {
+            let mut current_file = File::create(path.join("CURRENT"))?;
+            current_file.write_u32::<BE>(sequence_number)?;
+            current_file.sync_all()?;
+        }
+
+        let mut removed_files = Vec::new();
+        for ((seq, _), file) in listing.files.iter() {
+            if *seq > sequence_number {
+                fs::remove_file(file)
+                    .with_context(|| format!("Unable to remove {}", file.display()))?;
+                removed_files.push(file.clone());
+            }
+        }
+        truncate_commit_log(path, sequence_number)?;
+        if sequence_number > 0
+            && !read_commit_log(path)?
+                .iter()
+                .any(|entry| entry.sequence_number == sequence_number)
+        {
+            // The commit history is unknown, so the commit is treated as not revertible.
+            append_commit_log(
+                path,
+                CommitLogEntry {
+                    sequence_number,
+                    deletes_files: true,
+                },
+            )?;
+        }
+
+        Ok(RepairReport {
+            previous_sequence_number,
+            sequence_number,
+            removed_files,
+            unexpected_files: listing.unexpected_files,
+        })
+    }
+}
+
+/// Reads the committed sequence number from the CURRENT file.
+fn read_current(path: &Path) -> Result<u32> {
+    let content = fs::read(path.join("CURRENT")).context("Unable to read CURRENT file")?;
+    if content.len() != 4 {
+        bail!(
+            "CURRENT file has {} bytes, but 4 bytes are expected",
+            content.len()
+        );
+    }
+    Ok((&content[..]).read_u32::<BE>()?)
+}
+
+/// Lists all files in the database directory.
+fn list_directory(path: &Path) -> Result<DirectoryListing> {
+    let mut listing = DirectoryListing::default();
+    for entry in fs::read_dir(path).context("Unable to read database directory")? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|s| s.to_str()) else {
+            listing.unexpected_files.push(path);
+            continue;
+        };
+        if name == "CURRENT" || is_commit_log(name) {
+            continue;
+        }
+        let parsed = name.split_once('.').and_then(|(stem, ext)| {
+            let kind = match ext {
+                "sst" => FileKind::Sst,
+                "blob" => FileKind::Blob,
+                "del" => FileKind::Del,
+                _ => return None,
+            };
+            Some((stem.parse::<u32>().ok()?, kind))
+        });
+        match parsed {
+            Some(key) if path.is_file() => {
+                listing.files.insert(key, path);
+            }
+            _ => listing.unexpected_files.push(path),
+        }
+    }
+    Ok(listing)
+}
+
+/// Checks all files that are committed at the given sequence number.
+fn check_files(listing: &DirectoryListing, current: u32) -> Result<FileCheck> {
+    let mut check = FileCheck {
+        sst_files: 0,
+        blob_files: 0,
+        uncommitted_files: 0,
+        issues: listing
+            .unexpected_files
+            .iter()
+            .map(|path| VerifyIssue::UnexpectedFile { path: path.clone() })
+            .collect(),
+    };
+
+    // Files listed in del files are already deleted logically and only waiting to be removed.
+    let mut deleted = HashSet::new();
+    for ((seq, kind), path) in listing.files.iter() {
+        if *kind != FileKind::Del || *seq > current {
+            continue;
+        }
+        let content = fs::read(path)?;
+        if content.len() % 4 != 0 {
+            check.issues.push(VerifyIssue::CorruptedDelFile {
+                sequence_number: *seq,
+                error: format!("Unexpected file size {}", content.len()),
+            });
+            continue;
+        }
+        for mut entry in content.chunks_exact(4) {
+            deleted.insert(entry.read_u32::<BE>()?);
+        }
+    }
+
+    let mut blob_references = Vec::new();
+    for ((seq, kind), path) in listing.files.iter() {
+        let seq = *seq;
+        if seq > current {
+            check.uncommitted_files += 1;
+            continue;
+        }
+        if deleted.contains(&seq) {
+            continue;
+        }
+        match kind {
+            FileKind::Sst => {
+                check.sst_files += 1;
+                match StaticSortedFile::open(seq, path.clone()).and_then(|sst| sst.verify()) {
+                    Ok(stats) => blob_references
+                        .extend(stats.blob_references.into_iter().map(|blob| (blob, seq))),
+                    Err(err) => check.issues.push(VerifyIssue::CorruptedSstFile {
+                        sequence_number: seq,
+                        error: format!("{err:#}"),
+                    }),
+                }
+            }
+            FileKind::Blob => {
+                check.blob_files += 1;
+                if let Err(err) = verify_blob(path) {
+                    check.issues.push(VerifyIssue::CorruptedBlobFile {
+                        sequence_number: seq,
+                        error: format!("{err:#}"),
+                    });
+                }
+            }
+            FileKind::Del => {}
+        }
+    }
+
+    for (blob, referenced_by) in blob_references {
+        if blob > current || !listing.files.contains_key(&(blob, FileKind::Blob)) {
+            check.issues.push(VerifyIssue::MissingBlobFile {
+                sequence_number: blob,
+                referenced_by,
+            });
+        }
+    }
+    Ok(check)
+}
+
+/// Checks the header and the checksum of a blob file and decompresses it.
+fn verify_blob(path: &Path) -> Result<()> {
+    let content = fs::read(path)?;
+    if content.len() < BLOB_HEADER_SIZE {
+        bail!("File is truncated: {} bytes", content.len());
+    }
+    let mut header = &content[..BLOB_HEADER_SIZE];
+    let codec = header.read_u8()?;
+    let uncompressed_length = header.read_u32::<BE>()? as usize;
+    let expected_checksum = header.read_u32::<BE>()?;
+    let compressed = &content[BLOB_HEADER_SIZE..];
+    if checksum(compressed) != expected_checksum {
+        bail!("Checksum mismatch");
+    }
+    let mut decompressed = vec![0; uncompressed_length];
+    decompress(codec, compressed, &mut decompressed)?;
+    Ok(())
+}
+
+/// Returns the sequence number of the last logged commit before the first corrupted file whose
+/// files are all present and intact, or 0 when there is no such commit.
+fn last_consistent_sequence_number(
+    listing: &DirectoryListing,
+    log: &[CommitLogEntry],
+    first_corrupted: u32,
+) -> Result<u32> {
+    let mut candidates = log
+        .iter()
+        .map(|entry| entry.sequence_number)
+        .filter(|seq| *seq < first_corrupted)
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by(|a, b| b.cmp(a));
+    candidates.dedup();
+    for target in candidates {
+        // Checking for missing files is cheap, so it's done before the files are verified
+        if !deleted_files_exist(listing, log, target)? {
+            continue;
+        }
+        let check = check_files(listing, target)?;
+        if !check.issues.iter().any(VerifyIssue::is_corruption) {
+            return Ok(target);
+        }
+    }
+    Ok(0)
+}
+
+/// Returns true if all files that were deleted by commits after `target` and that are part of the
+/// database at `target` still exist.
+fn deleted_files_exist(
+    listing: &DirectoryListing,
+    log: &[CommitLogEntry],
+    target: u32,
+) -> Result<bool> {
+    for entry in log {
+        if entry.sequence_number <= target || !entry.deletes_files {
+            continue;
+        }
+        // The del file is removed when the database is opened after all listed files are gone
+        let Some(del_file) = listing.files.get(&(entry.sequence_number, FileKind::Del)) else {
+            return Ok(false);
+        };
+        let content = fs::read(del_file)?;
+        if content.len() % 4 != 0 {
+            return Ok(false);
+        }
+        for mut deleted in content.chunks_exact(4) {
+            let seq = deleted.read_u32::<BE>()?;
+            if seq <= target && !listing.files.contains_key(&(seq, FileKind::Sst)) {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
@@ -9,8 +9,7 @@ use std::{
 };
 
 use anyhow::{Context, Result};
-use byteorder::{WriteBytesExt, BE};
-use lzzzz::lz4::{self, ACC_LEVEL_DEFAULT};
+use byteorder::{ByteOrder, WriteBytesExt, BE};
 use parking_lot::Mutex;
 use rayon::{
     iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator},
@@ -19,8 +18,13 @@ use rayon::{
 use thread_local::ThreadLocal;
 
 use crate::{
-    collector::Collector, collector_entry::CollectorEntry, constants::MAX_MEDIUM_VALUE_SIZE,
-    key::StoreKey, static_sorted_file_builder::StaticSortedFileBuilder,
+    checksum::checksum,
+    collector::Collector,
+    collector_entry::CollectorEntry,
+    compression::CompressionCodec,
+    constants::{BLOB_HEADER_SIZE, MAX_MEDIUM_VALUE_SIZE},
+    key::StoreKey,
+    static_sorted_file_builder::StaticSortedFileBuilder,
 };
 
 /// The thread local state of a `WriteBatch`.
@@ -44,6 +48,8 @@ pub(crate) struct FinishResult {
 pub struct WriteBatch<K: StoreKey + Send, const FAMILIES: usize> {
     /// The database path
     path: PathBuf,
+    /// The compression codec for new SST and blob files.
+    codec: CompressionCodec,
     /// The current sequence number counter. Increased for every new SST file or blob file.
     current_sequence_number: AtomicU32,
     /// The thread local state.
@@ -54,10 +60,11 @@ pub struct WriteBatch<K: StoreKey + Send, const FAMILIES: usize> {
 
 impl<K: StoreKey + Send + Sync, const FAMILIES: usize> WriteBatch<K, FAMILIES> {
     /// Creates a new write batch for a database.
-    pub(crate) fn new(path: PathBuf, current: u32) -> Self {
+    pub(crate) fn new(path: PathBuf, codec: CompressionCodec, current: u32) -> Self {
         assert!(FAMILIES <= u32::MAX as usize);
         Self {
             path,
+            codec,
             current_sequence_number: AtomicU32::new(current),
             thread_locals: ThreadLocal::new(),
             idle_collectors: Mutex::new(Vec::new()),
@@ -236,9 +243,15 @@ impl<K: StoreKey + Send + Sync, const FAMILIES: usize> WriteBatch<K, FAMILIES> {
     fn create_blob(&self, value: &[u8]) -> Result<(u32, File)> {
         let seq = self.current_sequence_number.fetch_add(1, Ordering::SeqCst) + 1;
         let mut buffer = Vec::new();
+        buffer.write_u8(self.codec.id())?;
         buffer.write_u32::<BE>(value.len() as u32)?;
-        lz4::compress_to_vec(value, &mut buffer, ACC_LEVEL_DEFAULT)
+        // Placeholder for the checksum
+        buffer.write_u32::<BE>(0)?;
+        self.codec
+            .compress_to_vec(value, &mut buffer)
             .context("Compression of value for blob file failed")?;
+        let checksum = checksum(&buffer[BLOB_HEADER_SIZE..]);
+        BE::write_u32(&mut buffer[5..BLOB_HEADER_SIZE], checksum);
 
         let file = self.path.join(format!("{:08}.blob", seq));
         let mut file = File::create(&file).context("Unable to create blob file")?;
@@ -257,8 +270,13 @@ impl<K: StoreKey + Send + Sync, const FAMILIES: usize> WriteBatch<K, FAMILIES> {
         let (entries, total_key_size, total_value_size) = collector_data;
         let seq = self.current_sequence_number.fetch_add(1, Ordering::SeqCst) + 1;
 
-        let builder =
-            StaticSortedFileBuilder::new(family as u32, entries, total_key_size, total_value_size)?;
+        let builder = StaticSortedFileBuilder::new(
+            family as u32,
+            self.codec,
+            entries,
+            total_key_size,
+            total_value_size,
+        )?;
 
         let path = self.path.join(format!("{:08}.sst", seq));
         let file = builder
@@ -1,3 +1,5 @@
+use std::{env, process::Command};
+
 use anyhow::Result;
 use turbo_tasks_build::generate_register;
 use vergen_gitcl::{Emitter, GitclBuilder};
@@ -10,7 +12,17 @@ fn generate_version_info() -> Result<()> {
     Ok(())
 }
 
+/// Emits the rust toolchain version, it's part of the persistent cache manifest.
+fn generate_toolchain_info() -> Result<()> {
+    let rustc = env::var("RUSTC")?;
+    let output = Command::new(rustc).arg("-V").output()?;
+    let version = String::from_utf8(output.stdout)?;
+    println!("cargo:rustc-env=TURBO_ENGINE_TOOLCHAIN={}", version.trim());
+    Ok(())
+}
+
 fn main() {
     generate_register();
     generate_version_info().unwrap();
+    generate_toolchain_info().unwrap();
 }
@@ -5,13 +5,13 @@ mod storage;
 
 use std::{
     borrow::Cow,
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     future::Future,
     hash::BuildHasherDefault,
     mem::take,
     pin::Pin,
     sync::{
-        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
     thread::available_parallelism,
@@ -30,11 +30,15 @@ use turbo_tasks::{
         TransientTaskType, TypedCellContent,
     },
     event::{Event, EventListener},
+    function_statistics::{FunctionStatistics, FunctionStatisticsCollector},
+    invalidation_provenance::{InvalidationCause, InvalidationProvenance, InvalidationStep},
     registry,
-    util::IdFactoryWithReuse,
-    CellId, FunctionId, RawVc, ReadConsistency, SessionId, TaskId, TraitTypeId,
+    task_graph::{TaskDependency, TaskGraph, TaskGraphCollectible, TaskGraphNode},
+    util::{IdFactoryWithReuse, StaticOrArc},
+    CellId, FunctionId, InvalidationReason, RawVc, ReadConsistency, SessionId, TaskId, TraitTypeId,
     TurboTasksBackendApi, ValueTypeId, TRANSIENT_TASK_BIT,
 };
+use turbo_tasks_malloc::TurboMalloc;
 
 pub use self::{operation::AnyOperation, storage::TaskDataCategory};
 use crate::{
@@ -45,7 +49,7 @@ use crate::{
             ExecuteContext, ExecuteContextImpl, Operation, OutdatedEdge, TaskDirtyCause, TaskGuard,
         },
         persisted_storage_log::PersistedStorageLog,
-        storage::{get, get_many, get_mut, iter_many, remove, Storage},
+        storage::{get, get_many, get_mut, iter_many, remove, InnerStorage, Storage},
     },
     backing_storage::BackingStorage,
     data::{
@@ -120,6 +124,18 @@ pub struct BackendOptions {
 
     /// Enables the backing storage.
     pub storage_mode: Option<StorageMode>,
+
+    /// Records why tasks were invalidated, so that the chain of invalidations that led to the
+    /// re-execution of a task can be queried with [`Backend::invalidation_provenance`].
+    ///
+    /// This keeps the last invalidation cause of every invalidated task in memory.
+    pub invalidation_provenance: bool,
+
+    /// The memory usage in bytes above which cell contents and other data of inactive tasks are
+    /// evicted after a snapshot. Only data that was already written to the backing storage is
+    /// evicted, it's restored from there when the task is accessed again. Requires
+    /// [`StorageMode::ReadWrite`].
+    pub memory_limit: Option<usize>,
 }
 
 impl Default for BackendOptions {
@@ -128,6 +144,8 @@ impl Default for BackendOptions {
             dependency_tracking: true,
             children_tracking: true,
             storage_mode: Some(StorageMode::ReadWrite),
+            invalidation_provenance: false,
+            memory_limit: None,
         }
     }
 }
@@ -153,6 +171,14 @@ struct TurboTasksBackendInner<B: BackingStorage> {
     persisted_storage_meta_log: Option<PersistedStorageLog>,
     storage: Storage<TaskId, CachedDataItem>,
 
+    /// The last invalidation cause of every invalidated task, when
+    /// [`BackendOptions::invalidation_provenance`] is enabled.
+    invalidation_provenance:
+        Option<DashMap<TaskId, InvalidationCause, BuildHasherDefault<FxHasher>>>,
+
+    /// Execution counters per function, see [`Backend::function_statistics`].
+    function_statistics: FunctionStatisticsCollector,
+
     /// Number of executing operations + Highest bit is set when snapshot is
     /// requested. When that bit is set, operations should pause until the
     /// snapshot is completed. When the bit is set and in progress counter
@@ -170,6 +196,16 @@ struct TurboTasksBackendInner<B: BackingStorage> {
     snapshot_completed: Condvar,
     /// The timestamp of the last started snapshot since [`Self::start_time`].
     last_snapshot: AtomicU64,
+    /// The id of the next snapshot, which will persist all modifications that are logged now.
+    snapshot_id: AtomicU32,
+    /// The id of the last snapshot that was written to the backing storage. All modifications of
+    /// this and earlier snapshots are persisted.
+    persisted_snapshot_id: AtomicU32,
+    /// Set when a snapshot failed to be written to the backing storage. See
+    /// [`Self::evict_task_data`].
+    eviction_disabled: AtomicBool,
+    /// The number of times the data of a task was evicted.
+    evicted_tasks: AtomicUsize,
 
     stopping: AtomicBool,
     stopping_event: Event,
@@ -186,6 +222,12 @@ impl<B: BackingStorage> TurboTasksBackend<B> {
             backing_storage,
         )))
     }
+
+    /// The number of times the data of a task was evicted because the memory usage exceeded
+    /// [`BackendOptions::memory_limit`].
+    pub fn evicted_tasks(&self) -> usize {
+        self.0.evicted_tasks.load(Ordering::Relaxed)
+    }
 }
 
 impl<B: BackingStorage> TurboTasksBackendInner<B> {
@@ -194,6 +236,7 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
             (available_parallelism().map_or(4, |v| v.get()) * 64).next_power_of_two();
         let need_log = matches!(options.storage_mode, Some(StorageMode::ReadWrite));
         Self {
+            invalidation_provenance: options.invalidation_provenance.then(DashMap::default),
             options,
             start_time: Instant::now(),
             session_id: backing_storage.next_session_id(),
@@ -211,11 +254,16 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
             persisted_storage_data_log: need_log.then(|| PersistedStorageLog::new(shard_amount)),
             persisted_storage_meta_log: need_log.then(|| PersistedStorageLog::new(shard_amount)),
             storage: Storage::new(),
+            function_statistics: FunctionStatisticsCollector::default(),
             in_progress_operations: AtomicUsize::new(0),
             snapshot_request: Mutex::new(SnapshotRequest::new()),
             operations_suspended: Condvar::new(),
             snapshot_completed: Condvar::new(),
             last_snapshot: AtomicU64::new(0),
+            snapshot_id: AtomicU32::new(1),
+            persisted_snapshot_id: AtomicU32::new(0),
+            eviction_disabled: AtomicBool::new(false),
+            evicted_tasks: AtomicUsize::new(0),
             stopping: AtomicBool::new(false),
             stopping_event: Event::new(|| "TurboTasksBackend::stopping_event".to_string()),
             idle_start_event: Event::new(|| "TurboTasksBackend::idle_start_event".to_string()),
@@ -326,6 +374,10 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         matches!(self.options.storage_mode, Some(StorageMode::ReadWrite))
     }
 
+    fn current_snapshot_id(&self) -> u32 {
+        self.snapshot_id.load(Ordering::Relaxed)
+    }
+
     fn should_restore(&self) -> bool {
         self.options.storage_mode.is_some()
     }
@@ -337,6 +389,16 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
     fn should_track_children(&self) -> bool {
         self.options.children_tracking
     }
+
+    fn should_record_invalidation_provenance(&self) -> bool {
+        self.invalidation_provenance.is_some()
+    }
+
+    fn record_invalidation_provenance(&self, task_id: TaskId, cause: InvalidationCause) {
+        if let Some(invalidation_provenance) = &self.invalidation_provenance {
+            invalidation_provenance.insert(task_id, cause);
+        }
+    }
 }
 
 pub(crate) struct OperationGuard<'a, B: BackingStorage> {
@@ -704,6 +766,9 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
             .as_ref()
             .map(|l| l.take(|i| i))
             .unwrap_or_default();
+        // All operations are suspended, so all modifications that were logged so far are part of
+        // this snapshot
+        let snapshot_id = self.snapshot_id.fetch_add(1, Ordering::Relaxed);
         let mut snapshot_request = self.snapshot_request.lock();
         snapshot_request.snapshot_requested = false;
         self.in_progress_operations
@@ -712,19 +777,6 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         let snapshot_time = Instant::now();
         drop(snapshot_request);
 
-        // TODO track which items are persisting
-        // TODO This is very inefficient, maybe the BackingStorage could compute that since it need
-        // to iterate items anyway.
-        // let mut counts: FxHashMap<TaskId, u32> =
-        // FxHashMap::with_capacity_and_hasher(); for log in persisted_storage_meta_log
-        //     .iter()
-        //     .chain(persisted_storage_data_log.iter())
-        // {
-        //     for CachedDataUpdate { task, .. } in log.iter() {
-        //         *counts.entry(*task).or_default() += 1;
-        //     }
-        // }
-
         let mut new_items = false;
 
         fn shards_empty<T>(shards: &[ChunkedVec<T>]) -> bool {
@@ -744,21 +796,62 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
                 persisted_storage_data_log,
             ) {
                 println!("Persisting failed: {:?}", err);
+                // The modifications of this snapshot are lost, so the in-memory data is the only
+                // up-to-date copy of the modified tasks. Later snapshots would mark them as
+                // persisted, so eviction is disabled for the rest of the session.
+                self.eviction_disabled.store(true, Ordering::Release);
                 return None;
             }
         }
 
-        // TODO add when we need to track persisted items
-        // for (task_id, count) in counts {
-        //     self.storage
-        //         .access_mut(task_id)
-        //         .persistance_state_mut()
-        //         .finish_persisting_items(count);
-        // }
+        self.persisted_snapshot_id
+            .fetch_max(snapshot_id, Ordering::Release);
 
         Some((snapshot_time, new_items))
     }
 
+    /// Evicts the data of tasks that are persisted and weren't accessed since the last eviction,
+    /// until the memory usage is below [`BackendOptions::memory_limit`]. Evicted data is restored
+    /// from the backing storage when the task is accessed again.
+    ///
+    /// This runs concurrently with operations. The storage is processed one shard at a time and
+    /// a shard is locked while its tasks are evicted. Operations modify a task only while holding
+    /// its shard lock, and [`add_persisting_item`](storage::PersistanceState::add_persisting_item)
+    /// is called under the same lock as the modification. So a task is either evicted before a
+    /// modification, which restores the data again, or the modification is visible to the
+    /// eviction and keeps the task from being evicted until the snapshot that contains it is
+    /// persisted.
+    fn evict_task_data(&self) {
+        debug_assert!(self.should_persist());
+        let Some(memory_limit) = self.options.memory_limit else {
+            return;
+        };
+        if self.eviction_disabled.load(Ordering::Acquire)
+            || TurboMalloc::memory_usage() < memory_limit
+        {
+            return;
+        }
+        // Evict a bit more than needed, so that eviction doesn't run after every snapshot
+        let target = memory_limit / 4 * 3;
+        let persisted_snapshot_id = self.persisted_snapshot_id.load(Ordering::Acquire);
+        let _span = tracing::trace_span!("evict task data").entered();
+        // Like a clock algorithm: Recently accessed tasks are skipped in the first round
+        for skip_accessed in [true, false] {
+            for shard in 0..self.storage.shard_amount() {
+                let mut evicted = 0;
+                self.storage.for_each_in_shard_mut(shard, |task_id, task| {
+                    if evict_task_data(*task_id, task, persisted_snapshot_id, skip_accessed) {
+                        evicted += 1;
+                    }
+                });
+                self.evicted_tasks.fetch_add(evicted, Ordering::Relaxed);
+                if TurboMalloc::memory_usage() < target {
+                    return;
+                }
+            }
+        }
+    }
+
     fn startup(&self, turbo_tasks: &dyn TurboTasksBackendApi<TurboTasksBackend<B>>) {
         if self.should_restore() {
             // Continue all uncompleted operations
@@ -798,6 +891,12 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         self.idle_end_event.notify(usize::MAX);
     }
 
+    fn track_cache_hit(&self, task_type: &CachedTaskType) {
+        if let CachedTaskType::Native { fn_type, .. } = task_type {
+            self.function_statistics.record_cache_hit(*fn_type);
+        }
+    }
+
     fn get_or_create_persistent_task(
         &self,
         task_type: CachedTaskType,
@@ -805,6 +904,7 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         turbo_tasks: &dyn TurboTasksBackendApi<TurboTasksBackend<B>>,
     ) -> TaskId {
         if let Some(task_id) = self.task_cache.lookup_forward(&task_type) {
+            self.track_cache_hit(&task_type);
             self.connect_child(parent_task, task_id, turbo_tasks);
             return task_id;
         }
@@ -819,6 +919,7 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
                 self.backing_storage
                     .forward_lookup_task_cache(tx.as_ref(), &task_type)
             } {
+                self.track_cache_hit(&task_type);
                 let _ = self.task_cache.try_insert(Arc::new(task_type), task_id);
                 task_id
             } else {
@@ -863,6 +964,7 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
             );
         }
         if let Some(task_id) = self.task_cache.lookup_forward(&task_type) {
+            self.track_cache_hit(&task_type);
             // Safety: `tx` is a valid transaction from `self.backend.backing_storage`.
             self.connect_child(parent_task, task_id, turbo_tasks);
             return task_id;
@@ -896,7 +998,27 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         }
         operation::InvalidateOperation::run(
             smallvec![task_id],
-            TaskDirtyCause::Invalidator,
+            TaskDirtyCause::Invalidator { reason: None },
+            self.execute_context(turbo_tasks),
+        );
+    }
+
+    fn invalidate_task_with_reason(
+        &self,
+        task_id: TaskId,
+        reason: &StaticOrArc<dyn InvalidationReason>,
+        turbo_tasks: &dyn TurboTasksBackendApi<TurboTasksBackend<B>>,
+    ) {
+        if !self.should_track_dependencies() {
+            panic!("Dependency tracking is disabled so invalidation is not allowed");
+        }
+        // Formatting the reason is only worth it when it's recorded
+        let reason = self
+            .should_record_invalidation_provenance()
+            .then(|| reason.to_string());
+        operation::InvalidateOperation::run(
+            smallvec![task_id],
+            TaskDirtyCause::Invalidator { reason },
             self.execute_context(turbo_tasks),
         );
     }
@@ -951,6 +1073,91 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
         )
     }
 
+    fn task_graph(
+        &self,
+        root: TaskId,
+        turbo_tasks: &dyn TurboTasksBackendApi<TurboTasksBackend<B>>,
+    ) -> TaskGraph {
+        let mut ctx = self.execute_context(turbo_tasks);
+        let mut visited = HashSet::from([root]);
+        let mut queue = VecDeque::from([root]);
+        let mut tasks = Vec::new();
+        while let Some(task_id) = queue.pop_front() {
+            let task = ctx.task(task_id, TaskDataCategory::All);
+            let dirty = get!(task, Dirty).map_or(false, |dirty| dirty.get(self.session_id));
+            let mut children: Vec<_> = get_many!(task, Child { task } => *task);
+            children.sort_unstable();
+            let mut dependencies: Vec<_> = get_many!(task, OutputDependency { target } => {
+                TaskDependency::Output { task: *target }
+            });
+            dependencies.extend(iter_many!(task, CellDependency { target } => {
+                TaskDependency::Cell {
+                    task: target.task,
+                    cell: target.cell,
+                }
+            }));
+            dependencies.extend(iter_many!(task, CollectiblesDependency { target } => {
+                TaskDependency::Collectibles {
+                    task: target.task,
+                    collectible_type: target.collectible_type,
+                }
+            }));
+            let collectibles = get_many!(task, Collectible { collectible } count => {
+                TaskGraphCollectible {
+                    collectible_type: collectible.collectible_type,
+                    task: collectible.cell.task,
+                    cell: collectible.cell.cell,
+                    count: *count,
+                }
+            });
+            drop(task);
+
+            let reachable = children
+                .iter()
+                .copied()
+                .chain(dependencies.iter().map(TaskDependency::task));
+            for id in reachable {
+                if visited.insert(id) {
+                    queue.push_back(id);
+                }
+            }
+            tasks.push(TaskGraphNode {
+                id: task_id,
+                description: self.get_task_description(task_id),
+                dirty,
+                children,
+                dependencies,
+                collectibles,
+            });
+        }
+        TaskGraph { root, tasks }
+    }
+
+    fn invalidation_provenance(&self, task_id: TaskId) -> Option<InvalidationProvenance> {
+        let invalidation_provenance = self.invalidation_provenance.as_ref()?;
+        let mut visited = HashSet::new();
+        let mut steps = Vec::new();
+        let mut current = Some(task_id);
+        // Follows the source tasks of the causes until an external cause is reached. The
+        // recorded causes are the latest ones, so a cycle is possible when tasks were invalidated
+        // multiple times.
+        while let Some(task_id) = current.filter(|task_id| visited.insert(*task_id)) {
+            let Some(cause) = invalidation_provenance
+                .get(&task_id)
+                .map(|cause| cause.clone())
+            else {
+                break;
+            };
+            current = cause.source_task();
+            steps.push(InvalidationStep {
+                task: task_id,
+                description: self.get_task_description(task_id),
+                cause,
+            });
+        }
+        (!steps.is_empty()).then_some(InvalidationProvenance { steps })
+    }
+
     fn try_get_function_id(&self, task_id: TaskId) -> Option<FunctionId> {
         self.lookup_task_type(task_id)
             .and_then(|task_type| match &*task_type {
@@ -1205,12 +1412,18 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
     fn task_execution_completed(
         &self,
         task_id: TaskId,
-        _duration: Duration,
+        duration: Duration,
         _memory_usage: usize,
         cell_counters: &AutoMap<ValueTypeId, u32, BuildHasherDefault<FxHasher>, 8>,
         stateful: bool,
         turbo_tasks: &dyn TurboTasksBackendApi<TurboTasksBackend<B>>,
     ) -> bool {
+        if let Some(function_id) = self.try_get_function_id(task_id) {
+            let cells_written = cell_counters.values().map(|&count| count as u64).sum();
+            self.function_statistics
+                .record_execution(function_id, duration, cells_written);
+        }
+
         let mut ctx = self.execute_context(turbo_tasks);
         let mut task = ctx.task(task_id, TaskDataCategory::All);
         let Some(in_progress) = get!(task, InProgress) else {
@@ -1545,6 +1758,10 @@ impl<B: BackingStorage> TurboTasksBackendInner<B> {
                     let snapshot = turbo_tasks::spawn_blocking(move || this.snapshot()).await;
                     if let Some((snapshot_start, new_data)) = snapshot {
                         last_snapshot = snapshot_start;
+                        if self.options.memory_limit.is_some() {
+                            let this = self.clone();
+                            turbo_tasks::spawn_blocking(move || this.evict_task_data()).await;
+                        }
                         if new_data {
                             continue;
                         }
@@ -1854,6 +2071,16 @@ impl<B: BackingStorage> Backend for TurboTasksBackend<B> {
         self.0.invalidate_task(task_id, turbo_tasks);
     }
 
+    fn invalidate_task_with_reason(
+        &self,
+        task_id: TaskId,
+        reason: &StaticOrArc<dyn InvalidationReason>,
+        turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) {
+        self.0
+            .invalidate_task_with_reason(task_id, reason, turbo_tasks);
+    }
+
     fn invalidate_tasks(&self, tasks: &[TaskId], turbo_tasks: &dyn TurboTasksBackendApi<Self>) {
         self.0.invalidate_tasks(tasks, turbo_tasks);
     }
@@ -1878,6 +2105,29 @@ impl<B: BackingStorage> Backend for TurboTasksBackend<B> {
         self.0.get_task_description(task)
     }
 
+    fn task_graph(
+        &self,
+        root: TaskId,
+        turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Option<TaskGraph> {
+        Some(self.0.task_graph(root, turbo_tasks))
+    }
+
+    fn invalidation_provenance(
+        &self,
+        task_id: TaskId,
+        _turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Option<InvalidationProvenance> {
+        self.0.invalidation_provenance(task_id)
+    }
+
+    fn function_statistics(
+        &self,
+        _turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Vec<FunctionStatistics> {
+        self.0.function_statistics.get()
+    }
+
     fn try_get_function_id(&self, task_id: TaskId) -> Option<FunctionId> {
         self.0.try_get_function_id(task_id)
     }
@@ -1905,7 +2155,7 @@ impl<B: BackingStorage> Backend for TurboTasksBackend<B> {
     fn task_execution_completed(
         &self,
         task_id: TaskId,
-        _duration: Duration,
+        duration: Duration,
         _memory_usage: usize,
         cell_counters: &AutoMap<ValueTypeId, u32, BuildHasherDefault<FxHasher>, 8>,
         stateful: bool,
@@ -1913,7 +2163,7 @@ impl<B: BackingStorage> Backend for TurboTasksBackend<B> {
     ) -> bool {
         self.0.task_execution_completed(
             task_id,
-            _duration,
+            duration,
             _memory_usage,
             cell_counters,
             stateful,
@@ -2054,6 +2304,43 @@ impl<B: BackingStorage> Backend for TurboTasksBackend<B> {
     }
 }
 
+/// Removes the persisted data of a task from memory, when it's not executing and all its data is
+/// persisted. Returns false when the task was kept.
+fn evict_task_data(
+    task_id: TaskId,
+    task: &mut InnerStorage<CachedDataItem>,
+    persisted_snapshot_id: u32,
+    skip_accessed: bool,
+) -> bool {
+    if task_id.is_transient() {
+        return false;
+    }
+    let state = task.persistance_state_mut();
+    let accessed = state.take_accessed();
+    if (skip_accessed && accessed)
+        || !state.is_restored(TaskDataCategory::Data)
+        || !state.is_persisted(persisted_snapshot_id)
+    {
+        return false;
+    }
+    let evictable = task.iter_all().all(|(key, value)| match key.category() {
+        TaskDataCategory::Data => key.is_persistent() && value.is_persistent(),
+        TaskDataCategory::Meta => true,
+        // Executing tasks and tasks that are computing cells are kept
+        TaskDataCategory::All => matches!(
+            key,
+            CachedDataItemKey::AggregateRoot { .. } | CachedDataItemKey::Error { .. }
+        ),
+    });
+    if !evictable {
+        return false;
+    }
+    task.retain_and_shrink(|key, _| key.category() != TaskDataCategory::Data);
+    task.persistance_state_mut()
+        .set_unrestored(TaskDataCategory::Data);
+    true
+}
+
 // from https://github.com/tokio-rs/tokio/blob/29cd6ec1ec6f90a7ee1ad641c03e0e00badbcb0e/tokio/src/time/instant.rs#L57-L63
 fn far_future() -> Instant {
     // Roughly 30 years from now.
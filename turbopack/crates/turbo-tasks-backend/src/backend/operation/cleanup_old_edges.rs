@@ -179,10 +179,13 @@ impl Operation for CleanupOldEdgesOperation {
                                     });
                                 }
                             }
-                            OutdatedEdge::RemovedCellDependent(task_id, value_type) => {
+                            OutdatedEdge::RemovedCellDependent(dependent_task_id, value_type) => {
                                 make_task_dirty(
-                                    task_id,
-                                    TaskDirtyCause::CellRemoved { value_type },
+                                    dependent_task_id,
+                                    TaskDirtyCause::CellRemoved {
+                                        task_id,
+                                        value_type,
+                                    },
                                     queue,
                                     ctx,
                                 );
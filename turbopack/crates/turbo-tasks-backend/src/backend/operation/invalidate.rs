@@ -2,7 +2,9 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
-use turbo_tasks::{registry, TaskId, TraitTypeId, ValueTypeId};
+use turbo_tasks::{
+    invalidation_provenance::InvalidationCause, registry, TaskId, TraitTypeId, ValueTypeId,
+};
 
 use crate::{
     backend::{
@@ -51,7 +53,7 @@ impl Operation for InvalidateOperation {
                 InvalidateOperation::MakeDirty { task_ids, cause } => {
                     let mut queue = AggregationUpdateQueue::new();
                     for task_id in task_ids {
-                        make_task_dirty(task_id, cause, &mut queue, ctx);
+                        make_task_dirty(task_id, cause.clone(), &mut queue, ctx);
                     }
                     if queue.is_empty() {
                         self = InvalidateOperation::Done
@@ -73,17 +75,64 @@ impl Operation for InvalidateOperation {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum TaskDirtyCause {
     InitialDirty,
-    CellChange { value_type: ValueTypeId },
-    CellRemoved { value_type: ValueTypeId },
-    OutputChange { task_id: TaskId },
-    CollectiblesChange { collectible_type: TraitTypeId },
-    Invalidator,
+    CellChange {
+        task_id: TaskId,
+        value_type: ValueTypeId,
+    },
+    CellRemoved {
+        task_id: TaskId,
+        value_type: ValueTypeId,
+    },
+    OutputChange {
+        task_id: TaskId,
+    },
+    CollectiblesChange {
+        collectible_type: TraitTypeId,
+    },
+    /// The reason is only captured when invalidation provenance is recorded.
+    Invalidator {
+        reason: Option<String>,
+    },
     Unknown,
 }
 
+impl TaskDirtyCause {
+    /// The cause that is recorded as invalidation provenance. Initially dirty tasks were never
+    /// executed, so they are not recorded.
+    fn to_invalidation_cause(&self) -> Option<InvalidationCause> {
+        Some(match *self {
+            TaskDirtyCause::InitialDirty => return None,
+            TaskDirtyCause::CellChange {
+                task_id,
+                value_type,
+            } => InvalidationCause::CellChange {
+                task: task_id,
+                value_type,
+            },
+            TaskDirtyCause::CellRemoved {
+                task_id,
+                value_type,
+            } => InvalidationCause::CellRemoved {
+                task: task_id,
+                value_type,
+            },
+            TaskDirtyCause::OutputChange { task_id } => {
+                InvalidationCause::OutputChange { task: task_id }
+            }
+            TaskDirtyCause::CollectiblesChange { collectible_type } => {
+                InvalidationCause::CollectiblesChange { collectible_type }
+            }
+            TaskDirtyCause::Invalidator { ref reason } => InvalidationCause::External {
+                reason: reason.clone(),
+            },
+            TaskDirtyCause::Unknown => InvalidationCause::Unknown,
+        })
+    }
+}
+
 struct TaskDirtyCauseInContext<'l, 'e, E: ExecuteContext<'e>> {
     cause: &'l TaskDirtyCause,
     ctx: &'l E,
@@ -104,14 +153,14 @@ impl<'e, E: ExecuteContext<'e>> Display for TaskDirtyCauseInContext<'_, 'e, E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.cause {
             TaskDirtyCause::InitialDirty => write!(f, "initial dirty"),
-            TaskDirtyCause::CellChange { value_type } => {
+            TaskDirtyCause::CellChange { value_type, .. } => {
                 write!(
                     f,
                     "{} cell changed",
                     registry::get_value_type(*value_type).name
                 )
             }
-            TaskDirtyCause::CellRemoved { value_type } => {
+            TaskDirtyCause::CellRemoved { value_type, .. } => {
                 write!(
                     f,
                     "{} cell removed",
@@ -132,7 +181,10 @@ impl<'e, E: ExecuteContext<'e>> Display for TaskDirtyCauseInContext<'_, 'e, E> {
                     registry::get_trait(*collectible_type).name
                 )
             }
-            TaskDirtyCause::Invalidator => write!(f, "invalidator"),
+            TaskDirtyCause::Invalidator { reason: None } => write!(f, "invalidator"),
+            TaskDirtyCause::Invalidator {
+                reason: Some(reason),
+            } => write!(f, "invalidator ({reason})"),
             TaskDirtyCause::Unknown => write!(f, "unknown"),
         }
     }
@@ -222,6 +274,17 @@ pub fn make_task_dirty_internal(
     )
     .entered();
 
+    // Initially dirty tasks were never executed, so they are not invalidated
+    if !matches!(cause, TaskDirtyCause::InitialDirty) {
+        ctx.record_function_invalidation(task_id);
+    }
+
+    if ctx.should_record_invalidation_provenance() {
+        if let Some(cause) = cause.to_invalidation_cause() {
+            ctx.record_invalidation_provenance(task_id, cause);
+        }
+    }
+
     let should_schedule = if ctx.should_track_children() {
         let aggregated_update = dirty_container.update_with_dirty_state(&DirtyState {
             clean_in_session: None,
@@ -13,7 +13,10 @@ use std::{
 
 use either::Either;
 use serde::{Deserialize, Serialize};
-use turbo_tasks::{KeyValuePair, SessionId, TaskId, TurboTasksBackendApi};
+use turbo_tasks::{
+    backend::CachedTaskType, invalidation_provenance::InvalidationCause, KeyValuePair, SessionId,
+    TaskId, TurboTasksBackendApi,
+};
 
 use crate::{
     backend::{
@@ -85,6 +88,9 @@ pub trait ExecuteContext<'e>: Sized {
     fn get_task_description(&self, task_id: TaskId) -> String;
     fn should_track_children(&self) -> bool;
     fn should_track_dependencies(&self) -> bool;
+    fn should_record_invalidation_provenance(&self) -> bool;
+    fn record_invalidation_provenance(&self, task_id: TaskId, cause: InvalidationCause);
+    fn record_function_invalidation(&self, task_id: TaskId);
 }
 
 pub struct ParentRef<'a> {
@@ -200,6 +206,7 @@ where
                 }
             }
         }
+        task.persistance_state_mut().set_accessed();
         TaskGuardImpl {
             task,
             task_id,
@@ -255,6 +262,8 @@ where
                 }
             }
         }
+        task1.persistance_state_mut().set_accessed();
+        task2.persistance_state_mut().set_accessed();
         (
             TaskGuardImpl {
                 task: task1,
@@ -352,6 +361,26 @@ where
     fn should_track_dependencies(&self) -> bool {
         self.backend.should_track_dependencies()
     }
+
+    fn should_record_invalidation_provenance(&self) -> bool {
+        self.backend.should_record_invalidation_provenance()
+    }
+
+    fn record_invalidation_provenance(&self, task_id: TaskId, cause: InvalidationCause) {
+        self.backend.record_invalidation_provenance(task_id, cause)
+    }
+
+    fn record_function_invalidation(&self, task_id: TaskId) {
+        // Only the in-memory task cache is used. Looking up the task type in the backing storage
+        // would read from the database while the task is locked.
+        if let Some(task_type) = self.backend.task_cache.lookup_reverse(&task_id) {
+            if let CachedTaskType::Native { fn_type, .. } = &*task_type {
+                self.backend
+                    .function_statistics
+                    .record_invalidation(*fn_type);
+            }
+        }
+    }
 }
 
 pub trait TaskGuard: Debug {
@@ -450,7 +479,9 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
             self.task.add(item)
         } else if self.task.add(item.clone()) {
             let (key, value) = item.into_key_and_value();
-            self.task.persistance_state_mut().add_persisting_item();
+            self.task
+                .persistance_state_mut()
+                .add_persisting_item(self.backend.current_snapshot_id());
             self.backend
                 .persisted_storage_log(key.category())
                 .unwrap()
@@ -478,7 +509,9 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
                 key.clone(),
                 value.clone(),
             ));
-            self.task.persistance_state_mut().add_persisting_item();
+            self.task
+                .persistance_state_mut()
+                .add_persisting_item(self.backend.current_snapshot_id());
             self.backend
                 .persisted_storage_log(key.category())
                 .unwrap()
@@ -494,7 +527,9 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
             let item = CachedDataItem::from_key_and_value(key.clone(), value);
             if let Some(old) = self.task.insert(item) {
                 if old.is_persistent() {
-                    self.task.persistance_state_mut().add_persisting_item();
+                    self.task
+                        .persistance_state_mut()
+                        .add_persisting_item(self.backend.current_snapshot_id());
                     self.backend
                         .persisted_storage_log(key.category())
                         .unwrap()
@@ -557,7 +592,8 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
             new
         });
         if add_persisting_item {
-            task.persistance_state_mut().add_persisting_item();
+            task.persistance_state_mut()
+                .add_persisting_item(backend.current_snapshot_id());
         }
     }
 
@@ -571,7 +607,9 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
                 && value.is_persistent()
             {
                 let key = key.clone();
-                self.task.persistance_state_mut().add_persisting_item();
+                self.task
+                    .persistance_state_mut()
+                    .add_persisting_item(self.backend.current_snapshot_id());
                 self.backend
                     .persisted_storage_log(key.category())
                     .unwrap()
@@ -629,6 +667,10 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
         if !self.backend.should_persist() || self.task_id.is_transient() {
             return Either::Left(self.task.extract_if(Some(index), f));
         }
+        // Conservatively assume that items are extracted, since the iterator borrows the task
+        self.task
+            .persistance_state_mut()
+            .add_persisting_item(self.backend.current_snapshot_id());
         Either::Right(self.task.extract_if(Some(index), f).inspect(|item| {
             if item.is_persistent() {
                 let key = item.key();
@@ -648,6 +690,10 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
         if !self.backend.should_persist() || self.task_id.is_transient() {
             return Either::Left(self.task.extract_if_all(f));
         }
+        // Conservatively assume that items are extracted, since the iterator borrows the task
+        self.task
+            .persistance_state_mut()
+            .add_persisting_item(self.backend.current_snapshot_id());
         Either::Right(self.task.extract_if_all(f).inspect(|item| {
             if item.is_persistent() {
                 let key = item.key();
@@ -664,12 +710,10 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
         if !self.backend.should_persist() {
             return;
         }
-        let mut count = 0;
         let cell_data = self
             .iter(CachedDataItemIndex::CellData)
             .filter_map(|(key, value)| match (key, value) {
                 (CachedDataItemKey::CellData { cell }, CachedDataItemValue::CellData { value }) => {
-                    count += 1;
                     Some(CachedDataItem::CellData {
                         cell: *cell,
                         value: value.clone(),
@@ -684,7 +728,7 @@ impl<B: BackingStorage> TaskGuard for TaskGuardImpl<'_, B> {
                 .push_batch_insert(self.task_id, cell_data);
             self.task
                 .persistance_state_mut()
-                .add_persisting_items(count);
+                .add_persisting_item(self.backend.current_snapshot_id());
         }
     }
 }
@@ -51,6 +51,7 @@ impl UpdateCellOperation {
         InvalidateOperation::run(
             dependent,
             TaskDirtyCause::CellChange {
+                task_id,
                 value_type: cell.type_id,
             },
             ctx,
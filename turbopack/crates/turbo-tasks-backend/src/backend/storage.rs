@@ -19,6 +19,7 @@ use crate::{
 
 const META_UNRESTORED: u32 = 1 << 31;
 const DATA_UNRESTORED: u32 = 1 << 30;
+const ACCESSED: u32 = 1 << 29;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TaskDataCategory {
@@ -82,12 +83,16 @@ impl Iterator for TaskDataCategoryIterator {
 
 pub struct PersistanceState {
     value: u32,
+    /// The id of the snapshot that persists the last modification of the task. Zero when the task
+    /// wasn't modified since it was restored.
+    snapshot_id: u32,
 }
 
 impl Default for PersistanceState {
     fn default() -> Self {
         Self {
             value: META_UNRESTORED | DATA_UNRESTORED,
+            snapshot_id: 0,
         }
     }
 }
@@ -97,26 +102,38 @@ impl PersistanceState {
         self.value &= !category.flag();
     }
 
-    pub fn add_persisting_item(&mut self) {
-        // TODO add when we need to track unpersisted items
-        // self.value += 1;
+    /// Marks the data as evicted, it will be restored from the backing storage on next access.
+    pub fn set_unrestored(&mut self, category: TaskDataCategory) {
+        self.value |= category.flag();
     }
 
-    pub fn add_persisting_items(&mut self, _count: u32) {
-        // TODO add when we need to track unpersisted items
-        // self.value += count;
+    /// Records that a modification of the task was logged and will be persisted by the snapshot
+    /// with the given id.
+    pub fn add_persisting_item(&mut self, snapshot_id: u32) {
+        self.snapshot_id = snapshot_id;
     }
 
-    // TODO remove when we need to track unpersisted items
-    #[allow(dead_code)]
-    pub fn finish_persisting_items(&mut self, _count: u32) {
-        // TODO add when we need to track unpersisted items
-        // self.value -= count;
+    /// Returns true when all modifications of the task are persisted, given the id of the last
+    /// snapshot that was written to the backing storage.
+    pub fn is_persisted(&self, persisted_snapshot_id: u32) -> bool {
+        self.snapshot_id <= persisted_snapshot_id
     }
 
     pub fn is_restored(&self, category: TaskDataCategory) -> bool {
         (self.value & category.flag()) == 0
     }
+
+    /// Marks the task as recently accessed, so it's not evicted by the next eviction pass.
+    pub fn set_accessed(&mut self) {
+        self.value |= ACCESSED;
+    }
+
+    /// Returns whether the task was accessed since the last call and resets the flag.
+    pub fn take_accessed(&mut self) -> bool {
+        let accessed = (self.value & ACCESSED) != 0;
+        self.value &= !ACCESSED;
+        accessed
+    }
 }
 
 const INDEX_THRESHOLD: usize = 1024;
@@ -272,6 +289,24 @@ where
         matches!(self, InnerStorage::Indexed { .. })
     }
 
+    /// Removes all items for which `f` returns false and releases the unused capacity.
+    pub fn retain_and_shrink(&mut self, mut f: impl FnMut(&T::Key, &T::Value) -> bool) {
+        match self {
+            InnerStorage::Plain { map, .. } => {
+                map.retain(|key, value| f(key, &*value));
+                map.shrink_to_fit();
+            }
+            InnerStorage::Indexed { map, .. } => {
+                map.retain(|_, map| {
+                    map.retain(|key, value| f(key, &*value));
+                    map.shrink_to_fit();
+                    !map.is_empty()
+                });
+                map.shrink_to_fit();
+            }
+        }
+    }
+
     pub fn iter(
         &self,
         index: <T::Key as Indexed>::Index,
@@ -381,6 +416,26 @@ where
         }
     }
 
+    /// The number of shards of the storage, see [`Storage::for_each_in_shard_mut`].
+    pub fn shard_amount(&self) -> usize {
+        self.map.shards().len()
+    }
+
+    /// Calls `f` for all entries of a single shard. The shard is write locked while `f` runs, so
+    /// the entries can't be accessed concurrently, e. g. by an operation that holds a
+    /// [`StorageWriteGuard`].
+    pub fn for_each_in_shard_mut(&self, shard: usize, mut f: impl FnMut(&K, &mut InnerStorage<T>)) {
+        let guard = self.map.shards()[shard].write();
+        // SAFETY: The buckets are only used while the shard is locked and the table is not
+        // modified while iterating.
+        unsafe {
+            for bucket in guard.iter() {
+                let (key, value) = bucket.as_mut();
+                f(key, value.get_mut());
+            }
+        }
+    }
+
     pub fn access_pair_mut(
         &self,
         key1: K,
@@ -475,6 +475,11 @@ impl CachedDataItem {
         }
     }
 
+    /// Optional items can be dropped from the persistent cache. They are recomputed on demand.
+    pub fn is_optional(&self) -> bool {
+        matches!(self, CachedDataItem::CellData { .. })
+    }
+
     pub fn new_scheduled(description: impl Fn() -> String + Sync + Send + 'static) -> Self {
         CachedDataItem::InProgress {
             value: InProgressState::Scheduled {
@@ -663,6 +668,7 @@ pub mod indicies {
     pub const OutputDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
     pub const CellDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
     pub const CollectibleDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
+    pub const CollectiblesDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
     pub const OutdatedOutputDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
     pub const OutdatedCellDependency: CachedDataItemIndex = CachedDataItemIndex::Dependencies;
     pub const OutdatedCollectiblesDependency: CachedDataItemIndex =
@@ -6,6 +6,7 @@ pub struct ByKeySpace<T> {
     task_data: T,
     forward_task_cache: T,
     reverse_task_cache: T,
+    task_access: T,
 }
 
 impl<T> ByKeySpace<T> {
@@ -16,6 +17,7 @@ impl<T> ByKeySpace<T> {
             task_data: factory(KeySpace::TaskData),
             forward_task_cache: factory(KeySpace::ForwardTaskCache),
             reverse_task_cache: factory(KeySpace::ReverseTaskCache),
+            task_access: factory(KeySpace::TaskAccess),
         }
     }
 
@@ -26,6 +28,7 @@ impl<T> ByKeySpace<T> {
             KeySpace::TaskData => &self.task_data,
             KeySpace::ForwardTaskCache => &self.forward_task_cache,
             KeySpace::ReverseTaskCache => &self.reverse_task_cache,
+            KeySpace::TaskAccess => &self.task_access,
         }
     }
 
@@ -36,6 +39,7 @@ impl<T> ByKeySpace<T> {
             KeySpace::TaskData => &mut self.task_data,
             KeySpace::ForwardTaskCache => &mut self.forward_task_cache,
             KeySpace::ReverseTaskCache => &mut self.reverse_task_cache,
+            KeySpace::TaskAccess => &mut self.task_access,
         }
     }
 
@@ -46,6 +50,7 @@ impl<T> ByKeySpace<T> {
             (KeySpace::TaskData, &self.task_data),
             (KeySpace::ForwardTaskCache, &self.forward_task_cache),
             (KeySpace::ReverseTaskCache, &self.reverse_task_cache),
+            (KeySpace::TaskAccess, &self.task_access),
         ]
         .into_iter()
     }
@@ -7,6 +7,8 @@ use std::{
 
 use anyhow::Result;
 
+use crate::database::manifest::remove_manifest;
+
 /// Specifies many databases that have a different version than the current one are retained.
 /// For example if MAX_OTHER_DB_VERSIONS is 2, there can be at most 3 databases in the directory,
 /// the current one and two older/newer ones.
@@ -86,6 +88,7 @@ pub fn handle_db_versioning(base_path: &Path) -> Result<PathBuf> {
                 old_dbs.sort_by_key(|(_, age)| *age);
                 for (p, _) in old_dbs.into_iter().skip(MAX_OTHER_DB_VERSIONS) {
                     let _ = remove_dir_all(p);
+                    let _ = remove_manifest(p);
                 }
             }
         }
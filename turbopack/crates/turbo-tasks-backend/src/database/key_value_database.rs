@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 use crate::database::write_batch::{
     ConcurrentWriteBatch, SerialWriteBatch, UnimplementedWriteBatch, WriteBatch,
@@ -11,8 +11,14 @@ pub enum KeySpace {
     TaskData,
     ForwardTaskCache,
     ReverseTaskCache,
+    /// The session in which a task was last restored or updated. Used to find cold tasks when the
+    /// cache exceeds its size limit.
+    TaskAccess,
 }
 
+/// Visits a key and value pair of a key space, see [KeyValueDatabase::for_each_entry].
+pub type EntryVisitor<'l> = dyn FnMut(&[u8], &[u8]) -> Result<()> + 'l;
+
 pub trait KeyValueDatabase {
     type ReadTransaction<'l>
     where
@@ -52,6 +58,18 @@ pub trait KeyValueDatabase {
         &self,
     ) -> Result<WriteBatch<'_, Self::SerialWriteBatch<'_>, Self::ConcurrentWriteBatch<'_>>>;
 
+    /// Returns the size of the database on disk. `None` if the database can't tell, which disables
+    /// the cache size limit.
+    fn disk_size(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Calls `f` for every committed entry of a key space. Only needed when `disk_size` is
+    /// implemented.
+    fn for_each_entry(&self, _key_space: KeySpace, _f: &mut EntryVisitor<'_>) -> Result<()> {
+        bail!("Iterating a key space is not supported by this database")
+    }
+
     fn shutdown(&self) -> Result<()> {
         Ok(())
     }
@@ -20,6 +20,7 @@ pub struct LmbdKeyValueDatabase {
     meta_db: Database,
     forward_task_cache_db: Database,
     reverse_task_cache_db: Database,
+    task_access_db: Database,
 }
 
 impl LmbdKeyValueDatabase {
@@ -38,7 +39,7 @@ impl LmbdKeyValueDatabase {
                     | EnvironmentFlags::NO_TLS,
             )
             .set_max_readers((available_parallelism().map_or(16, |v| v.get()) * 8) as u32)
-            .set_max_dbs(5)
+            .set_max_dbs(6)
             .set_map_size(MAP_SIZE)
             .open(path)?;
         let infra_db = env.create_db(Some("infra"), DatabaseFlags::INTEGER_KEY)?;
@@ -48,6 +49,7 @@ impl LmbdKeyValueDatabase {
             env.create_db(Some("forward_task_cache"), DatabaseFlags::empty())?;
         let reverse_task_cache_db =
             env.create_db(Some("reverse_task_cache"), DatabaseFlags::INTEGER_KEY)?;
+        let task_access_db = env.create_db(Some("task_access"), DatabaseFlags::INTEGER_KEY)?;
         Ok(LmbdKeyValueDatabase {
             env,
             infra_db,
@@ -55,6 +57,7 @@ impl LmbdKeyValueDatabase {
             meta_db,
             forward_task_cache_db,
             reverse_task_cache_db,
+            task_access_db,
         })
     }
 
@@ -65,6 +68,7 @@ impl LmbdKeyValueDatabase {
             KeySpace::TaskData => self.data_db,
             KeySpace::ForwardTaskCache => self.forward_task_cache_db,
             KeySpace::ReverseTaskCache => self.reverse_task_cache_db,
+            KeySpace::TaskAccess => self.task_access_db,
         }
     }
 }
@@ -99,6 +103,7 @@ impl KeyValueDatabase for LmbdKeyValueDatabase {
             KeySpace::TaskData => self.data_db,
             KeySpace::ForwardTaskCache => self.forward_task_cache_db,
             KeySpace::ReverseTaskCache => self.reverse_task_cache_db,
+            KeySpace::TaskAccess => self.task_access_db,
         };
 
         let value = match extended_key::get(transaction, db, key) {
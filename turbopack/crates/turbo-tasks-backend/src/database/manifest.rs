@@ -0,0 +1,116 @@
+use std::{
+    env::consts::{ARCH, OS},
+    ffi::OsString,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+/// Describes the environment that created a persistent cache. A cache might be restored from
+/// another machine (e. g. on CI), so it's only reused when the manifest matches.
+#[derive(Debug, PartialEq, Eq)]
+struct Manifest {
+    /// The database version, see `handle_db_versioning`.
+    version: String,
+    /// The rust toolchain that compiled turbo-tasks.
+    toolchain: String,
+    /// The operating system and architecture.
+    platform: String,
+    /// Relocatable paths are stored relative to a declared project root.
+    relocatable: bool,
+}
+
+impl Manifest {
+    fn current(path: &Path, relocatable: bool) -> Self {
+        Self {
+            version: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            toolchain: env!("TURBO_ENGINE_TOOLCHAIN").to_string(),
+            platform: format!("{OS}-{ARCH}"),
+            relocatable,
+        }
+    }
+
+    fn serialize(&self) -> String {
+        format!(
+            "version: {}\ntoolchain: {}\nplatform: {}\nrelocatable: {}\n",
+            self.version, self.toolchain, self.platform, self.relocatable
+        )
+    }
+
+    fn parse(content: &str) -> Option<Self> {
+        let mut version = None;
+        let mut toolchain = None;
+        let mut platform = None;
+        let mut relocatable = None;
+        for line in content.lines() {
+            let (key, value) = line.split_once(": ")?;
+            match key {
+                "version" => version = Some(value.to_string()),
+                "toolchain" => toolchain = Some(value.to_string()),
+                "platform" => platform = Some(value.to_string()),
+                "relocatable" => relocatable = Some(value.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(Self {
+            version: version?,
+            toolchain: toolchain?,
+            platform: platform?,
+            relocatable: relocatable?,
+        })
+    }
+}
+
+/// The manifest is stored next to the database directory, since the database owns all files in
+/// its directory.
+fn manifest_path(path: &Path) -> PathBuf {
+    let mut manifest_path = OsString::from(path);
+    manifest_path.push(".manifest");
+    PathBuf::from(manifest_path)
+}
+
+/// Validates the manifest of the database at `path` before it's reused. When the cache was
+/// created in a different environment, it's removed. A new manifest is written for an empty
+/// cache.
+pub fn handle_manifest(path: &Path, relocatable: bool) -> Result<()> {
+    let manifest_path = manifest_path(path);
+    let expected = Manifest::current(path, relocatable);
+    let mismatch = match read_to_string(&manifest_path) {
+        Ok(content) => match Manifest::parse(&content) {
+            Some(manifest) if manifest == expected => return Ok(()),
+            Some(manifest) => Some(format!("expected {expected:?}, found {manifest:?}")),
+            None => Some("the manifest is invalid".to_string()),
+        },
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let is_empty = match read_dir(path) {
+                Ok(mut entries) => entries.next().is_none(),
+                Err(err) if err.kind() == ErrorKind::NotFound => true,
+                Err(err) => return Err(err).context("Unable to read the database directory"),
+            };
+            (!is_empty).then(|| "the manifest is missing".to_string())
+        }
+        Err(err) => return Err(err).context("Unable to read the database manifest"),
+    };
+    if let Some(mismatch) = mismatch {
+        println!(
+            "WARNING: The persistent cache was created in a different environment and is \
+             discarded: {mismatch}"
+        );
+        remove_dir_all(path).context("Unable to remove the database directory")?;
+    }
+    if let Some(parent) = manifest_path.parent() {
+        create_dir_all(parent).context("Unable to create the cache directory")?;
+    }
+    write(&manifest_path, expected.serialize()).context("Unable to write the database manifest")?;
+    Ok(())
+}
+
+/// Removes the manifest of a database that is removed.
+pub fn remove_manifest(path: &Path) -> Result<()> {
+    remove_file(manifest_path(path)).context("Unable to remove the database manifest")
+}
@@ -6,6 +6,7 @@ pub mod fresh_db_optimization;
 pub mod key_value_database;
 #[cfg(feature = "lmdb")]
 pub mod lmdb;
+pub mod manifest;
 pub mod noop_kv;
 #[cfg(feature = "lmdb")]
 pub mod read_transaction_cache;
@@ -88,6 +88,7 @@ impl<T: KeyValueDatabase> StartupCacheLayer<T> {
                         KeySpace::TaskData => 1024 * 1024,
                         KeySpace::ForwardTaskCache => 1024 * 1024,
                         KeySpace::ReverseTaskCache => 1024 * 1024,
+                        KeySpace::TaskAccess => 1024 * 1024,
                     },
                     Default::default(),
                 )
@@ -316,6 +317,7 @@ fn write_key_value_pair(
         KeySpace::TaskData => 2,
         KeySpace::ForwardTaskCache => 3,
         KeySpace::ReverseTaskCache => 4,
+        KeySpace::TaskAccess => 5,
     })?;
     let key_len = key.len();
     size_buffer.copy_from_slice(&(key_len as u32).to_be_bytes());
@@ -338,6 +340,7 @@ fn read_key_value_pair<'l>(
         2 => KeySpace::TaskData,
         3 => KeySpace::ForwardTaskCache,
         4 => KeySpace::ReverseTaskCache,
+        5 => KeySpace::TaskAccess,
         _ => return Err(anyhow::anyhow!("Invalid key space")),
     };
     *pos += 1;
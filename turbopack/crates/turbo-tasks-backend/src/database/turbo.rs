@@ -1,16 +1,19 @@
 use std::{
     borrow::Cow,
+    env,
     path::PathBuf,
     sync::Arc,
     thread::{spawn, JoinHandle},
 };
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use parking_lot::Mutex;
-use turbo_persistence::{ArcSlice, TurboPersistence};
+use turbo_persistence::{
+    ArcSlice, BackgroundCompaction, BackgroundCompactionConfig, DbConfig, TurboPersistence,
+};
 
 use crate::database::{
-    key_value_database::{KeySpace, KeyValueDatabase},
+    key_value_database::{EntryVisitor, KeySpace, KeyValueDatabase},
     write_batch::{BaseWriteBatch, ConcurrentWriteBatch, WriteBatch},
 };
 
@@ -20,17 +23,70 @@ const COMPACT_MAX_MERGE_SEQUENCE: usize = 8;
 pub struct TurboKeyValueDatabase {
     db: Arc<TurboPersistence>,
     compact_join_handle: Mutex<Option<JoinHandle<Result<()>>>>,
+    /// Set when the database is compacted incrementally in the background instead of after every
+    /// commit.
+    background_compaction: Mutex<Option<BackgroundCompaction>>,
 }
 
 impl TurboKeyValueDatabase {
     pub fn new(path: PathBuf) -> Result<Self> {
-        let db = Arc::new(TurboPersistence::open(path.to_path_buf())?);
+        // Pass `TURBO_ENGINE_COMPRESSION=zstd` (or `zstd:<level>`) to trade CPU time for a
+        // smaller database, e. g. when the cache is uploaded and downloaded on CI.
+        let mut config = DbConfig::default();
+        if let Ok(codec) = env::var("TURBO_ENGINE_COMPRESSION") {
+            config.compression = codec
+                .parse()
+                .context("Invalid TURBO_ENGINE_COMPRESSION value")?;
+        }
+        let db = match TurboPersistence::open_with_config(path.clone(), config.clone()) {
+            Ok(db) => db,
+            Err(err) => {
+                // A crash during a commit might leave the database in an inconsistent state. Roll
+                // back to the last consistent commit instead of losing the whole cache. Other
+                // errors (e. g. I/O or permission errors) are reported as they are, since a repair
+                // would throw away valid data.
+                let is_corrupted =
+                    TurboPersistence::verify(&path).is_ok_and(|report| report.is_corrupted());
+                if !is_corrupted {
+                    return Err(err);
+                }
+                let report = TurboPersistence::repair(&path).with_context(|| {
+                    format!("Repairing the database failed after opening failed: {err:?}")
+                })?;
+                println!(
+                    "Persistent cache was repaired after opening failed ({err}). Rolled back to \
+                     sequence number {}.",
+                    report.sequence_number
+                );
+                for file in report.removed_files.iter() {
+                    println!("  removed {}", file.display());
+                }
+                TurboPersistence::open_with_config(path, config)?
+            }
+        };
+        let db = Arc::new(db);
+        // Pass `TURBO_ENGINE_BACKGROUND_COMPACTION=1` to compact incrementally in a background
+        // thread with a limited I/O budget instead of compacting after every commit.
+        let background_compaction = if env::var("TURBO_ENGINE_BACKGROUND_COMPACTION")
+            .ok()
+            .is_some()
+        {
+            Some(db.start_background_compaction(BackgroundCompactionConfig {
+                max_coverage: COMPACT_MAX_COVERAGE,
+                max_merge_sequence: COMPACT_MAX_MERGE_SEQUENCE,
+                ..Default::default()
+            })?)
+        } else {
+            None
+        };
+        let start_compaction = background_compaction.is_none() && !db.is_empty();
         let mut this = Self {
             db: db.clone(),
             compact_join_handle: Mutex::new(None),
+            background_compaction: Mutex::new(background_compaction),
         };
         // start compaction in background if the database is not empty
-        if !db.is_empty() {
+        if start_compaction {
             let handle =
                 spawn(move || db.compact(COMPACT_MAX_COVERAGE, COMPACT_MAX_MERGE_SEQUENCE));
             this.compact_join_handle.get_mut().replace(handle);
@@ -90,23 +146,42 @@ impl KeyValueDatabase for TurboKeyValueDatabase {
             batch: self.db.write_batch()?,
             db: &self.db,
             compact_join_handle: &self.compact_join_handle,
+            background_compaction: self.background_compaction.lock().is_some(),
         }))
     }
 
+    fn disk_size(&self) -> Result<Option<u64>> {
+        Ok(Some(self.db.snapshot().disk_size()?))
+    }
+
+    fn for_each_entry(&self, key_space: KeySpace, f: &mut EntryVisitor<'_>) -> Result<()> {
+        let snapshot = self.db.snapshot();
+        for entry in snapshot.iter_family(key_space as usize)? {
+            let (key, value) = entry?;
+            f(&key, &value)?;
+        }
+        Ok(())
+    }
+
     fn shutdown(&self) -> Result<()> {
         // Wait for the compaction to finish
         if let Some(join_handle) = self.compact_join_handle.lock().take() {
             join_handle.join().unwrap()?;
         }
+        // Stop the background compaction, this cancels a running compaction
+        if let Some(background_compaction) = self.background_compaction.lock().take() {
+            background_compaction.stop()?;
+        }
         // Shutdown the database
         self.db.shutdown()
     }
 }
 
 pub struct TurboWriteBatch<'a> {
-    batch: turbo_persistence::WriteBatch<Vec<u8>, 5>,
+    batch: turbo_persistence::WriteBatch<Vec<u8>, 6>,
     db: &'a Arc<TurboPersistence>,
     compact_join_handle: &'a Mutex<Option<JoinHandle<Result<()>>>>,
+    background_compaction: bool,
 }
 
 impl<'a> BaseWriteBatch<'a> for TurboWriteBatch<'a> {
@@ -127,6 +202,11 @@ impl<'a> BaseWriteBatch<'a> for TurboWriteBatch<'a> {
         // Commit the write batch
         self.db.commit_write_batch(self.batch)?;
 
+        if self.background_compaction {
+            // The background compaction picks up the new files
+            return Ok(());
+        }
+
         // Start a new compaction in the background
         let db = self.db.clone();
         let handle = spawn(move || db.compact(COMPACT_MAX_COVERAGE, COMPACT_MAX_MERGE_SEQUENCE));
@@ -2,15 +2,24 @@ use std::{
     borrow::{Borrow, Cow},
     cmp::max,
     collections::hash_map::Entry,
-    sync::Arc,
+    hash::BuildHasherDefault,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use anyhow::{anyhow, Context, Result};
+use dashmap::DashSet;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 use serde::{ser::SerializeSeq, Serialize};
 use tracing::Span;
-use turbo_tasks::{backend::CachedTaskType, turbo_tasks_scope, KeyValuePair, SessionId, TaskId};
+use turbo_rcstr::RcStr;
+use turbo_tasks::{
+    backend::CachedTaskType, relocation::with_relocation_root, turbo_tasks_scope, KeyValuePair,
+    SessionId, TaskId,
+};
 
 use crate::{
     backend::{AnyOperation, TaskDataCategory},
@@ -39,6 +48,11 @@ const META_KEY_OPERATIONS: u32 = 0;
 const META_KEY_NEXT_FREE_TASK_ID: u32 = 1;
 const META_KEY_SESSION_ID: u32 = 2;
 
+/// When the cache size limit is exceeded, the data of cold tasks is evicted until the database is
+/// estimated to be below this percentage of the limit. This avoids evicting again in the next
+/// session.
+const EVICTION_TARGET_PERCENT: u64 = 80;
+
 struct IntKey([u8; 4]);
 
 impl IntKey {
@@ -58,13 +72,65 @@ fn as_u32(bytes: impl Borrow<[u8]>) -> Result<u32> {
     Ok(n)
 }
 
+/// Tasks that were restored or updated in the current session.
+type AccessedTasks = DashSet<TaskId, BuildHasherDefault<FxHasher>>;
+
 pub struct KeyValueDatabaseBackingStorage<T: KeyValueDatabase> {
     database: T,
+    /// The maximum size of the database on disk. `None` disables the limit.
+    max_cache_size: Option<u64>,
+    /// Tasks that were accessed since the last snapshot. Only tracked when there is a
+    /// `max_cache_size`.
+    accessed_tasks: AccessedTasks,
+    /// Set when cold tasks were evicted. This happens at most once per session, since the size on
+    /// disk only shrinks after the compaction has merged the affected files.
+    evicted: AtomicBool,
+    /// Paths inside of this root are stored relative to it, so the cache can be restored in a
+    /// different directory. See [`with_relocation_root`].
+    relocation_root: Option<RcStr>,
 }
 
 impl<T: KeyValueDatabase> KeyValueDatabaseBackingStorage<T> {
     pub fn new(database: T) -> Self {
-        Self { database }
+        Self {
+            database,
+            max_cache_size: None,
+            accessed_tasks: Default::default(),
+            evicted: AtomicBool::new(false),
+            relocation_root: None,
+        }
+    }
+
+    /// Limits the size of the database on disk. When a snapshot is saved and the database is
+    /// larger, the cell data of the least recently accessed tasks is evicted. Evicted cells are
+    /// recomputed when they are read again. Only databases that implement
+    /// [`KeyValueDatabase::disk_size`] support the limit.
+    ///
+    /// There is no limit by default. Enforcing it scans the whole database while the snapshot is
+    /// saved, so it should only be enabled when the cache size matters more than snapshot
+    /// latency.
+    pub fn with_max_cache_size(mut self, max_cache_size: Option<u64>) -> Self {
+        self.max_cache_size = max_cache_size;
+        self
+    }
+
+    /// Makes the cache relocatable by storing relocatable paths (see [`turbo_tasks::relocation`])
+    /// inside of `relocation_root` relative to it. They are rebased to the current root when the
+    /// cache is read. The same root must be passed for every session, the database manifest
+    /// ensures that.
+    pub fn with_relocation_root(mut self, relocation_root: Option<RcStr>) -> Self {
+        self.relocation_root = relocation_root;
+        self
+    }
+
+    /// Runs `f` with the relocation root of the cache. All (de)serialization must be wrapped in
+    /// that on the thread that does it.
+    fn relocate<R>(&self, f: impl FnOnce() -> R) -> R {
+        with_relocation_root(self.relocation_root.as_ref(), f)
+    }
+
+    fn accessed_tasks(&self) -> Option<&AccessedTasks> {
+        self.max_cache_size.map(|_| &self.accessed_tasks)
     }
 
     fn with_tx<R>(
@@ -126,7 +192,7 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
             let operations = POT_CONFIG.deserialize(operations.borrow())?;
             Ok(operations)
         }
-        get(&self.database).unwrap_or_default()
+        self.relocate(|| get(&self.database)).unwrap_or_default()
     }
 
     fn save_snapshot(
@@ -138,6 +204,8 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
         data_updates: Vec<ChunkedVec<CachedDataUpdate>>,
     ) -> Result<()> {
         let _span = tracing::trace_span!("save snapshot", session_id = ?session_id, operations = operations.len());
+        let accessed_tasks = self.accessed_tasks();
+        let relocation_root = self.relocation_root.as_ref();
         let mut batch = self.database.write_batch()?;
         let mut task_meta_items_result = Ok(Vec::new());
         let mut task_data_items_result = Ok(Vec::new());
@@ -152,6 +220,8 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                             &self.database,
                             KeySpace::TaskMeta,
                             meta_updates,
+                            accessed_tasks,
+                            relocation_root,
                             Some(batch),
                         );
                     });
@@ -161,6 +231,8 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                             &self.database,
                             KeySpace::TaskData,
                             data_updates,
+                            accessed_tasks,
+                            relocation_root,
                             Some(batch),
                         );
                     });
@@ -186,7 +258,12 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                                 let mut task_type_bytes = Vec::new();
                                 for (task_type, task_id) in updates {
                                     let task_id: u32 = *task_id;
-                                    serialize_task_type(&task_type, &mut task_type_bytes, task_id)?;
+                                    serialize_task_type(
+                                        &task_type,
+                                        &mut task_type_bytes,
+                                        task_id,
+                                        relocation_root,
+                                    )?;
 
                                     batch
                                         .put(
@@ -228,12 +305,14 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                         next_task_id = next_task_id.max(result);
                     }
 
-                    save_infra::<T::SerialWriteBatch<'_>, T::ConcurrentWriteBatch<'_>>(
-                        &mut WriteBatchRef::concurrent(batch),
-                        next_task_id,
-                        session_id,
-                        operations,
-                    )?;
+                    self.relocate(|| {
+                        save_infra::<T::SerialWriteBatch<'_>, T::ConcurrentWriteBatch<'_>>(
+                            &mut WriteBatchRef::concurrent(batch),
+                            next_task_id,
+                            session_id,
+                            operations,
+                        )
+                    })?;
                     anyhow::Ok(())
                 })?;
 
@@ -247,6 +326,8 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                             &self.database,
                             KeySpace::TaskMeta,
                             meta_updates,
+                            accessed_tasks,
+                            relocation_root,
                             None::<&T::ConcurrentWriteBatch<'_>>,
                         );
                     });
@@ -255,6 +336,8 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                             &self.database,
                             KeySpace::TaskData,
                             data_updates,
+                            accessed_tasks,
+                            relocation_root,
                             None::<&T::ConcurrentWriteBatch<'_>>,
                         );
                     });
@@ -274,7 +357,12 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                         let mut task_type_bytes = Vec::new();
                         for (task_type, task_id) in task_cache_updates.into_iter().flatten() {
                             let task_id = *task_id;
-                            serialize_task_type(&task_type, &mut task_type_bytes, task_id)?;
+                            serialize_task_type(
+                                &task_type,
+                                &mut task_type_bytes,
+                                task_id,
+                                relocation_root,
+                            )?;
 
                             batch
                                 .put(
@@ -298,12 +386,14 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
                         }
                     }
 
-                    save_infra::<T::SerialWriteBatch<'_>, T::ConcurrentWriteBatch<'_>>(
-                        &mut WriteBatchRef::serial(batch),
-                        next_task_id,
-                        session_id,
-                        operations,
-                    )?;
+                    self.relocate(|| {
+                        save_infra::<T::SerialWriteBatch<'_>, T::ConcurrentWriteBatch<'_>>(
+                            &mut WriteBatchRef::serial(batch),
+                            next_task_id,
+                            session_id,
+                            operations,
+                        )
+                    })?;
                     anyhow::Ok(())
                 })?;
 
@@ -334,6 +424,19 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
             }
         }
 
+        if let Some(max_cache_size) = self.max_cache_size {
+            self.relocate(|| {
+                update_task_access(
+                    &self.database,
+                    &mut batch,
+                    &self.accessed_tasks,
+                    &self.evicted,
+                    session_id,
+                    max_cache_size,
+                )
+            })?;
+        }
+
         {
             let _span = tracing::trace_span!("commit").entered();
             batch
@@ -371,7 +474,9 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
             return None;
         }
         let id = self
-            .with_tx(tx, |tx| lookup(&self.database, tx, task_type))
+            .with_tx(tx, |tx| {
+                self.relocate(|| lookup(&self.database, tx, task_type))
+            })
             .inspect_err(|err| println!("Looking up task id for {task_type:?} failed: {err:?}"))
             .ok()??;
         Some(id)
@@ -398,7 +503,9 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
             Ok(Some(POT_CONFIG.deserialize(bytes.borrow())?))
         }
         let result = self
-            .with_tx(tx, |tx| lookup(&self.database, tx, task_id))
+            .with_tx(tx, |tx| {
+                self.relocate(|| lookup(&self.database, tx, task_id))
+            })
             .inspect_err(|err| println!("Looking up task type for {task_id} failed: {err:?}"))
             .ok()??;
         Some(result)
@@ -431,9 +538,18 @@ impl<T: KeyValueDatabase + Send + Sync + 'static> BackingStorage
             let result: Vec<CachedDataItem> = POT_CONFIG.deserialize(bytes.borrow())?;
             Ok(result)
         }
-        self.with_tx(tx, |tx| lookup(&self.database, tx, task_id, category))
+        let result = self
+            .with_tx(tx, |tx| {
+                self.relocate(|| lookup(&self.database, tx, task_id, category))
+            })
             .inspect_err(|err| println!("Looking up data for {task_id} failed: {err:?}"))
-            .unwrap_or_default()
+            .unwrap_or_default();
+        if !result.is_empty() {
+            if let Some(accessed_tasks) = self.accessed_tasks() {
+                accessed_tasks.insert(task_id);
+            }
+        }
+        result
     }
 
     fn shutdown(&self) -> Result<()> {
@@ -505,15 +621,121 @@ where
     Ok(())
 }
 
+/// Stores the current session as access generation for all tasks that were accessed since the
+/// last snapshot. When the database exceeds `max_cache_size`, the optional data (cell contents) of
+/// the least recently accessed tasks is dropped. Reading such a cell later recomputes the task.
+/// Everything else is kept, since the task graph must stay consistent.
+fn update_task_access<'a>(
+    database: &impl KeyValueDatabase,
+    batch: &mut impl SerialWriteBatch<'a>,
+    accessed_tasks: &AccessedTasks,
+    evicted: &AtomicBool,
+    session_id: SessionId,
+    max_cache_size: u64,
+) -> Result<()> {
+    let mut accessed = FxHashSet::default();
+    accessed_tasks.retain(|task| {
+        accessed.insert(*task);
+        false
+    });
+    {
+        let _span = tracing::trace_span!("update task access", tasks = accessed.len()).entered();
+        for task in accessed.iter() {
+            batch
+                .put(
+                    KeySpace::TaskAccess,
+                    Cow::Borrowed(IntKey::new(**task).as_ref()),
+                    Cow::Borrowed(&session_id.to_le_bytes()),
+                )
+                .with_context(|| anyhow!("Unable to write access generation for {task}"))?;
+        }
+    }
+
+    if evicted.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let Some(disk_size) = database.disk_size()? else {
+        return Ok(());
+    };
+    if disk_size <= max_cache_size {
+        return Ok(());
+    }
+    evicted.store(true, Ordering::Relaxed);
+    let span = tracing::trace_span!(
+        "evict cold tasks",
+        disk_size,
+        max_cache_size,
+        evicted_tasks = tracing::field::Empty
+    )
+    .entered();
+
+    // Collect all tasks that were not accessed in this session, least recently accessed first
+    let mut cold_tasks = Vec::new();
+    database.for_each_entry(KeySpace::TaskAccess, &mut |key, value| {
+        let task = TaskId::from(as_u32(key)?);
+        let generation = as_u32(value)?;
+        if generation < *session_id && !accessed.contains(&task) {
+            cold_tasks.push((generation, task));
+        }
+        Ok(())
+    })?;
+    cold_tasks.sort_unstable();
+
+    // The removed bytes are only an estimate. The space is reclaimed when the compaction merges
+    // the files that contain the old values.
+    let bytes_to_free = disk_size - max_cache_size * EVICTION_TARGET_PERCENT / 100;
+    let mut freed_bytes = 0;
+    let mut evicted_tasks = 0;
+    let tx = database.begin_read_transaction()?;
+    for (_, task) in cold_tasks {
+        if freed_bytes >= bytes_to_free {
+            break;
+        }
+        let key = IntKey::new(*task);
+        // Evicted tasks are no longer tracked until they are accessed again
+        batch
+            .delete(KeySpace::TaskAccess, Cow::Borrowed(key.as_ref()))
+            .with_context(|| anyhow!("Unable to delete access generation for {task}"))?;
+        let Some(old_data) = database.get(&tx, KeySpace::TaskData, key.as_ref())? else {
+            continue;
+        };
+        let old_data: &[u8] = old_data.borrow();
+        let mut data: Vec<CachedDataItem> = POT_CONFIG
+            .deserialize(old_data)
+            .with_context(|| anyhow!("Unable to deserialize data of {task}"))?;
+        let len = data.len();
+        data.retain(|item| !item.is_optional());
+        if data.len() == len {
+            continue;
+        }
+        let value = POT_CONFIG
+            .serialize(&data)
+            .with_context(|| anyhow!("Unable to serialize data items for {task}"))?;
+        freed_bytes += old_data.len().saturating_sub(value.len()) as u64;
+        batch
+            .put(
+                KeySpace::TaskData,
+                Cow::Borrowed(key.as_ref()),
+                value.into(),
+            )
+            .with_context(|| anyhow!("Unable to write data items for {task}"))?;
+        evicted_tasks += 1;
+    }
+    span.record("evicted_tasks", evicted_tasks);
+    Ok(())
+}
+
 fn serialize_task_type(
     task_type: &Arc<CachedTaskType>,
     mut task_type_bytes: &mut Vec<u8>,
     task_id: u32,
+    relocation_root: Option<&RcStr>,
 ) -> Result<()> {
     task_type_bytes.clear();
-    POT_CONFIG
-        .serialize_into(&**task_type, &mut task_type_bytes)
-        .with_context(|| anyhow!("Unable to serialize task {task_id} cache key {task_type:?}"))?;
+    with_relocation_root(relocation_root, || {
+        POT_CONFIG.serialize_into(&**task_type, &mut task_type_bytes)
+    })
+    .with_context(|| anyhow!("Unable to serialize task {task_id} cache key {task_type:?}"))?;
     #[cfg(feature = "verify_serialization")]
     {
         let deserialize: Result<CachedTaskType, _> = serde_path_to_error::deserialize(
@@ -535,6 +757,8 @@ fn process_task_data<'a, B: ConcurrentWriteBatch<'a> + Send + Sync>(
     database: &(impl KeyValueDatabase + Sync),
     key_space: KeySpace,
     updates: Vec<ChunkedVec<CachedDataUpdate>>,
+    accessed_tasks: Option<&AccessedTasks>,
+    relocation_root: Option<&RcStr>,
     batch: Option<&B>,
 ) -> Result<SerializedTasks> {
     let span = Span::current();
@@ -657,6 +881,12 @@ fn process_task_data<'a, B: ConcurrentWriteBatch<'a> + Send + Sync>(
                     span.record("after", task_updates.len());
                 }
 
+                if let Some(accessed_tasks) = accessed_tasks {
+                    for task in task_updates.keys() {
+                        accessed_tasks.insert(*task);
+                    }
+                }
+
                 let tx = database.begin_read_transaction()?;
 
                 let span = tracing::trace_span!(
@@ -678,19 +908,23 @@ fn process_task_data<'a, B: ConcurrentWriteBatch<'a> + Send + Sync>(
                     if let Some(old_data) =
                         database.get(&tx, key_space, IntKey::new(*task).as_ref())?
                     {
-                        let old_data: Vec<CachedDataItem> = match POT_CONFIG
-                            .deserialize(old_data.borrow())
-                        {
-                            Ok(d) => d,
-                            Err(_) => serde_path_to_error::deserialize(
-                                &mut pot_de_symbol_list()
-                                    .deserializer_for_slice(old_data.borrow())?,
-                            )
-                            .with_context(|| {
-                                let old_data: &[u8] = old_data.borrow();
-                                anyhow!("Unable to deserialize old value of {task}: {old_data:?}")
-                            })?,
-                        };
+                        let old_data: Vec<CachedDataItem> =
+                            with_relocation_root(relocation_root, || {
+                                anyhow::Ok(match POT_CONFIG.deserialize(old_data.borrow()) {
+                                    Ok(d) => d,
+                                    Err(_) => serde_path_to_error::deserialize(
+                                        &mut pot_de_symbol_list()
+                                            .deserializer_for_slice(old_data.borrow())?,
+                                    )
+                                    .with_context(|| {
+                                        let old_data: &[u8] = old_data.borrow();
+                                        anyhow!(
+                                            "Unable to deserialize old value of {task}: \
+                                             {old_data:?}"
+                                        )
+                                    })?,
+                                })
+                            })?;
 
                         // Reserve capacity to avoid rehashing later
                         updates.reserve(old_data.len());
@@ -707,7 +941,8 @@ fn process_task_data<'a, B: ConcurrentWriteBatch<'a> + Send + Sync>(
                     updates.retain(|_, (_, value)| value.is_some());
 
                     // Serialize new data
-                    let value = serialize(task, &mut updates)?;
+                    let value =
+                        with_relocation_root(relocation_root, || serialize(task, &mut updates))?;
 
                     if let Some(batch) = batch {
                         batch.put(
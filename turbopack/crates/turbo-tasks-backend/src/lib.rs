@@ -9,18 +9,34 @@ mod database;
 mod kv_backing_storage;
 mod utils;
 
-use std::path::Path;
+use std::{env, path::Path};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use turbo_rcstr::RcStr;
 
 pub use self::{
     backend::{BackendOptions, StorageMode, TurboTasksBackend},
     kv_backing_storage::KeyValueDatabaseBackingStorage,
 };
 use crate::database::{
-    db_versioning::handle_db_versioning, noop_kv::NoopKvDb, turbo::TurboKeyValueDatabase,
+    db_versioning::handle_db_versioning, manifest::handle_manifest, noop_kv::NoopKvDb,
+    turbo::TurboKeyValueDatabase,
 };
 
+/// Pass `TURBO_ENGINE_PROJECT_ROOT=<absolute path>` to make the persistent cache relocatable.
+/// Relocatable paths inside of the project root (e. g. the roots of disk file systems) are stored
+/// relative to it, so a cache that is restored into a different directory (e. g. on CI) can be
+/// reused.
+fn relocation_root() -> Result<Option<RcStr>> {
+    let Ok(root) = env::var("TURBO_ENGINE_PROJECT_ROOT") else {
+        return Ok(None);
+    };
+    if !Path::new(&root).is_absolute() {
+        bail!("TURBO_ENGINE_PROJECT_ROOT must be an absolute path, but is {root}");
+    }
+    Ok(Some(root.trim_end_matches(['/', '\\']).into()))
+}
+
 #[cfg(feature = "lmdb")]
 pub type LmdbBackingStorage = KeyValueDatabaseBackingStorage<
     database::read_transaction_cache::ReadTransactionCache<
@@ -41,20 +57,37 @@ pub fn lmdb_backing_storage(path: &Path) -> Result<LmdbBackingStorage> {
     };
 
     let path = handle_db_versioning(path)?;
+    let relocation_root = relocation_root()?;
+    handle_manifest(&path, relocation_root.is_some())?;
     let fresh_db = is_fresh(&path);
     let database = crate::database::lmdb::LmbdKeyValueDatabase::new(&path)?;
     let database = FreshDbOptimization::new(database, fresh_db);
     let database = StartupCacheLayer::new(database, path.join("startup.cache"), fresh_db)?;
     let database = ReadTransactionCache::new(database);
-    Ok(KeyValueDatabaseBackingStorage::new(database))
+    Ok(KeyValueDatabaseBackingStorage::new(database).with_relocation_root(relocation_root))
 }
 
 pub type TurboBackingStorage = KeyValueDatabaseBackingStorage<TurboKeyValueDatabase>;
 
 pub fn turbo_backing_storage(path: &Path) -> Result<TurboBackingStorage> {
     let path = handle_db_versioning(path)?;
+    let relocation_root = relocation_root()?;
+    handle_manifest(&path, relocation_root.is_some())?;
     let database = TurboKeyValueDatabase::new(path)?;
-    Ok(KeyValueDatabaseBackingStorage::new(database))
+    // Pass `TURBO_ENGINE_MAX_CACHE_SIZE=<bytes>` to limit the size of the cache on disk. When the
+    // limit is exceeded, the cell data of the least recently used tasks is evicted. There is no
+    // limit by default.
+    let max_cache_size = match env::var("TURBO_ENGINE_MAX_CACHE_SIZE") {
+        Ok(value) => Some(
+            value
+                .parse::<u64>()
+                .context("Invalid TURBO_ENGINE_MAX_CACHE_SIZE value")?,
+        ),
+        Err(_) => None,
+    };
+    Ok(KeyValueDatabaseBackingStorage::new(database)
+        .with_max_cache_size(max_cache_size.filter(|&size| size > 0))
+        .with_relocation_root(relocation_root))
 }
 
 pub type NoopBackingStorage = KeyValueDatabaseBackingStorage<NoopKvDb>;
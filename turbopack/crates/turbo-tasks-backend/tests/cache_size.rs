@@ -0,0 +1,98 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use anyhow::Result;
+use turbo_tasks::{run_once, TurboTasks, Vc};
+use turbo_tasks_backend::{turbo_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!();
+
+static COLD_EXECUTIONS: AtomicU32 = AtomicU32::new(0);
+static HOT_EXECUTIONS: AtomicU32 = AtomicU32::new(0);
+
+#[tokio::test]
+async fn evict_cold_cells() {
+    REGISTRATION.ensure_registered();
+    let path = PathBuf::from(concat!(env!("OUT_DIR"), "/.cache/evict_cold_cells"));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    // Every database exceeds that, so the cell data of all cold tasks is evicted
+    std::env::set_var("TURBO_ENGINE_MAX_CACHE_SIZE", "1");
+
+    let (cold_value, hot_value) = session(&path, async {
+        let cold = cold().strongly_consistent().await?.random_value;
+        let hot = hot().strongly_consistent().await?.random_value;
+        Ok((cold, hot))
+    })
+    .await;
+    assert_eq!(COLD_EXECUTIONS.load(Ordering::SeqCst), 1);
+    assert_eq!(HOT_EXECUTIONS.load(Ordering::SeqCst), 1);
+
+    // Only the hot task is accessed, so the cold task's cells are dropped from the database when
+    // the snapshot is saved
+    let value = session(&path, async {
+        Ok(hot().strongly_consistent().await?.random_value)
+    })
+    .await;
+    assert_eq!(value, hot_value);
+    assert_eq!(HOT_EXECUTIONS.load(Ordering::SeqCst), 1);
+
+    let (cold, hot) = session(&path, async {
+        let cold = cold().strongly_consistent().await?.random_value;
+        let hot = hot().strongly_consistent().await?.random_value;
+        Ok((cold, hot))
+    })
+    .await;
+    // The recently accessed task keeps its cells
+    assert_eq!(hot, hot_value);
+    assert_eq!(HOT_EXECUTIONS.load(Ordering::SeqCst), 1);
+    // The evicted cell is recomputed on read
+    assert_ne!(cold, cold_value);
+    assert_eq!(COLD_EXECUTIONS.load(Ordering::SeqCst), 2);
+}
+
+/// Runs a session with the cache at `path`. Stopping it saves a snapshot, which enforces the cache
+/// size limit.
+async fn session<T: Send + 'static>(
+    path: &Path,
+    future: impl Future<Output = Result<T>> + Send + 'static,
+) -> T {
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions::default(),
+        turbo_backing_storage(path).unwrap(),
+    ));
+    let result = run_once(tt.clone(), future).await.unwrap();
+    tt.stop_and_wait().await;
+    result
+}
+
+#[turbo_tasks::value]
+struct Output {
+    random_value: u32,
+}
+
+#[turbo_tasks::function]
+fn cold() -> Vc<Output> {
+    COLD_EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+    Output {
+        random_value: rand::random(),
+    }
+    .cell()
+}
+
+#[turbo_tasks::function]
+fn hot() -> Vc<Output> {
+    HOT_EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+    Output {
+        random_value: rand::random(),
+    }
+    .cell()
+}
@@ -0,0 +1,95 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use turbo_tasks::{run_once, State, TurboTasks, Vc};
+use turbo_tasks_backend::{default_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!();
+
+#[tokio::test]
+async fn evict_and_restore() {
+    REGISTRATION.ensure_registered();
+    let path = std::path::PathBuf::from(concat!(env!("OUT_DIR"), "/.cache/evict_and_restore"));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions {
+            // Evict everything that is persisted after every snapshot
+            memory_limit: Some(0),
+            ..Default::default()
+        },
+        default_backing_storage(&path).unwrap(),
+    ));
+
+    let random_value = run_once(tt.clone(), async move {
+        let read = compute(create_input()).await?;
+        assert_eq!(read.value, 2);
+        Ok(read.random_value)
+    })
+    .await
+    .unwrap();
+
+    // A snapshot is taken when the backend is idle, which evicts the persisted tasks
+    let start = Instant::now();
+    while tt.backend().evicted_tasks() == 0 {
+        assert!(
+            start.elapsed() < Duration::from_secs(60),
+            "no tasks were evicted"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    run_once(tt.clone(), async move {
+        let input = create_input();
+        let output = compute(input);
+        // Evicted data is restored from the backing storage instead of being recomputed
+        let read = output.strongly_consistent().await?;
+        assert_eq!(read.value, 2);
+        assert_eq!(read.random_value, random_value);
+
+        // Evicted tasks are still invalidated and recomputed
+        input.await?.state.set(5);
+        let read = output.strongly_consistent().await?;
+        assert_eq!(read.value, 10);
+        assert_ne!(read.random_value, random_value);
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    tt.stop_and_wait().await;
+}
+
+#[turbo_tasks::value]
+struct ChangingInput {
+    state: State<u32>,
+}
+
+#[turbo_tasks::value]
+struct Output {
+    value: u32,
+    random_value: u32,
+}
+
+#[turbo_tasks::function]
+fn create_input() -> Vc<ChangingInput> {
+    ChangingInput {
+        state: State::new(1),
+    }
+    .cell()
+}
+
+#[turbo_tasks::function]
+async fn compute(input: Vc<ChangingInput>) -> Result<Vc<Output>> {
+    let value = *input.await?.state.get() * 2;
+    Ok(Output {
+        value,
+        random_value: rand::random(),
+    }
+    .cell())
+}
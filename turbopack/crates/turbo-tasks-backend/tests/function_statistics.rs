@@ -0,0 +1,79 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use anyhow::Result;
+use turbo_tasks::{function_statistics::FunctionStatistics, run_once, State, TurboTasks, Vc};
+use turbo_tasks_backend::{noop_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!();
+
+#[tokio::test]
+async fn function_statistics() {
+    REGISTRATION.ensure_registered();
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions {
+            storage_mode: None,
+            ..Default::default()
+        },
+        noop_backing_storage(),
+    ));
+
+    run_once(tt.clone(), async move {
+        let input = create_input().resolve().await?;
+        assert_eq!(*double(input).await?, 2);
+        // The second call reuses the task
+        assert_eq!(*double(input).await?, 2);
+
+        // Invalidating the task executes it again
+        input.await?.state.set(2);
+        assert_eq!(*double(input).strongly_consistent().await?, 4);
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let statistics = tt.function_statistics();
+    let find = |name: &str| -> &FunctionStatistics {
+        statistics
+            .iter()
+            .find(|statistics| statistics.name.ends_with(name))
+            .unwrap()
+    };
+    let double = find("::double");
+    assert_eq!(double.executions, 2);
+    assert_eq!(double.cache_hits, 2);
+    assert_eq!(double.invalidations, 1);
+    assert_eq!(double.cells_written, 2);
+    assert!(double.peak_duration <= double.total_duration);
+    let create_input = find("::create_input");
+    assert_eq!(create_input.executions, 1);
+    assert_eq!(create_input.cache_hits, 0);
+    assert_eq!(create_input.invalidations, 0);
+
+    // The slowest function is first
+    assert!(statistics
+        .windows(2)
+        .all(|pair| pair[0].total_duration >= pair[1].total_duration));
+
+    tt.stop_and_wait().await;
+}
+
+#[turbo_tasks::value]
+struct ChangingInput {
+    state: State<u32>,
+}
+
+#[turbo_tasks::function]
+fn create_input() -> Vc<ChangingInput> {
+    ChangingInput {
+        state: State::new(1),
+    }
+    .cell()
+}
+
+#[turbo_tasks::function]
+async fn double(input: Vc<ChangingInput>) -> Result<Vc<u32>> {
+    Ok(Vc::cell(*input.await?.state.get() * 2))
+}
@@ -0,0 +1,128 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::{
+    fmt::{Display, Formatter},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Result;
+use turbo_tasks::{
+    get_invalidator, invalidation_provenance::InvalidationCause, run_once, turbo_tasks,
+    InvalidationReason, Invalidator, TurboTasks, Vc,
+};
+use turbo_tasks_backend::{noop_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!();
+
+/// The content of the fake file that is read by [read_file].
+static FILE_CONTENT: AtomicU32 = AtomicU32::new(1);
+static FILE_INVALIDATOR: Mutex<Option<Invalidator>> = Mutex::new(None);
+
+#[tokio::test]
+async fn invalidation_provenance() {
+    REGISTRATION.ensure_registered();
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions {
+            storage_mode: None,
+            invalidation_provenance: true,
+            ..Default::default()
+        },
+        noop_backing_storage(),
+    ));
+
+    run_once(tt.clone(), async move {
+        let output = pipeline();
+        let pipeline_task = Vc::into_raw(output).get_task_id();
+        assert_eq!(*output.strongly_consistent().await?, 11);
+        assert!(turbo_tasks()
+            .invalidation_provenance(pipeline_task)
+            .is_none());
+
+        // A file write invalidates the read, the changed cell re-executes all dependents
+        FILE_CONTENT.store(2, Ordering::SeqCst);
+        FILE_INVALIDATOR
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap()
+            .invalidate_with_reason(FileWrite {
+                path: "input.txt".to_string(),
+            });
+        assert_eq!(*pipeline().strongly_consistent().await?, 21);
+
+        let provenance = turbo_tasks()
+            .invalidation_provenance(pipeline_task)
+            .unwrap();
+        let steps = &provenance.steps;
+        assert_eq!(steps.len(), 4, "{provenance}");
+        assert_eq!(steps[0].task, pipeline_task);
+        assert!(steps[0].description.ends_with("pipeline"), "{provenance}");
+        for (step, name) in steps[1..].iter().zip(["transform", "parse", "read_file"]) {
+            assert!(step.description.ends_with(name), "{provenance}");
+        }
+        // Every step was invalidated by a cell of the next step
+        for pair in steps.windows(2) {
+            assert!(
+                matches!(
+                    pair[0].cause,
+                    InvalidationCause::CellChange { task, .. } if task == pair[1].task
+                ),
+                "{provenance}"
+            );
+        }
+        assert_eq!(
+            provenance.root_cause(),
+            Some(&InvalidationCause::External {
+                reason: Some("input.txt written".to_string())
+            })
+        );
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    tt.stop_and_wait().await;
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct FileWrite {
+    path: String,
+}
+
+impl InvalidationReason for FileWrite {}
+
+impl Display for FileWrite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} written", self.path)
+    }
+}
+
+#[turbo_tasks::function]
+fn read_file() -> Vc<u32> {
+    *FILE_INVALIDATOR.lock().unwrap() = Some(get_invalidator());
+    Vc::cell(FILE_CONTENT.load(Ordering::SeqCst))
+}
+
+#[turbo_tasks::function]
+async fn parse(content: Vc<u32>) -> Result<Vc<u32>> {
+    Ok(Vc::cell(*content.await? * 10))
+}
+
+#[turbo_tasks::function]
+async fn transform(parsed: Vc<u32>) -> Result<Vc<u32>> {
+    Ok(Vc::cell(*parsed.await? + 1))
+}
+
+#[turbo_tasks::function]
+async fn pipeline() -> Result<Vc<u32>> {
+    // Resolving the arguments first keeps the resolve tasks out of the chain
+    let content = read_file().resolve().await?;
+    let parsed = parse(content).resolve().await?;
+    Ok(Vc::cell(*transform(parsed).await?))
+}
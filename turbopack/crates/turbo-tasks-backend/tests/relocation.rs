@@ -0,0 +1,69 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use anyhow::Result;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{run_once, TurboTasks, Vc};
+use turbo_tasks_backend::{default_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_fs::{DiskFileSystem, FileContent, FileSystem, FileSystemPath};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!(turbo_tasks_fs::register);
+
+static EXECUTIONS: AtomicU32 = AtomicU32::new(0);
+
+#[tokio::test]
+async fn relocate_cache() {
+    REGISTRATION.ensure_registered();
+    let base = Path::new(concat!(env!("OUT_DIR"), "/.cache/relocate_cache"));
+    let _ = std::fs::remove_dir_all(base);
+    let old_root = base.join("old");
+    let new_root = base.join("new");
+    std::fs::create_dir_all(old_root.join("src")).unwrap();
+    std::fs::write(old_root.join("src/index.js"), "index").unwrap();
+
+    assert_eq!(read_uppercase(&old_root).await, "INDEX");
+    assert_eq!(EXECUTIONS.load(Ordering::SeqCst), 1);
+
+    // Move the project together with its cache
+    std::fs::rename(&old_root, &new_root).unwrap();
+
+    // The file system root is rebased, so the file is read from the new location and the cached
+    // task output is reused
+    assert_eq!(read_uppercase(&new_root).await, "INDEX");
+    assert_eq!(EXECUTIONS.load(Ordering::SeqCst), 1);
+}
+
+/// Runs a session with the cache in `.cache` of the project at `root` and reads `src/index.js`.
+async fn read_uppercase(root: &Path) -> String {
+    std::env::set_var("TURBO_ENGINE_PROJECT_ROOT", root);
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions::default(),
+        default_backing_storage(&root.join(".cache")).unwrap(),
+    ));
+    let root: RcStr = root.to_string_lossy().into();
+    let content = run_once(tt.clone(), async move {
+        let fs = DiskFileSystem::new("project".into(), root, vec![]);
+        let path = fs.root().join("src/index.js".into());
+        Ok(uppercase(path).strongly_consistent().await?.to_string())
+    })
+    .await
+    .unwrap();
+    tt.stop_and_wait().await;
+    content
+}
+
+#[turbo_tasks::function]
+async fn uppercase(path: Vc<FileSystemPath>) -> Result<Vc<RcStr>> {
+    EXECUTIONS.fetch_add(1, Ordering::SeqCst);
+    let FileContent::Content(file) = &*path.read().await? else {
+        return Ok(Vc::cell("".into()));
+    };
+    Ok(Vc::cell(file.content().to_str()?.to_uppercase().into()))
+}
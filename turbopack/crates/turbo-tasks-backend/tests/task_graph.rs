@@ -0,0 +1,131 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use anyhow::Result;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{
+    emit, run_once,
+    task_graph::{TaskDependency, TaskGraph, TaskGraphNode},
+    CollectiblesSource, ResolvedVc, TurboTasks, ValueToString, Vc, VcValueTrait,
+};
+use turbo_tasks_backend::{noop_backing_storage, BackendOptions, TurboTasksBackend};
+use turbo_tasks_testing::{register, Registration};
+
+static REGISTRATION: Registration = register!();
+
+#[tokio::test]
+async fn task_graph() {
+    REGISTRATION.ensure_registered();
+    let tt = TurboTasks::new(TurboTasksBackend::new(
+        BackendOptions {
+            storage_mode: None,
+            ..Default::default()
+        },
+        noop_backing_storage(),
+    ));
+
+    let graph = run_once(tt.clone(), {
+        let tt = tt.clone();
+        async move {
+            let output = root();
+            let root_task = Vc::into_raw(output).get_task_id();
+            assert_eq!(*output.strongly_consistent().await?, 2);
+            Ok(tt.task_graph(root_task).unwrap())
+        }
+    })
+    .await
+    .unwrap();
+
+    let root = node(&graph, "root");
+    let leaf = node(&graph, "leaf");
+    let double = node(&graph, "double");
+    assert_eq!(graph.root, root.id);
+    assert_eq!(graph.tasks[0].id, root.id);
+    assert!(graph.tasks.iter().all(|task| !task.dirty));
+
+    let mut children = vec![leaf.id, double.id];
+    children.sort_unstable();
+    assert_eq!(root.children, children);
+    assert!(leaf.children.is_empty());
+    assert!(double.children.is_empty());
+
+    // The root resolves the leaf, reads the collectibles of the leaf and the cell of double
+    let collectible_type = <Box<dyn ValueToString> as VcValueTrait>::get_trait_type_id();
+    assert!(root
+        .dependencies
+        .contains(&TaskDependency::Output { task: leaf.id }));
+    assert!(root.dependencies.contains(&TaskDependency::Collectibles {
+        task: leaf.id,
+        collectible_type,
+    }));
+    assert!(root.dependencies.iter().any(
+        |dependency| matches!(dependency, TaskDependency::Cell { task, .. } if *task == double.id)
+    ));
+    // double only reads the cell of the resolved leaf
+    assert!(matches!(
+        &double.dependencies[..],
+        [TaskDependency::Cell { task, .. }] if *task == leaf.id
+    ));
+    assert!(leaf.dependencies.is_empty());
+
+    // Only the leaf emits a collectible, it's stored in a cell of the leaf
+    assert!(root.collectibles.is_empty());
+    assert!(double.collectibles.is_empty());
+    let [collectible] = &leaf.collectibles[..] else {
+        panic!("expected one collectible, got {:?}", leaf.collectibles);
+    };
+    assert_eq!(collectible.collectible_type, collectible_type);
+    assert_eq!(collectible.task, leaf.id);
+    assert_eq!(collectible.count, 1);
+
+    tt.stop_and_wait().await;
+}
+
+/// Returns the node of the task with the function `name`.
+fn node<'a>(graph: &'a TaskGraph, name: &str) -> &'a TaskGraphNode {
+    let mut nodes = graph
+        .tasks
+        .iter()
+        .filter(|task| task.description.ends_with(name));
+    let node = nodes
+        .next()
+        .unwrap_or_else(|| panic!("no {name} task in {graph:#?}"));
+    assert!(
+        nodes.next().is_none(),
+        "multiple {name} tasks in {graph:#?}"
+    );
+    node
+}
+
+#[turbo_tasks::function]
+fn leaf() -> Vc<u32> {
+    emit(ResolvedVc::upcast::<Box<dyn ValueToString>>(
+        Thing::resolved_cell(Thing(1)),
+    ));
+    Vc::cell(1)
+}
+
+#[turbo_tasks::function]
+async fn double(value: Vc<u32>) -> Result<Vc<u32>> {
+    Ok(Vc::cell(*value.await? * 2))
+}
+
+#[turbo_tasks::function]
+async fn root() -> Result<Vc<u32>> {
+    // Resolving the argument first keeps the resolve task out of the graph
+    let leaf = leaf().resolve().await?;
+    assert_eq!(leaf.peek_collectibles::<Box<dyn ValueToString>>().len(), 1);
+    Ok(Vc::cell(*double(leaf).await?))
+}
+
+#[turbo_tasks::value(shared)]
+struct Thing(u32);
+
+#[turbo_tasks::value_impl]
+impl ValueToString for Thing {
+    #[turbo_tasks::function]
+    fn to_string(&self) -> Vc<RcStr> {
+        Vc::cell(self.0.to_string().into())
+    }
+}
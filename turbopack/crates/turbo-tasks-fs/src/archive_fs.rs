@@ -0,0 +1,431 @@
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+    iter::once,
+    ops::Bound,
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use auto_hash_map::AutoMap;
+use flate2::read::GzDecoder;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{Completion, ResolvedVc, ValueToString, Vc};
+use zip::ZipArchive;
+
+use crate::{
+    attach::AttachedFileSystem, util::normalize_path, DirectoryContent, DirectoryEntry, File,
+    FileContent, FileMeta, FileSystem, FileSystemPath, LinkContent, LinkType, Permissions,
+};
+
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+/// The maximum total size of the decompressed files of an archive. Archives are decompressed into
+/// memory, this protects against archives that expand to huge sizes.
+const MAX_DECOMPRESSED_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// The maximum number of symlinks that are followed to resolve a path, like `MAXSYMLINKS` on
+/// Linux.
+const MAX_SYMLINKS: usize = 40;
+
+enum ArchiveData {
+    Zip(ZipArchive<Cursor<Arc<[u8]>>>),
+    /// The decompressed tar archive, files are stored as is.
+    Tar(Vec<u8>),
+}
+
+enum ArchiveEntry {
+    Directory,
+    File {
+        /// The index of a zip entry or the offset of a file in the tar archive.
+        position: usize,
+        size: usize,
+        executable: bool,
+    },
+    Symlink(RcStr),
+}
+
+struct Archive {
+    data: ArchiveData,
+    /// All entries keyed by their path relative to the root of the archive.
+    entries: BTreeMap<RcStr, ArchiveEntry>,
+}
+
+impl Archive {
+    /// Reads a zip, tar or gzipped tar archive, the format is detected from the content. Fails when
+    /// the decompressed files are larger than `max_size` bytes.
+    fn new(content: &[u8], max_size: u64) -> Result<Self> {
+        let mut archive = if content.starts_with(b"PK") {
+            Self::read_zip(Arc::from(content), max_size)?
+        } else if content.starts_with(&[0x1f, 0x8b]) {
+            let mut data = Vec::new();
+            GzDecoder::new(content)
+                .take(max_size + 1)
+                .read_to_end(&mut data)?;
+            if data.len() as u64 > max_size {
+                bail!("the decompressed archive exceeds the limit of {max_size} bytes");
+            }
+            Self::read_tar(data)?
+        } else {
+            Self::read_tar(content.to_vec())?
+        };
+        // Directories are not always stored in archives
+        let dirs = archive
+            .entries
+            .keys()
+            .flat_map(|path| {
+                path.match_indices('/')
+                    .map(|(i, _)| RcStr::from(&path[..i]))
+            })
+            .collect::<Vec<_>>();
+        for dir in dirs {
+            archive
+                .entries
+                .entry(dir)
+                .or_insert(ArchiveEntry::Directory);
+        }
+        Ok(archive)
+    }
+
+    fn read_zip(content: Arc<[u8]>, max_size: u64) -> Result<Self> {
+        let mut zip = ZipArchive::new(Cursor::new(content))?;
+        let mut entries = BTreeMap::new();
+        let mut total_size = 0u64;
+        for index in 0..zip.len() {
+            let file = zip.by_index(index)?;
+            // Entries are never read beyond their declared size, see `read_file`
+            total_size = total_size.saturating_add(file.size());
+            if total_size > max_size {
+                bail!("the decompressed archive exceeds the limit of {max_size} bytes");
+            }
+            let Some(path) = normalize_path(file.name()).filter(|path| !path.is_empty()) else {
+                continue;
+            };
+            let mode = file.unix_mode().unwrap_or(0);
+            let entry = if file.is_dir() {
+                ArchiveEntry::Directory
+            } else if mode & S_IFMT == S_IFLNK {
+                let mut target = String::new();
+                let size = file.size();
+                file.take(size).read_to_string(&mut target)?;
+                ArchiveEntry::Symlink(target.into())
+            } else {
+                ArchiveEntry::File {
+                    position: index,
+                    size: file.size() as usize,
+                    executable: mode & 0o111 != 0,
+                }
+            };
+            entries.insert(path.into(), entry);
+        }
+        Ok(Archive {
+            data: ArchiveData::Zip(zip),
+            entries,
+        })
+    }
+
+    fn read_tar(data: Vec<u8>) -> Result<Self> {
+        let mut entries = BTreeMap::new();
+        let mut tar = tar::Archive::new(&data[..]);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let Some(path) =
+                normalize_path(&entry.path()?.to_string_lossy()).filter(|path| !path.is_empty())
+            else {
+                continue;
+            };
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            let entry = if entry_type.is_dir() {
+                ArchiveEntry::Directory
+            } else if entry_type.is_symlink() {
+                let Some(target) = entry.link_name()? else {
+                    continue;
+                };
+                ArchiveEntry::Symlink(target.to_string_lossy().into())
+            } else if entry_type.is_file() {
+                ArchiveEntry::File {
+                    position: entry.raw_file_position() as usize,
+                    size: entry.size() as usize,
+                    executable: header.mode()? & 0o111 != 0,
+                }
+            } else {
+                continue;
+            };
+            entries.insert(path.into(), entry);
+        }
+        Ok(Archive {
+            data: ArchiveData::Tar(data),
+            entries,
+        })
+    }
+
+    /// Resolves the symlinks in `path` like the operating system does. Returns `None` when a
+    /// symlink points outside of the archive or the symlinks form a cycle.
+    fn resolve_path(&self, path: &str) -> Option<String> {
+        let mut path = path.to_string();
+        let mut links = 0;
+        loop {
+            let link = path
+                .match_indices('/')
+                .map(|(end, _)| end)
+                .chain(once(path.len()))
+                .find_map(|end| match self.entries.get(&path[..end]) {
+                    Some(ArchiveEntry::Symlink(target)) => Some((end, target)),
+                    _ => None,
+                });
+            let Some((end, target)) = link else {
+                return Some(path);
+            };
+            links += 1;
+            // Absolute symlinks point outside of the archive
+            if links > MAX_SYMLINKS || target.starts_with('/') {
+                return None;
+            }
+            path = normalize_path(&format!(
+                "{}{}",
+                link_target_path(&path[..end], target),
+                &path[end..]
+            ))?;
+        }
+    }
+
+    /// Returns the entry at `path`, following the symlinks in the path.
+    fn resolve_entry(&self, path: &str) -> Option<&ArchiveEntry> {
+        self.entries.get(self.resolve_path(path)?.as_str())
+    }
+
+    fn read_file(&self, position: usize, size: usize) -> Result<Vec<u8>> {
+        Ok(match &self.data {
+            ArchiveData::Zip(zip) => {
+                // Cloning only clones the shared reader
+                let mut zip = zip.clone();
+                let file = zip.by_index(position)?;
+                let mut content = Vec::with_capacity(size);
+                file.take(size as u64).read_to_end(&mut content)?;
+                content
+            }
+            ArchiveData::Tar(data) => data
+                .get(position..position + size)
+                .context("file is outside of the archive")?
+                .to_vec(),
+        })
+    }
+}
+
+/// The `target` of the symlink at `path`, relative to the root of the archive. It's not normalized.
+fn link_target_path(path: &str, target: &str) -> String {
+    let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    format!("{parent}/{target}")
+}
+
+/// A read-only [FileSystem] that is backed by a zip, tar or gzipped tar archive, e. g. a package
+/// of the Yarn PnP cache or a vendored npm tarball. The archive is read into memory, files are
+/// decompressed when they are read. Reading a path follows the symlinks in it, like a
+/// [crate::DiskFileSystem] does. Symlinks that point outside of the archive can't be followed.
+///
+/// Use [ArchiveFileSystem::mount] to make the archive available at a path of another
+/// [FileSystem].
+#[turbo_tasks::value(serialization = "none", cell = "new", eq = "manual")]
+pub struct ArchiveFileSystem {
+    archive_path: ResolvedVc<FileSystemPath>,
+    #[turbo_tasks(debug_ignore, trace_ignore)]
+    archive: Arc<Archive>,
+}
+
+#[turbo_tasks::value_impl]
+impl ArchiveFileSystem {
+    /// Creates an [ArchiveFileSystem] with the content of the archive at `archive_path`. It's
+    /// updated when the archive changes.
+    #[turbo_tasks::function]
+    pub async fn new(archive_path: ResolvedVc<FileSystemPath>) -> Result<Vc<Self>> {
+        let content = archive_path.read().await?;
+        let FileContent::Content(file) = &*content else {
+            bail!("archive {} not found", archive_path.to_string().await?);
+        };
+        let archive = match Archive::new(&file.content().to_bytes()?, MAX_DECOMPRESSED_SIZE) {
+            Ok(archive) => archive,
+            Err(err) => {
+                return Err(err.context(format!(
+                    "failed to read archive {}",
+                    archive_path.to_string().await?
+                )))
+            }
+        };
+        Ok(ArchiveFileSystem {
+            archive_path,
+            archive: Arc::new(archive),
+        }
+        .cell())
+    }
+
+    /// Attaches the content of the archive as the directory `mount_path` of the
+    /// [FileSystem] of `mount_path`.
+    #[turbo_tasks::function]
+    pub fn mount(self: Vc<Self>, mount_path: Vc<FileSystemPath>) -> Vc<AttachedFileSystem> {
+        AttachedFileSystem::new(mount_path, Vc::upcast(self))
+    }
+}
+
+fn file_meta(executable: bool) -> FileMeta {
+    FileMeta {
+        permissions: if executable {
+            Permissions::Executable
+        } else {
+            Permissions::Readable
+        },
+        content_type: None,
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl FileSystem for ArchiveFileSystem {
+    #[turbo_tasks::function]
+    async fn read(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileContent>> {
+        let path = &fs_path.await?.path;
+        let Some(&ArchiveEntry::File {
+            position,
+            size,
+            executable,
+        }) = self.archive.resolve_entry(path)
+        else {
+            return Ok(FileContent::NotFound.cell());
+        };
+        let content = self
+            .archive
+            .read_file(position, size)
+            .with_context(|| format!("failed to read {} from the archive", path))?;
+        Ok(FileContent::Content(File::new(file_meta(executable), content)).cell())
+    }
+
+    #[turbo_tasks::function]
+    async fn read_link(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<LinkContent>> {
+        let path = &fs_path.await?.path;
+        let Some(ArchiveEntry::Symlink(target)) = self.archive.entries.get(path) else {
+            return Ok(LinkContent::NotFound.cell());
+        };
+        // Absolute symlinks point outside of the archive
+        if target.starts_with('/') {
+            return Ok(LinkContent::Invalid.cell());
+        }
+        let link_type = match normalize_path(&link_target_path(path, target))
+            .and_then(|resolved| self.archive.resolve_entry(&resolved))
+        {
+            Some(ArchiveEntry::Directory) => LinkType::DIRECTORY,
+            _ => LinkType::empty(),
+        };
+        Ok(LinkContent::Link {
+            target: target.clone(),
+            link_type,
+        }
+        .cell())
+    }
+
+    #[turbo_tasks::function]
+    async fn read_dir(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<DirectoryContent>> {
+        let path = &fs_path.await?.path;
+        let resolved = self.archive.resolve_path(path);
+        let prefix = match resolved.as_deref() {
+            Some("") => String::new(),
+            Some(resolved)
+                if matches!(
+                    self.archive.entries.get(resolved),
+                    Some(ArchiveEntry::Directory)
+                ) =>
+            {
+                format!("{resolved}/")
+            }
+            _ => return Ok(DirectoryContent::not_found()),
+        };
+
+        let mut entries = AutoMap::new();
+        for (key, entry) in self
+            .archive
+            .entries
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+        {
+            let name = &key[prefix.len()..];
+            if name.contains('/') {
+                continue;
+            }
+            let entry_path = fs_path.join(name.into()).to_resolved().await?;
+            entries.insert(
+                name.into(),
+                match entry {
+                    ArchiveEntry::Directory => DirectoryEntry::Directory(entry_path),
+                    ArchiveEntry::File { .. } => DirectoryEntry::File(entry_path),
+                    ArchiveEntry::Symlink(_) => DirectoryEntry::Symlink(entry_path),
+                },
+            );
+        }
+        Ok(DirectoryContent::new(entries))
+    }
+
+    #[turbo_tasks::function]
+    fn track(&self, _fs_path: Vc<FileSystemPath>) -> Vc<Completion> {
+        Completion::new()
+    }
+
+    #[turbo_tasks::function]
+    fn write(&self, _fs_path: Vc<FileSystemPath>, _content: Vc<FileContent>) -> Result<Vc<()>> {
+        bail!("Writing is not possible to the archive file system")
+    }
+
+    #[turbo_tasks::function]
+    fn write_link(&self, _fs_path: Vc<FileSystemPath>, _target: Vc<LinkContent>) -> Result<Vc<()>> {
+        bail!("Writing is not possible to the archive file system")
+    }
+
+    #[turbo_tasks::function]
+    async fn metadata(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileMeta>> {
+        let path = &fs_path.await?.path;
+        Ok(match self.archive.resolve_entry(path) {
+            Some(ArchiveEntry::File { executable, .. }) => file_meta(*executable),
+            Some(_) => FileMeta::default(),
+            None if path.is_empty() => FileMeta::default(),
+            None => bail!("{} not found in the archive", path),
+        }
+        .cell())
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl ValueToString for ArchiveFileSystem {
+    #[turbo_tasks::function]
+    async fn to_string(&self) -> Result<Vc<RcStr>> {
+        Ok(Vc::cell(
+            format!("archive {}", self.archive_path.to_string().await?).into(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::Archive;
+
+    #[test]
+    fn decompressed_size_limit() {
+        let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4096);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, "zeros", &[0u8; 4096][..])
+            .unwrap();
+        let tgz = tar.into_inner().unwrap().finish().unwrap();
+        assert!(Archive::new(&tgz, 1024 * 1024).is_ok());
+        assert!(Archive::new(&tgz, 1024).is_err());
+
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.start_file("zeros", Default::default()).unwrap();
+        zip.write_all(&[0u8; 4096]).unwrap();
+        let zip = zip.finish().unwrap().into_inner();
+        assert!(Archive::new(&zip, 1024 * 1024).is_ok());
+        assert!(Archive::new(&zip, 1024).is_err());
+    }
+}
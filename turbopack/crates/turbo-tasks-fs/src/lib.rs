@@ -9,13 +9,16 @@
 #![feature(arbitrary_self_types_pointers)]
 #![allow(clippy::mutable_key_type)]
 
+mod archive_fs;
 pub mod attach;
 pub mod embed;
 pub mod glob;
 mod invalidation;
 mod invalidator_map;
 pub mod json;
+mod memory_fs;
 mod mutex_map;
+mod overlay_fs;
 mod read_glob;
 mod retry;
 pub mod rope;
@@ -38,6 +41,7 @@ use std::{
 };
 
 use anyhow::{anyhow, bail, Context, Result};
+pub use archive_fs::ArchiveFileSystem;
 use auto_hash_map::AutoMap;
 use bitflags::bitflags;
 use dunce::simplified;
@@ -45,7 +49,9 @@ use glob::Glob;
 use invalidation::InvalidateFilesystem;
 use invalidator_map::InvalidatorMap;
 use jsonc_parser::{parse_to_serde_value, ParseOptions};
+pub use memory_fs::MemoryFileSystem;
 use mime::Mime;
+pub use overlay_fs::OverlayFileSystem;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use read_glob::read_glob;
 pub use read_glob::ReadGlobResult;
@@ -60,7 +66,7 @@ use tracing::Instrument;
 use turbo_rcstr::RcStr;
 use turbo_tasks::{
     debug::ValueDebugFormat, effect, mark_session_dependent, mark_stateful, trace::TraceRawVcs,
-    Completion, Invalidator, NonLocalValue, ReadRef, ResolvedVc, ValueToString, Vc,
+    Completion, Invalidator, NonLocalValue, ReadRef, ResolvedVc, TaskInput, ValueToString, Vc,
 };
 use turbo_tasks_hash::{
     hash_xxh3_hash128, hash_xxh3_hash64, DeterministicHash, DeterministicHasher,
@@ -68,6 +74,7 @@ use turbo_tasks_hash::{
 use util::{extract_disk_access, join_path, normalize_path, sys_to_unix, unix_to_sys};
 pub use virtual_fs::VirtualFileSystem;
 use watcher::DiskWatcher;
+pub use watcher::{WatcherDegradation, WatcherDegradationKind, WatcherDegradations};
 
 use self::{invalidation::Write, json::UnparseableJson, mutex_map::MutexMap};
 use crate::{
@@ -191,6 +198,7 @@ pub trait FileSystem: ValueToString {
 #[derive(Serialize, Deserialize, TraceRawVcs, ValueDebugFormat, NonLocalValue)]
 struct DiskFileSystemInner {
     pub name: RcStr,
+    #[serde(with = "turbo_tasks::relocation")]
     pub root: RcStr,
     #[turbo_tasks(debug_ignore, trace_ignore)]
     #[serde(skip)]
@@ -426,7 +434,12 @@ pub fn path_to_key(path: impl AsRef<Path>) -> String {
     path.as_ref().to_string_lossy().to_string()
 }
 
-#[turbo_tasks::value_impl]
+/// The root of a [DiskFileSystem]. It's relocated when the persistent cache is restored into a
+/// different directory, see [turbo_tasks::relocation].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, TaskInput)]
+#[serde(transparent)]
+struct DiskFileSystemRoot(#[serde(with = "turbo_tasks::relocation")] RcStr);
+
 impl DiskFileSystem {
     /// Create a new instance of `DiskFileSystem`.
     /// # Arguments
@@ -436,14 +449,25 @@ impl DiskFileSystem {
     /// * `ignored_subpaths` - A list of subpaths that should not trigger invalidation. This should
     ///   be a full path, since it is possible that root & project dir is different and requires to
     ///   ignore specific subpaths from each.
+    pub fn new(name: RcStr, root: RcStr, ignored_subpaths: Vec<RcStr>) -> Vc<Self> {
+        Self::new_internal(name, DiskFileSystemRoot(root), ignored_subpaths)
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl DiskFileSystem {
     #[turbo_tasks::function]
-    pub async fn new(name: RcStr, root: RcStr, ignored_subpaths: Vec<RcStr>) -> Result<Vc<Self>> {
+    async fn new_internal(
+        name: RcStr,
+        root: DiskFileSystemRoot,
+        ignored_subpaths: Vec<RcStr>,
+    ) -> Result<Vc<Self>> {
         mark_stateful();
 
         let instance = DiskFileSystem {
             inner: Arc::new(DiskFileSystemInner {
                 name,
-                root,
+                root: root.0,
                 mutex_map: Default::default(),
                 invalidation_lock: Default::default(),
                 invalidator_map: InvalidatorMap::new(),
@@ -457,6 +481,14 @@ impl DiskFileSystem {
         Ok(Self::cell(instance))
     }
 
+    /// Returns the paths that the native file watcher can't observe alone, e. g. because the
+    /// OS limit of file watches was reached. These paths are polled or were rescanned instead.
+    #[turbo_tasks::function]
+    pub fn watcher_degradations(&self) -> Vc<WatcherDegradations> {
+        mark_session_dependent();
+        Vc::cell(self.inner.watcher.degradations())
+    }
+
     #[turbo_tasks::function(fs)]
     async fn read_dir_internal(
         &self,
@@ -2391,6 +2423,306 @@ mod tests {
         .unwrap()
     }
 
+    #[tokio::test]
+    async fn memory_file_system() {
+        crate::register();
+
+        let tt = turbo_tasks::TurboTasks::new(turbo_tasks_memory::MemoryBackend::new(usize::MAX));
+        tt.run_once(async {
+            let memory_fs = MemoryFileSystem::new("memory".into());
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(memory_fs);
+            let memory_fs = memory_fs.await?;
+            memory_fs.set_file("src/index.js", File::from("index").into())?;
+            memory_fs.set_link(
+                "src/link.js",
+                LinkContent::Link {
+                    target: "index.js".into(),
+                    link_type: LinkType::empty(),
+                },
+            )?;
+            assert!(memory_fs
+                .set_file("src/index.js/nested.js", File::from("").into())
+                .is_err());
+
+            let read = |path: &str| {
+                FileSystemPath::new_normalized(fs, path.into())
+                    .read()
+                    .strongly_consistent()
+            };
+            let read_dir = |path: &str| {
+                FileSystemPath::new_normalized(fs, path.into())
+                    .read_dir()
+                    .strongly_consistent()
+            };
+
+            let FileContent::Content(file) = &*read("src/index.js").await? else {
+                panic!("expected src/index.js to exist");
+            };
+            assert_eq!(file.content().to_str()?, "index");
+            let DirectoryContent::Entries(entries) = &*read_dir("src").await? else {
+                panic!("expected src to exist");
+            };
+            assert!(matches!(
+                entries.get("index.js"),
+                Some(DirectoryEntry::File(_))
+            ));
+            assert!(matches!(
+                entries.get("link.js"),
+                Some(DirectoryEntry::Symlink(_))
+            ));
+            assert!(matches!(
+                &*FileSystemPath::new_normalized(fs, "src/link.js".into())
+                    .read_link()
+                    .await?,
+                LinkContent::Link { target, .. } if target == "index.js"
+            ));
+
+            // Changes invalidate the tasks that read the changed paths
+            memory_fs.set_file("src/index.js", File::from("changed").into())?;
+            let FileContent::Content(file) = &*read("src/index.js").await? else {
+                panic!("expected src/index.js to exist");
+            };
+            assert_eq!(file.content().to_str()?, "changed");
+
+            memory_fs.set_file("src/index.js", FileContent::NotFound)?;
+            memory_fs.set_link("src/link.js", LinkContent::NotFound)?;
+            assert!(matches!(
+                &*read("src/index.js").await?,
+                FileContent::NotFound
+            ));
+            assert!(matches!(
+                &*read_dir("src").await?,
+                DirectoryContent::NotFound
+            ));
+            let DirectoryContent::Entries(entries) = &*read_dir("").await? else {
+                panic!("expected the root to exist");
+            };
+            assert!(entries.is_empty());
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn overlay_file_system() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/index.js"), "disk").unwrap();
+        let root: RcStr = dir.path().to_string_lossy().into();
+
+        let tt = turbo_tasks::TurboTasks::new(turbo_tasks_memory::MemoryBackend::new(usize::MAX));
+        tt.run_once(async move {
+            let disk_fs = DiskFileSystem::new("project".into(), root, vec![])
+                .to_resolved()
+                .await?;
+            let overlay_fs = OverlayFileSystem::new(disk_fs).await?;
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(overlay_fs);
+            let overlay_fs = overlay_fs.await?;
+
+            let read = |path: &'static str| async move {
+                let content = FileSystemPath::new_normalized(fs, path.into())
+                    .read()
+                    .strongly_consistent()
+                    .await?;
+                anyhow::Ok(match &*content {
+                    FileContent::Content(file) => Some(file.content().to_str()?.to_string()),
+                    FileContent::NotFound => None,
+                })
+            };
+            let read_dir = |path: &'static str| async move {
+                let content = FileSystemPath::new_normalized(fs, path.into())
+                    .read_dir()
+                    .strongly_consistent()
+                    .await?;
+                anyhow::Ok(match &*content {
+                    DirectoryContent::Entries(entries) => {
+                        let mut entries = entries
+                            .iter()
+                            .map(|(name, entry)| {
+                                (name.to_string(), FileSystemEntryType::from(entry))
+                            })
+                            .collect::<Vec<_>>();
+                        entries.sort_by(|a, b| a.0.cmp(&b.0));
+                        Some(entries)
+                    }
+                    DirectoryContent::NotFound => None,
+                })
+            };
+
+            assert_eq!(read("src/index.js").await?.as_deref(), Some("disk"));
+            overlay_fs.set_override("src/index.js", File::from("buffer").into())?;
+            assert_eq!(read("src/index.js").await?.as_deref(), Some("buffer"));
+
+            // Unsaved files and directories are listed
+            overlay_fs.set_override("lib/util.js", File::from("util").into())?;
+            let root_entries = read_dir("").await?.unwrap();
+            assert_eq!(root_entries.len(), 2);
+            assert_eq!(root_entries[0].0, "lib");
+            assert!(matches!(root_entries[0].1, FileSystemEntryType::Directory));
+            assert!(read_dir("lib").await?.is_some());
+
+            // Hidden files are not listed
+            overlay_fs.set_override("src/index.js", FileContent::NotFound)?;
+            assert_eq!(read("src/index.js").await?, None);
+            assert_eq!(read_dir("src").await?.unwrap().len(), 0);
+
+            overlay_fs.clear_override("src/index.js")?;
+            assert_eq!(read("src/index.js").await?.as_deref(), Some("disk"));
+            overlay_fs.clear_overrides();
+            assert_eq!(read("lib/util.js").await?, None);
+            assert!(read_dir("lib").await?.is_none());
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    /// Reads the archive `content` through an [ArchiveFileSystem], directly and mounted into
+    /// another [FileSystem]. The archive contains `src/index.js`, the executable `bin/cli.js` and
+    /// the symlink `src/link.js` to `index.js`.
+    async fn check_archive_file_system(content: Vec<u8>) {
+        crate::register();
+
+        let tt = turbo_tasks::TurboTasks::new(turbo_tasks_memory::MemoryBackend::new(usize::MAX));
+        tt.run_once(async move {
+            let memory_fs = MemoryFileSystem::new("memory".into());
+            memory_fs
+                .await?
+                .set_file("package.tgz", File::from(content).into())?;
+            let archive_path =
+                FileSystemPath::new_normalized(Vc::upcast(memory_fs), "package.tgz".into());
+            let fs = Vc::upcast::<Box<dyn FileSystem>>(ArchiveFileSystem::new(archive_path));
+            let path = |path: &str| FileSystemPath::new_normalized(fs, path.into());
+
+            let FileContent::Content(file) = &*path("src/index.js").read().await? else {
+                panic!("expected src/index.js to exist");
+            };
+            assert_eq!(file.content().to_str()?, "index");
+            assert_eq!(file.meta.permissions, Permissions::Readable);
+            let FileContent::Content(file) = &*path("bin/cli.js").read().await? else {
+                panic!("expected bin/cli.js to exist");
+            };
+            assert_eq!(file.content().to_str()?, "cli");
+            assert_eq!(file.meta.permissions, Permissions::Executable);
+            assert!(matches!(
+                &*path("src/missing.js").read().await?,
+                FileContent::NotFound
+            ));
+
+            let DirectoryContent::Entries(entries) = &*path("").read_dir().await? else {
+                panic!("expected the root to exist");
+            };
+            assert_eq!(entries.len(), 2);
+            assert!(matches!(
+                entries.get("bin"),
+                Some(DirectoryEntry::Directory(_))
+            ));
+            let DirectoryContent::Entries(entries) = &*path("src").read_dir().await? else {
+                panic!("expected src to exist");
+            };
+            assert_eq!(entries.len(), 2);
+            assert!(matches!(
+                entries.get("index.js"),
+                Some(DirectoryEntry::File(_))
+            ));
+            assert!(matches!(
+                entries.get("link.js"),
+                Some(DirectoryEntry::Symlink(_))
+            ));
+            assert!(matches!(
+                &*path("src/link.js").read_link().await?,
+                LinkContent::Link { target, .. } if target == "index.js"
+            ));
+            // Like on disk, reading a symlink reads its target
+            let FileContent::Content(file) = &*path("src/link.js").read().await? else {
+                panic!("expected src/link.js to be readable");
+            };
+            assert_eq!(file.content().to_str()?, "index");
+            assert_eq!(
+                *path("src/link.js").realpath().await?,
+                *path("src/index.js").await?
+            );
+
+            let mount_path = FileSystemPath::new_normalized(Vc::upcast(memory_fs), "mount".into());
+            let mounted = Vc::upcast::<Box<dyn FileSystem>>(
+                ArchiveFileSystem::new(archive_path).mount(mount_path),
+            );
+            let mounted_path = |path: &str| FileSystemPath::new_normalized(mounted, path.into());
+            let FileContent::Content(file) = &*mounted_path("mount/src/index.js").read().await?
+            else {
+                panic!("expected mount/src/index.js to exist");
+            };
+            assert_eq!(file.content().to_str()?, "index");
+            let DirectoryContent::Entries(entries) = &*mounted_path("mount").read_dir().await?
+            else {
+                panic!("expected the mount path to exist");
+            };
+            assert!(matches!(
+                entries.get("src"),
+                Some(DirectoryEntry::Directory(_))
+            ));
+
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn archive_file_system_zip() {
+        use std::io::Write;
+
+        use zip::{write::FileOptions, ZipWriter};
+
+        let mut zip = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip.add_directory("src/", FileOptions::default()).unwrap();
+        zip.start_file(
+            "src/index.js",
+            FileOptions::default().unix_permissions(0o644),
+        )
+        .unwrap();
+        zip.write_all(b"index").unwrap();
+        zip.add_symlink("src/link.js", "index.js", FileOptions::default())
+            .unwrap();
+        zip.start_file("bin/cli.js", FileOptions::default().unix_permissions(0o755))
+            .unwrap();
+        zip.write_all(b"cli").unwrap();
+        let content = zip.finish().unwrap().into_inner();
+
+        check_archive_file_system(content).await;
+    }
+
+    #[tokio::test]
+    async fn archive_file_system_tgz() {
+        use flate2::{write::GzEncoder, Compression};
+        use tar::{Builder, EntryType, Header};
+
+        let mut tar = Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let mut append_file = |path: &str, mode: u32, content: &[u8]| {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(mode);
+            tar.append_data(&mut header, path, content).unwrap();
+        };
+        // The directories are not stored in the archive
+        append_file("src/index.js", 0o644, b"index");
+        append_file("bin/cli.js", 0o755, b"cli");
+        let mut header = Header::new_gnu();
+        header.set_entry_type(EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        tar.append_link(&mut header, "src/link.js", "index.js")
+            .unwrap();
+        let content = tar.into_inner().unwrap().finish().unwrap();
+
+        check_archive_file_system(content).await;
+    }
+
     #[tokio::test]
     async fn file_stem() {
         crate::register();
@@ -0,0 +1,405 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Debug, Formatter},
+    mem::discriminant,
+    ops::Bound,
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use auto_hash_map::AutoMap;
+use parking_lot::Mutex;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{
+    effect, mark_session_dependent, Completion, InvalidationReason, Invalidator, ValueToString, Vc,
+};
+
+use crate::{
+    invalidation::{WatchChange, Write},
+    invalidator_map::InvalidatorMap,
+    util::normalize_path,
+    DirectoryContent, DirectoryEntry, File, FileContent, FileMeta, FileSystem, FileSystemPath,
+    LinkContent, LinkType,
+};
+
+#[derive(Clone, PartialEq)]
+enum MemoryEntry {
+    File(File),
+    Symlink { target: RcStr, link_type: LinkType },
+}
+
+/// The paths that are affected by a change of an entry.
+#[derive(Default)]
+struct ChangedPaths {
+    /// Paths that were created, changed or removed.
+    paths: Vec<String>,
+    /// Directories that got entries added or removed.
+    dirs: Vec<String>,
+}
+
+struct MemoryFileSystemInner {
+    name: RcStr,
+    /// Files and symlinks keyed by their path relative to the root. Directories are not stored,
+    /// they exist as long as they contain an entry.
+    entries: Mutex<BTreeMap<String, MemoryEntry>>,
+    invalidator_map: InvalidatorMap,
+    dir_invalidator_map: InvalidatorMap,
+}
+
+fn parent_path(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Returns true when `path` is the root or an entry exists below it.
+fn is_dir(entries: &BTreeMap<String, MemoryEntry>, path: &str) -> bool {
+    if path.is_empty() {
+        return true;
+    }
+    let prefix = format!("{path}/");
+    entries
+        .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+        .next()
+        .is_some_and(|(key, _)| key.starts_with(&prefix))
+}
+
+impl MemoryFileSystemInner {
+    fn format_path(&self, path: &str) -> String {
+        format!("[{}]/{}", self.name, path)
+    }
+
+    /// registers the path as an invalidator for the current task,
+    /// has to be called within a turbo-tasks function
+    fn register_invalidator(&self, path: &str) {
+        let invalidator = turbo_tasks::get_invalidator();
+        self.invalidator_map.insert(path.to_string(), invalidator);
+    }
+
+    /// registers the path as an invalidator for the current task,
+    /// has to be called within a turbo-tasks function
+    fn register_dir_invalidator(&self, path: &str) {
+        let invalidator = turbo_tasks::get_invalidator();
+        self.dir_invalidator_map
+            .insert(path.to_string(), invalidator);
+    }
+
+    fn get(&self, path: &str) -> Option<MemoryEntry> {
+        self.entries.lock().get(path).cloned()
+    }
+
+    /// Sets or removes the entry at `path`. When `writer` is passed, it becomes the only
+    /// invalidator of `path`, so that the writing task runs again when the entry changes.
+    fn set_entry<R: InvalidationReason>(
+        &self,
+        path: &str,
+        entry: Option<MemoryEntry>,
+        writer: Option<Invalidator>,
+        reason: impl Fn(String) -> R,
+    ) -> Result<()> {
+        let Some(path) = normalize_path(path).filter(|path| !path.is_empty()) else {
+            bail!("invalid path {} in {}", path, self.name);
+        };
+        let mut entries = self.entries.lock();
+        let ancestors = path
+            .match_indices('/')
+            .map(|(i, _)| &path[..i])
+            .collect::<Vec<_>>();
+        if let Some(file) = ancestors.iter().find(|dir| entries.contains_key(**dir)) {
+            bail!(
+                "{} is not a directory, unable to write {}",
+                self.format_path(file),
+                self.format_path(&path)
+            );
+        }
+        if entry.is_some() && is_dir(&entries, &path) {
+            bail!("{} is a directory", self.format_path(&path));
+        }
+
+        let old_entry = entries.get(&path);
+        if old_entry == entry.as_ref() {
+            drop(entries);
+            if let Some(writer) = writer {
+                self.invalidator_map.insert(path, writer);
+            }
+            return Ok(());
+        }
+        let kind_changed = match (old_entry, &entry) {
+            (Some(old_entry), Some(entry)) => discriminant(old_entry) != discriminant(entry),
+            _ => true,
+        };
+
+        let existed = ancestors
+            .iter()
+            .map(|dir| is_dir(&entries, dir))
+            .collect::<Vec<_>>();
+        match entry {
+            Some(entry) => entries.insert(path.clone(), entry),
+            None => entries.remove(&path),
+        };
+        let mut changed = ChangedPaths::default();
+        for (dir, existed) in ancestors.iter().zip(existed) {
+            if is_dir(&entries, dir) != existed {
+                changed.paths.push(dir.to_string());
+                changed.dirs.push(parent_path(dir).to_string());
+            }
+        }
+        drop(entries);
+        if kind_changed {
+            changed.dirs.push(parent_path(&path).to_string());
+        }
+
+        let old_invalidators = {
+            let mut invalidator_map = self.invalidator_map.lock().unwrap();
+            match writer {
+                Some(writer) => invalidator_map.insert(path.clone(), [writer].into()),
+                None => invalidator_map.remove(&path),
+            }
+        };
+        for invalidator in old_invalidators.into_iter().flatten() {
+            invalidator.invalidate_with_reason(reason(self.format_path(&path)));
+        }
+        self.invalidate(changed, reason);
+        Ok(())
+    }
+
+    fn invalidate<R: InvalidationReason>(
+        &self,
+        changed: ChangedPaths,
+        reason: impl Fn(String) -> R,
+    ) {
+        let ChangedPaths { paths, dirs } = changed;
+        let invalidators = {
+            let mut invalidator_map = self.invalidator_map.lock().unwrap();
+            let mut dir_invalidator_map = self.dir_invalidator_map.lock().unwrap();
+            paths
+                .into_iter()
+                .filter_map(|path| Some((invalidator_map.remove(&path)?, path)))
+                .chain(
+                    dirs.into_iter()
+                        .filter_map(|dir| Some((dir_invalidator_map.remove(&dir)?, dir))),
+                )
+                .collect::<Vec<(HashSet<Invalidator>, String)>>()
+        };
+        for (invalidators, path) in invalidators {
+            for invalidator in invalidators {
+                invalidator.invalidate_with_reason(reason(self.format_path(&path)));
+            }
+        }
+    }
+}
+
+/// A writable file system that is kept in memory, e. g. to compile a project from the buffers of
+/// an editor. Changes invalidate the tasks that read the affected paths like changes on disk do.
+///
+/// Directories are created implicitly when an entry is written into them and disappear with their
+/// last entry. Symlinks are not followed, use [`FileSystemPath::realpath`] to resolve them.
+#[turbo_tasks::value(serialization = "none", cell = "new", eq = "manual")]
+pub struct MemoryFileSystem {
+    #[turbo_tasks(debug_ignore, trace_ignore)]
+    inner: Arc<MemoryFileSystemInner>,
+}
+
+impl MemoryFileSystem {
+    /// Creates a new empty [`Vc<MemoryFileSystem>`].
+    ///
+    /// NOTE: This function is not a `turbo_tasks::function` to avoid instances
+    /// being equivalent identity-wise. Every file system holds its own entries.
+    pub fn new(name: RcStr) -> Vc<Self> {
+        Self::cell(MemoryFileSystem {
+            inner: Arc::new(MemoryFileSystemInner {
+                name,
+                entries: Default::default(),
+                invalidator_map: InvalidatorMap::new(),
+                dir_invalidator_map: InvalidatorMap::new(),
+            }),
+        })
+    }
+
+    pub fn name(&self) -> &RcStr {
+        &self.inner.name
+    }
+
+    /// Sets the content of the file at `path`, which is relative to the root.
+    /// [`FileContent::NotFound`] removes the file.
+    pub fn set_file(&self, path: &str, content: FileContent) -> Result<()> {
+        let entry = match content {
+            FileContent::Content(file) => Some(MemoryEntry::File(file)),
+            FileContent::NotFound => None,
+        };
+        self.inner
+            .set_entry(path, entry, None, |path| WatchChange { path })
+    }
+
+    /// Sets the symlink at `path`, which is relative to the root. [`LinkContent::NotFound`]
+    /// removes the symlink.
+    pub fn set_link(&self, path: &str, content: LinkContent) -> Result<()> {
+        let entry = match content {
+            LinkContent::Link { target, link_type } => {
+                Some(MemoryEntry::Symlink { target, link_type })
+            }
+            LinkContent::Invalid => bail!("invalid symlink target for {}", path),
+            LinkContent::NotFound => None,
+        };
+        self.inner
+            .set_entry(path, entry, None, |path| WatchChange { path })
+    }
+}
+
+impl Debug for MemoryFileSystem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "name: {}", self.inner.name)
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl FileSystem for MemoryFileSystem {
+    #[turbo_tasks::function]
+    async fn read(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(match self.inner.get(path) {
+            Some(MemoryEntry::File(file)) => FileContent::Content(file),
+            _ => FileContent::NotFound,
+        }
+        .cell())
+    }
+
+    #[turbo_tasks::function]
+    async fn read_link(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<LinkContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(match self.inner.get(path) {
+            Some(MemoryEntry::Symlink { target, link_type }) => {
+                LinkContent::Link { target, link_type }
+            }
+            _ => LinkContent::NotFound,
+        }
+        .cell())
+    }
+
+    #[turbo_tasks::function]
+    async fn read_dir(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<DirectoryContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_dir_invalidator(path);
+
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{path}/")
+        };
+        let children = {
+            let entries = self.inner.entries.lock();
+            if !is_dir(&entries, path) {
+                return Ok(DirectoryContent::not_found());
+            }
+            let mut children = BTreeMap::new();
+            for (key, entry) in entries
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|(key, _)| key.starts_with(&prefix))
+            {
+                let rest = &key[prefix.len()..];
+                let (name, is_nested) = match rest.split_once('/') {
+                    Some((name, _)) => (name, true),
+                    None => (rest, false),
+                };
+                children.entry(RcStr::from(name)).or_insert(if is_nested {
+                    None
+                } else {
+                    Some(matches!(entry, MemoryEntry::Symlink { .. }))
+                });
+            }
+            children
+        };
+
+        let mut entries = AutoMap::new();
+        for (name, is_symlink) in children {
+            let entry_path = fs_path.join(name.clone()).to_resolved().await?;
+            entries.insert(
+                name,
+                match is_symlink {
+                    None => DirectoryEntry::Directory(entry_path),
+                    Some(false) => DirectoryEntry::File(entry_path),
+                    Some(true) => DirectoryEntry::Symlink(entry_path),
+                },
+            );
+        }
+        Ok(DirectoryContent::new(entries))
+    }
+
+    #[turbo_tasks::function]
+    async fn track(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<Completion>> {
+        mark_session_dependent();
+        self.inner.register_invalidator(&fs_path.await?.path);
+        Ok(Completion::new())
+    }
+
+    #[turbo_tasks::function]
+    async fn write(&self, fs_path: Vc<FileSystemPath>, content: Vc<FileContent>) -> Result<()> {
+        mark_session_dependent();
+        let path = fs_path.await?.path.clone();
+        let content = content.await?;
+        let inner = self.inner.clone();
+        let invalidator = turbo_tasks::get_invalidator();
+
+        effect(async move {
+            let entry = match &*content {
+                FileContent::Content(file) => Some(MemoryEntry::File(file.clone())),
+                FileContent::NotFound => None,
+            };
+            inner.set_entry(&path, entry, Some(invalidator), |path| Write { path })
+        });
+
+        Ok(())
+    }
+
+    #[turbo_tasks::function]
+    async fn write_link(&self, fs_path: Vc<FileSystemPath>, target: Vc<LinkContent>) -> Result<()> {
+        mark_session_dependent();
+        let path = fs_path.await?.path.clone();
+        let content = target.await?;
+        let inner = self.inner.clone();
+        let invalidator = turbo_tasks::get_invalidator();
+
+        effect(async move {
+            let entry = match &*content {
+                LinkContent::Link { target, link_type } => Some(MemoryEntry::Symlink {
+                    target: target.clone(),
+                    link_type: *link_type,
+                }),
+                LinkContent::Invalid => {
+                    bail!("invalid symlink target: {}", inner.format_path(&path))
+                }
+                LinkContent::NotFound => None,
+            };
+            inner.set_entry(&path, entry, Some(invalidator), |path| Write { path })
+        });
+
+        Ok(())
+    }
+
+    #[turbo_tasks::function]
+    async fn metadata(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileMeta>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+
+        let entries = self.inner.entries.lock();
+        Ok(match entries.get(&**path) {
+            Some(MemoryEntry::File(file)) => file.meta.clone(),
+            Some(MemoryEntry::Symlink { .. }) => FileMeta::default(),
+            None if is_dir(&entries, path) => FileMeta::default(),
+            None => bail!("reading metadata for {}", self.inner.format_path(path)),
+        }
+        .cell())
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl ValueToString for MemoryFileSystem {
+    #[turbo_tasks::function]
+    fn to_string(&self) -> Vc<RcStr> {
+        Vc::cell(self.inner.name.clone())
+    }
+}
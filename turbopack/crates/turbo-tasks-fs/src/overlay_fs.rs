@@ -0,0 +1,310 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::{self, Debug, Formatter},
+    ops::Bound,
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use auto_hash_map::AutoMap;
+use parking_lot::Mutex;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{mark_session_dependent, Completion, Invalidator, ResolvedVc, ValueToString, Vc};
+
+use crate::{
+    invalidation::WatchChange, invalidator_map::InvalidatorMap, util::normalize_path,
+    DirectoryContent, DirectoryEntry, DiskFileSystem, File, FileContent, FileMeta, FileSystem,
+    FileSystemPath, LinkContent,
+};
+
+struct OverlayFileSystemInner {
+    name: RcStr,
+    /// Overridden files keyed by their path relative to the root. `None` hides the file on disk.
+    overrides: Mutex<BTreeMap<String, Option<File>>>,
+    invalidator_map: InvalidatorMap,
+    dir_invalidator_map: InvalidatorMap,
+}
+
+impl OverlayFileSystemInner {
+    /// registers the path as an invalidator for the current task,
+    /// has to be called within a turbo-tasks function
+    fn register_invalidator(&self, path: &str) {
+        let invalidator = turbo_tasks::get_invalidator();
+        self.invalidator_map.insert(path.to_string(), invalidator);
+    }
+
+    /// registers the path as an invalidator for the current task,
+    /// has to be called within a turbo-tasks function
+    fn register_dir_invalidator(&self, path: &str) {
+        let invalidator = turbo_tasks::get_invalidator();
+        self.dir_invalidator_map
+            .insert(path.to_string(), invalidator);
+    }
+
+    fn get(&self, path: &str) -> Option<Option<File>> {
+        self.overrides.lock().get(path).cloned()
+    }
+
+    /// Sets or clears the override of `path` and invalidates the tasks that read it. Directory
+    /// listings are only invalidated when the override hides or adds a file, but that might
+    /// create or remove a directory, so all ancestors are invalidated.
+    fn set(&self, path: &str, file: Option<Option<File>>) -> Result<()> {
+        let Some(path) = normalize_path(path).filter(|path| !path.is_empty()) else {
+            bail!("invalid path {} in {}", path, self.name);
+        };
+        let old_file = {
+            let mut overrides = self.overrides.lock();
+            let old_file = match &file {
+                Some(file) => overrides.insert(path.clone(), file.clone()),
+                None => overrides.remove(&path),
+            };
+            if old_file == file {
+                return Ok(());
+            }
+            old_file
+        };
+        let mut dirs = Vec::new();
+        if !matches!((old_file, file), (Some(Some(_)), Some(Some(_)))) {
+            dirs.push(String::new());
+            dirs.extend(path.match_indices('/').map(|(i, _)| path[..i].to_string()));
+        }
+        self.invalidate(vec![path], dirs);
+        Ok(())
+    }
+
+    fn invalidate(&self, paths: Vec<String>, dirs: Vec<String>) {
+        let invalidators = {
+            let mut invalidator_map = self.invalidator_map.lock().unwrap();
+            let mut dir_invalidator_map = self.dir_invalidator_map.lock().unwrap();
+            paths
+                .into_iter()
+                .filter_map(|path| Some((invalidator_map.remove(&path)?, path)))
+                .chain(
+                    dirs.into_iter()
+                        .filter_map(|dir| Some((dir_invalidator_map.remove(&dir)?, dir))),
+                )
+                .collect::<Vec<(HashSet<Invalidator>, String)>>()
+        };
+        for (invalidators, path) in invalidators {
+            for invalidator in invalidators {
+                invalidator.invalidate_with_reason(WatchChange {
+                    path: format!("[{}]/{}", self.name, path),
+                });
+            }
+        }
+    }
+}
+
+/// A [FileSystem] that layers in-memory overrides of files on top of a [DiskFileSystem], e. g.
+/// the unsaved buffers of an editor. Paths without an override are read from disk.
+///
+/// Writes always go to disk, they don't change the overrides.
+#[turbo_tasks::value(serialization = "none", cell = "new", eq = "manual")]
+pub struct OverlayFileSystem {
+    base: ResolvedVc<DiskFileSystem>,
+    #[turbo_tasks(debug_ignore, trace_ignore)]
+    inner: Arc<OverlayFileSystemInner>,
+}
+
+impl OverlayFileSystem {
+    /// Creates a new [`Vc<OverlayFileSystem>`] without overrides on top of `base`.
+    ///
+    /// NOTE: This function is not a `turbo_tasks::function` to avoid instances
+    /// being equivalent identity-wise. Every file system holds its own overrides.
+    pub async fn new(base: ResolvedVc<DiskFileSystem>) -> Result<Vc<Self>> {
+        let name = base.await?.name().clone();
+        Ok(Self::cell(OverlayFileSystem {
+            base,
+            inner: Arc::new(OverlayFileSystemInner {
+                name,
+                overrides: Default::default(),
+                invalidator_map: InvalidatorMap::new(),
+                dir_invalidator_map: InvalidatorMap::new(),
+            }),
+        }))
+    }
+
+    /// Overrides the file at `path`, which is relative to the root. [`FileContent::NotFound`]
+    /// hides the file on disk.
+    pub fn set_override(&self, path: &str, content: FileContent) -> Result<()> {
+        let file = match content {
+            FileContent::Content(file) => Some(file),
+            FileContent::NotFound => None,
+        };
+        self.inner.set(path, Some(file))
+    }
+
+    /// Removes the override of `path`, so that the file is read from disk again.
+    pub fn clear_override(&self, path: &str) -> Result<()> {
+        self.inner.set(path, None)
+    }
+
+    /// Removes all overrides.
+    pub fn clear_overrides(&self) {
+        let paths = std::mem::take(&mut *self.inner.overrides.lock())
+            .into_keys()
+            .collect::<Vec<_>>();
+        let mut dirs = vec![String::new()];
+        for path in paths.iter() {
+            dirs.extend(path.match_indices('/').map(|(i, _)| path[..i].to_string()));
+        }
+        dirs.sort_unstable();
+        dirs.dedup();
+        self.inner.invalidate(paths, dirs);
+    }
+
+    fn base_path(&self, path: &RcStr) -> Vc<FileSystemPath> {
+        FileSystemPath::new_normalized(Vc::upcast(*self.base), path.clone())
+    }
+}
+
+impl Debug for OverlayFileSystem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "overlay of {}", self.inner.name)
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl FileSystem for OverlayFileSystem {
+    #[turbo_tasks::function]
+    async fn read(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(match self.inner.get(path) {
+            Some(Some(file)) => FileContent::Content(file).cell(),
+            Some(None) => FileContent::NotFound.cell(),
+            None => self.base_path(path).read(),
+        })
+    }
+
+    #[turbo_tasks::function]
+    async fn read_link(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<LinkContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(match self.inner.get(path) {
+            Some(_) => LinkContent::NotFound.cell(),
+            None => self.base_path(path).read_link(),
+        })
+    }
+
+    #[turbo_tasks::function]
+    async fn read_dir(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<DirectoryContent>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_dir_invalidator(path);
+
+        let prefix = if path.is_empty() {
+            String::new()
+        } else {
+            format!("{path}/")
+        };
+        // `None` hides an entry, otherwise it's added as a directory or a file
+        let mut overrides: BTreeMap<RcStr, Option<bool>> = BTreeMap::new();
+        for (key, file) in self
+            .inner
+            .overrides
+            .lock()
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(&prefix))
+        {
+            let rest = &key[prefix.len()..];
+            match rest.split_once('/') {
+                // A file in a subdirectory makes sure that the subdirectory exists
+                Some((name, _)) => {
+                    if file.is_some() {
+                        overrides.insert(name.into(), Some(true));
+                    }
+                }
+                None => {
+                    overrides.insert(rest.into(), file.is_some().then_some(false));
+                }
+            }
+        }
+
+        let base_content = self.base_path(path).read_dir().await?;
+        let base_entries = match &*base_content {
+            DirectoryContent::Entries(entries) => Some(entries),
+            DirectoryContent::NotFound => None,
+        };
+        if base_entries.is_none() && !overrides.values().any(|entry| entry.is_some()) {
+            return Ok(DirectoryContent::not_found());
+        }
+
+        let mut entries = AutoMap::new();
+        for (name, entry) in base_entries.into_iter().flatten() {
+            if overrides.contains_key(name) {
+                continue;
+            }
+            let entry_path = fs_path.join(name.clone()).to_resolved().await?;
+            entries.insert(
+                name.clone(),
+                match entry {
+                    DirectoryEntry::File(_) => DirectoryEntry::File(entry_path),
+                    DirectoryEntry::Directory(_) => DirectoryEntry::Directory(entry_path),
+                    DirectoryEntry::Symlink(_) => DirectoryEntry::Symlink(entry_path),
+                    DirectoryEntry::Other(_) => DirectoryEntry::Other(entry_path),
+                    DirectoryEntry::Error => DirectoryEntry::Error,
+                },
+            );
+        }
+        for (name, is_dir) in overrides {
+            let Some(is_dir) = is_dir else {
+                continue;
+            };
+            let entry_path = fs_path.join(name.clone()).to_resolved().await?;
+            entries.insert(
+                name,
+                if is_dir {
+                    DirectoryEntry::Directory(entry_path)
+                } else {
+                    DirectoryEntry::File(entry_path)
+                },
+            );
+        }
+        Ok(DirectoryContent::new(entries))
+    }
+
+    #[turbo_tasks::function]
+    async fn track(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<Completion>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(self.base_path(path).track())
+    }
+
+    #[turbo_tasks::function]
+    async fn write(&self, fs_path: Vc<FileSystemPath>, content: Vc<FileContent>) -> Result<Vc<()>> {
+        Ok(self.base_path(&fs_path.await?.path).write(content))
+    }
+
+    #[turbo_tasks::function]
+    async fn write_link(
+        &self,
+        fs_path: Vc<FileSystemPath>,
+        target: Vc<LinkContent>,
+    ) -> Result<Vc<()>> {
+        Ok(self.base_path(&fs_path.await?.path).write_link(target))
+    }
+
+    #[turbo_tasks::function]
+    async fn metadata(&self, fs_path: Vc<FileSystemPath>) -> Result<Vc<FileMeta>> {
+        mark_session_dependent();
+        let path = &fs_path.await?.path;
+        self.inner.register_invalidator(path);
+        Ok(match self.inner.get(path) {
+            Some(Some(file)) => file.meta.clone().cell(),
+            Some(None) => bail!("{} is hidden by an override", fs_path.to_string().await?),
+            None => self.base_path(path).metadata(),
+        })
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl ValueToString for OverlayFileSystem {
+    #[turbo_tasks::function]
+    fn to_string(&self) -> Vc<RcStr> {
+        Vc::cell(self.inner.name.clone())
+    }
+}
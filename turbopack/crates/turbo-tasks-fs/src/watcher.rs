@@ -1,9 +1,10 @@
 use std::{
     collections::{HashMap, HashSet},
+    io,
     mem::take,
     path::{Path, PathBuf},
     sync::{
-        mpsc::{channel, Receiver, TryRecvError},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
         Arc, Mutex,
     },
     time::Duration,
@@ -17,28 +18,122 @@ use notify::{
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
+use turbo_rcstr::RcStr;
 use turbo_tasks::{spawn_thread, Invalidator};
 
 use crate::{
     format_absolute_fs_path,
     invalidation::{WatchChange, WatchStart},
-    path_to_key, DiskFileSystemInner,
+    path_to_key,
+    util::sys_to_unix,
+    DiskFileSystemInner,
 };
 
+/// The interval in which paths are polled that can't be watched natively.
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Directories are watched recursively on OSs that support it, otherwise every directory is
+/// watched on its own.
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const FALLBACK_RECURSIVE_MODE: RecursiveMode = RecursiveMode::Recursive;
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const FALLBACK_RECURSIVE_MODE: RecursiveMode = RecursiveMode::NonRecursive;
+
+/// Why a part of the file system isn't observed by the native file watcher alone.
+#[turbo_tasks::value(shared)]
+#[derive(Clone, Copy, Debug)]
+pub enum WatcherDegradationKind {
+    /// The OS limit of file watches was reached, e. g. `fs.inotify.max_user_watches` on Linux.
+    /// The path is polled instead.
+    WatchLimit,
+    /// The OS dropped events because they were produced faster than they were read, e. g. when
+    /// the inotify event queue overflowed. The path was rescanned and, if the OS reported it, is
+    /// polled from now on.
+    EventOverflow,
+}
+
+/// A path that isn't observed by the native file watcher alone, see [WatcherDegradationKind].
+#[turbo_tasks::value(shared)]
+#[derive(Clone, Debug)]
+pub struct WatcherDegradation {
+    pub kind: WatcherDegradationKind,
+    /// The path relative to the root of the file system, empty for the root.
+    pub path: RcStr,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct WatcherDegradations(Vec<WatcherDegradation>);
+
+#[derive(Default)]
+struct DegradationState {
+    degradations: Vec<WatcherDegradation>,
+    /// Tasks that read the degradations and need to be invalidated when new ones are reported.
+    invalidators: HashSet<Invalidator>,
+}
+
 enum DiskWatcherInternal {
-    Recommended(RecommendedWatcher),
+    Recommended {
+        watcher: RecommendedWatcher,
+        /// Polls the paths that can't be watched natively. It's created on demand.
+        fallback: Option<PollWatcher>,
+        sender: Sender<notify::Result<notify::Event>>,
+    },
     Polling(PollWatcher),
 }
 
 impl DiskWatcherInternal {
-    fn watch(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
+    /// Starts watching `path`. When the OS limit of native watches is reached, the path is
+    /// polled instead and `true` is returned.
+    fn watch(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<bool> {
+        match self {
+            DiskWatcherInternal::Recommended { watcher, .. } => {
+                match watcher.watch(path, recursive_mode) {
+                    Ok(()) => Ok(false),
+                    Err(err) if is_watch_limit_error(&err.kind) => {
+                        self.poll(path, recursive_mode)?;
+                        Ok(true)
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            DiskWatcherInternal::Polling(watcher) => {
+                watcher.watch(path, recursive_mode)?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Polls `path` in addition to watching it natively.
+    fn poll(&mut self, path: &Path, recursive_mode: RecursiveMode) -> notify::Result<()> {
         match self {
-            DiskWatcherInternal::Recommended(watcher) => watcher.watch(path, recursive_mode),
-            DiskWatcherInternal::Polling(watcher) => watcher.watch(path, recursive_mode),
+            DiskWatcherInternal::Recommended {
+                fallback, sender, ..
+            } => {
+                let fallback = match fallback {
+                    Some(fallback) => fallback,
+                    None => fallback.insert(PollWatcher::new(
+                        sender.clone(),
+                        Config::default().with_poll_interval(FALLBACK_POLL_INTERVAL),
+                    )?),
+                };
+                fallback.watch(path, recursive_mode)
+            }
+            // Everything is polled already
+            DiskWatcherInternal::Polling(_) => Ok(()),
         }
     }
 }
 
+/// Returns true when the error is caused by the OS limit of file watches, e. g. `ENOSPC` from
+/// `inotify_add_watch`.
+fn is_watch_limit_error(kind: &notify::ErrorKind) -> bool {
+    match kind {
+        notify::ErrorKind::MaxFilesWatch => true,
+        notify::ErrorKind::Io(err) => err.kind() == io::ErrorKind::StorageFull,
+        _ => false,
+    }
+}
+
 #[derive(Default, Serialize, Deserialize)]
 pub(crate) struct DiskWatcher {
     #[serde(skip)]
@@ -55,6 +150,10 @@ pub(crate) struct DiskWatcher {
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
     #[serde(skip)]
     watching: dashmap::DashSet<PathBuf>,
+
+    /// Paths that are not observed by the native watcher alone.
+    #[serde(skip)]
+    degradations: Mutex<DegradationState>,
 }
 
 impl DiskWatcher {
@@ -97,27 +196,84 @@ impl DiskWatcher {
 
         if let Some(watcher) = watcher.as_mut() {
             let mut path = dir_path;
-            while let Err(err) = watcher.watch(path, RecursiveMode::NonRecursive) {
-                if path == root_path {
-                    return Err(err).context(format!(
-                        "Unable to watch {} (tried up to {})",
-                        dir_path.display(),
-                        path.display()
-                    ));
+            let polling = loop {
+                match watcher.watch(path, RecursiveMode::NonRecursive) {
+                    Ok(polling) => break polling,
+                    Err(err) => {
+                        if path == root_path {
+                            return Err(err).context(format!(
+                                "Unable to watch {} (tried up to {})",
+                                dir_path.display(),
+                                path.display()
+                            ));
+                        }
+                        let Some(parent_path) = path.parent() else {
+                            return Err(err).context(format!(
+                                "Unable to watch {} (tried up to {})",
+                                dir_path.display(),
+                                path.display()
+                            ));
+                        };
+                        path = parent_path;
+                    }
                 }
-                let Some(parent_path) = path.parent() else {
-                    return Err(err).context(format!(
-                        "Unable to watch {} (tried up to {})",
-                        dir_path.display(),
-                        path.display()
-                    ));
-                };
-                path = parent_path;
+            };
+            if polling {
+                self.report_degradation(WatcherDegradationKind::WatchLimit, path, root_path);
             }
         }
         Ok(())
     }
 
+    /// Returns the paths that are not observed by the native watcher alone. The current task is
+    /// invalidated when more are reported.
+    pub(crate) fn degradations(&self) -> Vec<WatcherDegradation> {
+        let mut state = self.degradations.lock().unwrap();
+        state.invalidators.insert(turbo_tasks::get_invalidator());
+        state.degradations.clone()
+    }
+
+    fn report_degradation(&self, kind: WatcherDegradationKind, path: &Path, root_path: &Path) {
+        let path = path
+            .strip_prefix(root_path)
+            .unwrap_or(path)
+            .to_string_lossy();
+        let degradation = WatcherDegradation {
+            kind,
+            path: sys_to_unix(&path).into(),
+        };
+        let invalidators = {
+            let mut state = self.degradations.lock().unwrap();
+            if state.degradations.contains(&degradation) {
+                return;
+            }
+            state.degradations.push(degradation);
+            take(&mut state.invalidators)
+        };
+        for invalidator in invalidators {
+            invalidator.invalidate();
+        }
+    }
+
+    /// Polls `paths` in addition to watching them natively, e. g. because events were dropped.
+    fn fall_back_to_polling(
+        &self,
+        kind: WatcherDegradationKind,
+        paths: &[PathBuf],
+        root_path: &Path,
+    ) {
+        let mut watcher = self.watcher.lock().unwrap();
+        let Some(watcher) = watcher.as_mut() else {
+            return;
+        };
+        for path in paths {
+            match watcher.poll(path, FALLBACK_RECURSIVE_MODE) {
+                Ok(()) => self.report_degradation(kind, path, root_path),
+                Err(err) => println!("unable to poll {}: {}", path.display(), err),
+            }
+        }
+    }
+
     /// Create a watcher and start watching by creating `debounced` watcher
     /// via `full debouncer`
     ///
@@ -155,19 +311,35 @@ impl DiskWatcher {
 
             DiskWatcherInternal::Polling(PollWatcher::new(tx, config)?)
         } else {
-            DiskWatcherInternal::Recommended(RecommendedWatcher::new(tx, Config::default())?)
+            DiskWatcherInternal::Recommended {
+                watcher: RecommendedWatcher::new(tx.clone(), Config::default())?,
+                fallback: None,
+                sender: tx,
+            }
         };
 
         // Add a path to be watched. All files and directories at that path and
         // below will be monitored for changes.
         #[cfg(any(target_os = "macos", target_os = "windows"))]
         {
-            watcher.watch(inner.root_path(), RecursiveMode::Recursive)?;
+            if watcher.watch(inner.root_path(), RecursiveMode::Recursive)? {
+                self.report_degradation(
+                    WatcherDegradationKind::WatchLimit,
+                    inner.root_path(),
+                    inner.root_path(),
+                );
+            }
         }
 
         #[cfg(not(any(target_os = "macos", target_os = "windows")))]
         for dir_path in self.watching.iter() {
-            watcher.watch(&dir_path, RecursiveMode::NonRecursive)?;
+            if watcher.watch(&dir_path, RecursiveMode::NonRecursive)? {
+                self.report_degradation(
+                    WatcherDegradationKind::WatchLimit,
+                    &dir_path,
+                    inner.root_path(),
+                );
+            }
         }
 
         // We need to invalidate all reads that happened before watching
@@ -252,6 +424,29 @@ impl DiskWatcher {
             let mut event = rx.recv().or(Err(TryRecvError::Disconnected));
             loop {
                 match event {
+                    // Events were dropped, so everything below the reported paths might have
+                    // changed. inotify doesn't report which directories were affected by an
+                    // overflow, polling all of them would be too expensive, so they are only
+                    // rescanned.
+                    Ok(Ok(event)) if event.need_rescan() => {
+                        let paths = if event.paths.is_empty() {
+                            self.report_degradation(
+                                WatcherDegradationKind::EventOverflow,
+                                inner.root_path(),
+                                inner.root_path(),
+                            );
+                            vec![inner.root_path().to_path_buf()]
+                        } else {
+                            self.fall_back_to_polling(
+                                WatcherDegradationKind::EventOverflow,
+                                &event.paths,
+                                inner.root_path(),
+                            );
+                            event.paths
+                        };
+                        batched_invalidate_path_and_children.extend(paths.clone());
+                        batched_invalidate_path_and_children_dir.extend(paths);
+                    }
                     Ok(Ok(notify::Event { kind, paths, .. })) => {
                         let paths: Vec<PathBuf> = paths
                             .iter()
@@ -362,6 +557,14 @@ impl DiskWatcher {
                     Ok(Err(notify::Error { kind, paths })) => {
                         println!("watch error ({:?}): {:?} ", paths, kind);
 
+                        if is_watch_limit_error(&kind) {
+                            self.fall_back_to_polling(
+                                WatcherDegradationKind::WatchLimit,
+                                &paths,
+                                inner.root_path(),
+                            );
+                        }
+
                         if paths.is_empty() {
                             batched_invalidate_path_and_children
                                 .insert(inner.root_path().to_path_buf());
@@ -487,3 +690,188 @@ fn invalidate_path_and_children_execute(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use notify::event::Flag;
+    use turbo_tasks::Vc;
+
+    use super::*;
+    use crate::{DiskFileSystem, FileContent, FileSystem};
+
+    #[tokio::test]
+    async fn event_overflow() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("index.js");
+        std::fs::write(&file_path, "before").unwrap();
+        let root: RcStr = dir.path().to_string_lossy().into();
+
+        let tt = turbo_tasks::TurboTasks::new(turbo_tasks_memory::MemoryBackend::new(usize::MAX));
+        tt.run_once(async move {
+            let disk_fs = DiskFileSystem::new("project".into(), root, vec![]);
+            let inner = disk_fs.await?.inner.clone();
+            let read = || async move {
+                let content = Vc::upcast::<Box<dyn FileSystem>>(disk_fs)
+                    .root()
+                    .join("index.js".into())
+                    .read()
+                    .strongly_consistent()
+                    .await?;
+                let FileContent::Content(file) = &*content else {
+                    panic!("expected index.js to exist");
+                };
+                anyhow::Ok(file.content().to_str()?.to_string())
+            };
+            let degradations = || async move {
+                anyhow::Ok(
+                    disk_fs
+                        .watcher_degradations()
+                        .strongly_consistent()
+                        .await?
+                        .clone_value(),
+                )
+            };
+
+            assert_eq!(read().await?, "before");
+            assert!(degradations().await?.is_empty());
+
+            // The change is missed, e. g. because the event queue overflowed
+            std::fs::write(&file_path, "after").unwrap();
+            let (tx, rx) = channel();
+            let handle = tokio::runtime::Handle::current();
+            let thread = std::thread::spawn(move || {
+                let _guard = handle.enter();
+                inner.watcher.watch_thread(rx, inner.clone(), false);
+            });
+            tx.send(Ok(
+                notify::Event::new(EventKind::Other).set_flag(Flag::Rescan)
+            ))
+            .unwrap();
+
+            // Everything is rescanned and the overflow is reported
+            let mut content = read().await?;
+            for _ in 0..100 {
+                if content == "after" {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                content = read().await?;
+            }
+            assert_eq!(content, "after");
+            assert_eq!(
+                degradations().await?,
+                vec![WatcherDegradation {
+                    kind: WatcherDegradationKind::EventOverflow,
+                    path: "".into(),
+                }]
+            );
+
+            drop(tx);
+            thread.join().unwrap();
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn watch_limit() {
+        crate::register();
+
+        let dir = tempfile::tempdir().unwrap();
+        let sub_path = dir.path().join("sub");
+        std::fs::create_dir(&sub_path).unwrap();
+        let root: RcStr = dir.path().to_string_lossy().into();
+
+        let tt = turbo_tasks::TurboTasks::new(turbo_tasks_memory::MemoryBackend::new(usize::MAX));
+        tt.run_once(async move {
+            let disk_fs = DiskFileSystem::new("project".into(), root, vec![]);
+            let inner = disk_fs.await?.inner.clone();
+            let read = || async move {
+                let content = Vc::upcast::<Box<dyn FileSystem>>(disk_fs)
+                    .root()
+                    .join("sub/new.js".into())
+                    .read()
+                    .strongly_consistent()
+                    .await?;
+                anyhow::Ok(match &*content {
+                    FileContent::Content(file) => Some(file.content().to_str()?.to_string()),
+                    FileContent::NotFound => None,
+                })
+            };
+            let degradations = || async move {
+                anyhow::Ok(
+                    disk_fs
+                        .watcher_degradations()
+                        .strongly_consistent()
+                        .await?
+                        .clone_value(),
+                )
+            };
+
+            // The native watcher doesn't watch anything, so only polled paths are observed
+            let (tx, rx) = channel();
+            *inner.watcher.watcher.lock().unwrap() = Some(DiskWatcherInternal::Recommended {
+                watcher: RecommendedWatcher::new(tx.clone(), Config::default())?,
+                fallback: None,
+                sender: tx.clone(),
+            });
+            let handle = tokio::runtime::Handle::current();
+            let thread = std::thread::spawn({
+                let inner = inner.clone();
+                move || {
+                    let _guard = handle.enter();
+                    inner.watcher.watch_thread(rx, inner.clone(), false);
+                }
+            });
+            assert_eq!(read().await?, None);
+
+            // The OS limit is reached while watching the subdirectory
+            tx.send(Err(
+                notify::Error::new(notify::ErrorKind::MaxFilesWatch).add_path(sub_path.clone())
+            ))
+            .unwrap();
+            let expected = vec![WatcherDegradation {
+                kind: WatcherDegradationKind::WatchLimit,
+                path: "sub".into(),
+            }];
+            let mut reported = degradations().await?;
+            for _ in 0..100 {
+                if reported == expected {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                reported = degradations().await?;
+            }
+            assert_eq!(reported, expected);
+            assert!(matches!(
+                &*inner.watcher.watcher.lock().unwrap(),
+                Some(DiskWatcherInternal::Recommended {
+                    fallback: Some(_),
+                    ..
+                })
+            ));
+
+            // Changes in the subdirectory are picked up by polling it
+            std::fs::write(sub_path.join("new.js"), "created").unwrap();
+            let mut content = read().await?;
+            for _ in 0..100 {
+                if content.is_some() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                content = read().await?;
+            }
+            assert_eq!(content.as_deref(), Some("created"));
+
+            inner.watcher.stop_watching();
+            drop(tx);
+            thread.join().unwrap();
+            anyhow::Ok(())
+        })
+        .await
+        .unwrap()
+    }
+}
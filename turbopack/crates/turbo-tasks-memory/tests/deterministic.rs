@@ -0,0 +1 @@
+../../turbo-tasks-testing/tests/deterministic.rs
\ No newline at end of file
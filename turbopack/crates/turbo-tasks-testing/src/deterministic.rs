@@ -0,0 +1,277 @@
+//! Deterministic scheduling of task executions.
+//!
+//! Ordering bugs in backends usually only show up with a specific interleaving of task
+//! executions, which makes them hard to reproduce. [run_deterministic] executes a test with a
+//! [DeterministicScheduler] that starts scheduled tasks one at a time, in an order that is picked
+//! from a seed. The order is recorded as a list of choices and can be replayed exactly with
+//! [Schedule::Replay]. [minimize_schedule] reduces a failing schedule to a minimal list of
+//! choices that can be committed as a regression test.
+//!
+//! The order is only deterministic on a `current_thread` tokio runtime (the default of
+//! `#[tokio::test]`) and as long as tasks don't depend on timers, IO or blocking threads.
+
+use std::{
+    future::Future,
+    mem::take,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use futures::channel::oneshot;
+use tokio::runtime::RuntimeFlavor;
+use turbo_tasks::{
+    event::Event, run_once, test_helpers::ScheduleController, trace::TraceRawVcs, TaskId,
+    TurboTasksApi,
+};
+
+use crate::{run::closure_to_name, Registration};
+
+/// How often the scheduler yields to let all started tasks make progress before it picks the next
+/// task. Tasks that are still running after that don't affect which tasks are waiting, but the
+/// schedule is still deterministic.
+const SETTLE_YIELDS: usize = 32;
+
+/// The order in which [run_deterministic] starts scheduled tasks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schedule {
+    /// Picks the next task randomly from a seeded random number generator.
+    Seeded(u64),
+    /// Replays recorded choices. Every choice is the index of the next task in the list of
+    /// waiting tasks, sorted by task id. Once all choices are used up, tasks are started in
+    /// task id order, so trailing zeros can be omitted.
+    Replay(Vec<usize>),
+}
+
+/// The result of [run_deterministic] together with the schedule that was used.
+#[derive(Debug)]
+pub struct DeterministicRun<T> {
+    pub result: Result<T>,
+    /// Can be passed to [Schedule::Replay] to reproduce the run.
+    pub choices: Vec<usize>,
+    /// The tasks in the order in which they were started.
+    pub executions: Vec<TaskId>,
+}
+
+/// A [ScheduleController] that starts one waiting task at a time, whenever all previously
+/// started tasks are blocked or finished.
+pub struct DeterministicScheduler {
+    state: Mutex<SchedulerState>,
+    /// Notified when a task starts waiting.
+    event: Event,
+}
+
+struct SchedulerState {
+    /// Tasks that wait for their execution, sorted by task id.
+    waiting: Vec<(TaskId, oneshot::Sender<()>)>,
+    rng: Option<SplitMix64>,
+    replay: std::vec::IntoIter<usize>,
+    choices: Vec<usize>,
+    executions: Vec<TaskId>,
+    /// The test finished, tasks are no longer delayed.
+    stopped: bool,
+}
+
+impl DeterministicScheduler {
+    pub fn new(schedule: Schedule) -> Arc<Self> {
+        let (rng, replay) = match schedule {
+            Schedule::Seeded(seed) => (Some(SplitMix64(seed)), Vec::new()),
+            Schedule::Replay(choices) => (None, choices),
+        };
+        Arc::new(Self {
+            state: Mutex::new(SchedulerState {
+                waiting: Vec::new(),
+                rng,
+                replay: replay.into_iter(),
+                choices: Vec::new(),
+                executions: Vec::new(),
+                stopped: false,
+            }),
+            event: Event::new(|| "DeterministicScheduler::event".to_string()),
+        })
+    }
+
+    /// Starts waiting tasks one after another until [DeterministicScheduler::stop] is called.
+    pub async fn drive(&self) {
+        loop {
+            for _ in 0..SETTLE_YIELDS {
+                tokio::task::yield_now().await;
+            }
+            let listener = self.event.listen();
+            let sender = {
+                let mut state = self.state.lock().unwrap();
+                if state.stopped {
+                    return;
+                }
+                if state.waiting.is_empty() {
+                    None
+                } else {
+                    let len = state.waiting.len();
+                    let choice = match &mut state.rng {
+                        Some(rng) => (rng.next() % len as u64) as usize,
+                        None => state.replay.next().unwrap_or(0).min(len - 1),
+                    };
+                    state.choices.push(choice);
+                    let (task, sender) = state.waiting.remove(choice);
+                    state.executions.push(task);
+                    Some(sender)
+                }
+            };
+            let Some(sender) = sender else {
+                listener.await;
+                continue;
+            };
+            // The task might have been dropped when TurboTasks was stopped
+            let _ = sender.send(());
+        }
+    }
+
+    /// Starts all waiting tasks and stops delaying tasks.
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopped = true;
+        for (_, sender) in take(&mut state.waiting) {
+            let _ = sender.send(());
+        }
+        drop(state);
+        self.event.notify(usize::MAX);
+    }
+
+    /// Returns the recorded choices and the executed tasks. Trailing zeros are removed from the
+    /// choices, since they are implied when replaying.
+    pub fn recording(&self) -> (Vec<usize>, Vec<TaskId>) {
+        let state = self.state.lock().unwrap();
+        let mut choices = state.choices.clone();
+        while choices.last() == Some(&0) {
+            choices.pop();
+        }
+        (choices, state.executions.clone())
+    }
+}
+
+impl ScheduleController for DeterministicScheduler {
+    fn before_execution(&self, task: TaskId) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let mut state = self.state.lock().unwrap();
+        if state.stopped {
+            return Box::pin(async {});
+        }
+        let (sender, receiver) = oneshot::channel();
+        let index = state
+            .waiting
+            .partition_point(|(waiting, _)| *waiting <= task);
+        state.waiting.insert(index, (task, sender));
+        drop(state);
+        self.event.notify(usize::MAX);
+        Box::pin(async move {
+            let _ = receiver.await;
+        })
+    }
+}
+
+/// Runs `fut` in a once task of a new TurboTasks instance, starting all scheduled tasks in the
+/// order given by `schedule`. Must be called on a `current_thread` tokio runtime.
+pub async fn run_deterministic<T, F>(
+    registration: &Registration,
+    schedule: Schedule,
+    fut: impl FnOnce(Arc<dyn TurboTasksApi>) -> F,
+) -> DeterministicRun<T>
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: TraceRawVcs + Send + 'static,
+{
+    assert_eq!(
+        tokio::runtime::Handle::current().runtime_flavor(),
+        RuntimeFlavor::CurrentThread,
+        "deterministic scheduling requires a current_thread runtime"
+    );
+    registration.ensure_registered();
+    let tt = registration.create_turbo_tasks(&closure_to_name(&fut), true);
+    let scheduler = DeterministicScheduler::new(schedule);
+    tt.set_schedule_controller(scheduler.clone());
+    let driver = tokio::spawn({
+        let scheduler = scheduler.clone();
+        async move { scheduler.drive().await }
+    });
+    let result = run_once(tt.clone(), fut(tt.clone())).await;
+    scheduler.stop();
+    driver.await.unwrap();
+    tt.stop_and_wait().await;
+    let (choices, executions) = scheduler.recording();
+    DeterministicRun {
+        result,
+        choices,
+        executions,
+    }
+}
+
+/// Reduces the choices of a failing schedule while `is_failure` still holds for the result of a
+/// replay. Choices are removed from the end and reset to zero one at a time, so the returned
+/// schedule only contains the choices that are needed to reproduce the failure.
+pub async fn minimize_schedule<T, F>(
+    registration: &Registration,
+    choices: Vec<usize>,
+    fut: impl Fn(Arc<dyn TurboTasksApi>) -> F,
+    is_failure: impl Fn(&Result<T>) -> bool,
+) -> Vec<usize>
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: TraceRawVcs + Send + 'static,
+{
+    let mut choices = choices;
+    assert!(
+        replay_fails(registration, &choices, &fut, &is_failure).await,
+        "the schedule to minimize doesn't fail"
+    );
+    while !choices.is_empty()
+        && replay_fails(
+            registration,
+            &choices[..choices.len() - 1],
+            &fut,
+            &is_failure,
+        )
+        .await
+    {
+        choices.pop();
+    }
+    for i in (0..choices.len()).rev() {
+        if choices[i] == 0 {
+            continue;
+        }
+        let mut candidate = choices.clone();
+        candidate[i] = 0;
+        if replay_fails(registration, &candidate, &fut, &is_failure).await {
+            choices = candidate;
+        }
+    }
+    while choices.last() == Some(&0) {
+        choices.pop();
+    }
+    choices
+}
+
+async fn replay_fails<T, F>(
+    registration: &Registration,
+    choices: &[usize],
+    fut: impl Fn(Arc<dyn TurboTasksApi>) -> F,
+    is_failure: impl Fn(&Result<T>) -> bool,
+) -> bool
+where
+    F: Future<Output = Result<T>> + Send + 'static,
+    T: TraceRawVcs + Send + 'static,
+{
+    let run = run_deterministic(registration, Schedule::Replay(choices.to_vec()), fut).await;
+    is_failure(&run.result)
+}
+
+/// A small and fast random number generator, so that schedules only depend on the seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
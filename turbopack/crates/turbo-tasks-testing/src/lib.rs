@@ -1,5 +1,6 @@
 //! Testing utilities and macros for turbo-tasks and applications based on it.
 
+pub mod deterministic;
 pub mod retry;
 mod run;
 
@@ -18,10 +19,10 @@ use turbo_tasks::{
     backend::{CellContent, TaskCollectiblesMap, TypedCellContent},
     event::{Event, EventListener},
     registry,
-    test_helpers::with_turbo_tasks_for_testing,
+    test_helpers::{with_turbo_tasks_for_testing, ScheduleController},
     util::{SharedError, StaticOrArc},
     CellId, ExecutionId, InvalidationReason, LocalTaskId, MagicAny, RawVc, ReadConsistency, TaskId,
-    TaskPersistence, TraitTypeId, TurboTasksApi, TurboTasksCallApi,
+    TaskPersistence, TaskPriority, TraitTypeId, TurboTasksApi, TurboTasksCallApi,
 };
 
 pub use crate::run::{run, run_with_tt, run_without_cache_check, Registration};
@@ -152,6 +153,14 @@ impl TurboTasksCallApi for VcStorage {
         unreachable!()
     }
 
+    fn run_once_with_priority(
+        &self,
+        _priority: TaskPriority,
+        _future: std::pin::Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>,
+    ) -> TaskId {
+        unreachable!()
+    }
+
     fn run_once_process(
         &self,
         _future: std::pin::Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>,
@@ -177,6 +186,25 @@ impl TurboTasksApi for VcStorage {
         unreachable!()
     }
 
+    fn invalidation_provenance(
+        &self,
+        _task: TaskId,
+    ) -> Option<turbo_tasks::invalidation_provenance::InvalidationProvenance> {
+        None
+    }
+
+    fn set_task_priority(&self, _task: TaskId, _priority: TaskPriority) -> bool {
+        false
+    }
+
+    fn cancel_task(&self, _task: TaskId) -> bool {
+        false
+    }
+
+    fn set_schedule_controller(&self, _controller: Arc<dyn ScheduleController>) {
+        unreachable!()
+    }
+
     fn invalidate_serialization(&self, _task: TaskId) {
         // ingore
     }
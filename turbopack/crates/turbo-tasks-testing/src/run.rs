@@ -77,7 +77,7 @@ where
     run_once(tt, async move { Ok(fut.await) }).await.unwrap()
 }
 
-fn closure_to_name<T>(value: &T) -> String {
+pub(crate) fn closure_to_name<T>(value: &T) -> String {
     let name = std::any::type_name_of_val(value);
     name.replace("::{{closure}}", "").replace("::", "_")
 }
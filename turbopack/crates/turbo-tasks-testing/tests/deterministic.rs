@@ -0,0 +1,106 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use turbo_tasks::{TryJoinIterExt, Vc};
+use turbo_tasks_testing::{
+    deterministic::{minimize_schedule, run_deterministic, Schedule},
+    register, Registration,
+};
+
+static REGISTRATION: Registration = register!();
+
+/// The order in which `ordered_leaf` tasks were executed.
+static EXECUTED: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+#[tokio::test]
+async fn replay_seeded_schedule() {
+    for seed in 0..5 {
+        let run = run_deterministic(&REGISTRATION, Schedule::Seeded(seed), |_| async {
+            Ok(*sum(4).await?)
+        })
+        .await;
+        assert_eq!(run.result.unwrap(), 10);
+
+        let replay = run_deterministic(
+            &REGISTRATION,
+            Schedule::Replay(run.choices.clone()),
+            |_| async { Ok(*sum(4).await?) },
+        )
+        .await;
+        assert_eq!(replay.result.unwrap(), 10);
+        assert_eq!(replay.choices, run.choices);
+        assert_eq!(replay.executions, run.executions);
+    }
+}
+
+#[tokio::test]
+async fn minimize_failing_schedule() {
+    // Without choices, tasks are executed in task id order, which is the order of creation
+    let run = run_deterministic(&REGISTRATION, Schedule::Replay(vec![]), |_| {
+        leaves_in_order()
+    })
+    .await;
+    assert!(run.result.is_ok());
+    assert!(run.choices.is_empty());
+
+    let mut failing = None;
+    for seed in 0..100 {
+        let run =
+            run_deterministic(&REGISTRATION, Schedule::Seeded(seed), |_| leaves_in_order()).await;
+        if run.result.is_err() {
+            failing = Some(run.choices);
+            break;
+        }
+    }
+    let failing = failing.expect("no seed executes the leaves out of order");
+
+    let minimized = minimize_schedule(
+        &REGISTRATION,
+        failing.clone(),
+        |_| leaves_in_order(),
+        |result| result.is_err(),
+    )
+    .await;
+    assert!(!minimized.is_empty());
+    assert!(minimized.len() <= failing.len());
+    let replay = run_deterministic(&REGISTRATION, Schedule::Replay(minimized), |_| {
+        leaves_in_order()
+    })
+    .await;
+    assert!(replay.result.is_err());
+}
+
+async fn leaves_in_order() -> Result<()> {
+    EXECUTED.lock().unwrap().clear();
+    (1..=3).map(ordered_leaf).try_join().await?;
+    let executed = EXECUTED.lock().unwrap().clone();
+    if executed != [1, 2, 3] {
+        bail!("leaves were executed out of order: {executed:?}");
+    }
+    Ok(())
+}
+
+#[turbo_tasks::function]
+fn ordered_leaf(i: u32) -> Vc<u32> {
+    EXECUTED.lock().unwrap().push(i);
+    Vc::cell(i)
+}
+
+#[turbo_tasks::function]
+fn leaf(i: u32) -> Vc<u32> {
+    Vc::cell(i)
+}
+
+#[turbo_tasks::function]
+async fn sum(n: u32) -> Result<Vc<u32>> {
+    if n == 0 {
+        return Ok(Vc::cell(0));
+    }
+    let value = leaf(n);
+    let rest = sum(n - 1);
+    Ok(Vc::cell(*value.await? + *rest.await?))
+}
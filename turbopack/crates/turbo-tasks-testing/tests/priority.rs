@@ -0,0 +1,170 @@
+#![feature(arbitrary_self_types)]
+#![feature(arbitrary_self_types_pointers)]
+#![allow(clippy::needless_return)] // tokio macro-generated code doesn't respect this
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tokio::sync::oneshot;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{run_once, Completion, RawVc, TaskId, TaskPriority, TurboTasksApi, Vc};
+use turbo_tasks_testing::{register, run_with_tt, Registration};
+
+static REGISTRATION: Registration = register!();
+
+/// The names of the executed [`record`] tasks in execution order, prefixed with the test name.
+static EXECUTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn executed(test: &str) -> Vec<String> {
+    EXECUTED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|entry| Some(entry.strip_prefix(test)?.strip_prefix(':')?.to_string()))
+        .collect()
+}
+
+/// Spawns a once task with `priority` that executes the `record` task for `name` once `gate` is
+/// opened. The task is scheduled when this returns.
+fn spawn_record(
+    tt: &Arc<dyn TurboTasksApi>,
+    priority: TaskPriority,
+    gate: oneshot::Receiver<()>,
+    test: &'static str,
+    name: &'static str,
+) -> TaskId {
+    tt.run_once_with_priority(
+        priority,
+        Box::pin(async move {
+            gate.await?;
+            record(test.into(), name.into()).await?;
+            Ok(())
+        }),
+    )
+}
+
+/// A gate that is already open.
+fn opened() -> oneshot::Receiver<()> {
+    let (open, gate) = oneshot::channel();
+    let _ = open.send(());
+    gate
+}
+
+/// Waits until the once task `task_id` has finished.
+async fn wait_for(tt: &Arc<dyn TurboTasksApi>, task_id: TaskId) -> Result<()> {
+    run_once(tt.clone(), async move {
+        Vc::<Completion>::from(RawVc::TaskOutput(task_id)).await?;
+        Ok(())
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn priorities() -> Result<()> {
+    REGISTRATION.ensure_registered();
+    let tt = REGISTRATION.create_turbo_tasks("priorities", true);
+    // The interactive task is pending until its gate is opened, so the background task that is
+    // scheduled in the meantime waits for it
+    let (open_interactive, gate) = oneshot::channel();
+    let interactive = spawn_record(
+        &tt,
+        TaskPriority::Interactive,
+        gate,
+        "priorities",
+        "interactive",
+    );
+    let background = spawn_record(
+        &tt,
+        TaskPriority::Background,
+        opened(),
+        "priorities",
+        "background",
+    );
+    let _ = open_interactive.send(());
+    wait_for(&tt, interactive).await?;
+    wait_for(&tt, background).await?;
+    assert_eq!(executed("priorities"), vec!["interactive", "background"]);
+    tt.stop_and_wait().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn boost() -> Result<()> {
+    REGISTRATION.ensure_registered();
+    let tt = REGISTRATION.create_turbo_tasks("boost", true);
+    // The background task starts while nothing else is pending, but it only schedules its work
+    // once the interactive task is pending
+    let (started_tx, started) = oneshot::channel();
+    let (scheduled_tx, scheduled) = oneshot::channel();
+    let (open_background, gate) = oneshot::channel::<()>();
+    let background = tt.run_once_with_priority(
+        TaskPriority::Background,
+        Box::pin(async move {
+            let _ = started_tx.send(());
+            gate.await?;
+            let work = record("boost".into(), "background".into());
+            let _ = scheduled_tx.send(());
+            work.await?;
+            Ok(())
+        }),
+    );
+    started.await?;
+    let (open_interactive, gate) = oneshot::channel();
+    let interactive = spawn_record(&tt, TaskPriority::Interactive, gate, "boost", "interactive");
+    let _ = open_background.send(());
+    scheduled.await?;
+
+    // Another interactive task reads the waiting work, which boosts it to the interactive
+    // priority. The first interactive task is only released after that has finished.
+    let reader = spawn_record(
+        &tt,
+        TaskPriority::Interactive,
+        opened(),
+        "boost",
+        "background",
+    );
+    wait_for(&tt, reader).await?;
+    let _ = open_interactive.send(());
+    wait_for(&tt, interactive).await?;
+    wait_for(&tt, background).await?;
+    assert_eq!(executed("boost"), vec!["background", "interactive"]);
+    tt.stop_and_wait().await;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_once_task() -> Result<()> {
+    run_with_tt(&REGISTRATION, |tt| async move {
+        let task_id =
+            tt.run_once_with_priority(TaskPriority::Background, Box::pin(std::future::pending()));
+        assert!(tt.cancel_task(task_id));
+        let result = run_once(tt.clone(), async move {
+            Vc::<Completion>::from(RawVc::TaskOutput(task_id)).await?;
+            Ok(())
+        })
+        .await;
+        let error = format!("{:?}", result.unwrap_err());
+        assert!(error.contains("the task was cancelled"), "{error}");
+        // The task is removed from the scheduler shortly after its output is set. Then it can't be
+        // cancelled anymore.
+        let start = Instant::now();
+        while tt.cancel_task(task_id) {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "the task was not removed from the scheduler"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[turbo_tasks::function]
+fn record(test: RcStr, name: RcStr) -> Vc<Completion> {
+    EXECUTED.lock().unwrap().push(format!("{test}:{name}"));
+    Completion::new()
+}
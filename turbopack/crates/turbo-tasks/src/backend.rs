@@ -16,15 +16,19 @@ use tracing::Span;
 pub use crate::id::{BackendJobId, ExecutionId};
 use crate::{
     event::EventListener,
+    function_statistics::FunctionStatistics,
+    invalidation_provenance::InvalidationProvenance,
     magic_any::MagicAny,
     manager::{ReadConsistency, TurboTasksBackendApi},
     raw_vc::CellId,
     registry,
     task::shared_reference::TypedSharedReference,
+    task_graph::TaskGraph,
     trait_helpers::{get_trait_method, has_trait, traits},
     triomphe_utils::unchecked_sidecast_triomphe_arc,
-    FunctionId, RawVc, ReadRef, SharedReference, TaskId, TaskIdSet, TaskPersistence, TraitRef,
-    TraitTypeId, ValueTypeId, VcRead, VcValueTrait, VcValueType,
+    util::StaticOrArc,
+    FunctionId, InvalidationReason, RawVc, ReadRef, SharedReference, TaskId, TaskIdSet,
+    TaskPersistence, TraitRef, TraitTypeId, ValueTypeId, VcRead, VcValueTrait, VcValueType,
 };
 
 pub type TransientTaskRoot =
@@ -539,6 +543,17 @@ pub trait Backend: Sync + Send {
 
     fn invalidate_task(&self, task: TaskId, turbo_tasks: &dyn TurboTasksBackendApi<Self>);
 
+    /// Like [`Backend::invalidate_task`], but the backend may record the `reason` as provenance of
+    /// the invalidation.
+    fn invalidate_task_with_reason(
+        &self,
+        task: TaskId,
+        _reason: &StaticOrArc<dyn InvalidationReason>,
+        turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) {
+        self.invalidate_task(task, turbo_tasks);
+    }
+
     fn invalidate_tasks(&self, tasks: &[TaskId], turbo_tasks: &dyn TurboTasksBackendApi<Self>);
     fn invalidate_tasks_set(&self, tasks: &TaskIdSet, turbo_tasks: &dyn TurboTasksBackendApi<Self>);
 
@@ -551,6 +566,36 @@ pub trait Backend: Sync + Send {
 
     fn get_task_description(&self, task: TaskId) -> String;
 
+    /// Returns the part of the task graph that is reachable from `root` via children and
+    /// dependencies, for debugging. Returns `None` when the backend doesn't support it.
+    fn task_graph(
+        &self,
+        _root: TaskId,
+        _turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Option<TaskGraph> {
+        None
+    }
+
+    /// Returns the execution statistics of all functions that were called. Returns an empty list
+    /// when the backend doesn't collect them.
+    fn function_statistics(
+        &self,
+        _turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Vec<FunctionStatistics> {
+        Vec::new()
+    }
+
+    /// Returns the chain of invalidations that led to the last re-execution of `task`. Returns
+    /// `None` when the backend doesn't record invalidation provenance or the task was never
+    /// invalidated.
+    fn invalidation_provenance(
+        &self,
+        _task: TaskId,
+        _turbo_tasks: &dyn TurboTasksBackendApi<Self>,
+    ) -> Option<InvalidationProvenance> {
+        None
+    }
+
     /// Task-local state that stored inside of [`TurboTasksBackendApi`]. Constructed with
     /// [`Self::new_task_state`].
     ///
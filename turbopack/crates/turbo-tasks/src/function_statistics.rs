@@ -0,0 +1,121 @@
+use std::{
+    hash::BuildHasherDefault,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use dashmap::DashMap;
+use rustc_hash::FxHasher;
+use serde::Serialize;
+
+use crate::{registry, FunctionId};
+
+/// Execution statistics of a `#[turbo_tasks::function]`, accumulated over the lifetime of the
+/// backend. They are created by
+/// [`Backend::function_statistics`][crate::backend::Backend::function_statistics] and meant to
+/// find the functions that dominate the build time without recording a full trace.
+#[derive(Debug, Clone, Serialize)]
+pub struct FunctionStatistics {
+    /// See [`registry::get_function_global_name`].
+    pub name: &'static str,
+    pub executions: u64,
+    /// Calls that reused an existing task instead of creating a new one.
+    pub cache_hits: u64,
+    pub total_duration: Duration,
+    /// The duration of the slowest execution.
+    pub peak_duration: Duration,
+    pub cells_written: u64,
+    /// How often tasks of the function were marked as dirty.
+    pub invalidations: u64,
+}
+
+#[derive(Default)]
+struct FunctionCounters {
+    executions: AtomicU64,
+    cache_hits: AtomicU64,
+    total_duration_nanos: AtomicU64,
+    peak_duration_nanos: AtomicU64,
+    cells_written: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+/// Lock-free counters per [`FunctionId`] that a backend can update on its hot paths to implement
+/// [`Backend::function_statistics`][crate::backend::Backend::function_statistics].
+#[derive(Default)]
+pub struct FunctionStatisticsCollector {
+    counters: DashMap<FunctionId, FunctionCounters, BuildHasherDefault<FxHasher>>,
+}
+
+impl FunctionStatisticsCollector {
+    fn with_counters(&self, function_id: FunctionId, update: impl FnOnce(&FunctionCounters)) {
+        if let Some(counters) = self.counters.get(&function_id) {
+            update(&counters);
+        } else {
+            update(&self.counters.entry(function_id).or_default());
+        }
+    }
+
+    pub fn record_cache_hit(&self, function_id: FunctionId) {
+        self.with_counters(function_id, |counters| {
+            counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    pub fn record_execution(
+        &self,
+        function_id: FunctionId,
+        duration: Duration,
+        cells_written: u64,
+    ) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.with_counters(function_id, |counters| {
+            counters.executions.fetch_add(1, Ordering::Relaxed);
+            counters
+                .total_duration_nanos
+                .fetch_add(nanos, Ordering::Relaxed);
+            counters
+                .peak_duration_nanos
+                .fetch_max(nanos, Ordering::Relaxed);
+            counters
+                .cells_written
+                .fetch_add(cells_written, Ordering::Relaxed);
+        });
+    }
+
+    pub fn record_invalidation(&self, function_id: FunctionId) {
+        self.with_counters(function_id, |counters| {
+            counters.invalidations.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Returns the statistics of all functions that were called, sorted by total duration with
+    /// the slowest function first.
+    pub fn get(&self) -> Vec<FunctionStatistics> {
+        let mut statistics = self
+            .counters
+            .iter()
+            .map(|entry| {
+                let counters = entry.value();
+                FunctionStatistics {
+                    name: registry::get_function_global_name(*entry.key()),
+                    executions: counters.executions.load(Ordering::Relaxed),
+                    cache_hits: counters.cache_hits.load(Ordering::Relaxed),
+                    total_duration: Duration::from_nanos(
+                        counters.total_duration_nanos.load(Ordering::Relaxed),
+                    ),
+                    peak_duration: Duration::from_nanos(
+                        counters.peak_duration_nanos.load(Ordering::Relaxed),
+                    ),
+                    cells_written: counters.cells_written.load(Ordering::Relaxed),
+                    invalidations: counters.invalidations.load(Ordering::Relaxed),
+                }
+            })
+            .collect::<Vec<_>>();
+        statistics.sort_by(|a, b| {
+            b.total_duration
+                .cmp(&a.total_duration)
+                .then_with(|| a.name.cmp(b.name))
+        });
+        statistics
+    }
+}
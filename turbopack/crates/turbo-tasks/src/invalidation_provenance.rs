@@ -0,0 +1,169 @@
+use std::fmt::{Display, Formatter};
+
+use serde::Serialize;
+
+use crate::{registry, TaskId, TraitTypeId, ValueTypeId};
+
+/// The reason why a task was marked as dirty, as recorded by a backend that tracks invalidation
+/// provenance.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InvalidationCause {
+    /// The task was invalidated from outside of the task graph, e.g. by a file watcher via an
+    /// [Invalidator][crate::Invalidator]. The reason is the [Display] of the
+    /// [InvalidationReason][crate::InvalidationReason], when one was given.
+    External {
+        reason: Option<String>,
+    },
+    /// A cell of `task` that was read by the task changed.
+    CellChange {
+        task: TaskId,
+        value_type: ValueTypeId,
+    },
+    /// A cell of `task` that was read by the task was removed.
+    CellRemoved {
+        task: TaskId,
+        value_type: ValueTypeId,
+    },
+    /// The output of `task` that was read by the task changed.
+    OutputChange {
+        task: TaskId,
+    },
+    /// Collectibles that were read by the task changed.
+    CollectiblesChange {
+        collectible_type: TraitTypeId,
+    },
+    Unknown,
+}
+
+impl InvalidationCause {
+    /// The task whose change caused the invalidation, if it's part of the task graph.
+    pub fn source_task(&self) -> Option<TaskId> {
+        match *self {
+            InvalidationCause::CellChange { task, .. }
+            | InvalidationCause::CellRemoved { task, .. }
+            | InvalidationCause::OutputChange { task } => Some(task),
+            InvalidationCause::External { .. }
+            | InvalidationCause::CollectiblesChange { .. }
+            | InvalidationCause::Unknown => None,
+        }
+    }
+}
+
+impl Display for InvalidationCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidationCause::External {
+                reason: Some(reason),
+            } => write!(f, "{reason}"),
+            InvalidationCause::External { reason: None } => write!(f, "external invalidation"),
+            InvalidationCause::CellChange { value_type, .. } => {
+                write!(
+                    f,
+                    "{} cell changed",
+                    registry::get_value_type(*value_type).name
+                )
+            }
+            InvalidationCause::CellRemoved { value_type, .. } => {
+                write!(
+                    f,
+                    "{} cell removed",
+                    registry::get_value_type(*value_type).name
+                )
+            }
+            InvalidationCause::OutputChange { .. } => write!(f, "output changed"),
+            InvalidationCause::CollectiblesChange { collectible_type } => {
+                write!(
+                    f,
+                    "{} collectibles changed",
+                    registry::get_trait(*collectible_type).name
+                )
+            }
+            InvalidationCause::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct InvalidationStep {
+    pub task: TaskId,
+    /// See [`Backend::get_task_description`][crate::backend::Backend::get_task_description].
+    pub description: String,
+    pub cause: InvalidationCause,
+}
+
+/// The chain of invalidations that led to the last re-execution of a task. It's created by
+/// [`Backend::invalidation_provenance`][crate::backend::Backend::invalidation_provenance] and
+/// meant to explain slow updates, e.g. which file write re-executed a task.
+#[derive(Debug, Clone, Serialize)]
+pub struct InvalidationProvenance {
+    /// The first step is the queried task, every following step is the source task of the
+    /// previous cause. The last step holds the root cause.
+    pub steps: Vec<InvalidationStep>,
+}
+
+impl InvalidationProvenance {
+    pub fn root_cause(&self) -> Option<&InvalidationCause> {
+        self.steps.last().map(|step| &step.cause)
+    }
+}
+
+impl Display for InvalidationProvenance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (i, step) in self.steps.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:indent$}{} was invalidated: {}",
+                "",
+                step.description,
+                step.cause,
+                indent = i * 2
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let provenance = InvalidationProvenance {
+            steps: vec![
+                InvalidationStep {
+                    task: TaskId::from(2),
+                    description: "parse".to_string(),
+                    cause: InvalidationCause::OutputChange {
+                        task: TaskId::from(1),
+                    },
+                },
+                InvalidationStep {
+                    task: TaskId::from(1),
+                    description: "read".to_string(),
+                    cause: InvalidationCause::External {
+                        reason: Some("/src/index.js modified".to_string()),
+                    },
+                },
+            ],
+        };
+        assert_eq!(
+            provenance.to_string(),
+            "parse was invalidated: output changed\n  read was invalidated: /src/index.js modified"
+        );
+        assert_eq!(
+            provenance.steps[0].cause.source_task(),
+            Some(TaskId::from(1))
+        );
+        assert_eq!(
+            provenance.root_cause(),
+            Some(&InvalidationCause::External {
+                reason: Some("/src/index.js modified".to_string())
+            })
+        );
+    }
+}
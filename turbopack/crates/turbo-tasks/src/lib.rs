@@ -49,10 +49,12 @@ mod display;
 pub mod duration_span;
 mod effect;
 pub mod event;
+pub mod function_statistics;
 pub mod graph;
 mod id;
 mod id_factory;
 mod invalidation;
+pub mod invalidation_provenance;
 mod join_iter_ext;
 mod key_value_pair;
 #[doc(hidden)]
@@ -66,15 +68,18 @@ mod once_map;
 mod output;
 pub mod persisted_graph;
 pub mod primitives;
+mod priority;
 mod raw_vc;
 mod read_ref;
 pub mod registry;
+pub mod relocation;
 mod scope;
 mod serialization_invalidation;
 mod shrink_to_fit;
 pub mod small_duration;
 mod state;
 pub mod task;
+pub mod task_graph;
 pub mod trace;
 mod trait_helpers;
 mod trait_ref;
@@ -105,13 +110,14 @@ pub use key_value_pair::KeyValuePair;
 pub use magic_any::MagicAny;
 pub use manager::{
     dynamic_call, dynamic_this_call, emit, mark_finished, mark_session_dependent, mark_stateful,
-    prevent_gc, run_once, run_once_with_reason, spawn_blocking, spawn_thread, trait_call,
-    turbo_tasks, turbo_tasks_scope, CurrentCellRef, ReadConsistency, TaskPersistence, TurboTasks,
-    TurboTasksApi, TurboTasksBackendApi, TurboTasksBackendApiExt, TurboTasksCallApi, Unused,
-    UpdateInfo,
+    prevent_gc, run_once, run_once_with_priority, run_once_with_reason, spawn_blocking,
+    spawn_thread, trait_call, turbo_tasks, turbo_tasks_scope, CurrentCellRef, ReadConsistency,
+    TaskPersistence, TurboTasks, TurboTasksApi, TurboTasksBackendApi, TurboTasksBackendApiExt,
+    TurboTasksCallApi, Unused, UpdateInfo,
 };
 pub use native_function::{FunctionMeta, NativeFunction};
 pub use output::OutputContent;
+pub use priority::TaskPriority;
 pub use raw_vc::{CellId, RawVc, ReadRawVcFuture, ResolveTypeError};
 pub use read_ref::ReadRef;
 use rustc_hash::FxHasher;
@@ -277,7 +283,9 @@ pub use turbo_tasks_macros::value;
 pub type TaskIdSet = AutoSet<TaskId, BuildHasherDefault<FxHasher>, 2>;
 
 pub mod test_helpers {
-    pub use super::manager::{current_task_for_testing, with_turbo_tasks_for_testing};
+    pub use super::manager::{
+        current_task_for_testing, with_turbo_tasks_for_testing, ScheduleController,
+    };
 }
 
 pub fn register() {
@@ -8,13 +8,13 @@ use std::{
     pin::Pin,
     sync::{
         atomic::{AtomicBool, AtomicUsize, Ordering},
-        Arc, Mutex, RwLock, Weak,
+        Arc, Mutex, OnceLock, RwLock, Weak,
     },
     thread,
     time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use auto_hash_map::AutoMap;
 use futures::FutureExt;
 use rustc_hash::FxHasher;
@@ -31,16 +31,20 @@ use crate::{
     },
     capture_future::{self, CaptureFuture},
     event::{Event, EventListener},
+    function_statistics::FunctionStatistics,
     id::{
         BackendJobId, ExecutionId, FunctionId, LocalCellId, LocalTaskId, TraitTypeId,
         TRANSIENT_TASK_BIT,
     },
     id_factory::{IdFactory, IdFactoryWithReuse},
+    invalidation_provenance::InvalidationProvenance,
     magic_any::MagicAny,
+    priority::{PriorityScheduler, PriorityScope, TaskPriority, NORMAL_LEVEL},
     raw_vc::{CellId, RawVc},
     registry::{self, get_function},
     serialization_invalidation::SerializationInvalidator,
     task::shared_reference::TypedSharedReference,
+    task_graph::TaskGraph,
     trace::TraceRawVcs,
     trait_helpers::get_trait_method,
     util::StaticOrArc,
@@ -108,6 +112,21 @@ pub trait TurboTasksCallApi: Sync + Send {
         &self,
         future: Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>,
     ) -> TaskId;
+    /// Like [`TurboTasksCallApi::run_once`], but the once task and all tasks it schedules are
+    /// executed with the given priority. It can be cancelled with [`TurboTasksApi::cancel_task`].
+    fn run_once_with_priority(
+        &self,
+        priority: TaskPriority,
+        future: Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>,
+    ) -> TaskId;
+}
+
+/// Decides when scheduled tasks start executing. This allows tests to control the interleaving of
+/// task executions, e.g. to execute them in a deterministic order.
+pub trait ScheduleController: Send + Sync {
+    /// Called every time before a scheduled task starts executing. The execution starts when the
+    /// returned future resolves.
+    fn before_execution(&self, task: TaskId) -> Pin<Box<dyn Future<Output = ()> + Send>>;
 }
 
 pub trait TurboTasksApi: TurboTasksCallApi + Sync + Send {
@@ -116,6 +135,32 @@ pub trait TurboTasksApi: TurboTasksCallApi + Sync + Send {
     fn invalidate(&self, task: TaskId);
     fn invalidate_with_reason(&self, task: TaskId, reason: StaticOrArc<dyn InvalidationReason>);
 
+    /// Returns the chain of invalidations that led to the last re-execution of `task`, see
+    /// [`Backend::invalidation_provenance`].
+    fn invalidation_provenance(&self, task: TaskId) -> Option<InvalidationProvenance>;
+
+    /// Changes the priority of a root or once task that was spawned with a priority, and of all
+    /// tasks that it schedules. Returns false when the task wasn't spawned with a priority or
+    /// already finished.
+    fn set_task_priority(&self, task: TaskId, priority: TaskPriority) -> bool;
+
+    /// Cancels a root or once task that was spawned with a priority, because its result is no
+    /// longer needed. Returns false when the task wasn't spawned with a priority or already
+    /// finished.
+    ///
+    /// Cancellation is cooperative: The future of a once task is dropped and it fails with an
+    /// error. Tasks that were scheduled on behalf of the cancelled task are still executed, since
+    /// other tasks might depend on them, but only when no other work is pending.
+    ///
+    /// Next.js doesn't cancel tasks yet, e.g. a route request that is aborted by the browser still
+    /// runs to completion.
+    fn cancel_task(&self, task: TaskId) -> bool;
+
+    /// Installs a [ScheduleController] that decides when scheduled tasks start executing. It can
+    /// only be installed once and should be installed before any task is spawned. Only meant for
+    /// testing.
+    fn set_schedule_controller(&self, controller: Arc<dyn ScheduleController>);
+
     fn invalidate_serialization(&self, task: TaskId);
 
     /// Eagerly notifies all tasks that were scheduled for notifications via
@@ -378,6 +423,8 @@ pub struct TurboTasks<B: Backend + 'static> {
     event_foreground: Event,
     event_background: Event,
     program_start: Instant,
+    priorities: PriorityScheduler,
+    schedule_controller: OnceLock<Arc<dyn ScheduleController>>,
 }
 
 /// Information about a "global" task. A global task can contain multiple "local" tasks (see
@@ -444,13 +491,28 @@ struct CurrentLocalTaskState {
     /// The function's metadata if this is a persistent task. Contains information about arguments
     /// passed to the `#[turbo_tasks::function(...)]` macro.
     function_meta: Option<&'static FunctionMeta>,
+
+    /// The priority of the root or once task this task was scheduled on behalf of. It's inherited
+    /// by all tasks that are scheduled during the execution.
+    priority_scope: Option<Arc<PriorityScope>>,
+
+    /// The priority level the task is executed with, including boosts. Tasks that are read by
+    /// this task are boosted to this level.
+    priority_level: usize,
 }
 
 impl CurrentLocalTaskState {
-    fn new(execution_id: ExecutionId, function_meta: Option<&'static FunctionMeta>) -> Self {
+    fn new(
+        execution_id: ExecutionId,
+        function_meta: Option<&'static FunctionMeta>,
+        priority_scope: Option<Arc<PriorityScope>>,
+        priority_level: usize,
+    ) -> Self {
         Self {
             execution_id,
             function_meta,
+            priority_scope,
+            priority_level,
         }
     }
 }
@@ -492,6 +554,8 @@ impl<B: Backend + 'static> TurboTasks<B> {
             event_foreground: Event::new(|| "TurboTasks::event_foreground".to_string()),
             event_background: Event::new(|| "TurboTasks::event_background".to_string()),
             program_start: Instant::now(),
+            priorities: PriorityScheduler::new(),
+            schedule_controller: OnceLock::new(),
         });
         this.backend.startup(&*this);
         this
@@ -519,7 +583,31 @@ impl<B: Backend + 'static> TurboTasks<B> {
         id
     }
 
+    /// Creates a new root task that is executed with the given priority, see [TaskPriority].
+    pub fn spawn_root_task_with_priority<T, F, Fut>(
+        &self,
+        priority: TaskPriority,
+        functor: F,
+    ) -> TaskId
+    where
+        T: ?Sized,
+        F: Fn() -> Fut + Send + Sync + Clone + 'static,
+        Fut: Future<Output = Result<Vc<T>>> + Send,
+    {
+        let id = self.backend.create_transient_task(
+            TransientTaskType::Root(Box::new(move || {
+                let functor = functor.clone();
+                Box::pin(async move { Ok(functor().await?.node) })
+            })),
+            self,
+        );
+        self.priorities.create_scope(id, priority);
+        self.schedule(id);
+        id
+    }
+
     pub fn dispose_root_task(&self, task_id: TaskId) {
+        self.priorities.detach(task_id);
         self.backend.dispose_root_task(task_id, self);
     }
 
@@ -540,6 +628,42 @@ impl<B: Backend + 'static> TurboTasks<B> {
         id
     }
 
+    /// Creates a new root task that is only executed once with the given priority, see
+    /// [TaskPriority]. It can be cancelled with [`TurboTasksApi::cancel_task`].
+    #[track_caller]
+    pub fn spawn_once_task_with_priority<T, Fut>(
+        &self,
+        priority: TaskPriority,
+        future: Fut,
+    ) -> TaskId
+    where
+        T: ?Sized,
+        Fut: Future<Output = Result<Vc<T>>> + Send + 'static,
+    {
+        let id = self.backend.create_transient_task(
+            TransientTaskType::Once(Box::pin(async move {
+                let scope = CURRENT_LOCAL_TASK_STATE.with(|ts| ts.priority_scope.clone());
+                let vc = match scope {
+                    Some(scope) => {
+                        select! {
+                            result = future => result?,
+                            _ = scope.cancelled() => bail!("the task was cancelled"),
+                        }
+                    }
+                    None => future.await?,
+                };
+                Ok(vc.node)
+            })),
+            self,
+        );
+        self.priorities.create_scope(id, priority);
+        self.schedule(id);
+        // A once task is never scheduled again, the scope is removed when it and all tasks it
+        // scheduled have finished
+        self.priorities.detach(id);
+        id
+    }
+
     pub async fn run_once<T: TraceRawVcs + Send + 'static>(
         &self,
         future: impl Future<Output = Result<T>> + Send + 'static,
@@ -759,10 +883,27 @@ impl<B: Backend + 'static> TurboTasks<B> {
         #[cfg(feature = "tokio_tracing")]
         let description = self.backend.get_task_description(task_id);
 
+        // Root and once tasks have their own priority, other tasks inherit it from the task that
+        // schedules them
+        let priority_scope = self.priorities.scope(task_id).or_else(|| {
+            CURRENT_LOCAL_TASK_STATE
+                .try_with(|ts| ts.priority_scope.clone())
+                .ok()
+                .flatten()
+        });
+        self.priorities.begin(priority_scope.as_deref());
+
         let this = self.pin();
         let future = async move {
             let mut schedule_again = true;
             while schedule_again {
+                let priority_level = this
+                    .priorities
+                    .wait_for_turn(task_id, priority_scope.as_deref())
+                    .await;
+                if let Some(controller) = this.schedule_controller.get() {
+                    controller.before_execution(task_id).await;
+                }
                 let backend_state = this.backend.new_task_state(task_id);
                 let global_task_state = Arc::new(RwLock::new(CurrentGlobalTaskState::new(
                     task_id,
@@ -773,6 +914,8 @@ impl<B: Backend + 'static> TurboTasks<B> {
                     this.backend
                         .try_get_function_id(task_id)
                         .map(|func_id| &get_function(func_id).function_meta),
+                    priority_scope.clone(),
+                    priority_level,
                 );
                 let single_execution_future = async {
                     if this.stopped.load(Ordering::Acquire) {
@@ -828,6 +971,7 @@ impl<B: Backend + 'static> TurboTasks<B> {
                     )
                     .await;
             }
+            this.priorities.finish(priority_scope.as_ref());
             this.finish_primary_job();
             anyhow::Ok(())
         };
@@ -885,6 +1029,16 @@ impl<B: Backend + 'static> TurboTasks<B> {
         }
     }
 
+    /// A task that has to wait for a read boosts the read task to its own priority, so that it
+    /// doesn't wait for other tasks with a higher priority than the reading task.
+    fn boost_read_task<T>(&self, task: TaskId, result: &Result<Result<T, EventListener>>) {
+        if let Ok(Err(_)) = result {
+            if let Ok(level) = CURRENT_LOCAL_TASK_STATE.try_with(|ts| ts.priority_level) {
+                self.priorities.boost(task, level);
+            }
+        }
+    }
+
     fn finish_foreground_job(&self) {
         if self
             .currently_scheduled_foreground_jobs
@@ -1156,6 +1310,17 @@ impl<B: Backend + 'static> TurboTasks<B> {
     pub fn backend(&self) -> &B {
         &self.backend
     }
+
+    /// Returns the part of the task graph that is reachable from `root`, see
+    /// [`Backend::task_graph`].
+    pub fn task_graph(&self, root: TaskId) -> Option<TaskGraph> {
+        self.backend.task_graph(root, self)
+    }
+
+    /// Returns the execution statistics of all functions, see [`Backend::function_statistics`].
+    pub fn function_statistics(&self) -> Vec<FunctionStatistics> {
+        self.backend.function_statistics(self)
+    }
 }
 
 impl<B: Backend + 'static> TurboTasksCallApi for TurboTasks<B> {
@@ -1244,6 +1409,18 @@ impl<B: Backend + 'static> TurboTasksCallApi for TurboTasks<B> {
             Ok(Completion::new())
         })
     }
+
+    #[track_caller]
+    fn run_once_with_priority(
+        &self,
+        priority: TaskPriority,
+        future: Pin<Box<dyn Future<Output = Result<()>> + Send + 'static>>,
+    ) -> TaskId {
+        self.spawn_once_task_with_priority(priority, async move {
+            future.await?;
+            Ok(Completion::new())
+        })
+    }
 }
 
 impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
@@ -1260,9 +1437,28 @@ impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
     fn invalidate_with_reason(&self, task: TaskId, reason: StaticOrArc<dyn InvalidationReason>) {
         {
             let (_, reason_set) = &mut *self.aggregated_update.lock().unwrap();
-            reason_set.insert(reason);
+            reason_set.insert(reason.clone());
+        }
+        self.backend
+            .invalidate_task_with_reason(task, &reason, self);
+    }
+
+    fn invalidation_provenance(&self, task: TaskId) -> Option<InvalidationProvenance> {
+        self.backend.invalidation_provenance(task, self)
+    }
+
+    fn set_task_priority(&self, task: TaskId, priority: TaskPriority) -> bool {
+        self.priorities.set_priority(task, priority)
+    }
+
+    fn cancel_task(&self, task: TaskId) -> bool {
+        self.priorities.cancel(task)
+    }
+
+    fn set_schedule_controller(&self, controller: Arc<dyn ScheduleController>) {
+        if self.schedule_controller.set(controller).is_err() {
+            panic!("a schedule controller is already installed");
         }
-        self.backend.invalidate_task(task, self);
     }
 
     fn invalidate_serialization(&self, task: TaskId) {
@@ -1289,8 +1485,11 @@ impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
         task: TaskId,
         consistency: ReadConsistency,
     ) -> Result<Result<RawVc, EventListener>> {
-        self.backend
-            .try_read_task_output(task, current_task("reading Vcs"), consistency, self)
+        let result =
+            self.backend
+                .try_read_task_output(task, current_task("reading Vcs"), consistency, self);
+        self.boost_read_task(task, &result);
+        result
     }
 
     fn try_read_task_output_untracked(
@@ -1298,8 +1497,11 @@ impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
         task: TaskId,
         consistency: ReadConsistency,
     ) -> Result<Result<RawVc, EventListener>> {
-        self.backend
-            .try_read_task_output_untracked(task, consistency, self)
+        let result = self
+            .backend
+            .try_read_task_output_untracked(task, consistency, self);
+        self.boost_read_task(task, &result);
+        result
     }
 
     fn try_read_task_cell(
@@ -1307,8 +1509,11 @@ impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
         task: TaskId,
         index: CellId,
     ) -> Result<Result<TypedCellContent, EventListener>> {
-        self.backend
-            .try_read_task_cell(task, index, current_task("reading Vcs"), self)
+        let result =
+            self.backend
+                .try_read_task_cell(task, index, current_task("reading Vcs"), self);
+        self.boost_read_task(task, &result);
+        result
     }
 
     fn try_read_task_cell_untracked(
@@ -1316,7 +1521,9 @@ impl<B: Backend + 'static> TurboTasksApi for TurboTasks<B> {
         task: TaskId,
         index: CellId,
     ) -> Result<Result<TypedCellContent, EventListener>> {
-        self.backend.try_read_task_cell_untracked(task, index, self)
+        let result = self.backend.try_read_task_cell_untracked(task, index, self);
+        self.boost_read_task(task, &result);
+        result
     }
 
     fn try_read_own_task_cell_untracked(
@@ -1629,6 +1836,31 @@ pub async fn run_once_with_reason<T: Send + 'static>(
     Ok(rx.await?)
 }
 
+pub async fn run_once_with_priority<T: Send + 'static>(
+    tt: Arc<dyn TurboTasksApi>,
+    priority: TaskPriority,
+    future: impl Future<Output = Result<T>> + Send + 'static,
+) -> Result<T> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    let task_id = tt.run_once_with_priority(
+        priority,
+        Box::pin(async move {
+            let result = future.await?;
+            tx.send(result)
+                .map_err(|_| anyhow!("unable to send result"))?;
+            Ok(())
+        }),
+    );
+
+    // INVALIDATION: A Once task will never invalidate, therefore we don't need to
+    // track a dependency
+    let raw_result = read_task_output_untracked(&*tt, task_id, ReadConsistency::Eventual).await?;
+    ReadVcFuture::<Completion>::from(raw_result.into_read_untracked_with_turbo_tasks(&*tt)).await?;
+
+    Ok(rx.await?)
+}
+
 /// Calls [`TurboTasks::dynamic_call`] for the current turbo tasks instance.
 pub fn dynamic_call(
     func: FunctionId,
@@ -1692,7 +1924,10 @@ pub fn with_turbo_tasks_for_testing<T>(
                 current_task,
                 Box::new(()),
             ))),
-            CURRENT_LOCAL_TASK_STATE.scope(CurrentLocalTaskState::new(execution_id, None), f),
+            CURRENT_LOCAL_TASK_STATE.scope(
+                CurrentLocalTaskState::new(execution_id, None, None, NORMAL_LEVEL),
+                f,
+            ),
         ),
     )
 }
@@ -0,0 +1,351 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use rustc_hash::FxHashMap;
+use tokio::time::{timeout_at, Instant};
+
+use crate::{event::Event, TaskId};
+
+/// How long a task waits at most for higher priority tasks before it's executed anyway. This
+/// prevents starvation and deadlocks when a higher priority task waits for a lower priority task
+/// without reading it, e.g. for a strongly consistent read of its children.
+const MAX_PRIORITY_DELAY: Duration = Duration::from_millis(250);
+
+/// The priority of a root or once task. It's inherited by all tasks that are scheduled while a
+/// task with that priority is executing. A task is only executed when no tasks with a higher
+/// priority are pending, or after a short delay. Tasks that are read by a higher priority task
+/// are boosted to its priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(u8)]
+pub enum TaskPriority {
+    /// Work that nobody is waiting for, e.g. eagerly compiling routes in the background.
+    Background,
+    #[default]
+    Normal,
+    /// Work that the user is waiting for, e.g. compiling a route that was requested in the
+    /// browser.
+    Interactive,
+}
+
+impl TaskPriority {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => TaskPriority::Background,
+            1 => TaskPriority::Normal,
+            _ => TaskPriority::Interactive,
+        }
+    }
+}
+
+/// Levels are priorities shifted by one, level 0 is used for cancelled tasks.
+const CANCELLED_LEVEL: usize = 0;
+pub(crate) const NORMAL_LEVEL: usize = TaskPriority::Normal as usize + 1;
+
+fn level_of(priority: TaskPriority) -> usize {
+    priority as usize + 1
+}
+
+/// The priority and cancellation state of a root or once task that is shared with all tasks
+/// that are scheduled on its behalf.
+pub(crate) struct PriorityScope {
+    task_id: TaskId,
+    priority: AtomicU8,
+    cancelled: AtomicBool,
+    /// Notified when the scope is cancelled.
+    cancel_event: Event,
+    /// Number of scheduled tasks of this scope that didn't finish yet.
+    pending: AtomicUsize,
+    /// The root task was disposed or the once task completed, the scope is removed as soon as
+    /// there are no pending tasks anymore.
+    detached: AtomicBool,
+}
+
+impl PriorityScope {
+    fn level(&self) -> usize {
+        if self.cancelled.load(Ordering::Acquire) {
+            CANCELLED_LEVEL
+        } else {
+            level_of(TaskPriority::from_u8(self.priority.load(Ordering::Acquire)))
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Resolves when the scope is cancelled.
+    pub async fn cancelled(&self) {
+        loop {
+            let listener = self.cancel_event.listen();
+            if self.is_cancelled() {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+
+/// Decides when scheduled tasks are executed based on the [TaskPriority] of their root or once
+/// task.
+pub(crate) struct PriorityScheduler {
+    /// The scopes of root and once tasks that were spawned with a priority.
+    scopes: RwLock<FxHashMap<TaskId, Arc<PriorityScope>>>,
+    /// Number of scheduled tasks without a scope that didn't finish yet. They have the
+    /// [TaskPriority::Normal] priority.
+    unscoped_pending: AtomicUsize,
+    /// Tasks that are waiting for higher priority tasks, with the level they were boosted to.
+    waiting: Mutex<FxHashMap<TaskId, usize>>,
+    waiting_count: AtomicUsize,
+    /// Notified when pending tasks of a scope finished, a priority changed or a waiting task was
+    /// boosted.
+    event: Event,
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        Self {
+            scopes: Default::default(),
+            unscoped_pending: AtomicUsize::new(0),
+            waiting: Default::default(),
+            waiting_count: AtomicUsize::new(0),
+            event: Event::new(|| "PriorityScheduler::event".to_string()),
+        }
+    }
+
+    pub fn create_scope(&self, task_id: TaskId, priority: TaskPriority) -> Arc<PriorityScope> {
+        let scope = Arc::new(PriorityScope {
+            task_id,
+            priority: AtomicU8::new(priority as u8),
+            cancelled: AtomicBool::new(false),
+            cancel_event: Event::new(move || format!("PriorityScope::cancel_event {task_id}")),
+            pending: AtomicUsize::new(0),
+            detached: AtomicBool::new(false),
+        });
+        self.scopes.write().unwrap().insert(task_id, scope.clone());
+        scope
+    }
+
+    /// Returns the scope of a root or once task.
+    pub fn scope(&self, task_id: TaskId) -> Option<Arc<PriorityScope>> {
+        self.scopes.read().unwrap().get(&task_id).cloned()
+    }
+
+    /// Returns false when the task has no scope.
+    pub fn set_priority(&self, task_id: TaskId, priority: TaskPriority) -> bool {
+        let Some(scope) = self.scope(task_id) else {
+            return false;
+        };
+        scope.priority.store(priority as u8, Ordering::Release);
+        self.event.notify(usize::MAX);
+        true
+    }
+
+    /// Returns false when the task has no scope.
+    pub fn cancel(&self, task_id: TaskId) -> bool {
+        let Some(scope) = self.scope(task_id) else {
+            return false;
+        };
+        scope.cancelled.store(true, Ordering::Release);
+        scope.cancel_event.notify(usize::MAX);
+        self.event.notify(usize::MAX);
+        true
+    }
+
+    /// Removes the scope of a root or once task once all its pending tasks have finished.
+    pub fn detach(&self, task_id: TaskId) {
+        let Some(scope) = self.scope(task_id) else {
+            return;
+        };
+        scope.detached.store(true, Ordering::Release);
+        if scope.pending.load(Ordering::Acquire) == 0 {
+            self.remove(&scope);
+        }
+    }
+
+    fn remove(&self, scope: &Arc<PriorityScope>) {
+        let mut scopes = self.scopes.write().unwrap();
+        if scopes
+            .get(&scope.task_id)
+            .is_some_and(|existing| Arc::ptr_eq(existing, scope))
+        {
+            scopes.remove(&scope.task_id);
+        }
+    }
+
+    /// Called when a task of the scope is scheduled.
+    pub fn begin(&self, scope: Option<&PriorityScope>) {
+        match scope {
+            Some(scope) => scope.pending.fetch_add(1, Ordering::AcqRel),
+            None => self.unscoped_pending.fetch_add(1, Ordering::AcqRel),
+        };
+    }
+
+    /// Called when a task of the scope finished all its executions.
+    pub fn finish(&self, scope: Option<&Arc<PriorityScope>>) {
+        match scope {
+            Some(scope) => {
+                if scope.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    if scope.detached.load(Ordering::Acquire) {
+                        self.remove(scope);
+                    }
+                    self.event.notify(usize::MAX);
+                }
+            }
+            None => {
+                if self.unscoped_pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                    self.event.notify(usize::MAX);
+                }
+            }
+        }
+    }
+
+    fn has_higher_pending(&self, level: usize) -> bool {
+        (level < NORMAL_LEVEL && self.unscoped_pending.load(Ordering::Acquire) > 0)
+            || self
+                .scopes
+                .read()
+                .unwrap()
+                .values()
+                .any(|scope| scope.level() > level && scope.pending.load(Ordering::Acquire) > 0)
+    }
+
+    fn effective_level(&self, task_id: TaskId, scope: Option<&PriorityScope>) -> usize {
+        let level = scope.map_or(NORMAL_LEVEL, PriorityScope::level);
+        if self.waiting_count.load(Ordering::Acquire) == 0 {
+            return level;
+        }
+        let boosted = self.waiting.lock().unwrap().get(&task_id).copied();
+        level.max(boosted.unwrap_or(CANCELLED_LEVEL))
+    }
+
+    /// Waits until no tasks with a higher priority are pending, or at most
+    /// [MAX_PRIORITY_DELAY]. Returns the level the task is executed with.
+    pub async fn wait_for_turn(&self, task_id: TaskId, scope: Option<&PriorityScope>) -> usize {
+        let level = self.effective_level(task_id, scope);
+        if !self.has_higher_pending(level) {
+            return level;
+        }
+        let _waiting = WaitingGuard::new(self, task_id);
+        let deadline = Instant::now() + MAX_PRIORITY_DELAY;
+        loop {
+            let listener = self.event.listen();
+            let level = self.effective_level(task_id, scope);
+            if !self.has_higher_pending(level) || timeout_at(deadline, listener).await.is_err() {
+                return level;
+            }
+        }
+    }
+
+    /// Boosts a task that is waiting for higher priority tasks to the level of a task that reads
+    /// it.
+    pub fn boost(&self, task_id: TaskId, level: usize) {
+        if self.waiting_count.load(Ordering::Acquire) == 0 {
+            return;
+        }
+        let mut waiting = self.waiting.lock().unwrap();
+        if let Some(boosted) = waiting.get_mut(&task_id) {
+            if *boosted < level {
+                *boosted = level;
+                drop(waiting);
+                self.event.notify(usize::MAX);
+            }
+        }
+    }
+}
+
+/// Registers a task as waiting for higher priority tasks, so that it can be boosted.
+struct WaitingGuard<'a> {
+    scheduler: &'a PriorityScheduler,
+    task_id: TaskId,
+}
+
+impl<'a> WaitingGuard<'a> {
+    fn new(scheduler: &'a PriorityScheduler, task_id: TaskId) -> Self {
+        let mut waiting = scheduler.waiting.lock().unwrap();
+        waiting.entry(task_id).or_insert(CANCELLED_LEVEL);
+        scheduler
+            .waiting_count
+            .store(waiting.len(), Ordering::Release);
+        Self { scheduler, task_id }
+    }
+}
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        let mut waiting = self.scheduler.waiting.lock().unwrap();
+        waiting.remove(&self.task_id);
+        self.scheduler
+            .waiting_count
+            .store(waiting.len(), Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_higher_priority() {
+        let scheduler = Arc::new(PriorityScheduler::new());
+        let interactive = scheduler.create_scope(TaskId::from(1), TaskPriority::Interactive);
+        let background = scheduler.create_scope(TaskId::from(2), TaskPriority::Background);
+
+        // Nothing with a higher priority is pending
+        scheduler.begin(Some(&background));
+        assert_eq!(
+            scheduler
+                .wait_for_turn(TaskId::from(3), Some(&background))
+                .await,
+            level_of(TaskPriority::Background)
+        );
+
+        scheduler.begin(Some(&interactive));
+        scheduler.begin(None);
+        let waiter = tokio::spawn({
+            let scheduler = scheduler.clone();
+            let background = background.clone();
+            async move {
+                scheduler
+                    .wait_for_turn(TaskId::from(4), Some(&background))
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        // An unscoped task is only waiting for the interactive task
+        assert_eq!(
+            scheduler
+                .wait_for_turn(TaskId::from(5), None)
+                .now_or_never(),
+            None
+        );
+
+        // Reading a waiting task boosts it
+        while scheduler.waiting_count.load(Ordering::Acquire) == 0 {
+            tokio::task::yield_now().await;
+        }
+        scheduler.boost(TaskId::from(4), level_of(TaskPriority::Interactive));
+        assert_eq!(waiter.await.unwrap(), level_of(TaskPriority::Interactive));
+
+        // Cancelled tasks wait for everything else
+        scheduler.finish(Some(&interactive));
+        assert!(scheduler.cancel(TaskId::from(2)));
+        assert!(background.is_cancelled());
+        assert!(scheduler.has_higher_pending(CANCELLED_LEVEL));
+        scheduler.finish(None);
+        assert!(!scheduler.has_higher_pending(CANCELLED_LEVEL));
+
+        // Detached scopes are removed when all their tasks finished
+        scheduler.detach(TaskId::from(2));
+        assert!(scheduler.scope(TaskId::from(2)).is_some());
+        scheduler.finish(Some(&background));
+        assert!(scheduler.scope(TaskId::from(2)).is_none());
+    }
+}
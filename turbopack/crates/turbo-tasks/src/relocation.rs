@@ -0,0 +1,120 @@
+//! Makes absolute paths in the persistent cache relocatable.
+//!
+//! Persistent caching sets a relocation root with [`with_relocation_root`] while it
+//! (de)serializes task data. Values that store an absolute path, e. g. the root of a disk file
+//! system, opt in with `#[serde(with = "turbo_tasks::relocation")]`. Paths inside of the root are
+//! stored relative to it, so the cache can be restored into a different directory or on a
+//! different machine. All other strings are serialized as is.
+
+use std::cell::RefCell;
+
+use serde::{Deserialize, Deserializer, Serializer};
+use turbo_rcstr::RcStr;
+
+/// Marks a serialized path that was made relative to the relocation root. It can't appear in a
+/// path.
+const RELOCATED_PREFIX: &str = "\0root\0";
+
+thread_local! {
+    static RELOCATION_ROOT: RefCell<Option<RcStr>> = const { RefCell::new(None) };
+}
+
+/// Restores the previous relocation root when dropped.
+struct RestoreRoot(Option<RcStr>);
+
+impl Drop for RestoreRoot {
+    fn drop(&mut self) {
+        RELOCATION_ROOT.set(self.0.take());
+    }
+}
+
+/// Relocates the paths that are (de)serialized while `f` is running on the current thread to
+/// `root`. `None` disables relocation.
+pub fn with_relocation_root<R>(root: Option<&RcStr>, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreRoot(RELOCATION_ROOT.replace(root.cloned()));
+    f()
+}
+
+/// Serializes `path` relative to the current relocation root.
+pub fn serialize<S: Serializer>(path: &RcStr, serializer: S) -> Result<S::Ok, S::Error> {
+    relativize(path, |path| serializer.serialize_str(path))
+}
+
+/// Deserializes a path and rebases it on the current relocation root.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RcStr, D::Error> {
+    let path = String::deserialize(deserializer)?;
+    Ok(rebase(path).into())
+}
+
+/// Replaces the relocation root at the start of `path` with a marker.
+fn relativize<R>(path: &str, f: impl FnOnce(&str) -> R) -> R {
+    RELOCATION_ROOT.with_borrow(|root| {
+        if let Some(root) = root {
+            if let Some(rest) = path.strip_prefix(root.as_str()) {
+                if rest.is_empty() || rest.starts_with(['/', '\\']) {
+                    return f(&format!("{RELOCATED_PREFIX}{rest}"));
+                }
+            }
+        }
+        f(path)
+    })
+}
+
+/// Replaces the marker at the start of `path` with the current relocation root.
+fn rebase(path: String) -> String {
+    if !path.starts_with(RELOCATED_PREFIX) {
+        return path;
+    }
+    RELOCATION_ROOT.with_borrow(|root| match root {
+        Some(root) => format!("{root}{}", &path[RELOCATED_PREFIX.len()..]),
+        None => path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(serialize_root: &str, deserialize_root: &str, path: &str) -> String {
+        let serialized = with_relocation_root(Some(&serialize_root.into()), || {
+            relativize(path, |s| s.to_string())
+        });
+        with_relocation_root(Some(&deserialize_root.into()), || rebase(serialized))
+    }
+
+    #[test]
+    fn relocates_paths_inside_of_the_root() {
+        assert_eq!(
+            roundtrip(
+                "/home/ci/work/a",
+                "/builds/b",
+                "/home/ci/work/a/src/index.js"
+            ),
+            "/builds/b/src/index.js"
+        );
+        assert_eq!(
+            roundtrip("/home/ci/work/a", "/builds/b", "/home/ci/work/a"),
+            "/builds/b"
+        );
+        assert_eq!(
+            roundtrip("C:\\work\\a", "D:\\b", "C:\\work\\a\\src"),
+            "D:\\b\\src"
+        );
+    }
+
+    #[test]
+    fn keeps_other_paths() {
+        assert_eq!(
+            roundtrip("/home/ci/work/a", "/builds/b", "/home/ci/work/abc"),
+            "/home/ci/work/abc"
+        );
+        assert_eq!(
+            roundtrip("/home/ci/work/a", "/builds/b", "src/index.js"),
+            "src/index.js"
+        );
+        assert_eq!(
+            relativize("/home/ci/work/a/src", |s| s.to_string()),
+            "/home/ci/work/a/src"
+        );
+    }
+}
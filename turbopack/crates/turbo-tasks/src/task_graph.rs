@@ -0,0 +1,177 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::{registry, CellId, TaskId, TraitTypeId};
+
+/// A snapshot of the part of the task graph that is reachable from a root task via children and
+/// dependencies. It's created by [`Backend::task_graph`][crate::backend::Backend::task_graph] and
+/// meant for debugging, e.g. to find out why a file change re-executed a task.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskGraph {
+    pub root: TaskId,
+    /// All reachable tasks in breadth-first order, starting with the root.
+    pub tasks: Vec<TaskGraphNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskGraphNode {
+    pub id: TaskId,
+    /// See [`Backend::get_task_description`][crate::backend::Backend::get_task_description].
+    pub description: String,
+    /// The task was invalidated and will be re-executed when it's read again.
+    pub dirty: bool,
+    pub children: Vec<TaskId>,
+    /// The outputs, cells and collectibles of other tasks that were read by the last execution.
+    pub dependencies: Vec<TaskDependency>,
+    /// The collectibles that were emitted by the task itself.
+    pub collectibles: Vec<TaskGraphCollectible>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TaskDependency {
+    Output {
+        task: TaskId,
+    },
+    Cell {
+        task: TaskId,
+        cell: CellId,
+    },
+    Collectibles {
+        task: TaskId,
+        collectible_type: TraitTypeId,
+    },
+}
+
+impl TaskDependency {
+    /// The task that is depended on.
+    pub fn task(&self) -> TaskId {
+        match *self {
+            TaskDependency::Output { task }
+            | TaskDependency::Cell { task, .. }
+            | TaskDependency::Collectibles { task, .. } => task,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct TaskGraphCollectible {
+    pub collectible_type: TraitTypeId,
+    /// The cell that holds the collectible.
+    pub task: TaskId,
+    pub cell: CellId,
+    /// The number of times the collectible was emitted, negative when it was unemitted.
+    pub count: i32,
+}
+
+impl TaskGraph {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Renders the graph in the Graphviz DOT language. Child edges are solid, dependency edges are
+    /// dashed and labeled with what was read. Dirty tasks are highlighted.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph tasks {\n  node [shape=box];\n");
+        for task in self.tasks.iter() {
+            let mut label = task.description.clone();
+            for collectible in task.collectibles.iter() {
+                write!(
+                    label,
+                    "\nemits {}x {}",
+                    collectible.count,
+                    registry::get_trait(collectible.collectible_type).name
+                )
+                .unwrap();
+            }
+            let mut attributes = format!("label=\"{}\"", escape(&label));
+            if task.id == self.root {
+                attributes.push_str(", penwidth=3");
+            }
+            if task.dirty {
+                attributes.push_str(", style=filled, fillcolor=\"#ffcccc\"");
+            }
+            writeln!(dot, "  t{} [{attributes}];", *task.id).unwrap();
+            for child in task.children.iter() {
+                writeln!(dot, "  t{} -> t{};", *task.id, **child).unwrap();
+            }
+            for dependency in task.dependencies.iter() {
+                let label = match dependency {
+                    TaskDependency::Output { .. } => "output".to_string(),
+                    TaskDependency::Cell { cell, .. } => cell.to_string(),
+                    TaskDependency::Collectibles {
+                        collectible_type, ..
+                    } => format!(
+                        "collectibles {}",
+                        registry::get_trait(*collectible_type).name
+                    ),
+                };
+                writeln!(
+                    dot,
+                    "  t{} -> t{} [style=dashed, label=\"{}\"];",
+                    *task.id,
+                    *dependency.task(),
+                    escape(&label)
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_dot() {
+        let graph = TaskGraph {
+            root: TaskId::from(1),
+            tasks: vec![
+                TaskGraphNode {
+                    id: TaskId::from(1),
+                    description: "root".to_string(),
+                    dirty: false,
+                    children: vec![TaskId::from(2)],
+                    dependencies: vec![TaskDependency::Output {
+                        task: TaskId::from(2),
+                    }],
+                    collectibles: vec![],
+                },
+                TaskGraphNode {
+                    id: TaskId::from(2),
+                    description: "read \"file\"".to_string(),
+                    dirty: true,
+                    children: vec![],
+                    dependencies: vec![],
+                    collectibles: vec![],
+                },
+            ],
+        };
+        assert_eq!(
+            graph.to_dot(),
+            r##"digraph tasks {
+  node [shape=box];
+  t1 [label="root", penwidth=3];
+  t1 -> t2;
+  t1 -> t2 [style=dashed, label="output"];
+  t2 [label="read \"file\"", style=filled, fillcolor="#ffcccc"];
+}
+"##
+        );
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json["tasks"][0]["dependencies"],
+            serde_json::json!([{ "kind": "output", "task": 2 }])
+        );
+    }
+}
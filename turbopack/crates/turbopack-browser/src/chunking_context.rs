@@ -15,6 +15,7 @@ use turbopack_core::{
     ident::AssetIdent,
     module::Module,
     output::{OutputAsset, OutputAssets},
+    resolve::web_import_map::WebImportMap,
 };
 use turbopack_ecmascript::{
     async_chunk::module::AsyncLoaderModule,
@@ -23,10 +24,13 @@ use turbopack_ecmascript::{
 };
 use turbopack_ecmascript_runtime::RuntimeType;
 
-use crate::ecmascript::{
-    chunk::EcmascriptDevChunk,
-    evaluate::chunk::EcmascriptDevEvaluateChunk,
-    list::asset::{EcmascriptDevChunkList, EcmascriptDevChunkListSource},
+use crate::{
+    ecmascript::{
+        chunk::EcmascriptDevChunk,
+        evaluate::chunk::EcmascriptDevEvaluateChunk,
+        list::asset::{EcmascriptDevChunkList, EcmascriptDevChunkListSource},
+    },
+    import_map::runtime_import_map_asset,
 };
 
 pub struct BrowserChunkingContextBuilder {
@@ -97,6 +101,11 @@ impl BrowserChunkingContextBuilder {
         self
     }
 
+    pub fn web_import_map(mut self, web_import_map: ResolvedVc<WebImportMap>) -> Self {
+        self.chunking_context.web_import_map = Some(web_import_map);
+        self
+    }
+
     pub fn build(self) -> Vc<BrowserChunkingContext> {
         BrowserChunkingContext::new(Value::new(self.chunking_context))
     }
@@ -149,6 +158,9 @@ pub struct BrowserChunkingContext {
     manifest_chunks: bool,
     /// The module id strategy to use
     module_id_strategy: ResolvedVc<Box<dyn ModuleIdStrategy>>,
+    /// The import map the modules are resolved with. Its runtime import map is
+    /// emitted next to the evaluated chunks.
+    web_import_map: Option<ResolvedVc<WebImportMap>>,
 }
 
 impl BrowserChunkingContext {
@@ -181,6 +193,7 @@ impl BrowserChunkingContext {
                 minify_type: MinifyType::NoMinify,
                 manifest_chunks: false,
                 module_id_strategy: ResolvedVc::upcast(DevModuleIdStrategy::new_resolved()),
+                web_import_map: None,
             },
         }
     }
@@ -497,6 +510,17 @@ impl ChunkingContext for BrowserChunkingContext {
                     .await?,
             );
 
+            if let Some(web_import_map) = this.web_import_map {
+                assets.push(
+                    runtime_import_map_asset(
+                        this.chunk_root_path.join("importmap.json".into()),
+                        *web_import_map,
+                    )
+                    .to_resolved()
+                    .await?,
+                );
+            }
+
             Ok(ChunkGroupResult {
                 assets: ResolvedVc::cell(assets),
                 availability_info,
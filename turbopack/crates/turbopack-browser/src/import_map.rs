@@ -0,0 +1,22 @@
+use anyhow::Result;
+use turbo_tasks::{ResolvedVc, Vc};
+use turbo_tasks_fs::{File, FileContent, FileSystemPath};
+use turbopack_core::{
+    asset::AssetContent, output::OutputAsset, resolve::web_import_map::WebImportMap,
+    virtual_output::VirtualOutputAsset,
+};
+
+/// Emits the runtime import map of a [WebImportMap] at `path`. Inlined into a
+/// `<script type="importmap">` of the page that loads the chunks, it makes the
+/// browser resolve the URL externals of the import map to the same URLs.
+#[turbo_tasks::function]
+pub async fn runtime_import_map_asset(
+    path: ResolvedVc<FileSystemPath>,
+    import_map: Vc<WebImportMap>,
+) -> Result<Vc<Box<dyn OutputAsset>>> {
+    let content = import_map.runtime_import_map().await?;
+    Ok(Vc::upcast(VirtualOutputAsset::new(
+        *path,
+        AssetContent::file(FileContent::Content(File::from(content)).cell()),
+    )))
+}
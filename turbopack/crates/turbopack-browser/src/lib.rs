@@ -5,6 +5,7 @@
 
 pub(crate) mod chunking_context;
 pub mod ecmascript;
+pub mod import_map;
 pub mod react_refresh;
 
 pub use chunking_context::{BrowserChunkingContext, BrowserChunkingContextBuilder};
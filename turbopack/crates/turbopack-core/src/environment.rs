@@ -129,6 +129,18 @@ impl Environment {
         }
     }
 
+    #[turbo_tasks::function]
+    pub fn supports_browser_externals(&self) -> Vc<bool> {
+        match self.execution {
+            ExecutionEnvironment::NodeJsBuildTime(..) | ExecutionEnvironment::NodeJsLambda(_) => {
+                Vc::cell(false)
+            }
+            ExecutionEnvironment::Browser(_) => Vc::cell(true),
+            ExecutionEnvironment::EdgeWorker(_) => Vc::cell(false),
+            ExecutionEnvironment::Custom(_) => Vc::cell(false),
+        }
+    }
+
     #[turbo_tasks::function]
     pub fn supports_wasm(&self) -> Vc<bool> {
         match self.execution {
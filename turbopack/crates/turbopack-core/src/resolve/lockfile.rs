@@ -0,0 +1,737 @@
+//! Versions of packages as pinned by a lockfile (`pnpm-lock.yaml`, `package-lock.json` or
+//! `yarn.lock`). Packages that are resolved through `node_modules` are compared against them to
+//! detect installations that are out of date with the lockfile.
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use turbo_rcstr::RcStr;
+use turbo_tasks::{FxIndexMap, ResolvedVc, ValueToString, Vc};
+use turbo_tasks_fs::{FileContent, FileSystemPath};
+
+use super::{find_context_file, package_json, FindContextFileResult};
+use crate::{
+    issue::{Issue, IssueExt, IssueSeverity, IssueStage, OptionStyledString, StyledString},
+    package_json::read_package_json,
+};
+
+/// The file names of supported lockfiles, in order of preference.
+#[turbo_tasks::function]
+pub fn lockfiles() -> Vc<Vec<RcStr>> {
+    Vc::cell(vec![
+        "pnpm-lock.yaml".into(),
+        "package-lock.json".into(),
+        "yarn.lock".into(),
+    ])
+}
+
+/// Dependency names with the version they are pinned to.
+type PinnedDependencies = FxIndexMap<RcStr, RcStr>;
+
+#[turbo_tasks::value(shared)]
+#[derive(Debug)]
+pub struct LockedVersions {
+    pub lockfile: ResolvedVc<FileSystemPath>,
+    /// The dependencies of workspace packages, by their directory relative to the lockfile. The
+    /// root workspace has an empty directory.
+    importers: FxIndexMap<RcStr, PinnedDependencies>,
+    /// The dependencies of installed packages, by `name@version`, which is how `pnpm-lock.yaml` is
+    /// organized.
+    packages: FxIndexMap<RcStr, PinnedDependencies>,
+    /// The dependencies of installed packages, by their directory relative to the lockfile,
+    /// which is how `package-lock.json` is organized. Copies of a package with the same version
+    /// can resolve their dependencies to different versions.
+    installs: FxIndexMap<RcStr, PinnedDependencies>,
+    /// Versions by descriptor (`name@range`), which is how `yarn.lock` is organized.
+    descriptors: PinnedDependencies,
+}
+
+#[derive(Default)]
+struct LockedGraph {
+    importers: FxIndexMap<RcStr, PinnedDependencies>,
+    packages: FxIndexMap<RcStr, PinnedDependencies>,
+    installs: FxIndexMap<RcStr, PinnedDependencies>,
+    descriptors: PinnedDependencies,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionLockedVersions(Option<ResolvedVc<LockedVersions>>);
+
+/// Reads and parses a lockfile. Emits a [LockfileIssue] if the lockfile can't be parsed.
+#[turbo_tasks::function]
+pub async fn read_locked_versions(
+    lockfile: ResolvedVc<FileSystemPath>,
+) -> Result<Vc<OptionLockedVersions>> {
+    let FileContent::Content(file) = &*lockfile.read().await? else {
+        return Ok(Vc::cell(None));
+    };
+    let content = file.content().to_str()?;
+    let graph = match lockfile.await?.file_name() {
+        "pnpm-lock.yaml" => parse_pnpm_lock(&content),
+        "package-lock.json" => parse_package_lock(&content),
+        "yarn.lock" => parse_yarn_lock(&content),
+        name => bail!("{name} is not a supported lockfile"),
+    };
+    match graph {
+        Ok(graph) => Ok(Vc::cell(Some(
+            LockedVersions {
+                lockfile,
+                importers: graph.importers,
+                packages: graph.packages,
+                installs: graph.installs,
+                descriptors: graph.descriptors,
+            }
+            .resolved_cell(),
+        ))),
+        Err(err) => {
+            LockfileIssue {
+                path: lockfile,
+                error_message: format!("{err:#}").into(),
+            }
+            .resolved_cell()
+            .emit();
+            Ok(Vc::cell(None))
+        }
+    }
+}
+
+/// `package-lock.json` (lockfile version 2 and 3) lists all installed packages by their path.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NpmLockfile {
+    lockfile_version: u32,
+    #[serde(default)]
+    packages: FxIndexMap<RcStr, NpmPackage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NpmPackage {
+    version: Option<RcStr>,
+    /// Set for workspace packages that are symlinked into `node_modules`.
+    #[serde(default)]
+    link: bool,
+    resolved: Option<RcStr>,
+    #[serde(default)]
+    dependencies: FxIndexMap<RcStr, RcStr>,
+    #[serde(default)]
+    dev_dependencies: FxIndexMap<RcStr, RcStr>,
+    #[serde(default)]
+    optional_dependencies: FxIndexMap<RcStr, RcStr>,
+    #[serde(default)]
+    peer_dependencies: FxIndexMap<RcStr, RcStr>,
+}
+
+fn parse_package_lock(content: &str) -> Result<LockedGraph> {
+    let lockfile: NpmLockfile = serde_json::from_str(content)?;
+    if lockfile.lockfile_version < 2 {
+        bail!(
+            "lockfileVersion {} is not supported, run `npm install` with npm 7 or later to \
+             upgrade the lockfile",
+            lockfile.lockfile_version
+        );
+    }
+    let mut graph = LockedGraph::default();
+    for (path, package) in &lockfile.packages {
+        if package.link {
+            continue;
+        }
+        let dependencies = package
+            .dependencies
+            .keys()
+            .chain(package.dev_dependencies.keys())
+            .chain(package.optional_dependencies.keys())
+            .chain(package.peer_dependencies.keys())
+            .filter_map(|name| {
+                let version = npm_installed_version(&lockfile.packages, path, name)?;
+                Some((name.clone(), version))
+            })
+            .collect();
+        if path.contains("node_modules/") {
+            graph.installs.insert(path.clone(), dependencies);
+        } else {
+            graph.importers.insert(path.clone(), dependencies);
+        }
+    }
+    Ok(graph)
+}
+
+/// Finds the version of `name` that Node.js would resolve from the package at `path`, by looking
+/// into the `node_modules` directories of the package and all of its parents.
+fn npm_installed_version(
+    packages: &FxIndexMap<RcStr, NpmPackage>,
+    path: &str,
+    name: &str,
+) -> Option<RcStr> {
+    let mut dir = Some(path);
+    while let Some(current) = dir {
+        let candidate = if current.is_empty() {
+            format!("node_modules/{name}")
+        } else {
+            format!("{current}/node_modules/{name}")
+        };
+        if let Some(package) = packages.get(candidate.as_str()) {
+            if package.link {
+                return packages.get(package.resolved.as_deref()?)?.version.clone();
+            }
+            return package.version.clone();
+        }
+        dir = npm_parent_path(current);
+    }
+    None
+}
+
+/// The parent of an installed package is the package that contains the `node_modules`
+/// directory, the parent of a workspace package is the parent directory.
+fn npm_parent_path(path: &str) -> Option<&str> {
+    if let Some(index) = path.rfind("node_modules/") {
+        return Some(path[..index].trim_end_matches('/'));
+    }
+    match path.rsplit_once('/') {
+        Some((parent, _)) => Some(parent),
+        None => (!path.is_empty()).then_some(""),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmLockfile {
+    #[serde(default)]
+    importers: FxIndexMap<RcStr, PnpmImporter>,
+    /// Lockfiles of a project without workspaces list the dependencies at the top level.
+    #[serde(flatten)]
+    root: PnpmImporter,
+    #[serde(default)]
+    packages: FxIndexMap<RcStr, PnpmPackage>,
+    /// Since lockfile version 9, dependencies of packages are listed here instead of in
+    /// `packages`.
+    #[serde(default)]
+    snapshots: FxIndexMap<RcStr, PnpmPackage>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmImporter {
+    #[serde(default)]
+    dependencies: FxIndexMap<RcStr, PnpmImporterDependency>,
+    #[serde(default)]
+    dev_dependencies: FxIndexMap<RcStr, PnpmImporterDependency>,
+    #[serde(default)]
+    optional_dependencies: FxIndexMap<RcStr, PnpmImporterDependency>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PnpmImporterDependency {
+    /// Before lockfile version 6, the specifiers are listed separately.
+    Version(RcStr),
+    Specified {
+        version: RcStr,
+    },
+}
+
+impl PnpmImporter {
+    fn pinned_dependencies(self) -> PinnedDependencies {
+        self.dependencies
+            .into_iter()
+            .chain(self.dev_dependencies)
+            .chain(self.optional_dependencies)
+            .map(|(name, dependency)| {
+                let (PnpmImporterDependency::Version(version)
+                | PnpmImporterDependency::Specified { version }) = dependency;
+                (name, pnpm_version(&version))
+            })
+            .collect()
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PnpmPackage {
+    #[serde(default)]
+    dependencies: FxIndexMap<RcStr, RcStr>,
+    #[serde(default)]
+    optional_dependencies: FxIndexMap<RcStr, RcStr>,
+}
+
+fn parse_pnpm_lock(content: &str) -> Result<LockedGraph> {
+    let lockfile: PnpmLockfile = serde_yaml::from_str(content)?;
+    let mut graph = LockedGraph::default();
+    if lockfile.importers.is_empty() {
+        graph
+            .importers
+            .insert(RcStr::default(), lockfile.root.pinned_dependencies());
+    }
+    for (dir, importer) in lockfile.importers {
+        let dir = if dir == "." { RcStr::default() } else { dir };
+        graph.importers.insert(dir, importer.pinned_dependencies());
+    }
+    for (key, package) in lockfile.packages.into_iter().chain(lockfile.snapshots) {
+        let dependencies: PinnedDependencies = package
+            .dependencies
+            .into_iter()
+            .chain(package.optional_dependencies)
+            .map(|(name, version)| (name, pnpm_version(&version)))
+            .collect();
+        // `packages` has no dependencies since version 9, they are in `snapshots`
+        if !dependencies.is_empty() {
+            graph.packages.insert(pnpm_package_key(&key), dependencies);
+        }
+    }
+    Ok(graph)
+}
+
+/// Removes the suffix that pnpm appends to packages with peer dependencies, which is
+/// `(react@18.2.0)` since lockfile version 6 and `_react@18.2.0` before.
+fn strip_pnpm_peer_suffix(value: &str) -> &str {
+    let value = value.split('(').next().unwrap_or(value);
+    let version_start = if value.starts_with(|c: char| c.is_ascii_digit()) {
+        0
+    } else {
+        value
+            .char_indices()
+            .skip(1)
+            .find(|&(index, c)| {
+                matches!(c, '@' | '/')
+                    && value[index + 1..].starts_with(|c: char| c.is_ascii_digit())
+            })
+            .map_or(0, |(index, _)| index + 1)
+    };
+    match value[version_start..].find('_') {
+        Some(index) => &value[..version_start + index],
+        None => value,
+    }
+}
+
+/// Normalizes a version in `pnpm-lock.yaml` (`18.2.0(react@18.2.0)`, or `string-width@4.2.3`
+/// for aliased dependencies) to the version in the package.json of the installed package.
+fn pnpm_version(version: &str) -> RcStr {
+    let version = strip_pnpm_peer_suffix(version.strip_prefix('/').unwrap_or(version));
+    match version.rfind('@') {
+        Some(index) if index > 0 => version[index + 1..].into(),
+        _ => version.into(),
+    }
+}
+
+/// Normalizes a key of `packages` or `snapshots` (`/react-dom@18.2.0(react@18.2.0)`, or
+/// `/react-dom/18.2.0_react@18.2.0` before lockfile version 6) to `name@version`.
+fn pnpm_package_key(key: &str) -> RcStr {
+    let key = strip_pnpm_peer_suffix(key.strip_prefix('/').unwrap_or(key));
+    if key.get(1..).is_some_and(|rest| rest.contains('@')) {
+        return key.into();
+    }
+    match key.rsplit_once('/') {
+        Some((name, version)) => format!("{name}@{version}").into(),
+        None => key.into(),
+    }
+}
+
+/// Both the classic `yarn.lock` format and the YAML format of Yarn 2+ map comma-separated lists
+/// of descriptors to the version they resolve to.
+fn parse_yarn_lock(content: &str) -> Result<LockedGraph> {
+    let mut graph = LockedGraph::default();
+    if content.lines().any(|line| line.starts_with("__metadata:")) {
+        let entries: FxIndexMap<RcStr, serde_yaml::Value> = serde_yaml::from_str(content)?;
+        for (descriptors, entry) in entries {
+            if descriptors == "__metadata" {
+                continue;
+            }
+            let version: RcStr = match entry.get("version") {
+                Some(serde_yaml::Value::String(version)) => version.as_str().into(),
+                Some(serde_yaml::Value::Number(version)) => version.to_string().into(),
+                _ => continue,
+            };
+            for descriptor in descriptors.split(", ") {
+                graph.descriptors.insert(descriptor.into(), version.clone());
+            }
+        }
+    } else {
+        let mut descriptors = Vec::new();
+        for line in content.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(' ') {
+                descriptors = line
+                    .trim_end_matches(':')
+                    .split(", ")
+                    .map(|descriptor| RcStr::from(descriptor.trim_matches('"')))
+                    .collect();
+            } else if let Some(version) = line.strip_prefix("  version ") {
+                let version = RcStr::from(version.trim_matches('"'));
+                for descriptor in descriptors.drain(..) {
+                    graph.descriptors.insert(descriptor, version.clone());
+                }
+            }
+        }
+    }
+    Ok(graph)
+}
+
+impl LockedVersions {
+    /// Returns the version of `name` that the lockfile pins for the package with the package.json
+    /// `issuer`, which is located in `dir` relative to the lockfile.
+    fn pinned_version(&self, dir: &str, issuer: &serde_json::Value, name: &str) -> Option<&RcStr> {
+        let dependencies = if dir.contains("node_modules/") {
+            self.installs.get(dir).or_else(|| {
+                let issuer_name = issuer.get("name")?.as_str()?;
+                let issuer_version = issuer.get("version")?.as_str()?;
+                self.packages
+                    .get(format!("{issuer_name}@{issuer_version}").as_str())
+            })
+        } else {
+            self.importers.get(dir)
+        };
+        if let Some(version) = dependencies.and_then(|dependencies| dependencies.get(name)) {
+            return Some(version);
+        }
+        let range = [
+            "dependencies",
+            "devDependencies",
+            "optionalDependencies",
+            "peerDependencies",
+        ]
+        .into_iter()
+        .find_map(|field| issuer.get(field)?.get(name)?.as_str())?;
+        self.descriptors
+            .get(format!("{name}@{range}").as_str())
+            .or_else(|| self.descriptors.get(format!("{name}@npm:{range}").as_str()))
+    }
+}
+
+/// Emits a [LockedVersionMismatchIssue] when the package in `package_dir`, which was resolved
+/// from `lookup_path` through `node_modules`, has a different version than the one the lockfile
+/// pins for the package that contains `lookup_path`.
+pub(super) async fn check_locked_version(
+    locked_versions: Vc<LockedVersions>,
+    lookup_path: Vc<FileSystemPath>,
+    package_name: &str,
+    package_dir: ResolvedVc<FileSystemPath>,
+) -> Result<()> {
+    let FindContextFileResult::Found(issuer_package_json, _) =
+        &*find_context_file(lookup_path, package_json()).await?
+    else {
+        return Ok(());
+    };
+    let locked_versions = locked_versions.await?;
+    let Some(dir) = locked_versions
+        .lockfile
+        .parent()
+        .await?
+        .get_relative_path_to(&*issuer_package_json.parent().await?)
+    else {
+        return Ok(());
+    };
+    let dir = if dir == "." {
+        ""
+    } else if let Some(dir) = dir.strip_prefix("./") {
+        dir
+    } else {
+        // outside of the directory of the lockfile
+        return Ok(());
+    };
+    let issuer = read_package_json(**issuer_package_json).await?;
+    let Some(issuer) = &*issuer else {
+        return Ok(());
+    };
+    let Some(locked_version) = locked_versions.pinned_version(dir, issuer, package_name) else {
+        return Ok(());
+    };
+    // links, git dependencies, tarballs, ...
+    if !locked_version.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(());
+    }
+    let package = read_package_json(package_dir.join("package.json".into())).await?;
+    let Some(package) = &*package else {
+        return Ok(());
+    };
+    let Some(resolved_version) = package.get("version").and_then(|version| version.as_str()) else {
+        return Ok(());
+    };
+    if resolved_version != &**locked_version {
+        LockedVersionMismatchIssue {
+            file_path: *issuer_package_json,
+            lockfile: locked_versions.lockfile,
+            package_name: package_name.into(),
+            package_dir,
+            resolved_version: resolved_version.into(),
+            locked_version: locked_version.clone(),
+        }
+        .resolved_cell()
+        .emit();
+    }
+    Ok(())
+}
+
+#[turbo_tasks::value(shared)]
+pub struct LockfileIssue {
+    pub path: ResolvedVc<FileSystemPath>,
+    pub error_message: RcStr,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for LockfileIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Warning.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Text("Error parsing lockfile".into()).cell()
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Parse.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.path
+    }
+
+    #[turbo_tasks::function]
+    fn description(&self) -> Vc<OptionStyledString> {
+        Vc::cell(Some(
+            StyledString::Text(self.error_message.clone()).resolved_cell(),
+        ))
+    }
+}
+
+/// A package resolved through `node_modules` has a different version than the lockfile pins.
+#[turbo_tasks::value(shared)]
+pub struct LockedVersionMismatchIssue {
+    /// The package.json of the package that depends on the mismatching package.
+    pub file_path: ResolvedVc<FileSystemPath>,
+    pub lockfile: ResolvedVc<FileSystemPath>,
+    pub package_name: RcStr,
+    pub package_dir: ResolvedVc<FileSystemPath>,
+    pub resolved_version: RcStr,
+    pub locked_version: RcStr,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for LockedVersionMismatchIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Warning.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Line(vec![
+            StyledString::Text("Installed version of ".into()),
+            StyledString::Code(self.package_name.clone()),
+            StyledString::Text(" doesn't match the lockfile".into()),
+        ])
+        .cell()
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Resolve.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.file_path
+    }
+
+    #[turbo_tasks::function]
+    async fn description(&self) -> Result<Vc<OptionStyledString>> {
+        Ok(Vc::cell(Some(
+            StyledString::Text(
+                format!(
+                    "{} contains version {}, but {} pins version {}. The installed packages are \
+                     out of date, reinstall them to match the lockfile.",
+                    self.package_dir.to_string().await?,
+                    self.resolved_version,
+                    self.lockfile.to_string().await?,
+                    self.locked_version
+                )
+                .into(),
+            )
+            .resolved_cell(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_package_lock, parse_pnpm_lock, parse_yarn_lock, pnpm_package_key};
+
+    #[test]
+    fn test_package_lock() {
+        let graph = parse_package_lock(
+            r#"{
+                "lockfileVersion": 3,
+                "packages": {
+                    "": { "workspaces": ["packages/app"], "devDependencies": { "react": "^18.0.0" } },
+                    "packages/app": {
+                        "version": "1.0.0",
+                        "dependencies": { "react": "^17.0.0", "lib": "*", "scheduler": "*" }
+                    },
+                    "packages/app/node_modules/react": { "version": "17.0.2" },
+                    "node_modules/app": { "resolved": "packages/app", "link": true },
+                    "node_modules/lib": { "resolved": "packages/lib", "link": true },
+                    "packages/lib": { "version": "0.1.0" },
+                    "node_modules/react": {
+                        "version": "18.2.0",
+                        "dependencies": { "loose-envify": "^1.1.0" }
+                    },
+                    "node_modules/loose-envify": { "version": "1.4.0" },
+                    "packages/app/node_modules/react-dom": {
+                        "version": "18.2.0",
+                        "dependencies": { "react": "^18.0.0 || ^17.0.0" }
+                    },
+                    "node_modules/react-dom": {
+                        "version": "18.2.0",
+                        "dependencies": { "react": "^18.0.0 || ^17.0.0" }
+                    },
+                    "node_modules/@scope/scheduler": { "version": "0.23.0" },
+                    "node_modules/scheduler": { "name": "@scope/scheduler", "version": "0.23.0" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(graph.importers[""]["react"], "18.2.0");
+        assert_eq!(graph.importers["packages/app"]["react"], "17.0.2");
+        assert_eq!(graph.importers["packages/app"]["lib"], "0.1.0");
+        assert_eq!(graph.importers["packages/app"]["scheduler"], "0.23.0");
+        assert_eq!(
+            graph.installs["node_modules/react"]["loose-envify"],
+            "1.4.0"
+        );
+        // Copies of the same version keep the dependencies of their own install path
+        assert_eq!(
+            graph.installs["packages/app/node_modules/react-dom"]["react"],
+            "17.0.2"
+        );
+        assert_eq!(graph.installs["node_modules/react-dom"]["react"], "18.2.0");
+        assert!(graph.installs.contains_key("node_modules/scheduler"));
+
+        assert!(parse_package_lock(r#"{ "lockfileVersion": 1, "dependencies": {} }"#).is_err());
+    }
+
+    #[test]
+    fn test_pnpm_lock() {
+        let graph = parse_pnpm_lock(
+            "lockfileVersion: '9.0'
+importers:
+  .:
+    devDependencies:
+      typescript:
+        specifier: ^5.0.0
+        version: 5.3.3
+  packages/app:
+    dependencies:
+      react-dom:
+        specifier: ^18.2.0
+        version: 18.2.0(react@18.2.0)
+      string-width-cjs:
+        specifier: npm:string-width@^4.2.0
+        version: string-width@4.2.3
+      lib:
+        specifier: workspace:*
+        version: link:../lib
+packages:
+  react-dom@18.2.0:
+    resolution: {integrity: sha512-abc}
+    peerDependencies:
+      react: ^18.2.0
+snapshots:
+  react-dom@18.2.0(react@18.2.0):
+    dependencies:
+      react: 18.2.0
+      scheduler: 0.23.0
+",
+        )
+        .unwrap();
+        assert_eq!(graph.importers[""]["typescript"], "5.3.3");
+        assert_eq!(graph.importers["packages/app"]["react-dom"], "18.2.0");
+        assert_eq!(graph.importers["packages/app"]["string-width-cjs"], "4.2.3");
+        assert_eq!(graph.importers["packages/app"]["lib"], "link:../lib");
+        assert_eq!(graph.packages["react-dom@18.2.0"]["scheduler"], "0.23.0");
+
+        let graph = parse_pnpm_lock(
+            "lockfileVersion: 5.4
+specifiers:
+  react: ^18.2.0
+dependencies:
+  react: 18.2.0
+packages:
+  /string_decoder/1.3.0:
+    dependencies:
+      safe-buffer: 5.2.1
+",
+        )
+        .unwrap();
+        assert_eq!(graph.importers[""]["react"], "18.2.0");
+        assert_eq!(
+            graph.packages["string_decoder@1.3.0"]["safe-buffer"],
+            "5.2.1"
+        );
+    }
+
+    #[test]
+    fn test_pnpm_package_key() {
+        assert_eq!(pnpm_package_key("/react@18.2.0"), "react@18.2.0");
+        assert_eq!(
+            pnpm_package_key("/react-dom@18.2.0(react@18.2.0)"),
+            "react-dom@18.2.0"
+        );
+        assert_eq!(
+            pnpm_package_key("/react-dom/18.2.0_react@18.2.0"),
+            "react-dom@18.2.0"
+        );
+        assert_eq!(pnpm_package_key("/@babel/core/7.0.0"), "@babel/core@7.0.0");
+        assert_eq!(
+            pnpm_package_key("@babel/core@7.0.0(supports-color@8.1.1)"),
+            "@babel/core@7.0.0"
+        );
+    }
+
+    #[test]
+    fn test_yarn_lock() {
+        let graph = parse_yarn_lock(
+            r#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+"@babel/code-frame@^7.0.0", "@babel/code-frame@^7.10.4":
+  version "7.12.13"
+  resolved "https://registry.yarnpkg.com/@babel/code-frame/-/code-frame-7.12.13.tgz"
+  dependencies:
+    "@babel/highlight" "^7.12.13"
+
+react@^18.2.0:
+  version "18.2.0"
+"#,
+        )
+        .unwrap();
+        assert_eq!(graph.descriptors["@babel/code-frame@^7.0.0"], "7.12.13");
+        assert_eq!(graph.descriptors["@babel/code-frame@^7.10.4"], "7.12.13");
+        assert_eq!(graph.descriptors["react@^18.2.0"], "18.2.0");
+
+        let graph = parse_yarn_lock(
+            r#"# This file is generated by running "yarn install" inside your project.
+
+__metadata:
+  version: 8
+  cacheKey: 10c0
+
+"react@npm:^18.0.0, react@npm:^18.2.0":
+  version: 18.2.0
+  resolution: "react@npm:18.2.0"
+  dependencies:
+    loose-envify: "npm:^1.1.0"
+  languageName: node
+  linkType: hard
+"#,
+        )
+        .unwrap();
+        assert_eq!(graph.descriptors["react@npm:^18.0.0"], "18.2.0");
+        assert_eq!(graph.descriptors["react@npm:^18.2.0"], "18.2.0");
+        assert!(!graph.descriptors.contains_key("__metadata"));
+    }
+}
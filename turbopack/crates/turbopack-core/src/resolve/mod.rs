@@ -19,6 +19,7 @@ use turbo_tasks_fs::{
 };
 
 use self::{
+    lockfile::check_locked_version,
     options::{
         resolve_modules_options, ConditionValue, ImportMapResult, ResolveInPackage,
         ResolveIntoPackage, ResolveModules, ResolveModulesOptions, ResolveOptions,
@@ -27,6 +28,7 @@ use self::{
     parse::Request,
     pattern::Pattern,
     plugin::BeforeResolvePlugin,
+    pnp::{resolve_pnp_package, PnpResolveResult},
     remap::{ExportsField, ImportsField},
 };
 use crate::{
@@ -49,13 +51,16 @@ use crate::{
 };
 
 mod alias_map;
+pub mod lockfile;
 pub mod node;
 pub mod options;
 pub mod origin;
 pub mod parse;
 pub mod pattern;
 pub mod plugin;
+pub mod pnp;
 pub(crate) mod remap;
+pub mod web_import_map;
 
 pub use alias_map::{
     AliasMap, AliasMapIntoIter, AliasMapLookupIterator, AliasMatch, AliasPattern, AliasTemplate,
@@ -402,6 +407,14 @@ pub enum ExternalType {
     Url,
     CommonJs,
     EcmaScriptModule,
+    /// A global variable provided by the page, e.g. `React`. Nested properties can be accessed
+    /// with dots, e.g. `MyLib.utils`.
+    Global,
+    /// A UMD module that is loaded through the page's AMD loader, or read from the global
+    /// variable of the same name when there is none.
+    Umd,
+    /// A module that is loaded with the page's SystemJS loader (`System.import`).
+    SystemJs,
 }
 
 impl Display for ExternalType {
@@ -410,6 +423,9 @@ impl Display for ExternalType {
             ExternalType::CommonJs => write!(f, "commonjs"),
             ExternalType::EcmaScriptModule => write!(f, "esm"),
             ExternalType::Url => write!(f, "url"),
+            ExternalType::Global => write!(f, "global"),
+            ExternalType::Umd => write!(f, "umd"),
+            ExternalType::SystemJs => write!(f, "system"),
         }
     }
 }
@@ -1317,6 +1333,8 @@ async fn find_package(
     for resolve_modules in &options.modules {
         match resolve_modules {
             ResolveModules::Nested(root_vc, names) => {
+                let issuer_path = lookup_path;
+                let first_package = packages.len();
                 let mut lookup_path = lookup_path;
                 let mut lookup_path_value = lookup_path.await?;
                 // For clippy -- This explicit deref is necessary
@@ -1340,6 +1358,20 @@ async fn find_package(
                     }
                     lookup_path_value = new_context_value;
                 }
+                // Node.js uses the closest package, the others are only alternatives
+                if let (
+                    Some(locked_versions),
+                    Some(FindPackageItem::PackageDirectory(package_dir)),
+                ) = (options.locked_versions, packages.get(first_package))
+                {
+                    check_locked_version(
+                        *locked_versions,
+                        issuer_path,
+                        &package_name,
+                        *package_dir,
+                    )
+                    .await?;
+                }
             }
             ResolveModules::Path {
                 dir,
@@ -1375,6 +1407,31 @@ async fn find_package(
                     }
                 }
             }
+            ResolveModules::Pnp { manifest } => {
+                match &*resolve_pnp_package(**manifest, lookup_path, package_name.clone()).await? {
+                    PnpResolveResult::Package(package_dir) => {
+                        affecting_sources.push(ResolvedVc::upcast(
+                            FileSource::new(**manifest).to_resolved().await?,
+                        ));
+                        if let Some(package_dir) =
+                            dir_exists(**package_dir, &mut affecting_sources).await?
+                        {
+                            packages.push(FindPackageItem::PackageDirectory(package_dir));
+                        }
+                    }
+                    // The manifest denies the access, so other ways of resolving don't apply
+                    PnpResolveResult::Unresolvable => {
+                        affecting_sources.push(ResolvedVc::upcast(
+                            FileSource::new(**manifest).to_resolved().await?,
+                        ));
+                        return Ok(FindPackageResult::cell(FindPackageResult {
+                            packages,
+                            affecting_sources,
+                        }));
+                    }
+                    PnpResolveResult::NotInTree => {}
+                }
+            }
         }
     }
     Ok(FindPackageResult::cell(FindPackageResult {
@@ -2660,11 +2717,19 @@ async fn resolve_import_map_result(
             {
                 None
             } else {
-                let is_external_resolvable = !resolve_internal(
+                // Browser externals are provided by the page at runtime, so there is nothing to
+                // resolve at build time.
+                let is_external_resolvable = matches!(
+                    ty,
+                    ExternalType::Global | ExternalType::Umd | ExternalType::SystemJs
+                ) || !resolve_internal(
                     **alias_lookup_path,
                     request,
                     match ty {
-                        ExternalType::Url => options,
+                        ExternalType::Url
+                        | ExternalType::Global
+                        | ExternalType::Umd
+                        | ExternalType::SystemJs => options,
                         // TODO is that root correct?
                         ExternalType::CommonJs => {
                             node_cjs_resolve_options(alias_lookup_path.root())
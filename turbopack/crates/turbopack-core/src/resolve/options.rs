@@ -9,6 +9,7 @@ use turbo_tasks::{
 };
 use turbo_tasks_fs::{glob::Glob, FileSystemPath};
 
+pub use super::lockfile::LockedVersions;
 use super::{
     alias_map::{AliasMap, AliasTemplate},
     pattern::Pattern,
@@ -17,10 +18,6 @@ use super::{
 };
 use crate::resolve::{parse::Request, plugin::AfterResolvePlugin, ExternalTraced};
 
-#[turbo_tasks::value(shared)]
-#[derive(Hash, Debug)]
-pub struct LockedVersions {}
-
 #[turbo_tasks::value(transparent)]
 #[derive(Debug)]
 pub struct ExcludedExtensions(pub FxIndexSet<RcStr>);
@@ -47,6 +44,11 @@ pub enum ResolveModules {
         dir: ResolvedVc<FileSystemPath>,
         excluded_extensions: ResolvedVc<ExcludedExtensions>,
     },
+    /// look up the package in the dependency tree of a Yarn Plug'n'Play
+    /// manifest (`.pnp.cjs` or `.pnp.data.json`)
+    Pnp {
+        manifest: ResolvedVc<FileSystemPath>,
+    },
 }
 
 #[derive(
@@ -603,6 +605,9 @@ pub struct ResolveOptions {
     pub enable_typescript_with_output_extension: bool,
     /// Warn instead of error for resolve errors
     pub loose_errors: bool,
+    /// Compare packages resolved from node_modules with the versions pinned
+    /// by a lockfile and warn when they differ.
+    pub locked_versions: Option<ResolvedVc<LockedVersions>>,
 
     pub placeholder_for_future_extensions: (),
 }
@@ -676,6 +681,7 @@ impl ResolveOptions {
 pub struct ResolveModulesOptions {
     pub modules: Vec<ResolveModules>,
     pub extensions: Vec<RcStr>,
+    pub locked_versions: Option<ResolvedVc<LockedVersions>>,
 }
 
 #[turbo_tasks::function]
@@ -686,6 +692,7 @@ pub async fn resolve_modules_options(
     Ok(ResolveModulesOptions {
         modules: options.modules.clone(),
         extensions: options.extensions.clone(),
+        locked_versions: options.locked_versions,
     }
     .into())
 }
@@ -0,0 +1,874 @@
+//! Resolving of bare specifiers through the dependency tree of a Yarn Plug'n'Play manifest.
+//!
+//! Instead of a `node_modules` directory, Yarn writes a manifest (`.pnp.cjs` or `.pnp.data.json`)
+//! that lists the dependencies of every package and where each package is located. See
+//! <https://yarnpkg.com/advanced/pnp-spec> for the resolution algorithm.
+
+use std::{
+    cmp::Reverse,
+    fmt::{Display, Formatter},
+};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use turbo_rcstr::RcStr;
+use turbo_tasks::{FxIndexMap, FxIndexSet, ResolvedVc, ValueToString, Vc};
+use turbo_tasks_fs::{
+    util::normalize_request, ArchiveFileSystem, FileContent, FileSystem, FileSystemPath,
+};
+
+use crate::issue::{Issue, IssueExt, IssueSeverity, IssueStage, OptionStyledString, StyledString};
+
+/// The file names of a Plug'n'Play manifest, in order of preference. `.pnp.data.json` is only
+/// written when the data isn't inlined into `.pnp.cjs` (`pnpEnableInlining: false`).
+#[turbo_tasks::function]
+pub fn pnp_manifest_files() -> Vc<Vec<RcStr>> {
+    Vc::cell(vec![".pnp.data.json".into(), ".pnp.cjs".into()])
+}
+
+/// Identifies a package in the manifest. The top-level workspace has neither a name nor a
+/// reference.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct PnpLocator {
+    name: Option<RcStr>,
+    reference: Option<RcStr>,
+}
+
+impl PnpLocator {
+    fn top_level() -> Self {
+        PnpLocator {
+            name: None,
+            reference: None,
+        }
+    }
+}
+
+impl Display for PnpLocator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.name, &self.reference) {
+            (Some(name), Some(reference)) => write!(f, "{name}@{reference}"),
+            (Some(name), None) => write!(f, "{name}"),
+            _ => write!(f, "the top-level workspace"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct PnpPackage {
+    /// Relative to the directory of the manifest, without a leading `./` and with a trailing `/`
+    /// (the top-level workspace has an empty location).
+    location: RcStr,
+    /// `None` for peer dependencies that aren't provided by the dependent package.
+    dependencies: FxIndexMap<RcStr, Option<PnpLocator>>,
+}
+
+/// A regular expression that is compiled once and (de)serialized and compared as its pattern.
+#[derive(Debug)]
+struct IgnorePattern(Regex);
+
+impl PartialEq for IgnorePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for IgnorePattern {}
+
+impl Serialize for IgnorePattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for IgnorePattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern)
+            .map(IgnorePattern)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PnpManifest {
+    packages: FxIndexMap<PnpLocator, PnpPackage>,
+    /// All package locations, longest first, so that the first location that is a prefix of a
+    /// path belongs to the package that contains the path. Virtual instances of a package share
+    /// its location.
+    locations: Vec<(RcStr, Vec<PnpLocator>)>,
+    enable_top_level_fallback: bool,
+    fallback_pool: FxIndexMap<RcStr, Option<PnpLocator>>,
+    fallback_exclusion_list: FxIndexSet<PnpLocator>,
+    /// A regular expression for paths that are not part of the dependency tree.
+    ignore_pattern: Option<IgnorePattern>,
+}
+
+/// The serialized state as written by Yarn.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPnpData {
+    #[serde(default)]
+    enable_top_level_fallback: bool,
+    #[serde(default)]
+    ignore_pattern_data: Option<String>,
+    #[serde(default)]
+    fallback_exclusion_list: Vec<(RcStr, Vec<RcStr>)>,
+    #[serde(default)]
+    fallback_pool: Vec<(RcStr, Option<RawPnpDependency>)>,
+    package_registry_data: Vec<(Option<RcStr>, RawPnpReferences)>,
+}
+
+/// The packages of one name, by reference.
+type RawPnpReferences = Vec<(Option<RcStr>, RawPnpPackage)>;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawPnpPackage {
+    package_location: RcStr,
+    #[serde(default)]
+    package_dependencies: Vec<(RcStr, Option<RawPnpDependency>)>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPnpDependency {
+    Reference(RcStr),
+    /// An aliased dependency (`"foo": "npm:bar@1.0.0"`) that points to a package with a different
+    /// name.
+    Alias(RcStr, RcStr),
+}
+
+impl RawPnpDependency {
+    fn into_locator(self, name: &RcStr) -> PnpLocator {
+        match self {
+            RawPnpDependency::Reference(reference) => PnpLocator {
+                name: Some(name.clone()),
+                reference: Some(reference),
+            },
+            RawPnpDependency::Alias(name, reference) => PnpLocator {
+                name: Some(name),
+                reference: Some(reference),
+            },
+        }
+    }
+}
+
+/// Normalizes a path relative to the manifest directory (`./`, `./packages/a`, `../lib/`) to the
+/// form of [PnpPackage::location] (``, `packages/a/`, `../lib/`).
+fn normalize_location(location: &str) -> RcStr {
+    let location = if location == "." {
+        ""
+    } else {
+        location.strip_prefix("./").unwrap_or(location)
+    };
+    if location.is_empty() || location.ends_with('/') {
+        location.into()
+    } else {
+        format!("{location}/").into()
+    }
+}
+
+/// Maps a location inside of a `__virtual__` folder to the location on disk.
+///
+/// Yarn creates a virtual instance of a package for every set of peer dependencies it is used
+/// with. The virtual path `<base>/__virtual__/<hash>/<depth>/<subpath>` only exists in the Yarn
+/// runtime and points to `<base>/` + `../` * depth + `<subpath>`.
+fn devirtualize_location(location: &str) -> RcStr {
+    let Some(index) = location.find("__virtual__/") else {
+        return normalize_location(location);
+    };
+    let mut segments = location[index + "__virtual__/".len()..].splitn(3, '/');
+    let (Some(_hash), Some(Ok(depth)), Some(subpath)) = (
+        segments.next(),
+        segments.next().map(|depth| depth.parse::<usize>()),
+        segments.next(),
+    ) else {
+        return normalize_location(location);
+    };
+    normalize_location(&normalize_request(
+        &[&location[..index], &"../".repeat(depth), subpath].concat(),
+    ))
+}
+
+/// Extracts the serialized state that Yarn inlines into `.pnp.cjs` as a single-quoted string
+/// literal assigned to `RAW_RUNTIME_STATE`. Returns `None` if the state isn't inlined.
+fn extract_inlined_state(script: &str) -> Result<Option<String>> {
+    let Some(start) = script.find("const RAW_RUNTIME_STATE") else {
+        return Ok(None);
+    };
+    let Some(quote) = script[start..].find('\'') else {
+        bail!("RAW_RUNTIME_STATE is not a string literal");
+    };
+    let mut chars = script[start + quote + 1..].chars().peekable();
+    let mut state = String::new();
+    loop {
+        match chars.next() {
+            Some('\'') => return Ok(Some(state)),
+            Some('\\') => match chars.next() {
+                // line continuations
+                Some('\n') => {}
+                Some('\r') => {
+                    chars.next_if_eq(&'\n');
+                }
+                Some('n') => state.push('\n'),
+                Some('r') => state.push('\r'),
+                Some('t') => state.push('\t'),
+                Some('u') => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    state.push(
+                        u32::from_str_radix(&code, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .with_context(|| format!("invalid unicode escape \\u{code}"))?,
+                    );
+                }
+                Some(c) => state.push(c),
+                None => break,
+            },
+            Some(c) => state.push(c),
+            None => break,
+        }
+    }
+    bail!("RAW_RUNTIME_STATE is an unterminated string literal")
+}
+
+impl PnpManifest {
+    fn parse(json: &str) -> Result<Self> {
+        let data: RawPnpData = serde_json::from_str(json)?;
+        let mut packages = FxIndexMap::default();
+        for (name, references) in data.package_registry_data {
+            for (reference, package) in references {
+                let dependencies = package
+                    .package_dependencies
+                    .into_iter()
+                    .map(|(dependency, target)| {
+                        let locator = target.map(|target| target.into_locator(&dependency));
+                        (dependency, locator)
+                    })
+                    .collect();
+                packages.insert(
+                    PnpLocator {
+                        name: name.clone(),
+                        reference,
+                    },
+                    PnpPackage {
+                        location: devirtualize_location(&package.package_location),
+                        dependencies,
+                    },
+                );
+            }
+        }
+        // Turbopack has no virtual file system, so all virtual instances of a package share the
+        // location on disk. Paths in that location belong to all of these instances.
+        let mut locations: FxIndexMap<RcStr, Vec<PnpLocator>> = FxIndexMap::default();
+        for (locator, package) in packages.iter() {
+            locations
+                .entry(package.location.clone())
+                .or_default()
+                .push(locator.clone());
+        }
+        let mut locations = locations.into_iter().collect::<Vec<_>>();
+        locations.sort_by_key(|(location, _)| Reverse(location.len()));
+        Ok(PnpManifest {
+            packages,
+            locations,
+            enable_top_level_fallback: data.enable_top_level_fallback,
+            fallback_pool: data
+                .fallback_pool
+                .into_iter()
+                .map(|(name, target)| {
+                    let locator = target.map(|target| target.into_locator(&name));
+                    (name, locator)
+                })
+                .collect(),
+            fallback_exclusion_list: data
+                .fallback_exclusion_list
+                .into_iter()
+                .flat_map(|(name, references)| {
+                    references.into_iter().map(move |reference| PnpLocator {
+                        name: Some(name.clone()),
+                        reference: Some(reference),
+                    })
+                })
+                .collect(),
+            // Yarn writes JavaScript regular expressions, patterns with syntax that the regex
+            // crate doesn't support are ignored
+            ignore_pattern: data
+                .ignore_pattern_data
+                .and_then(|pattern| Regex::new(&pattern).ok())
+                .map(IgnorePattern),
+        })
+    }
+
+    /// Finds the package that contains `dir`, which is a directory relative to the manifest in the
+    /// form of [PnpPackage::location]. Returns all virtual instances of the package, or `None` if
+    /// the directory is not part of the dependency tree.
+    fn find_locators(&self, dir: &str) -> Option<&[PnpLocator]> {
+        if let Some(IgnorePattern(ignore_pattern)) = &self.ignore_pattern {
+            if ignore_pattern.is_match(dir) {
+                return None;
+            }
+        }
+        self.locations
+            .iter()
+            .find(|(location, _)| {
+                dir.starts_with(&**location)
+                    // the top-level workspace doesn't contain paths outside of the manifest directory
+                    && (!location.is_empty() || !dir.starts_with("../"))
+            })
+            .map(|(_, locators)| &locators[..])
+    }
+
+    /// Returns the location of the package that `issuer` gets when it requests `name`. Fails when
+    /// the manifest is inconsistent.
+    fn resolve_dependency(&self, issuer: &PnpLocator, name: &str) -> Result<PnpDependency<'_>> {
+        let Some(issuer_package) = self.packages.get(issuer) else {
+            bail!("{issuer} is missing from the Plug'n'Play manifest");
+        };
+        let dependency = match issuer_package.dependencies.get(name) {
+            Some(Some(dependency)) => dependency,
+            Some(None) => {
+                return Ok(PnpDependency::Inaccessible(format!(
+                    "{issuer} tried to access {name} (a peer dependency) but it isn't provided by \
+                     its ancestors; this makes the require call ambiguous and unsound."
+                )))
+            }
+            None => match self.fallback(issuer, name) {
+                Some(dependency) => dependency,
+                None if issuer.name.is_none() => {
+                    return Ok(PnpDependency::Inaccessible(format!(
+                        "Your application tried to access {name}, but it isn't declared in your \
+                         dependencies; this makes the require call ambiguous and unsound."
+                    )))
+                }
+                None => {
+                    return Ok(PnpDependency::Inaccessible(format!(
+                        "{issuer} tried to access {name}, but it isn't declared in its \
+                         dependencies; this makes the require call ambiguous and unsound."
+                    )))
+                }
+            },
+        };
+        let Some(package) = self.packages.get(dependency) else {
+            bail!("{dependency} (requested by {issuer}) is missing from the Plug'n'Play manifest");
+        };
+        Ok(PnpDependency::Location(&package.location))
+    }
+
+    /// Virtual instances of a package can resolve the same dependency to different packages, e.g.
+    /// when they are used with different versions of a peer dependency. Since they share their
+    /// files, only the first instance is used to resolve. Returns how each instance resolves
+    /// `name` when they disagree.
+    fn conflicting_instances(&self, issuers: &[PnpLocator], name: &str) -> Option<Vec<String>> {
+        let resolved = issuers
+            .iter()
+            .map(|issuer| match self.resolve_dependency(issuer, name) {
+                Ok(PnpDependency::Location(location)) => Some(location.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if resolved.iter().all(|location| *location == resolved[0]) {
+            return None;
+        }
+        Some(
+            issuers
+                .iter()
+                .zip(resolved)
+                .map(|(issuer, location)| match location {
+                    Some(location) => format!("{issuer} resolves it to {location}"),
+                    None => format!("{issuer} can't access it"),
+                })
+                .collect(),
+        )
+    }
+
+    /// Packages that don't declare a dependency can still access the dependencies of the
+    /// top-level workspace and the fallback pool, unless they are on the exclusion list.
+    fn fallback(&self, issuer: &PnpLocator, name: &str) -> Option<&PnpLocator> {
+        if !self.enable_top_level_fallback || self.fallback_exclusion_list.contains(issuer) {
+            return None;
+        }
+        self.packages
+            .get(&PnpLocator::top_level())
+            .and_then(|package| package.dependencies.get(name))
+            .or_else(|| self.fallback_pool.get(name))
+            .and_then(|dependency| dependency.as_ref())
+    }
+}
+
+enum PnpDependency<'a> {
+    /// The location of the package, see [PnpPackage::location].
+    Location(&'a RcStr),
+    /// The manifest doesn't give the issuer access to the package, with an explanation.
+    Inaccessible(String),
+}
+
+/// Splits a path into the path of the zip archive that contains it and the path inside of the
+/// archive. Yarn stores packages in zip archives in its cache, e. g.
+/// `.yarn/cache/foo-npm-1.0.0-abc.zip/node_modules/foo/`.
+fn split_archive_path(path: &str) -> Option<(&str, &str)> {
+    let index = path.find(".zip/")?;
+    Some((
+        &path[..index + ".zip".len()],
+        &path[index + ".zip/".len()..],
+    ))
+}
+
+/// Mounts the zip archive at `archive` (relative to the root of `fs`) at its own path, so the
+/// files of zipped packages keep the paths that the manifest uses.
+#[turbo_tasks::function]
+fn mount_archive(fs: Vc<Box<dyn FileSystem>>, archive: RcStr) -> Vc<Box<dyn FileSystem>> {
+    let archive_path = fs.root().join(archive);
+    Vc::upcast(ArchiveFileSystem::new(archive_path).mount(archive_path))
+}
+
+#[turbo_tasks::value(shared)]
+pub enum PnpManifestResult {
+    Some(#[turbo_tasks(debug_ignore, trace_ignore)] PnpManifest),
+    None,
+}
+
+/// Reads and parses a `.pnp.cjs` or `.pnp.data.json` file.
+#[turbo_tasks::function]
+pub async fn read_pnp_manifest(path: Vc<FileSystemPath>) -> Result<Vc<PnpManifestResult>> {
+    let FileContent::Content(file) = &*path.read().await? else {
+        return Ok(PnpManifestResult::None.cell());
+    };
+    let content = file.content().to_str()?;
+    let manifest = if path.await?.path.ends_with(".cjs") {
+        let Some(state) = extract_inlined_state(&content)? else {
+            return Ok(PnpManifestResult::None.cell());
+        };
+        PnpManifest::parse(&state)
+    } else {
+        PnpManifest::parse(&content)
+    };
+    match manifest {
+        Ok(manifest) => Ok(PnpManifestResult::Some(manifest).cell()),
+        Err(err) => Err(err.context(format!(
+            "failed to parse the Plug'n'Play manifest {}",
+            path.to_string().await?
+        ))),
+    }
+}
+
+#[turbo_tasks::value(shared)]
+pub(super) enum PnpResolveResult {
+    Package(ResolvedVc<FileSystemPath>),
+    /// The manifest doesn't give `lookup_path` access to the package. An issue was emitted.
+    Unresolvable,
+    /// `lookup_path` is not part of the dependency tree, other ways of resolving apply.
+    NotInTree,
+}
+
+/// Resolves a request for `package_name` from `lookup_path` to the directory of the package.
+/// Undeclared dependencies and missing peer dependencies are reported as issues. Packages in zip
+/// archives are read through a mount of the archive.
+#[turbo_tasks::function]
+pub(super) async fn resolve_pnp_package(
+    manifest: Vc<FileSystemPath>,
+    lookup_path: Vc<FileSystemPath>,
+    package_name: RcStr,
+) -> Result<Vc<PnpResolveResult>> {
+    let PnpManifestResult::Some(pnp_manifest) = &*read_pnp_manifest(manifest).await? else {
+        return Ok(PnpResolveResult::NotInTree.cell());
+    };
+    let manifest_dir = manifest.parent();
+    let manifest_dir_value = manifest_dir.await?;
+    let mut lookup_path_value = lookup_path.await?.clone_value();
+    // Paths inside of a zipped package are on the file system of its mount
+    if let Some((archive, _)) = split_archive_path(&lookup_path_value.path) {
+        let mount = mount_archive(*manifest_dir_value.fs, archive.into())
+            .to_resolved()
+            .await?;
+        if lookup_path_value.fs == mount {
+            lookup_path_value.fs = manifest_dir_value.fs;
+        }
+    }
+    let Some(dir) = manifest_dir_value.get_relative_path_to(&lookup_path_value) else {
+        return Ok(PnpResolveResult::NotInTree.cell());
+    };
+    let Some(issuers) = pnp_manifest.find_locators(&normalize_location(&dir)) else {
+        return Ok(PnpResolveResult::NotInTree.cell());
+    };
+    let issuer = &issuers[0];
+    if let Some(instances) = pnp_manifest.conflicting_instances(issuers, &package_name) {
+        PnpVirtualInstancesIssue {
+            file_path: lookup_path.to_resolved().await?,
+            manifest: manifest.to_resolved().await?,
+            package_name: package_name.clone(),
+            instances: instances.into_iter().map(RcStr::from).collect(),
+        }
+        .resolved_cell()
+        .emit();
+    }
+    let location = match pnp_manifest.resolve_dependency(issuer, &package_name)? {
+        PnpDependency::Location(location) => location,
+        PnpDependency::Inaccessible(error_message) => {
+            PnpResolvingIssue {
+                file_path: lookup_path.to_resolved().await?,
+                manifest: manifest.to_resolved().await?,
+                package_name,
+                error_message: error_message.into(),
+            }
+            .resolved_cell()
+            .emit();
+            return Ok(PnpResolveResult::Unresolvable.cell());
+        }
+    };
+    let Some(package_dir) = *manifest_dir.try_join(location.clone()).await? else {
+        bail!(
+            "{package_name} is located at {location}, which is outside of the root of {}",
+            manifest.to_string().await?
+        );
+    };
+    let package_dir_value = package_dir.await?;
+    if let Some((archive, _)) = split_archive_path(&package_dir_value.path) {
+        let mount = mount_archive(*package_dir_value.fs, archive.into());
+        let package_dir = mount
+            .root()
+            .join(package_dir_value.path.clone())
+            .to_resolved()
+            .await?;
+        return Ok(PnpResolveResult::Package(package_dir).cell());
+    }
+    Ok(PnpResolveResult::Package(package_dir).cell())
+}
+
+/// The Plug'n'Play manifest doesn't give a package access to a dependency it requests.
+#[turbo_tasks::value(shared)]
+pub struct PnpResolvingIssue {
+    pub file_path: ResolvedVc<FileSystemPath>,
+    pub manifest: ResolvedVc<FileSystemPath>,
+    pub package_name: RcStr,
+    pub error_message: RcStr,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for PnpResolvingIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Error.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Line(vec![
+            StyledString::Strong("Module not found".into()),
+            StyledString::Text(": Can't resolve ".into()),
+            StyledString::Code(self.package_name.clone()),
+        ])
+        .cell()
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Resolve.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.file_path
+    }
+
+    #[turbo_tasks::function]
+    async fn description(&self) -> Result<Vc<OptionStyledString>> {
+        Ok(Vc::cell(Some(
+            StyledString::Stack(vec![
+                StyledString::Text(self.error_message.clone()),
+                StyledString::Line(vec![
+                    StyledString::Text("The dependency tree is declared in ".into()),
+                    StyledString::Code(self.manifest.to_string().await?.clone_value()),
+                ]),
+            ])
+            .resolved_cell(),
+        )))
+    }
+}
+
+/// Virtual instances of the package that contains a file resolve one of its dependencies to
+/// different packages. Only the first instance is used.
+#[turbo_tasks::value(shared)]
+pub struct PnpVirtualInstancesIssue {
+    pub file_path: ResolvedVc<FileSystemPath>,
+    pub manifest: ResolvedVc<FileSystemPath>,
+    pub package_name: RcStr,
+    /// How each instance resolves the dependency.
+    pub instances: Vec<RcStr>,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for PnpVirtualInstancesIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Warning.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Line(vec![
+            StyledString::Text("Ambiguous resolution of ".into()),
+            StyledString::Code(self.package_name.clone()),
+            StyledString::Text(" in a package with multiple virtual instances".into()),
+        ])
+        .cell()
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Resolve.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.file_path
+    }
+
+    #[turbo_tasks::function]
+    async fn description(&self) -> Result<Vc<OptionStyledString>> {
+        let mut lines = vec![StyledString::Text(
+            "Yarn creates a virtual instance of a package for every set of peer dependencies it \
+             is used with. Turbopack shares the files of all instances, so the dependency is \
+             resolved for the first instance only:"
+                .into(),
+        )];
+        lines.extend(
+            self.instances
+                .iter()
+                .map(|instance| StyledString::Text(format!("- {instance}").into())),
+        );
+        lines.push(StyledString::Line(vec![
+            StyledString::Text("The dependency tree is declared in ".into()),
+            StyledString::Code(self.manifest.to_string().await?.clone_value()),
+        ]));
+        Ok(Vc::cell(Some(StyledString::Stack(lines).resolved_cell())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        devirtualize_location, extract_inlined_state, normalize_location, split_archive_path,
+        PnpDependency, PnpLocator, PnpManifest,
+    };
+
+    const MANIFEST: &str = r#"{
+        "enableTopLevelFallback": true,
+        "ignorePatternData": null,
+        "fallbackExclusionList": [["strict", ["npm:1.0.0"]]],
+        "fallbackPool": [["pooled", "npm:3.0.0"]],
+        "packageRegistryData": [
+            [null, [[null, {
+                "packageLocation": "./",
+                "packageDependencies": [["app", "workspace:packages/app"], ["shared", "npm:1.0.0"]]
+            }]]],
+            ["app", [["workspace:packages/app", {
+                "packageLocation": "./packages/app/",
+                "packageDependencies": [
+                    ["lib", "portal:../lib::locator=app%40workspace%3Apackages%2Fapp"],
+                    ["react", "npm:18.2.0"],
+                    ["react-dom", "virtual:abc#npm:18.2.0"],
+                    ["alias", ["react", "npm:18.2.0"]],
+                    ["zipped", "npm:1.0.0"]
+                ]
+            }]]],
+            ["lib", [["portal:../lib::locator=app%40workspace%3Apackages%2Fapp", {
+                "packageLocation": "../lib/",
+                "packageDependencies": [["peer", null]]
+            }]]],
+            ["react", [["npm:18.2.0", {
+                "packageLocation": "./.yarn/unplugged/react-npm-18.2.0/node_modules/react/",
+                "packageDependencies": []
+            }], ["npm:17.0.2", {
+                "packageLocation": "./.yarn/unplugged/react-npm-17.0.2/node_modules/react/",
+                "packageDependencies": []
+            }]]],
+            ["ui", [["virtual:111#npm:1.0.0", {
+                "packageLocation": "./.yarn/__virtual__/ui-virtual-111/0/unplugged/ui-npm-1.0.0/node_modules/ui/",
+                "packageDependencies": [["react", "npm:18.2.0"], ["shared", "npm:1.0.0"]]
+            }], ["virtual:222#npm:1.0.0", {
+                "packageLocation": "./.yarn/__virtual__/ui-virtual-222/0/unplugged/ui-npm-1.0.0/node_modules/ui/",
+                "packageDependencies": [["react", "npm:17.0.2"], ["shared", "npm:1.0.0"]]
+            }]]],
+            ["react-dom", [["virtual:abc#npm:18.2.0", {
+                "packageLocation": "./.yarn/__virtual__/react-dom-virtual-abc/0/unplugged/react-dom-npm-18.2.0/node_modules/react-dom/",
+                "packageDependencies": [["react", "npm:18.2.0"]]
+            }]]],
+            ["shared", [["npm:1.0.0", {
+                "packageLocation": "./.yarn/unplugged/shared-npm-1.0.0/node_modules/shared/",
+                "packageDependencies": []
+            }]]],
+            ["strict", [["npm:1.0.0", {
+                "packageLocation": "./.yarn/unplugged/strict-npm-1.0.0/node_modules/strict/",
+                "packageDependencies": []
+            }]]],
+            ["pooled", [["npm:3.0.0", {
+                "packageLocation": "./.yarn/unplugged/pooled-npm-3.0.0/node_modules/pooled/",
+                "packageDependencies": []
+            }]]],
+            ["zipped", [["npm:1.0.0", {
+                "packageLocation": "./.yarn/cache/zipped-npm-1.0.0.zip/node_modules/zipped/",
+                "packageDependencies": []
+            }]]]
+        ]
+    }"#;
+
+    fn resolve(manifest: &PnpManifest, dir: &str, name: &str) -> Result<String, String> {
+        let issuer = &manifest
+            .find_locators(&normalize_location(dir))
+            .ok_or_else(|| "not in tree".to_string())?[0];
+        match manifest
+            .resolve_dependency(issuer, name)
+            .map_err(|err| err.to_string())?
+        {
+            PnpDependency::Location(location) => Ok(location.to_string()),
+            PnpDependency::Inaccessible(message) => Err(message),
+        }
+    }
+
+    #[test]
+    fn test_locations() {
+        assert_eq!(normalize_location("."), "");
+        assert_eq!(normalize_location("./"), "");
+        assert_eq!(normalize_location("./packages/app"), "packages/app/");
+        assert_eq!(normalize_location("../lib/"), "../lib/");
+        assert_eq!(
+            devirtualize_location("./.yarn/__virtual__/a-virtual-abc/0/cache/a/"),
+            ".yarn/cache/a/"
+        );
+        assert_eq!(
+            devirtualize_location("./.yarn/__virtual__/a-virtual-abc/2/packages/a/"),
+            "../packages/a/"
+        );
+        assert_eq!(
+            split_archive_path(".yarn/cache/a-npm-1.0.0.zip/node_modules/a/index.js"),
+            Some((".yarn/cache/a-npm-1.0.0.zip", "node_modules/a/index.js"))
+        );
+        assert_eq!(split_archive_path(".yarn/unplugged/a/node_modules/a"), None);
+    }
+
+    #[test]
+    fn test_extract_inlined_state() {
+        let script = "#!/usr/bin/env node\n/* eslint-disable */\n\"use strict\";\n\nconst \
+                      RAW_RUNTIME_STATE =\n'{\\\n  \"name\": \"it\\'s \
+                      \\\\u0041\"\\\n}';\n\nfunction $$SETUP_STATE() {}\n";
+        assert_eq!(
+            extract_inlined_state(script).unwrap().as_deref(),
+            Some("{  \"name\": \"it's \\u0041\"}")
+        );
+        assert_eq!(extract_inlined_state("module.exports = {};").unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let manifest = PnpManifest::parse(MANIFEST).unwrap();
+
+        // declared dependencies, including aliases and virtual packages
+        assert_eq!(
+            resolve(&manifest, "./packages/app/src", "react").unwrap(),
+            ".yarn/unplugged/react-npm-18.2.0/node_modules/react/"
+        );
+        assert_eq!(
+            resolve(&manifest, "./packages/app/src", "alias").unwrap(),
+            ".yarn/unplugged/react-npm-18.2.0/node_modules/react/"
+        );
+        assert_eq!(
+            resolve(&manifest, "./packages/app", "react-dom").unwrap(),
+            ".yarn/unplugged/react-dom-npm-18.2.0/node_modules/react-dom/"
+        );
+        assert_eq!(
+            resolve(
+                &manifest,
+                "./.yarn/unplugged/react-dom-npm-18.2.0/node_modules/react-dom/cjs",
+                "react"
+            )
+            .unwrap(),
+            ".yarn/unplugged/react-npm-18.2.0/node_modules/react/"
+        );
+
+        // workspaces and portals
+        assert_eq!(resolve(&manifest, ".", "app").unwrap(), "packages/app/");
+        assert_eq!(
+            resolve(&manifest, "./packages/app", "lib").unwrap(),
+            "../lib/"
+        );
+
+        // the top-level fallback and its exclusion list
+        assert_eq!(
+            resolve(&manifest, "./packages/app", "shared").unwrap(),
+            ".yarn/unplugged/shared-npm-1.0.0/node_modules/shared/"
+        );
+        assert_eq!(
+            resolve(&manifest, "./packages/app", "pooled").unwrap(),
+            ".yarn/unplugged/pooled-npm-3.0.0/node_modules/pooled/"
+        );
+        assert!(resolve(
+            &manifest,
+            "./.yarn/unplugged/strict-npm-1.0.0/node_modules/strict",
+            "shared"
+        )
+        .unwrap_err()
+        .contains("isn't declared in its dependencies"));
+
+        // zipped packages
+        assert_eq!(
+            resolve(&manifest, "./packages/app", "zipped").unwrap(),
+            ".yarn/cache/zipped-npm-1.0.0.zip/node_modules/zipped/"
+        );
+        assert_eq!(
+            resolve(
+                &manifest,
+                "./.yarn/cache/zipped-npm-1.0.0.zip/node_modules/zipped/lib",
+                "shared"
+            )
+            .unwrap(),
+            ".yarn/unplugged/shared-npm-1.0.0/node_modules/shared/"
+        );
+
+        // inaccessible dependencies
+        assert!(resolve(&manifest, ".", "missing")
+            .unwrap_err()
+            .starts_with("Your application tried to access missing"));
+        assert!(resolve(&manifest, "../lib", "peer")
+            .unwrap_err()
+            .contains("(a peer dependency)"));
+        assert_eq!(
+            resolve(&manifest, "../outside", "react").unwrap_err(),
+            "not in tree"
+        );
+        assert_eq!(
+            manifest.find_locators("packages/app/"),
+            Some(
+                &[PnpLocator {
+                    name: Some("app".into()),
+                    reference: Some("workspace:packages/app".into()),
+                }][..]
+            )
+        );
+    }
+
+    #[test]
+    fn test_virtual_instances() {
+        let manifest = PnpManifest::parse(MANIFEST).unwrap();
+        let dir = ".yarn/unplugged/ui-npm-1.0.0/node_modules/ui/";
+        let instances = manifest.find_locators(dir).unwrap();
+        assert_eq!(instances.len(), 2);
+
+        // the instances agree on regular dependencies
+        assert_eq!(manifest.conflicting_instances(instances, "shared"), None);
+
+        // but not on their peer dependencies
+        assert_eq!(
+            manifest.conflicting_instances(instances, "react"),
+            Some(vec![
+                "ui@virtual:111#npm:1.0.0 resolves it to \
+                 .yarn/unplugged/react-npm-18.2.0/node_modules/react/"
+                    .to_string(),
+                "ui@virtual:222#npm:1.0.0 resolves it to \
+                 .yarn/unplugged/react-npm-17.0.2/node_modules/react/"
+                    .to_string(),
+            ])
+        );
+    }
+}
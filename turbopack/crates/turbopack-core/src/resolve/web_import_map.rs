@@ -0,0 +1,435 @@
+//! Standard [import maps] (`importmap.json`) as a way to declare aliases and externals. Entries
+//! with URL addresses become [ExternalType::Url] externals, all other addresses are resolved
+//! relative to the directory of the import map. Scopes are mapped to the directories they refer
+//! to and apply to the modules inside of them.
+//!
+//! [import maps]: https://html.spec.whatwg.org/multipage/webappapis.html#import-maps
+
+use std::cmp::Reverse;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use turbo_rcstr::RcStr;
+use turbo_tasks::{FxIndexMap, ResolvedVc, Value, Vc};
+use turbo_tasks_fs::{FileContent, FileSystemPath};
+
+use super::{
+    options::{
+        ImportMap, ImportMapResult, ImportMapping, ImportMappingReplacement, ReplacedImportMapping,
+    },
+    parse::Request,
+    pattern::Pattern,
+    ExternalTraced, ExternalType, ResolveResult,
+};
+use crate::issue::{Issue, IssueExt, IssueSeverity, IssueStage, OptionStyledString, StyledString};
+
+/// Addresses by specifier. A `null` address blocks the specifier.
+type SpecifierMap = FxIndexMap<RcStr, Option<RcStr>>;
+
+#[derive(Deserialize)]
+struct RawImportMap {
+    #[serde(default)]
+    imports: SpecifierMap,
+    #[serde(default)]
+    scopes: FxIndexMap<RcStr, SpecifierMap>,
+}
+
+#[turbo_tasks::value(shared)]
+#[derive(Debug)]
+pub struct WebImportMap {
+    /// The directory of the import map file. Relative addresses and scopes are resolved against
+    /// it.
+    pub base: ResolvedVc<FileSystemPath>,
+    imports: SpecifierMap,
+    scopes: FxIndexMap<RcStr, SpecifierMap>,
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct OptionWebImportMap(Option<ResolvedVc<WebImportMap>>);
+
+/// Reads and parses an import map file. Emits an [ImportMapIssue] if the file can't be parsed.
+#[turbo_tasks::function]
+pub async fn read_web_import_map(
+    path: ResolvedVc<FileSystemPath>,
+) -> Result<Vc<OptionWebImportMap>> {
+    let FileContent::Content(file) = &*path.read().await? else {
+        return Ok(Vc::cell(None));
+    };
+    match parse_import_map(&file.content().to_str()?) {
+        Ok((imports, scopes)) => Ok(Vc::cell(Some(
+            WebImportMap {
+                base: path.parent().to_resolved().await?,
+                imports,
+                scopes,
+            }
+            .resolved_cell(),
+        ))),
+        Err(err) => {
+            ImportMapIssue {
+                path,
+                error_message: format!("{err:#}").into(),
+            }
+            .resolved_cell()
+            .emit();
+            Ok(Vc::cell(None))
+        }
+    }
+}
+
+fn parse_import_map(content: &str) -> Result<(SpecifierMap, FxIndexMap<RcStr, SpecifierMap>)> {
+    let RawImportMap { imports, scopes } = serde_json::from_str(content)?;
+    Ok((
+        normalize_specifier_map(imports),
+        scopes
+            .into_iter()
+            .map(|(scope, map)| (scope, normalize_specifier_map(map)))
+            .collect(),
+    ))
+}
+
+/// Applies the validation of the spec: specifiers with a trailing slash only match prefixes and
+/// are blocked when their address doesn't have a trailing slash as well. Specifiers like `./a.js`
+/// that address a single module are dropped as they can't be expressed as an alias.
+fn normalize_specifier_map(map: SpecifierMap) -> SpecifierMap {
+    map.into_iter()
+        .filter(|(specifier, _)| {
+            !specifier.is_empty() && !is_relative(specifier) && !specifier.starts_with('/')
+        })
+        .map(|(specifier, address)| {
+            let address =
+                address.filter(|address| !specifier.ends_with('/') || address.ends_with('/'));
+            (specifier, address)
+        })
+        .collect()
+}
+
+fn is_relative(specifier: &str) -> bool {
+    specifier.starts_with("./") || specifier.starts_with("../")
+}
+
+/// Whether the address starts with a URL scheme like `https:`.
+fn has_url_scheme(address: &str) -> bool {
+    let Some((scheme, _)) = address.split_once(':') else {
+        return false;
+    };
+    let mut chars = scheme.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// The request for an address that isn't a URL. Addresses starting with `/` are relative to the
+/// import map as well.
+fn address_request(address: &str) -> RcStr {
+    if address.starts_with('/') {
+        format!(".{address}").into()
+    } else {
+        address.into()
+    }
+}
+
+/// The directory a scope refers to, relative to the import map.
+fn scope_path(scope: &str) -> Option<&str> {
+    if has_url_scheme(scope) {
+        return None;
+    }
+    let scope = scope.strip_prefix("./").unwrap_or(scope);
+    Some(scope.trim_matches('/'))
+}
+
+#[derive(Serialize)]
+struct RuntimeImportMap<'a> {
+    imports: FxIndexMap<&'a str, &'a str>,
+}
+
+/// The runtime import map with the top-level entries that have URL addresses. Scopes are left
+/// out: they refer to source directories, but the browser matches them against the URLs of the
+/// importing chunks, which can contain modules of multiple scopes. Bundled modules import the
+/// address of their scope directly.
+fn runtime_import_map_json(imports: &SpecifierMap) -> Result<String> {
+    let runtime_import_map = RuntimeImportMap {
+        imports: imports
+            .iter()
+            .filter_map(|(specifier, address)| {
+                let address = address.as_deref()?;
+                has_url_scheme(address).then_some((specifier.as_str(), address))
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&runtime_import_map)?)
+}
+
+#[turbo_tasks::value_impl]
+impl WebImportMap {
+    /// The [ImportMap] for [super::options::ResolveOptions] with an entry for each specifier
+    /// of the import map.
+    #[turbo_tasks::function]
+    pub async fn import_map(&self) -> Result<Vc<ImportMap>> {
+        let mut scopes = Vec::with_capacity(self.scopes.len());
+        for (scope, map) in &self.scopes {
+            let Some(path) = scope_path(scope) else {
+                continue;
+            };
+            let dir = self.base.join(path.into()).to_resolved().await?;
+            scopes.push((scope.len(), dir, map));
+        }
+        // The most specific scope takes precedence.
+        scopes.sort_by_key(|(len, _, _)| Reverse(*len));
+
+        let mut specifiers: FxIndexMap<&RcStr, Vec<_>> = FxIndexMap::default();
+        for (_, dir, map) in &scopes {
+            for (specifier, address) in map.iter() {
+                specifiers
+                    .entry(specifier)
+                    .or_default()
+                    .push((Some(*dir), address));
+            }
+        }
+        for (specifier, address) in &self.imports {
+            specifiers
+                .entry(specifier)
+                .or_default()
+                .push((None, address));
+        }
+
+        let mut import_map = ImportMap::empty();
+        for (specifier, addresses) in specifiers {
+            let is_prefix = specifier.ends_with('/');
+            let addresses = addresses
+                .into_iter()
+                .map(|(scope, address)| {
+                    let address = address.as_ref().map(|address| {
+                        let address = if has_url_scheme(address) {
+                            address.clone()
+                        } else {
+                            address_request(address)
+                        };
+                        if is_prefix {
+                            Pattern::Constant(format!("{address}*").into())
+                        } else {
+                            Pattern::Constant(address)
+                        }
+                    });
+                    (scope, address)
+                })
+                .collect();
+            let mapping = ImportMapping::Dynamic(ResolvedVc::upcast(
+                ScopedImportMapping {
+                    base: self.base,
+                    addresses,
+                }
+                .resolved_cell(),
+            ))
+            .resolved_cell();
+            if is_prefix {
+                import_map.insert_wildcard_alias(specifier.clone(), mapping);
+            } else {
+                import_map.insert_exact_alias(specifier.clone(), mapping);
+            }
+        }
+        Ok(import_map.cell())
+    }
+
+    /// The import map the browser needs at runtime to load the [ExternalType::Url] externals
+    /// of this import map from unbundled code, i.e. its top-level entries with URL addresses, as
+    /// JSON.
+    #[turbo_tasks::function]
+    pub fn runtime_import_map(&self) -> Result<Vc<RcStr>> {
+        Ok(Vc::cell(runtime_import_map_json(&self.imports)?.into()))
+    }
+}
+
+/// The [ImportMapping] of a specifier of a [WebImportMap], which can have a different address in
+/// each scope.
+#[turbo_tasks::value(shared)]
+struct ScopedImportMapping {
+    base: ResolvedVc<FileSystemPath>,
+    /// Addresses by scope directory, the most specific scope first. The top-level `imports` have
+    /// no scope directory and apply everywhere.
+    addresses: Vec<(Option<ResolvedVc<FileSystemPath>>, Option<Pattern>)>,
+}
+
+#[turbo_tasks::value_impl]
+impl ImportMappingReplacement for ScopedImportMapping {
+    #[turbo_tasks::function]
+    async fn replace(&self, capture: Vc<Pattern>) -> Result<Vc<ReplacedImportMapping>> {
+        let capture = capture.await?;
+        let addresses = self
+            .addresses
+            .iter()
+            .map(|(scope, address)| {
+                let address = address.as_ref().map(|address| match address.as_string() {
+                    Some(address) => capture.spread_into_star(address),
+                    None => address.clone(),
+                });
+                (*scope, address)
+            })
+            .collect();
+        Ok(ReplacedImportMapping::Dynamic(ResolvedVc::upcast(
+            ScopedImportMapping {
+                base: self.base,
+                addresses,
+            }
+            .resolved_cell(),
+        ))
+        .cell())
+    }
+
+    #[turbo_tasks::function]
+    async fn result(
+        &self,
+        lookup_path: Vc<FileSystemPath>,
+        _request: Vc<Request>,
+    ) -> Result<Vc<ImportMapResult>> {
+        let lookup_path = lookup_path.await?;
+        for (scope, address) in &self.addresses {
+            if let Some(scope) = *scope {
+                if !lookup_path.is_inside_or_equal_ref(&*scope.await?) {
+                    continue;
+                }
+            }
+            let Some(address) = address else {
+                return Ok(
+                    ImportMapResult::Result(ResolveResult::unresolvable().resolved_cell()).cell(),
+                );
+            };
+            return Ok(match address.as_string() {
+                Some(url) if has_url_scheme(url) => ImportMapResult::External(
+                    url.into(),
+                    ExternalType::Url,
+                    ExternalTraced::Untraced,
+                ),
+                _ => ImportMapResult::Alias(
+                    Request::parse(Value::new(address.clone()))
+                        .to_resolved()
+                        .await?,
+                    Some(self.base),
+                ),
+            }
+            .cell());
+        }
+        Ok(ImportMapResult::NoEntry.cell())
+    }
+}
+
+#[turbo_tasks::value(shared)]
+pub struct ImportMapIssue {
+    pub path: ResolvedVc<FileSystemPath>,
+    pub error_message: RcStr,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for ImportMapIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Error.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Text("Error parsing import map".into()).cell()
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Parse.cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.path
+    }
+
+    #[turbo_tasks::function]
+    fn description(&self) -> Vc<OptionStyledString> {
+        Vc::cell(Some(
+            StyledString::Text(self.error_message.clone()).resolved_cell(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_imports_and_scopes() {
+        let (imports, scopes) = parse_import_map(
+            r#"{
+                "imports": {
+                    "react": "https://esm.sh/react@19",
+                    "lodash/": "/vendor/lodash/",
+                    "broken/": "/vendor/broken.js",
+                    "./local.js": "./other.js",
+                    "blocked": null
+                },
+                "scopes": {
+                    "/legacy/": { "react": "https://esm.sh/react@18" }
+                },
+                "integrity": {}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            imports.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("react".into(), Some("https://esm.sh/react@19".into())),
+                ("lodash/".into(), Some("/vendor/lodash/".into())),
+                ("broken/".into(), None),
+                ("blocked".into(), None),
+            ]
+        );
+        assert_eq!(
+            scopes["/legacy/"]["react"].as_deref(),
+            Some("https://esm.sh/react@18")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_addresses() {
+        assert!(parse_import_map(r#"{ "imports": { "a": 1 } }"#).is_err());
+        assert!(parse_import_map(r#"{ "imports": [] }"#).is_err());
+    }
+
+    #[test]
+    fn url_schemes() {
+        assert!(has_url_scheme("https://esm.sh/react"));
+        assert!(has_url_scheme("data:text/javascript,export{}"));
+        assert!(!has_url_scheme("./a:b.js"));
+        assert!(!has_url_scheme("/vendor/react.js"));
+        assert!(!has_url_scheme("react"));
+    }
+
+    #[test]
+    fn scope_paths() {
+        assert_eq!(scope_path("/legacy/"), Some("legacy"));
+        assert_eq!(scope_path("./legacy/nested/"), Some("legacy/nested"));
+        assert_eq!(scope_path("/"), Some(""));
+        assert_eq!(scope_path("https://example.com/"), None);
+        assert_eq!(address_request("/vendor/lodash/"), "./vendor/lodash/");
+        assert_eq!(address_request("./vendor.js"), "./vendor.js");
+    }
+
+    #[test]
+    fn runtime_import_map_without_scopes() {
+        let (imports, _) = parse_import_map(
+            r#"{
+                "imports": {
+                    "react": "https://esm.sh/react@19",
+                    "lodash/": "/vendor/lodash/"
+                },
+                "scopes": {
+                    "/legacy/": { "react": "https://esm.sh/react@18" }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            runtime_import_map_json(&imports).unwrap(),
+            r#"{
+  "imports": {
+    "react": "https://esm.sh/react@19"
+  }
+}"#
+        );
+    }
+}
@@ -125,6 +125,9 @@ function instantiateModule(id: ModuleId, source: SourceInfo): Module {
         U: relativeURL,
         R: createResolvePathFromModule(r),
         b: getWorkerBlobURL,
+        G: externalGlobal,
+        D: externalUmd,
+        S: externalSystem,
         __dirname: typeof module.id === "string" ? module.id.replace(/(^|\/)\/+$/, "") : module.id
       })
     );
@@ -227,6 +227,9 @@ function instantiateModule(id: ModuleId, source: SourceInfo): Module {
           k: refresh,
           R: createResolvePathFromModule(r),
           b: getWorkerBlobURL,
+          G: externalGlobal,
+          D: externalUmd,
+          S: externalSystem,
           z: requireStub,
           __dirname: typeof module.id === "string" ? module.id.replace(/(^|\/)\/+$/, "") : module.id
         })
@@ -244,6 +244,65 @@ function getWorkerBlobURL(chunks: ChunkPath[]): string {
   return URL.createObjectURL(blob);
 }
 
+/**
+ * Reads an external module from a global variable provided by the page, e.g.
+ * `React`. Nested properties can be accessed with dots, e.g. `MyLib.utils`.
+ */
+function externalGlobal(name: string): Exports {
+  let value: any = globalThis;
+  for (const key of name.split(".")) {
+    value = value?.[key];
+  }
+  if (value === undefined) {
+    throw new Error(
+      `Failed to load external module ${name}: the global variable is not defined`
+    );
+  }
+  return value;
+}
+
+/**
+ * Loads a UMD external module through the page's AMD loader, or reads it from
+ * the global variable of the same name when there is none.
+ */
+async function externalUmd(id: string): Promise<Exports> {
+  const amdRequire = (globalThis as any).require;
+  const amdDefine = (globalThis as any).define;
+  const hasAmdLoader =
+    typeof amdDefine === "function" &&
+    amdDefine.amd &&
+    typeof amdRequire === "function";
+  const raw: Exports = hasAmdLoader
+    ? await new Promise<Exports>((resolve, reject) =>
+        amdRequire([id], resolve, reject)
+      )
+    : externalGlobal(id);
+  return externalNamespace(raw);
+}
+
+/**
+ * Loads an external module with the page's SystemJS loader.
+ */
+async function externalSystem(id: string): Promise<Exports> {
+  const system = (globalThis as any).System;
+  if (typeof system?.import !== "function") {
+    throw new Error(
+      `Failed to load external module ${id}: SystemJS is not loaded on the page`
+    );
+  }
+  return system.import(id);
+}
+
+/**
+ * Exposes a raw UMD export as an ES module namespace.
+ */
+function externalNamespace(raw: Exports): Exports {
+  if (raw == null || raw.__esModule) {
+    return raw;
+  }
+  return interopEsm(raw, createNS(raw), true);
+}
+
 /**
  * Adds a module to a chunk.
  */
@@ -61,6 +61,9 @@ type AsyncModule = (
 
 type ResolveAbsolutePath = (modulePath?: string) => string;
 type GetWorkerBlobURL = (chunks: ChunkPath[]) => string;
+type ExternalGlobal = (name: string) => Exports;
+type ExternalUmd = (id: string) => Promise<Exports>;
+type ExternalSystem = (id: string) => Promise<Exports>;
 
 interface Module {
   exports: Function | Exports | Promise<Exports> | AsyncModulePromise;
@@ -102,5 +105,8 @@ interface TurbopackBaseContext<M> {
   U: RelativeURL;
   b: GetWorkerBlobURL,
   z: CommonJsRequire
+  G?: ExternalGlobal;
+  D?: ExternalUmd;
+  S?: ExternalSystem;
   __dirname: string;
 }
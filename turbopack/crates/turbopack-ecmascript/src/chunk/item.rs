@@ -129,6 +129,11 @@ impl EcmascriptChunkItemContent {
             args.push("w: __turbopack_wasm__");
             args.push("u: __turbopack_wasm_module__");
         }
+        if self.options.browser_externals {
+            args.push("G: __turbopack_external_global__");
+            args.push("D: __turbopack_external_umd__");
+            args.push("S: __turbopack_external_system__");
+        }
         let mut code = CodeBuilder::default();
         let args = FormatIter(|| args.iter().copied().intersperse(", "));
         if self.options.this {
@@ -209,6 +214,10 @@ pub struct EcmascriptChunkItemOptions {
     /// Whether this chunk item's module factory should include
     /// `__turbopack_wasm__` to load WebAssembly.
     pub wasm: bool,
+    /// Whether this chunk item's module factory should include
+    /// `__turbopack_external_global__`, `__turbopack_external_umd__` and
+    /// `__turbopack_external_system__` to load browser externals.
+    pub browser_externals: bool,
     pub placeholder_for_future_extensions: (),
 }
 
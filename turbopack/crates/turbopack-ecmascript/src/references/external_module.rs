@@ -1,6 +1,6 @@
 use std::{fmt::Display, io::Write};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use serde::{Deserialize, Serialize};
 use turbo_rcstr::RcStr;
 use turbo_tasks::{trace::TraceRawVcs, NonLocalValue, ResolvedVc, TaskInput, Vc};
@@ -15,8 +15,8 @@ use turbopack_core::{
 
 use crate::{
     chunk::{
-        EcmascriptChunkItem, EcmascriptChunkItemContent, EcmascriptChunkPlaceable,
-        EcmascriptChunkType, EcmascriptExports,
+        EcmascriptChunkItem, EcmascriptChunkItemContent, EcmascriptChunkItemOptions,
+        EcmascriptChunkPlaceable, EcmascriptChunkType, EcmascriptExports,
     },
     references::async_module::{AsyncModule, OptionAsyncModule},
     utils::StringifyJs,
@@ -45,6 +45,29 @@ pub enum CachedExternalType {
     CommonJs,
     EcmaScriptViaRequire,
     EcmaScriptViaImport,
+    Global,
+    Umd,
+    SystemJs,
+}
+
+impl CachedExternalType {
+    /// Whether the external is loaded asynchronously and makes its importers async.
+    fn is_async(self) -> bool {
+        matches!(
+            self,
+            CachedExternalType::EcmaScriptViaImport
+                | CachedExternalType::Umd
+                | CachedExternalType::SystemJs
+        )
+    }
+
+    /// Whether the external is loaded by the browser runtime instead of the Node.js one.
+    fn is_browser_external(self) -> bool {
+        matches!(
+            self,
+            CachedExternalType::Global | CachedExternalType::Umd | CachedExternalType::SystemJs
+        )
+    }
 }
 
 impl Display for CachedExternalType {
@@ -53,6 +76,9 @@ impl Display for CachedExternalType {
             CachedExternalType::CommonJs => write!(f, "cjs"),
             CachedExternalType::EcmaScriptViaRequire => write!(f, "esm_require"),
             CachedExternalType::EcmaScriptViaImport => write!(f, "esm_import"),
+            CachedExternalType::Global => write!(f, "global"),
+            CachedExternalType::Umd => write!(f, "umd"),
+            CachedExternalType::SystemJs => write!(f, "system"),
         }
     }
 }
@@ -83,24 +109,48 @@ impl CachedExternalModule {
     pub fn content(&self) -> Result<Vc<EcmascriptModuleContent>> {
         let mut code = RopeBuilder::default();
 
-        if self.external_type == CachedExternalType::EcmaScriptViaImport {
-            writeln!(
-                code,
-                "const mod = await __turbopack_external_import__({});",
-                StringifyJs(&self.request)
-            )?;
-        } else {
-            writeln!(
-                code,
-                "const mod = __turbopack_external_require__({}, () => require({}));",
-                StringifyJs(&self.request),
-                StringifyJs(&self.request)
-            )?;
+        match self.external_type {
+            CachedExternalType::EcmaScriptViaImport => {
+                writeln!(
+                    code,
+                    "const mod = await __turbopack_external_import__({});",
+                    StringifyJs(&self.request)
+                )?;
+            }
+            CachedExternalType::CommonJs | CachedExternalType::EcmaScriptViaRequire => {
+                writeln!(
+                    code,
+                    "const mod = __turbopack_external_require__({}, () => require({}));",
+                    StringifyJs(&self.request),
+                    StringifyJs(&self.request)
+                )?;
+            }
+            CachedExternalType::Global => {
+                writeln!(
+                    code,
+                    "const mod = __turbopack_external_global__({});",
+                    StringifyJs(&self.request)
+                )?;
+            }
+            CachedExternalType::Umd => {
+                writeln!(
+                    code,
+                    "const mod = await __turbopack_external_umd__({});",
+                    StringifyJs(&self.request)
+                )?;
+            }
+            CachedExternalType::SystemJs => {
+                writeln!(
+                    code,
+                    "const mod = await __turbopack_external_system__({});",
+                    StringifyJs(&self.request)
+                )?;
+            }
         }
 
         writeln!(code)?;
 
-        if self.external_type == CachedExternalType::CommonJs {
+        if self.is_commonjs() {
             writeln!(code, "module.exports = mod;")?;
         } else {
             writeln!(code, "__turbopack_export_namespace__(mod);")?;
@@ -109,12 +159,22 @@ impl CachedExternalModule {
         Ok(EcmascriptModuleContent {
             inner_code: code.build(),
             source_map: None,
-            is_esm: self.external_type != CachedExternalType::CommonJs,
+            is_esm: !self.is_commonjs(),
         }
         .cell())
     }
 }
 
+impl CachedExternalModule {
+    /// Global variables are exposed as they are, like a CommonJS module's `module.exports`.
+    fn is_commonjs(&self) -> bool {
+        matches!(
+            self.external_type,
+            CachedExternalType::CommonJs | CachedExternalType::Global
+        )
+    }
+}
+
 #[turbo_tasks::value_impl]
 impl Module for CachedExternalModule {
     #[turbo_tasks::function]
@@ -158,7 +218,7 @@ impl ChunkableModule for CachedExternalModule {
 impl EcmascriptChunkPlaceable for CachedExternalModule {
     #[turbo_tasks::function]
     fn get_exports(&self) -> Vc<EcmascriptExports> {
-        if self.external_type == CachedExternalType::CommonJs {
+        if self.is_commonjs() {
             EcmascriptExports::CommonJs.cell()
         } else {
             EcmascriptExports::DynamicNamespace.cell()
@@ -167,19 +227,17 @@ impl EcmascriptChunkPlaceable for CachedExternalModule {
 
     #[turbo_tasks::function]
     fn get_async_module(&self) -> Vc<OptionAsyncModule> {
-        Vc::cell(
-            if self.external_type == CachedExternalType::EcmaScriptViaImport {
-                Some(
-                    AsyncModule {
-                        has_top_level_await: true,
-                        import_externals: true,
-                    }
-                    .resolved_cell(),
-                )
-            } else {
-                None
-            },
-        )
+        Vc::cell(if self.external_type.is_async() {
+            Some(
+                AsyncModule {
+                    has_top_level_await: true,
+                    import_externals: self.external_type == CachedExternalType::EcmaScriptViaImport,
+                }
+                .resolved_cell(),
+            )
+        } else {
+            None
+        })
     }
 
     #[turbo_tasks::function]
@@ -239,9 +297,7 @@ impl ChunkItem for CachedExternalModuleChunkItem {
 
     #[turbo_tasks::function]
     async fn is_self_async(&self) -> Result<Vc<bool>> {
-        Ok(Vc::cell(
-            self.module.await?.external_type == CachedExternalType::EcmaScriptViaImport,
-        ))
+        Ok(Vc::cell(self.module.await?.external_type.is_async()))
     }
 }
 
@@ -258,21 +314,50 @@ impl EcmascriptChunkItem for CachedExternalModuleChunkItem {
     }
 
     #[turbo_tasks::function]
-    fn content_with_async_module_info(
+    async fn content_with_async_module_info(
         &self,
         async_module_info: Option<Vc<AsyncModuleInfo>>,
-    ) -> Vc<EcmascriptChunkItemContent> {
+    ) -> Result<Vc<EcmascriptChunkItemContent>> {
+        let module = self.module.await?;
         let async_module_options = self
             .module
             .get_async_module()
             .module_options(async_module_info);
 
-        EcmascriptChunkItemContent::new(
+        let content = EcmascriptChunkItemContent::new(
             self.module.content(),
             *self.chunking_context,
             EcmascriptOptions::default().cell(),
             async_module_options,
-        )
+        );
+
+        if !module.external_type.is_browser_external() {
+            return Ok(content);
+        }
+
+        if !*self
+            .chunking_context
+            .environment()
+            .supports_browser_externals()
+            .await?
+        {
+            bail!(
+                "the chunking context ({}) does not support {} externals (request: {})",
+                self.chunking_context.name().await?,
+                module.external_type,
+                module.request
+            );
+        }
+
+        let content = content.await?;
+        Ok(EcmascriptChunkItemContent {
+            options: EcmascriptChunkItemOptions {
+                browser_externals: true,
+                ..content.options.clone()
+            },
+            ..content.clone_value()
+        }
+        .cell())
     }
 }
 
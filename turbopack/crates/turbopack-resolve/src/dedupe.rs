@@ -0,0 +1,466 @@
+use anyhow::Result;
+use semver::{Version, VersionReq};
+use turbo_rcstr::RcStr;
+use turbo_tasks::{FxIndexMap, ResolvedVc, Value, ValueToString, Vc};
+use turbo_tasks_fs::{glob::Glob, FileContent, FileSystemEntryType, FileSystemPath};
+use turbopack_core::{
+    file_source::FileSource,
+    issue::{Issue, IssueExt, IssueSeverity, IssueStage, OptionStyledString, StyledString},
+    module::Modules,
+    package_json::read_package_json,
+    reference_type::ReferenceType,
+    resolve::{
+        find_context_file, package_json,
+        parse::Request,
+        plugin::{AfterResolvePlugin, AfterResolvePluginCondition},
+        FindContextFileResult, RequestKey, ResolveResult, ResolveResultOption,
+    },
+};
+
+/// A file inside of a package directory in `node_modules`.
+#[derive(Debug, PartialEq, Eq)]
+struct NodeModulesPackage<'a> {
+    /// The package directory, e.g. `node_modules/a/node_modules/@scope/b`.
+    dir: &'a str,
+    name: &'a str,
+    /// The path of the file relative to the package directory.
+    subpath: &'a str,
+}
+
+/// Finds the package a file belongs to by looking at the innermost `node_modules` directory of
+/// its path.
+fn node_modules_package(path: &str) -> Option<NodeModulesPackage<'_>> {
+    const NODE_MODULES: &str = "node_modules/";
+    let start = match path.rfind("/node_modules/") {
+        Some(index) => index + 1,
+        None if path.starts_with(NODE_MODULES) => 0,
+        None => return None,
+    };
+    let name_start = start + NODE_MODULES.len();
+    let rest = &path[name_start..];
+    let name_len = if rest.starts_with('@') {
+        let scope_len = rest.find('/')?;
+        scope_len + 1 + rest[scope_len + 1..].find('/')?
+    } else {
+        rest.find('/')?
+    };
+    let name = &rest[..name_len];
+    let subpath = &rest[name_len + 1..];
+    if name.starts_with('.') || subpath.is_empty() {
+        return None;
+    }
+    Some(NodeModulesPackage {
+        dir: &path[..name_start + name_len],
+        name,
+        subpath,
+    })
+}
+
+/// Converts an npm comparator to the syntax of the semver crate, which treats a version without
+/// an operator as a caret range while npm treats it as an exact version. Returns `None` for
+/// comparators that the semver crate would interpret differently from npm (`x` wildcards and
+/// `v` prefixes).
+fn npm_comparator(comparator: &str) -> Option<String> {
+    let version = comparator.trim_start_matches(['^', '~', '>', '<', '=']);
+    if version.starts_with(['v', 'V'])
+        || version
+            .split('.')
+            .any(|part| part.eq_ignore_ascii_case("x") || (part == "*" && version != "*"))
+    {
+        return None;
+    }
+    if version.len() == comparator.len() && version != "*" {
+        Some(format!("={comparator}"))
+    } else {
+        Some(comparator.to_string())
+    }
+}
+
+/// Returns true when `version` satisfies the npm version `range` that a dependent declares.
+/// Ranges that aren't semver ranges (tags, urls, `workspace:` and `npm:` protocols, ...) or that
+/// can't be evaluated with npm semantics (hyphen ranges, `x` wildcards, ...) are never satisfied.
+fn satisfies_range(version: &str, range: &str) -> bool {
+    let Ok(version) = Version::parse(version) else {
+        return false;
+    };
+    range.split("||").any(|range| {
+        let comparators = range.split_whitespace().collect::<Vec<_>>();
+        if comparators.contains(&"-") {
+            return false;
+        }
+        // npm separates comparators with spaces, the semver crate with commas
+        let Some(comparators) = comparators
+            .into_iter()
+            .map(npm_comparator)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return false;
+        };
+        VersionReq::parse(&comparators.join(", ")).is_ok_and(|req| req.matches(&version))
+    })
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1000 {
+        format!("{bytes} B")
+    } else if bytes < 1_000_000 {
+        format!("{:.1} kB", bytes as f64 / 1000.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+async fn package_version(package_dir: Vc<FileSystemPath>) -> Result<Option<RcStr>> {
+    let package_json = package_dir
+        .join("package.json".into())
+        .to_resolved()
+        .await?;
+    Ok(match &*read_package_json(*package_json).await? {
+        Some(package_json) => package_json["version"].as_str().map(RcStr::from),
+        None => None,
+    })
+}
+
+/// Returns the package.json of the package that contains `lookup_path` and the version range it
+/// declares for its dependency `name`.
+async fn dependency_range(
+    lookup_path: Vc<FileSystemPath>,
+    name: &str,
+) -> Result<Option<(ResolvedVc<FileSystemPath>, RcStr)>> {
+    let context_file = find_context_file(lookup_path, package_json()).await?;
+    let FindContextFileResult::Found(package_json_path, _) = &*context_file else {
+        return Ok(None);
+    };
+    let package_json = read_package_json(**package_json_path).await?;
+    let Some(package_json) = &*package_json else {
+        return Ok(None);
+    };
+    Ok(["dependencies", "peerDependencies", "optionalDependencies"]
+        .into_iter()
+        .find_map(|field| package_json[field][name].as_str())
+        .map(|range| (*package_json_path, range.into())))
+}
+
+/// Resolve plugin that redirects files of nested copies of a package in `node_modules` to the
+/// copy in `<root>/node_modules` when its version satisfies the range that the importing package
+/// declares in its package.json, so that only a single copy of the package ends up in the
+/// bundle.
+#[turbo_tasks::value]
+pub struct DedupePackagesResolvePlugin {
+    root: ResolvedVc<FileSystemPath>,
+}
+
+#[turbo_tasks::value_impl]
+impl DedupePackagesResolvePlugin {
+    #[turbo_tasks::function]
+    pub fn new(root: ResolvedVc<FileSystemPath>) -> Vc<Self> {
+        DedupePackagesResolvePlugin { root }.cell()
+    }
+}
+
+#[turbo_tasks::value_impl]
+impl AfterResolvePlugin for DedupePackagesResolvePlugin {
+    #[turbo_tasks::function]
+    fn after_resolve_condition(&self) -> Vc<AfterResolvePluginCondition> {
+        AfterResolvePluginCondition::new(self.root.root(), Glob::new("**/node_modules/**".into()))
+    }
+
+    #[turbo_tasks::function]
+    async fn after_resolve(
+        &self,
+        fs_path: ResolvedVc<FileSystemPath>,
+        lookup_path: ResolvedVc<FileSystemPath>,
+        _reference_type: Value<ReferenceType>,
+        _request: ResolvedVc<Request>,
+    ) -> Result<Vc<ResolveResultOption>> {
+        let path = fs_path.await?;
+        let Some(package) = node_modules_package(&path.path) else {
+            return Ok(ResolveResultOption::none());
+        };
+
+        let package_dir = fs_path.root().join(package.dir.into());
+        let canonical_dir = self
+            .root
+            .join(format!("node_modules/{}", package.name).into())
+            .realpath();
+        if *package_dir.await? == *canonical_dir.await? {
+            return Ok(ResolveResultOption::none());
+        }
+
+        // Files of the package that import each other have no declared range, so only the
+        // imports of dependents are redirected
+        let Some((issuer_package_json, range)) =
+            dependency_range(*lookup_path, package.name).await?
+        else {
+            return Ok(ResolveResultOption::none());
+        };
+        let Some(canonical_version) = package_version(canonical_dir).await? else {
+            return Ok(ResolveResultOption::none());
+        };
+        if !satisfies_range(&canonical_version, &range) {
+            return Ok(ResolveResultOption::none());
+        }
+
+        let canonical_path = canonical_dir.join(package.subpath.into());
+        if !matches!(
+            &*canonical_path.get_type().await?,
+            FileSystemEntryType::File
+        ) {
+            return Ok(ResolveResultOption::none());
+        }
+
+        Ok(Vc::cell(Some(
+            ResolveResult::source_with_affecting_sources(
+                RequestKey::default(),
+                ResolvedVc::upcast(FileSource::new(canonical_path).to_resolved().await?),
+                vec![
+                    ResolvedVc::upcast(FileSource::new(*issuer_package_json).to_resolved().await?),
+                    ResolvedVc::upcast(
+                        FileSource::new(canonical_dir.join("package.json".into()))
+                            .to_resolved()
+                            .await?,
+                    ),
+                ],
+            )
+            .resolved_cell(),
+        )))
+    }
+}
+
+/// A copy of a package in a `node_modules` directory that modules were resolved from.
+#[turbo_tasks::value(shared)]
+#[derive(Clone, Debug, Hash)]
+pub struct PackageCopy {
+    pub path: ResolvedVc<FileSystemPath>,
+    pub version: Option<RcStr>,
+    /// The number of modules from this copy.
+    pub modules: usize,
+    /// The size of the sources of these modules in bytes.
+    pub size: u64,
+}
+
+#[turbo_tasks::value(shared)]
+#[derive(Clone, Debug, Hash)]
+pub struct DuplicatePackage {
+    pub name: RcStr,
+    pub copies: Vec<PackageCopy>,
+}
+
+impl DuplicatePackage {
+    /// The size in bytes that would be saved if only the largest copy was bundled.
+    pub fn duplicated_size(&self) -> u64 {
+        let total: u64 = self.copies.iter().map(|copy| copy.size).sum();
+        let largest = self.copies.iter().map(|copy| copy.size).max().unwrap_or(0);
+        total - largest
+    }
+}
+
+#[turbo_tasks::value(transparent)]
+pub struct DuplicatePackages(Vec<DuplicatePackage>);
+
+/// Groups the modules by the package in `node_modules` they belong to and returns the packages
+/// with more than one copy, sorted by [DuplicatePackage::duplicated_size]. Emits a
+/// [DuplicatePackageIssue] for each of them.
+#[turbo_tasks::function]
+pub async fn find_duplicate_packages(modules: Vc<Modules>) -> Result<Vc<DuplicatePackages>> {
+    let mut files = FxIndexMap::default();
+    for module in modules.await?.iter() {
+        let path = module.ident().path().to_resolved().await?;
+        files.insert(path.await?.path.clone(), path);
+    }
+
+    // package directory -> (package name, copy)
+    let mut copies: FxIndexMap<&str, (&str, PackageCopy)> = FxIndexMap::default();
+    for (path, file) in &files {
+        let Some(package) = node_modules_package(path) else {
+            continue;
+        };
+        let size = match &*file.read().await? {
+            FileContent::Content(content) => content.content().len() as u64,
+            FileContent::NotFound => 0,
+        };
+        if let Some((_, copy)) = copies.get_mut(package.dir) {
+            copy.modules += 1;
+            copy.size += size;
+        } else {
+            let package_dir = file.root().join(package.dir.into());
+            let copy = PackageCopy {
+                path: package_dir.to_resolved().await?,
+                version: package_version(package_dir).await?,
+                modules: 1,
+                size,
+            };
+            copies.insert(package.dir, (package.name, copy));
+        }
+    }
+
+    let mut packages: FxIndexMap<&str, Vec<PackageCopy>> = FxIndexMap::default();
+    for (name, copy) in copies.into_values() {
+        packages.entry(name).or_default().push(copy);
+    }
+    let mut duplicates = packages
+        .into_iter()
+        .filter(|(_, copies)| copies.len() > 1)
+        .map(|(name, copies)| DuplicatePackage {
+            name: name.into(),
+            copies,
+        })
+        .collect::<Vec<_>>();
+    duplicates.sort_by_key(|package| std::cmp::Reverse(package.duplicated_size()));
+
+    for package in &duplicates {
+        emit_duplicate_package_issue(Value::new(package.clone())).await?;
+    }
+
+    Ok(Vc::cell(duplicates))
+}
+
+/// Emits the [DuplicatePackageIssue] of `package`. Module graphs that contain the same copies of
+/// a package share the task and with it the issue, so it's reported once per project.
+#[turbo_tasks::function]
+fn emit_duplicate_package_issue(package: Value<DuplicatePackage>) -> Vc<()> {
+    DuplicatePackageIssue {
+        package: package.into_value(),
+    }
+    .resolved_cell()
+    .emit();
+    Vc::cell(())
+}
+
+#[turbo_tasks::value(shared)]
+pub struct DuplicatePackageIssue {
+    package: DuplicatePackage,
+}
+
+#[turbo_tasks::value_impl]
+impl Issue for DuplicatePackageIssue {
+    #[turbo_tasks::function]
+    fn severity(&self) -> Vc<IssueSeverity> {
+        IssueSeverity::Warning.into()
+    }
+
+    #[turbo_tasks::function]
+    fn title(&self) -> Vc<StyledString> {
+        StyledString::Text(
+            format!(
+                "{} copies of package {} are bundled",
+                self.package.copies.len(),
+                self.package.name
+            )
+            .into(),
+        )
+        .cell()
+    }
+
+    #[turbo_tasks::function]
+    fn file_path(&self) -> Vc<FileSystemPath> {
+        *self.package.copies[0].path
+    }
+
+    #[turbo_tasks::function]
+    fn stage(&self) -> Vc<IssueStage> {
+        IssueStage::Analysis.cell()
+    }
+
+    #[turbo_tasks::function]
+    async fn description(&self) -> Result<Vc<OptionStyledString>> {
+        let mut lines = Vec::with_capacity(self.package.copies.len() + 1);
+        for copy in &self.package.copies {
+            lines.push(StyledString::Text(
+                format!(
+                    "- {} at {} ({} modules, {})",
+                    copy.version.as_deref().unwrap_or("unknown version"),
+                    copy.path.to_string().await?,
+                    copy.modules,
+                    format_size(copy.size)
+                )
+                .into(),
+            ));
+        }
+        lines.push(StyledString::Text(
+            format!(
+                "Deduplicating the package would save {} of source code. Align the version ranges \
+                 of its dependents or enable package deduplication to resolve compatible versions \
+                 to a single copy.",
+                format_size(self.package.duplicated_size())
+            )
+            .into(),
+        ));
+        Ok(Vc::cell(Some(StyledString::Stack(lines).resolved_cell())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_innermost_package() {
+        assert_eq!(
+            node_modules_package("node_modules/a/node_modules/b/lib/index.js"),
+            Some(NodeModulesPackage {
+                dir: "node_modules/a/node_modules/b",
+                name: "b",
+                subpath: "lib/index.js",
+            })
+        );
+        assert_eq!(
+            node_modules_package("app/node_modules/@scope/pkg/index.js"),
+            Some(NodeModulesPackage {
+                dir: "app/node_modules/@scope/pkg",
+                name: "@scope/pkg",
+                subpath: "index.js",
+            })
+        );
+        assert_eq!(
+            node_modules_package("node_modules/.pnpm/a@1.0.0/node_modules/a/index.js"),
+            Some(NodeModulesPackage {
+                dir: "node_modules/.pnpm/a@1.0.0/node_modules/a",
+                name: "a",
+                subpath: "index.js",
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_paths_outside_of_packages() {
+        assert_eq!(node_modules_package("src/index.js"), None);
+        assert_eq!(node_modules_package("my_node_modules/a/index.js"), None);
+        assert_eq!(node_modules_package("node_modules/a"), None);
+        assert_eq!(node_modules_package("node_modules/@scope/index.js"), None);
+        assert_eq!(node_modules_package("node_modules/.bin/a"), None);
+    }
+
+    #[test]
+    fn compatible_ranges() {
+        assert!(satisfies_range("1.2.0", "^1.0.3"));
+        assert!(satisfies_range("1.0.3", "1.0.3"));
+        assert!(satisfies_range("1.0.3", "=1.0.3"));
+        assert!(satisfies_range("1.0.7", "1.0"));
+        assert!(satisfies_range("0.3.5", "~0.3.1"));
+        assert!(satisfies_range("1.4.0", ">=1.2.0 <2.0.0"));
+        assert!(satisfies_range("2.1.0", "^1.0.0 || ^2.0.0"));
+        assert!(satisfies_range("3.0.0", "*"));
+    }
+
+    #[test]
+    fn incompatible_ranges() {
+        // the range of the dependent decides, not the version of the nested copy
+        assert!(!satisfies_range("1.2.0", "~1.1.0"));
+        assert!(!satisfies_range("1.0.0", "^1.2.0"));
+        assert!(!satisfies_range("1.0.4", "1.0.3"));
+        assert!(!satisfies_range("4.17.21", "=4.17.20"));
+        assert!(!satisfies_range("2.0.0", "^1.2.0"));
+        assert!(!satisfies_range("0.4.0", "^0.3.1"));
+        assert!(!satisfies_range("1.4.0", ">=1.0.0 <1.4.0"));
+        assert!(!satisfies_range("3.0.0", "^1.0.0 || ^2.0.0"));
+        assert!(!satisfies_range("1.0.0", "workspace:*"));
+        assert!(!satisfies_range("1.0.0", "npm:other@^1.0.0"));
+        assert!(!satisfies_range("not a version", "^1.0.0"));
+        // ranges that the semver crate would not evaluate like npm
+        assert!(!satisfies_range("1.5.0", "1.0.0 - 2.0.0"));
+        assert!(!satisfies_range("1.5.0", "1.x"));
+        assert!(!satisfies_range("1.5.0", "^1.X"));
+        assert!(!satisfies_range("1.0.3", "v1.0.3"));
+    }
+}
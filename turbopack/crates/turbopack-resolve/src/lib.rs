@@ -1,6 +1,7 @@
 #![feature(arbitrary_self_types)]
 #![feature(arbitrary_self_types_pointers)]
 
+pub mod dedupe;
 pub mod ecmascript;
 pub mod node_native_binding;
 pub mod resolve;
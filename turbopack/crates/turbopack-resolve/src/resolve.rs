@@ -1,16 +1,20 @@
 use anyhow::Result;
-use turbo_tasks::Vc;
+use turbo_tasks::{ResolvedVc, Vc};
 use turbo_tasks_fs::{FileSystem, FileSystemPath};
 use turbopack_core::resolve::{
     find_context_file,
+    lockfile::{lockfiles, read_locked_versions},
     options::{
         ConditionValue, ImportMap, ImportMapping, ResolutionConditions, ResolveInPackage,
         ResolveIntoPackage, ResolveModules, ResolveOptions,
     },
+    pnp::pnp_manifest_files,
+    web_import_map::read_web_import_map,
     AliasMap, AliasPattern, ExternalTraced, ExternalType, FindContextFileResult,
 };
 
 use crate::{
+    dedupe::DedupePackagesResolvePlugin,
     resolve_options_context::ResolveOptionsContext,
     typescript::{apply_tsconfig_resolve_options, tsconfig, tsconfig_resolve_options},
 };
@@ -135,14 +139,34 @@ async fn base_resolve_options(
         }
     }
 
+    for (request, (name, ty)) in opt.externals.iter() {
+        direct_mappings.insert(
+            AliasPattern::exact(request.clone()),
+            ImportMapping::External(Some(name.clone()), *ty, ExternalTraced::Untraced)
+                .resolved_cell(),
+        );
+    }
+
     let mut import_map = ImportMap::new(direct_mappings);
     if let Some(additional_import_map) = opt.import_map {
         let additional_import_map = additional_import_map.await?;
         import_map.extend_ref(&additional_import_map);
     }
+    if let Some(web_import_map) = opt.web_import_map {
+        if let Some(web_import_map) = *read_web_import_map(*web_import_map).await? {
+            import_map.extend_ref(&*web_import_map.import_map().await?);
+        }
+    }
     let import_map = import_map.resolved_cell();
 
-    let plugins = opt.after_resolve_plugins.clone();
+    let mut plugins = opt.after_resolve_plugins.clone();
+    if let Some(dir) = opt.enable_node_modules {
+        if opt.enable_package_dedupe {
+            plugins.push(ResolvedVc::upcast(
+                DedupePackagesResolvePlugin::new(*dir).to_resolved().await?,
+            ));
+        }
+    }
 
     let conditions = {
         let mut conditions: ResolutionConditions = [
@@ -230,6 +254,15 @@ async fn base_resolve_options(
         } else {
             let mut mods = Vec::new();
             if let Some(dir) = opt.enable_node_modules {
+                if opt.enable_pnp {
+                    if let FindContextFileResult::Found(manifest, _) =
+                        &*find_context_file(*dir, pnp_manifest_files()).await?
+                    {
+                        mods.push(ResolveModules::Pnp {
+                            manifest: *manifest,
+                        });
+                    }
+                }
                 mods.push(ResolveModules::Nested(dir, vec!["node_modules".into()]));
             }
             mods
@@ -270,6 +303,17 @@ async fn base_resolve_options(
         plugins,
         before_resolve_plugins: opt.before_resolve_plugins.clone(),
         loose_errors: opt.loose_errors,
+        locked_versions: match opt.enable_node_modules {
+            Some(dir) if opt.enable_locked_versions => {
+                match &*find_context_file(*dir, lockfiles()).await? {
+                    FindContextFileResult::Found(lockfile, _) => {
+                        *read_locked_versions(**lockfile).await?
+                    }
+                    FindContextFileResult::NotFound(_) => None,
+                }
+            }
+            _ => None,
+        },
         ..Default::default()
     }
     .into())
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use turbo_rcstr::RcStr;
-use turbo_tasks::{ResolvedVc, ValueDefault, Vc};
+use turbo_tasks::{FxIndexMap, ResolvedVc, ValueDefault, Vc};
 use turbo_tasks_fs::FileSystemPath;
 use turbopack_core::{
     condition::ContextCondition,
@@ -8,6 +8,7 @@ use turbopack_core::{
     resolve::{
         options::{ImportMap, ResolvedMap},
         plugin::{AfterResolvePlugin, BeforeResolvePlugin},
+        ExternalType,
     },
 };
 
@@ -29,9 +30,15 @@ pub struct ResolveOptionsContext {
     pub enable_mjs_extension: bool,
     #[serde(default)]
     /// Enable resolving of the node_modules folder when within the provided
-    /// directory
+    /// directory.
     pub enable_node_modules: Option<ResolvedVc<FileSystemPath>>,
     #[serde(default)]
+    /// Resolve packages through the Yarn Plug'n'Play manifest (`.pnp.cjs` or
+    /// `.pnp.data.json`) in the `enable_node_modules` directory or one of its
+    /// parents before falling back to node_modules. Requests that the manifest
+    /// doesn't allow are not resolved from node_modules.
+    pub enable_pnp: bool,
+    #[serde(default)]
     /// Mark well-known Node.js modules as external imports and load them using
     /// native `require`. e.g. url, querystring, os
     pub enable_node_externals: bool,
@@ -39,6 +46,11 @@ pub struct ResolveOptionsContext {
     /// native `require`. e.g. buffer, events, assert
     pub enable_edge_node_externals: bool,
     #[serde(default)]
+    /// Requests that are provided at runtime instead of being bundled, like
+    /// webpack's `externals`. Maps a request to the name and type of the
+    /// external, e.g. `react` to `React` with [ExternalType::Global].
+    pub externals: FxIndexMap<RcStr, (RcStr, ExternalType)>,
+    #[serde(default)]
     /// Enables the "browser" field and export condition in package.json
     pub browser: bool,
     #[serde(default)]
@@ -63,6 +75,11 @@ pub struct ResolveOptionsContext {
     /// any mapping defined within will take precedence over any other.
     pub fallback_import_map: Option<ResolvedVc<ImportMap>>,
     #[serde(default)]
+    /// A standard import map file (e.g. `importmap.json`). Its `imports` and
+    /// `scopes` are applied like the `import_map`, URL addresses become
+    /// externals.
+    pub web_import_map: Option<ResolvedVc<FileSystemPath>>,
+    #[serde(default)]
     /// An additional resolved map to use after modules have been resolved.
     pub resolved_map: Option<ResolvedVc<ResolvedMap>>,
     #[serde(default)]
@@ -75,6 +92,16 @@ pub struct ResolveOptionsContext {
     pub before_resolve_plugins: Vec<ResolvedVc<Box<dyn BeforeResolvePlugin>>>,
     /// Warn instead of error for resolve errors
     pub loose_errors: bool,
+    #[serde(default)]
+    /// Warn when a package resolved from node_modules has a different version
+    /// than the lockfile (pnpm-lock.yaml, package-lock.json or yarn.lock) that
+    /// is found from the `enable_node_modules` directory.
+    pub enable_locked_versions: bool,
+    #[serde(default)]
+    /// Resolve files of nested copies of a package in node_modules to the copy
+    /// in the `enable_node_modules` directory when its version satisfies the
+    /// range that the importing package declares in its package.json.
+    pub enable_package_dedupe: bool,
 
     #[serde(default)]
     pub placeholder_for_future_extensions: (),
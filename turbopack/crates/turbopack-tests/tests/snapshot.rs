@@ -15,8 +15,8 @@ use serde::Deserialize;
 use serde_json::json;
 use turbo_rcstr::RcStr;
 use turbo_tasks::{
-    apply_effects, ReadConsistency, ReadRef, ResolvedVc, TryJoinIterExt, TurboTasks, Value,
-    ValueToString, Vc,
+    apply_effects, FxIndexMap, ReadConsistency, ReadRef, ResolvedVc, TryJoinIterExt, TurboTasks,
+    Value, ValueToString, Vc,
 };
 use turbo_tasks_env::DotenvProcessEnv;
 use turbo_tasks_fs::{
@@ -50,6 +50,7 @@ use turbopack_core::{
     module::Module,
     output::{OutputAsset, OutputAssets},
     reference_type::{EntryReferenceSubType, ReferenceType},
+    resolve::{web_import_map::read_web_import_map, ExternalType},
     source::Source,
 };
 use turbopack_ecmascript_plugins::transform::{
@@ -95,6 +96,12 @@ struct SnapshotOptions {
     environment: SnapshotEnvironment,
     #[serde(default)]
     tree_shaking_mode: Option<TreeShakingMode>,
+    /// Requests that are not bundled, e.g. `{ "react": ["React", "Global"] }`.
+    #[serde(default)]
+    externals: FxIndexMap<RcStr, (RcStr, ExternalType)>,
+    /// An import map file relative to the test directory, e.g. `input/importmap.json`.
+    #[serde(default)]
+    import_map: Option<RcStr>,
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -121,6 +128,8 @@ impl Default for SnapshotOptions {
             runtime_type: default_runtime_type(),
             environment: Default::default(),
             tree_shaking_mode: Default::default(),
+            externals: Default::default(),
+            import_map: Default::default(),
         }
     }
 }
@@ -220,6 +229,10 @@ async fn run_test(resource: RcStr) -> Result<Vc<FileSystemPath>> {
         .await?;
 
     let entry_asset = project_path.join(options.entry.into());
+    let web_import_map = match options.import_map {
+        Some(import_map) => Some(project_path.join(import_map).to_resolved().await?),
+        None => None,
+    };
 
     let env = Environment::new(Value::new(match options.environment {
         SnapshotEnvironment::Browser => {
@@ -317,6 +330,8 @@ async fn run_test(resource: RcStr) -> Result<Vc<FileSystemPath>> {
             enable_react: true,
             enable_node_modules: Some(project_root),
             custom_conditions: vec!["development".into()],
+            externals: options.externals,
+            web_import_map,
             rules: vec![(
                 ContextCondition::InDirectory("node_modules".into()),
                 ResolveOptionsContext {
@@ -340,8 +355,8 @@ async fn run_test(resource: RcStr) -> Result<Vc<FileSystemPath>> {
     let static_root_path = path.join("static".into()).to_resolved().await?;
 
     let chunking_context: Vc<Box<dyn ChunkingContext>> = match options.runtime {
-        Runtime::Browser => Vc::upcast(
-            BrowserChunkingContext::builder(
+        Runtime::Browser => {
+            let mut builder = BrowserChunkingContext::builder(
                 project_root,
                 path,
                 path,
@@ -349,9 +364,14 @@ async fn run_test(resource: RcStr) -> Result<Vc<FileSystemPath>> {
                 static_root_path,
                 env,
                 options.runtime_type,
-            )
-            .build(),
-        ),
+            );
+            if let Some(web_import_map) = web_import_map {
+                if let Some(web_import_map) = *read_web_import_map(*web_import_map).await? {
+                    builder = builder.web_import_map(web_import_map);
+                }
+            }
+            Vc::upcast(builder.build())
+        }
         Runtime::NodeJs => Vc::upcast(
             NodeJsChunkingContext::builder(
                 project_root,
@@ -0,0 +1,4 @@
+import React, { createElement } from "react";
+import { format } from "my-lib/utils";
+
+console.log(React, createElement, format);
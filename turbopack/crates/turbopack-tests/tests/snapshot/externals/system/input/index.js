@@ -0,0 +1,3 @@
+import { Button } from "@shared/design-system";
+
+console.log(Button);
@@ -0,0 +1,3 @@
+import _, { chunk } from "lodash";
+
+console.log(_, chunk);
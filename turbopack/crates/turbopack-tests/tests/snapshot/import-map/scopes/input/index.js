@@ -0,0 +1,5 @@
+import React from "react";
+import { format } from "utils/format.js";
+import "./legacy/index.js";
+
+console.log("modern", React, format);
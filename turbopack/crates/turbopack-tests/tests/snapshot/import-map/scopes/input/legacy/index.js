@@ -0,0 +1,5 @@
+import React from "react";
+import { format } from "utils/format.js";
+import "./nested/index.js";
+
+console.log("legacy", React, format);
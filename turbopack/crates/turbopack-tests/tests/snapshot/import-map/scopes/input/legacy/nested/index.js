@@ -0,0 +1,4 @@
+import React from "react";
+import { format } from "utils/format.js";
+
+console.log("nested", React, format);
@@ -497,6 +497,48 @@ function getWorkerBlobURL(chunks) {
     });
     return URL.createObjectURL(blob);
 }
+/**
+ * Reads an external module from a global variable provided by the page, e.g.
+ * `React`. Nested properties can be accessed with dots, e.g. `MyLib.utils`.
+ */ function externalGlobal(name) {
+    let value = globalThis;
+    for (const key of name.split(".")){
+        value = value?.[key];
+    }
+    if (value === undefined) {
+        throw new Error(`Failed to load external module ${name}: the global variable is not defined`);
+    }
+    return value;
+}
+/**
+ * Loads a UMD external module through the page's AMD loader, or reads it from
+ * the global variable of the same name when there is none.
+ */ async function externalUmd(id) {
+    const amdRequire = globalThis.require;
+    const amdDefine = globalThis.define;
+    const hasAmdLoader = typeof amdDefine === "function" && amdDefine.amd && typeof amdRequire === "function";
+    const raw = hasAmdLoader ? await new Promise((resolve, reject)=>amdRequire([
+            id
+        ], resolve, reject)) : externalGlobal(id);
+    return externalNamespace(raw);
+}
+/**
+ * Loads an external module with the page's SystemJS loader.
+ */ async function externalSystem(id) {
+    const system = globalThis.System;
+    if (typeof system?.import !== "function") {
+        throw new Error(`Failed to load external module ${id}: SystemJS is not loaded on the page`);
+    }
+    return system.import(id);
+}
+/**
+ * Exposes a raw UMD export as an ES module namespace.
+ */ function externalNamespace(raw) {
+    if (raw == null || raw.__esModule) {
+        return raw;
+    }
+    return interopEsm(raw, createNS(raw), true);
+}
 /**
  * Adds a module to a chunk.
  */ function addModuleToChunk(moduleId, chunkPath) {
@@ -713,6 +755,9 @@ function instantiateModule(id, source) {
                 k: refresh,
                 R: createResolvePathFromModule(r),
                 b: getWorkerBlobURL,
+                G: externalGlobal,
+                D: externalUmd,
+                S: externalSystem,
                 z: requireStub,
                 __dirname: typeof module.id === "string" ? module.id.replace(/(^|\/)\/+$/, "") : module.id
             }));
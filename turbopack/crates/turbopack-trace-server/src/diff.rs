@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{span_ref::SpanRef, store::Store, FxIndexMap};
+
+/// The totals of all spans that share a name path.
+#[derive(Default, Clone, Copy)]
+struct Totals {
+    count: u64,
+    duration: u64,
+    cpu: u64,
+    allocations: u64,
+    persistent_allocations: u64,
+}
+
+impl Totals {
+    fn add(&mut self, span: &SpanRef<'_>) {
+        self.count += 1;
+        self.duration += span.corrected_total_time();
+        self.cpu += span.total_time();
+        self.allocations += span.total_allocations();
+        self.persistent_allocations += span.total_persistent_allocations();
+    }
+}
+
+/// A node of the tree of name paths that merges the spans of both traces.
+#[derive(Default)]
+struct DiffNode {
+    baseline: Totals,
+    comparison: Totals,
+    children: FxIndexMap<String, DiffNode>,
+}
+
+impl DiffNode {
+    fn add_children(&mut self, span: SpanRef<'_>, is_baseline: bool) {
+        for child in span.children() {
+            let node = self
+                .children
+                .entry(child.nice_name().1.to_string())
+                .or_default();
+            if is_baseline {
+                node.baseline.add(&child);
+            } else {
+                node.comparison.add(&child);
+            }
+            node.add_children(child, is_baseline);
+        }
+    }
+
+    fn collect(&self, path: &mut Vec<String>, result: &mut Vec<SpanDiff>) {
+        for (name, node) in self.children.iter() {
+            path.push(name.clone());
+            result.push(SpanDiff::new(
+                path.clone(),
+                &node.baseline,
+                &node.comparison,
+            ));
+            node.collect(path, result);
+            path.pop();
+        }
+    }
+}
+
+/// The difference of all spans with the same name path between the baseline and the comparison
+/// trace. Deltas are positive when the comparison trace is slower or allocates more.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpanDiff {
+    pub path: Vec<String>,
+    pub baseline_count: u64,
+    pub comparison_count: u64,
+    pub duration: i64,
+    pub cpu: i64,
+    pub allocations: i64,
+    pub persistent_allocations: i64,
+}
+
+impl SpanDiff {
+    fn new(path: Vec<String>, baseline: &Totals, comparison: &Totals) -> Self {
+        fn delta(baseline: u64, comparison: u64) -> i64 {
+            comparison as i64 - baseline as i64
+        }
+        Self {
+            path,
+            baseline_count: baseline.count,
+            comparison_count: comparison.count,
+            duration: delta(baseline.duration, comparison.duration),
+            cpu: delta(baseline.cpu, comparison.cpu),
+            allocations: delta(baseline.allocations, comparison.allocations),
+            persistent_allocations: delta(
+                baseline.persistent_allocations,
+                comparison.persistent_allocations,
+            ),
+        }
+    }
+}
+
+/// Aligns the spans of both stores by their name path from the root and returns the `limit`
+/// paths with the largest change in duration.
+pub fn diff_stores(baseline: &Store, comparison: &Store, limit: usize) -> Vec<SpanDiff> {
+    let mut root = DiffNode::default();
+    root.add_children(baseline.root_span(), true);
+    root.add_children(comparison.root_span(), false);
+    let mut result = Vec::new();
+    root.collect(&mut Vec::new(), &mut result);
+    result.sort_by_key(|diff| std::cmp::Reverse(diff.duration.unsigned_abs()));
+    result.truncate(limit);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::span::SpanIndex;
+
+    fn add_span(
+        store: &mut Store,
+        parent: Option<SpanIndex>,
+        name: &str,
+        start: u64,
+        self_time: u64,
+    ) -> SpanIndex {
+        let mut outdated_spans = HashSet::new();
+        let span = store.add_span(
+            parent,
+            start,
+            "".to_string(),
+            name.to_string(),
+            vec![],
+            &mut outdated_spans,
+        );
+        store.add_self_time(span, start, start + self_time, &mut outdated_spans);
+        store.complete_span(span);
+        store.invalidate_outdated_spans(&outdated_spans);
+        span
+    }
+
+    #[test]
+    fn aligns_spans_by_name_path() {
+        let mut baseline = Store::new();
+        let root = add_span(&mut baseline, None, "build", 0, 10);
+        add_span(&mut baseline, Some(root), "parse", 10, 40);
+        add_span(&mut baseline, Some(root), "resolve", 50, 20);
+
+        let mut comparison = Store::new();
+        let root = add_span(&mut comparison, None, "build", 0, 10);
+        add_span(&mut comparison, Some(root), "parse", 10, 40);
+        add_span(&mut comparison, Some(root), "resolve", 50, 30);
+        add_span(&mut comparison, Some(root), "resolve", 80, 30);
+
+        let diff = diff_stores(&baseline, &comparison, 10);
+        assert!(diff
+            .windows(2)
+            .all(|w| w[0].duration.abs() >= w[1].duration.abs()));
+        let find = |path: &[&str]| diff.iter().find(|diff| diff.path == path).unwrap();
+        assert_eq!(find(&["build"]).cpu, 40);
+        assert_eq!(find(&["build", "parse"]).cpu, 0);
+        let resolve = find(&["build", "resolve"]);
+        assert_eq!(resolve.cpu, 40);
+        assert_eq!(resolve.baseline_count, 1);
+        assert_eq!(resolve.comparison_count, 2);
+
+        assert_eq!(diff_stores(&baseline, &comparison, 1).len(), 1);
+    }
+}
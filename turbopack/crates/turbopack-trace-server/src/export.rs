@@ -0,0 +1,102 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::{reader::TraceReader, span::SpanIndex, store::Store, FxIndexMap};
+
+#[derive(Serialize)]
+struct ChromeEvent<'a> {
+    name: &'a str,
+    cat: &'a str,
+    ph: &'static str,
+    ts: u64,
+    dur: u64,
+    pid: u32,
+    tid: usize,
+    args: FxIndexMap<&'a str, &'a str>,
+}
+
+/// Writes all spans of `store` as complete events in the Chrome Trace Event format, which can be
+/// opened in Perfetto.
+///
+/// Spans of the store are not bound to threads and might overlap with their siblings, but events
+/// on a thread must be nested. So every span is placed on a thread where it's nested into its
+/// parent or where no other span is open.
+pub fn export_chrome_trace(store: &Store, mut writer: impl Write) -> Result<()> {
+    let mut spans = store
+        .spans()
+        .map(|span| (span.start(), span.end(), span))
+        .collect::<Vec<_>>();
+    spans.sort_by_key(|&(start, end, _)| (start, Reverse(end)));
+
+    // The spans that are open on every thread, innermost last
+    let mut threads: Vec<Vec<(SpanIndex, u64)>> = Vec::new();
+    let mut span_threads: HashMap<SpanIndex, usize> = HashMap::new();
+
+    write!(writer, "{{\"traceEvents\":[")?;
+    for (i, (start, end, span)) in spans.into_iter().enumerate() {
+        for thread in threads.iter_mut() {
+            while thread
+                .last()
+                .is_some_and(|&(_, open_end)| open_end <= start)
+            {
+                thread.pop();
+            }
+        }
+        let parent = span.parent().map(|parent| parent.index());
+        let parent_thread = parent.and_then(|parent| {
+            let tid = *span_threads.get(&parent)?;
+            threads[tid]
+                .last()
+                .is_some_and(|&(index, open_end)| index == parent && open_end >= end)
+                .then_some(tid)
+        });
+        let tid =
+            match parent_thread.or_else(|| threads.iter().position(|thread| thread.is_empty())) {
+                Some(tid) => tid,
+                None => {
+                    threads.push(Vec::new());
+                    threads.len() - 1
+                }
+            };
+        threads[tid].push((span.index(), end));
+        span_threads.insert(span.index(), tid);
+
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        writeln!(writer)?;
+        serde_json::to_writer(
+            &mut writer,
+            &ChromeEvent {
+                name: &span.span.name,
+                cat: &span.span.category,
+                ph: "X",
+                ts: start,
+                dur: end - start,
+                pid: 1,
+                tid,
+                args: span.args().collect(),
+            },
+        )?;
+    }
+    writeln!(writer, "\n]}}")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the trace file at `path` once and writes it to `output` in the Chrome Trace Event format.
+pub fn export_chrome_trace_file(path: PathBuf, output: PathBuf) -> Result<()> {
+    let store = TraceReader::read_to_end(path)?;
+    let file =
+        File::create(&output).with_context(|| format!("Unable to create {}", output.display()))?;
+    let store = store.read();
+    export_chrome_trace(&store, BufWriter::new(file))
+}
@@ -4,11 +4,17 @@
 
 use std::{hash::BuildHasherDefault, path::PathBuf, sync::Arc};
 
+use anyhow::Result;
 use rustc_hash::FxHasher;
 
-use self::{reader::TraceReader, server::serve, store_container::StoreContainer};
+use self::{
+    export::export_chrome_trace_file, reader::TraceReader, server::serve,
+    store_container::StoreContainer, summary::print_summary,
+};
 
 mod bottom_up;
+mod diff;
+mod export;
 mod reader;
 mod self_time_tree;
 mod server;
@@ -18,6 +24,7 @@ mod span_graph_ref;
 mod span_ref;
 mod store;
 mod store_container;
+mod summary;
 mod u64_empty_string;
 mod u64_string;
 mod viewer;
@@ -29,10 +36,44 @@ mod viewer;
 type FxIndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<FxHasher>>;
 
 pub fn start_turbopack_trace_server(path: PathBuf) {
-    let store = Arc::new(StoreContainer::new());
+    serve_trace(path, None, 5747);
+}
+
+/// Starts the trace server for `path` and allows to compare it against the trace at
+/// `baseline_path`.
+pub fn start_turbopack_trace_diff_server(baseline_path: PathBuf, path: PathBuf) {
+    serve_trace(path, Some(baseline_path), 5747);
+}
+
+/// Serves the trace file at `path` on `port` and keeps reading it while it grows. When a
+/// `baseline_path` is given, the trace can be compared against the trace at that path.
+pub fn serve_trace(path: PathBuf, baseline_path: Option<PathBuf>, port: u16) {
+    let baseline = baseline_path.map(|baseline_path| {
+        let baseline = Arc::new(StoreContainer::new());
+        let reader = TraceReader::spawn(baseline.clone(), baseline_path);
+        (baseline, reader)
+    });
+    let store = Arc::new(match &baseline {
+        Some((baseline, _)) => StoreContainer::with_baseline(baseline.clone()),
+        None => StoreContainer::new(),
+    });
     let reader = TraceReader::spawn(store.clone(), path);
 
-    serve(store, 5747);
+    serve(store, port);
 
     reader.join().unwrap();
+    if let Some((_, baseline_reader)) = baseline {
+        baseline_reader.join().unwrap();
+    }
+}
+
+/// Reads the trace file at `path` once and prints the `top` spans by self time, allocations and
+/// persistent allocations, either as JSON or as a table.
+pub fn print_turbopack_trace_summary(path: PathBuf, top: usize, json: bool) -> Result<()> {
+    print_summary(path, top, json)
+}
+
+/// Reads the trace file at `path` once and writes it to `output` in the Chrome Trace Event format.
+pub fn export_turbopack_trace_to_chrome(path: PathBuf, output: PathBuf) -> Result<()> {
+    export_chrome_trace_file(path, output)
 }
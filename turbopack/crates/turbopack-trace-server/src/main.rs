@@ -1,40 +1,54 @@
-#![feature(iter_intersperse)]
-#![feature(hash_raw_entry)]
-#![feature(box_patterns)]
-
-use std::{collections::HashSet, hash::BuildHasherDefault, sync::Arc};
-
-use rustc_hash::FxHasher;
-
-use self::{reader::TraceReader, server::serve, store_container::StoreContainer};
-
-mod bottom_up;
-mod reader;
-mod self_time_tree;
-mod server;
-mod span;
-mod span_bottom_up_ref;
-mod span_graph_ref;
-mod span_ref;
-mod store;
-mod store_container;
-mod u64_empty_string;
-mod u64_string;
-mod viewer;
+use turbopack_trace_server::{
+    export_turbopack_trace_to_chrome, print_turbopack_trace_summary, serve_trace,
+};
+
+/// Removes `flag` from `args` and returns whether it was present.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let position = args.iter().position(|arg| arg == flag);
+    if let Some(i) = position {
+        args.remove(i);
+    }
+    position.is_some()
+}
 
-type FxIndexMap<K, V> = indexmap::IndexMap<K, V, BuildHasherDefault<FxHasher>>;
+/// Removes `flag` and its value from `args` and returns the value.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.remove(i);
+    assert!(i < args.len(), "missing value for argument: {flag}");
+    Some(args.remove(i))
+}
 
 fn main() {
-    let args: HashSet<String> = std::env::args().skip(1).collect();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    let baseline_path = take_option(&mut args, "--baseline");
+    let summary = take_flag(&mut args, "--summary");
+    let json = take_flag(&mut args, "--json");
+    let top = take_option(&mut args, "--top").map_or(20, |s| s.parse().unwrap());
+    let export_chrome = take_option(&mut args, "--export-chrome");
 
     let mut iter = args.iter();
     let arg = iter.next().expect("missing argument: trace file path");
     let port = iter.next().map_or(5747, |s| s.parse().unwrap());
 
-    let store = Arc::new(StoreContainer::new());
-    let reader = TraceReader::spawn(store.clone(), arg.into());
-
-    serve(store, port);
-
-    reader.join().unwrap();
+    if summary {
+        // Prints the top spans without starting the server, e. g. to check performance budgets
+        // on CI.
+        if let Err(err) = print_turbopack_trace_summary(arg.into(), top, json) {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(output) = export_chrome {
+        if let Err(err) = export_turbopack_trace_to_chrome(arg.into(), output.into()) {
+            eprintln!("Error: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    serve_trace(arg.into(), baseline_path.map(Into::into), port);
 }
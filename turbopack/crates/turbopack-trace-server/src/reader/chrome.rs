@@ -0,0 +1,475 @@
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+use super::TraceFormat;
+use crate::{span::SpanIndex, store::Store, store_container::StoreContainer, FxIndexMap};
+
+/// Reads the [Chrome Trace Event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU),
+/// which is also supported by Perfetto. Duration events (`B`/`E`) and complete events (`X`) are
+/// read, all other events are ignored.
+///
+/// Complete events don't reference their parent, so they are nested by time on their thread. This
+/// expects the events of a thread to be roughly sorted by time.
+pub struct ChromeTraceFormat {
+    store: Arc<StoreContainer>,
+    state: State,
+    /// The spans that are currently open on every thread, keyed by pid and tid.
+    threads: HashMap<(String, String), Vec<OpenSpan>>,
+    /// The latest timestamp seen in the trace. Spans that are still open at the end of the trace
+    /// are completed at this timestamp.
+    last_ts: u64,
+}
+
+enum State {
+    /// Before the array of events.
+    Start,
+    /// Inside of the array of events.
+    Events,
+    /// After the array of events, the remaining data is ignored.
+    Done,
+}
+
+struct OpenSpan {
+    index: SpanIndex,
+    /// The end of a complete event. Duration events end with their `E` event.
+    end: Option<u64>,
+    /// The start of the self time that hasn't been added yet. Self time is interrupted by
+    /// children.
+    self_start: u64,
+}
+
+/// Adds the self time of `stack` until `ts` and starts a child span.
+fn push_span(
+    store: &mut Store,
+    stack: &mut Vec<OpenSpan>,
+    span: OpenSpan,
+    outdated_spans: &mut HashSet<SpanIndex>,
+) {
+    if let Some(parent) = stack.last_mut() {
+        if span.self_start > parent.self_start {
+            store.add_self_time(
+                parent.index,
+                parent.self_start,
+                span.self_start,
+                outdated_spans,
+            );
+            parent.self_start = span.self_start;
+        }
+    }
+    stack.push(span);
+}
+
+/// Completes the innermost span of `stack` at `ts` and resumes the self time of its parent.
+fn pop_span(
+    store: &mut Store,
+    stack: &mut Vec<OpenSpan>,
+    ts: u64,
+    outdated_spans: &mut HashSet<SpanIndex>,
+) -> Option<OpenSpan> {
+    let span = stack.pop()?;
+    let end = span.end.unwrap_or(ts);
+    if end > span.self_start {
+        store.add_self_time(span.index, span.self_start, end, outdated_spans);
+    }
+    store.complete_span(span.index);
+    if let Some(parent) = stack.last_mut() {
+        parent.self_start = parent.self_start.max(end);
+    }
+    Some(span)
+}
+
+impl ChromeTraceFormat {
+    pub fn new(store: Arc<StoreContainer>) -> Self {
+        Self {
+            store,
+            state: State::Start,
+            threads: HashMap::new(),
+            last_ts: 0,
+        }
+    }
+
+    /// Returns true when `buffer` starts with a Chrome trace. An array of events might look like
+    /// the Next.js format, so the first event is checked for a phase.
+    pub fn is_chrome_trace(buffer: &[u8]) -> bool {
+        let buffer = buffer.trim_ascii_start();
+        if buffer.starts_with(b"{") {
+            return true;
+        }
+        if !buffer.starts_with(b"[") {
+            return false;
+        }
+        let first_object = match buffer.iter().position(|b| *b == b'}') {
+            Some(end) => &buffer[..end],
+            None => buffer,
+        };
+        first_object.windows(4).any(|w| w == b"\"ph\"")
+    }
+
+    fn add_event(
+        &mut self,
+        store: &mut Store,
+        event: ChromeEvent,
+        outdated_spans: &mut HashSet<SpanIndex>,
+    ) {
+        let ChromeEvent {
+            name,
+            cat,
+            ph,
+            ts,
+            dur,
+            pid,
+            tid,
+            args,
+        } = event;
+        let ts = ts as u64;
+        let stack = self
+            .threads
+            .entry((pid.to_string(), tid.to_string()))
+            .or_default();
+        // Complete events that ended before this event can't be a parent of it.
+        while let Some(&OpenSpan { end: Some(end), .. }) = stack.last() {
+            if end > ts {
+                break;
+            }
+            pop_span(store, stack, end, outdated_spans);
+        }
+        match ph.as_ref() {
+            "B" | "X" => {
+                let index = store.add_span(
+                    stack.last().map(|span| span.index),
+                    ts,
+                    cat.into_owned(),
+                    name.into_owned(),
+                    args.into_iter()
+                        .map(|(k, v)| {
+                            let v = match v {
+                                serde_json::Value::String(s) => s,
+                                v => v.to_string(),
+                            };
+                            (k.into_owned(), v)
+                        })
+                        .collect(),
+                    outdated_spans,
+                );
+                let span = OpenSpan {
+                    index,
+                    end: (ph == "X").then(|| ts + dur.unwrap_or_default() as u64),
+                    self_start: ts,
+                };
+                self.last_ts = self.last_ts.max(span.end.unwrap_or(ts));
+                push_span(store, stack, span, outdated_spans);
+            }
+            "E" => {
+                self.last_ts = self.last_ts.max(ts);
+                // Closes the innermost duration event and all complete events inside of it
+                while let Some(span) = pop_span(store, stack, ts, outdated_spans) {
+                    if span.end.is_none() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl TraceFormat for ChromeTraceFormat {
+    type Reused = ();
+
+    fn read(&mut self, buffer: &[u8], _reuse: &mut Self::Reused) -> Result<usize> {
+        let mut events = Vec::new();
+        let mut pos = 0;
+        loop {
+            while pos < buffer.len() && buffer[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if pos == buffer.len() {
+                break;
+            }
+            match self.state {
+                State::Start => match buffer[pos] {
+                    b'[' => {
+                        pos += 1;
+                        self.state = State::Events;
+                    }
+                    b'{' => {
+                        // The object format might contain other keys before the events
+                        let Some(key) = find(&buffer[pos..], b"\"traceEvents\"") else {
+                            break;
+                        };
+                        let Some(array) = buffer[pos + key..].iter().position(|b| *b == b'[')
+                        else {
+                            break;
+                        };
+                        pos += key + array + 1;
+                        self.state = State::Events;
+                    }
+                    _ => bail!("Invalid Chrome trace"),
+                },
+                State::Events => match buffer[pos] {
+                    b',' => pos += 1,
+                    b']' => {
+                        pos += 1;
+                        self.state = State::Done;
+                    }
+                    _ => {
+                        let mut stream = serde_json::Deserializer::from_slice(&buffer[pos..])
+                            .into_iter::<ChromeEvent>();
+                        match stream.next() {
+                            Some(Ok(event)) => {
+                                events.push(event);
+                                pos += stream.byte_offset();
+                            }
+                            Some(Err(err)) if err.is_eof() => break,
+                            Some(Err(err)) => return Err(err.into()),
+                            None => break,
+                        }
+                    }
+                },
+                State::Done => pos = buffer.len(),
+            }
+        }
+
+        if events.is_empty() && !matches!(self.state, State::Done) {
+            return Ok(pos);
+        }
+        events.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+        let mut outdated_spans = HashSet::new();
+        let store = self.store.clone();
+        let mut store = store.write();
+        for event in events {
+            self.add_event(&mut store, event, &mut outdated_spans);
+        }
+        if matches!(self.state, State::Done) {
+            // Complete all spans that are still open at the end of the trace
+            for stack in self.threads.values_mut() {
+                while pop_span(&mut store, stack, self.last_ts, &mut outdated_spans).is_some() {}
+            }
+        }
+        store.invalidate_outdated_spans(&outdated_spans);
+        Ok(pos)
+    }
+}
+
+fn find(buffer: &[u8], needle: &[u8]) -> Option<usize> {
+    buffer.windows(needle.len()).position(|w| w == needle)
+}
+
+#[derive(Debug, Deserialize)]
+struct ChromeEvent<'a> {
+    #[serde(default)]
+    name: Cow<'a, str>,
+    #[serde(default)]
+    cat: Cow<'a, str>,
+    ph: Cow<'a, str>,
+    #[serde(default)]
+    ts: f64,
+    dur: Option<f64>,
+    #[serde(default)]
+    pid: serde_json::Value,
+    #[serde(default)]
+    tid: serde_json::Value,
+    #[serde(default)]
+    args: FxIndexMap<Cow<'a, str>, serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+
+    use super::*;
+    use crate::export::export_chrome_trace;
+
+    fn read(trace: &[u8]) -> Arc<StoreContainer> {
+        let store = Arc::new(StoreContainer::new());
+        let mut format = ChromeTraceFormat::new(store.clone());
+        // Split the trace to check that incomplete events are read later
+        let split = trace.len() / 2;
+        let read = format.read(&trace[..split], &mut ()).unwrap();
+        let mut rest = trace[read..split].to_vec();
+        rest.extend_from_slice(&trace[split..]);
+        assert_eq!(format.read(&rest, &mut ()).unwrap(), rest.len());
+        store
+    }
+
+    fn spans(store: &Store) -> Vec<(String, Option<String>, u64, u64)> {
+        store
+            .spans()
+            .map(|span| {
+                (
+                    span.span.name.clone(),
+                    span.parent()
+                        .filter(|parent| !parent.is_root())
+                        .map(|parent| parent.span.name.clone()),
+                    span.total_time(),
+                    span.self_time(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_and_exports_events() {
+        let store = read(
+            br#"{"displayTimeUnit":"ms","traceEvents":[
+                {"name":"build","cat":"app","ph":"B","ts":100,"pid":1,"tid":1},
+                {"name":"parse","cat":"app","ph":"X","ts":110,"dur":30,"pid":1,"tid":1,"args":{"file":"a.js"}},
+                {"name":"resolve","cat":"app","ph":"X","ts":120,"dur":10,"pid":1,"tid":1},
+                {"name":"thread_name","ph":"M","pid":1,"tid":1,"args":{"name":"main"}},
+                {"name":"build","ph":"E","ts":200,"pid":1,"tid":1},
+                {"name":"worker","cat":"app","ph":"X","ts":150,"dur":20,"pid":1,"tid":2}
+            ]}"#,
+        );
+        let store = store.read();
+        let expected = vec![
+            ("build".to_string(), None, 100, 70),
+            ("parse".to_string(), Some("build".to_string()), 30, 20),
+            ("resolve".to_string(), Some("parse".to_string()), 10, 10),
+            ("worker".to_string(), None, 20, 20),
+        ];
+        assert_eq!(spans(&store), expected);
+        let (parse, _) = store.span(store.spans().nth(1).unwrap().id()).unwrap();
+        assert_eq!(parse.args().collect::<Vec<_>>(), vec![("file", "a.js")]);
+
+        let mut exported = Vec::new();
+        export_chrome_trace(&store, &mut exported).unwrap();
+        assert!(ChromeTraceFormat::is_chrome_trace(&exported));
+        let reimported = read(&exported);
+        assert_eq!(spans(&reimported.read()), expected);
+    }
+
+    #[test]
+    fn completes_open_spans_at_the_end_of_the_trace() {
+        let store = read(
+            br#"[
+                {"name":"build","ph":"B","ts":100,"pid":1,"tid":1},
+                {"name":"parse","ph":"B","ts":110,"pid":1,"tid":1},
+                {"name":"resolve","ph":"X","ts":120,"dur":10,"pid":1,"tid":1},
+                {"name":"worker","ph":"X","ts":300,"dur":50,"pid":1,"tid":2}
+            ]"#,
+        );
+        assert_eq!(
+            spans(&store.read()),
+            vec![
+                ("build".to_string(), None, 250, 10),
+                ("parse".to_string(), Some("build".to_string()), 240, 230),
+                ("resolve".to_string(), Some("parse".to_string()), 10, 10),
+                ("worker".to_string(), None, 50, 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_overlapping_spans_nested_per_thread() {
+        fn add_span(
+            store: &mut Store,
+            parent: Option<SpanIndex>,
+            name: &str,
+            self_times: &[(u64, u64)],
+            outdated_spans: &mut HashSet<SpanIndex>,
+        ) -> SpanIndex {
+            let span = store.add_span(
+                parent,
+                self_times[0].0,
+                "app".to_string(),
+                name.to_string(),
+                vec![],
+                outdated_spans,
+            );
+            for &(start, end) in self_times {
+                store.add_self_time(span, start, end, outdated_spans);
+            }
+            store.complete_span(span);
+            span
+        }
+
+        // Siblings of a store are not bound to threads and can overlap
+        let mut store = Store::new();
+        let mut outdated_spans = HashSet::new();
+        let build = add_span(
+            &mut store,
+            None,
+            "build",
+            &[(0, 10), (90, 100)],
+            &mut outdated_spans,
+        );
+        add_span(
+            &mut store,
+            Some(build),
+            "a",
+            &[(10, 60)],
+            &mut outdated_spans,
+        );
+        let b = add_span(
+            &mut store,
+            Some(build),
+            "b",
+            &[(40, 45), (50, 90)],
+            &mut outdated_spans,
+        );
+        add_span(&mut store, Some(b), "c", &[(45, 50)], &mut outdated_spans);
+        add_span(&mut store, None, "worker", &[(20, 30)], &mut outdated_spans);
+        store.invalidate_outdated_spans(&outdated_spans);
+
+        let mut exported = Vec::new();
+        export_chrome_trace(&store, &mut exported).unwrap();
+
+        // Events on a thread must not partially overlap
+        let trace: serde_json::Value = serde_json::from_slice(&exported).unwrap();
+        let mut threads: HashMap<u64, Vec<(u64, u64)>> = HashMap::new();
+        for event in trace["traceEvents"].as_array().unwrap() {
+            let ts = event["ts"].as_u64().unwrap();
+            let dur = event["dur"].as_u64().unwrap();
+            threads
+                .entry(event["tid"].as_u64().unwrap())
+                .or_default()
+                .push((ts, ts + dur));
+        }
+        assert_eq!(threads.len(), 2);
+        for (tid, events) in threads.iter_mut() {
+            events.sort_by_key(|&(start, end)| (start, Reverse(end)));
+            let mut open: Vec<u64> = Vec::new();
+            for &(start, end) in events.iter() {
+                while open.last().is_some_and(|&open_end| open_end <= start) {
+                    open.pop();
+                }
+                if let Some(&open_end) = open.last() {
+                    assert!(
+                        end <= open_end,
+                        "event {start}-{end} on thread {tid} overlaps its parent"
+                    );
+                }
+                open.push(end);
+            }
+        }
+
+        // Spans keep their times, but overlapping siblings are no longer nested into their parent
+        let timings = |store: &Store| {
+            let mut timings = store
+                .spans()
+                .map(|span| (span.span.name.clone(), span.start(), span.end()))
+                .collect::<Vec<_>>();
+            timings.sort();
+            timings
+        };
+        let reimported = read(&exported);
+        let reimported = reimported.read();
+        assert_eq!(timings(&reimported), timings(&store));
+        assert_eq!(
+            spans(&reimported),
+            vec![
+                ("build".to_string(), None, 100, 50),
+                ("a".to_string(), Some("build".to_string()), 50, 50),
+                ("worker".to_string(), None, 10, 10),
+                ("b".to_string(), None, 50, 45),
+                ("c".to_string(), Some("b".to_string()), 5, 5),
+            ]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+mod chrome;
 mod heaptrack;
 mod nextjs;
 mod turbopack;
@@ -13,11 +14,14 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use flate2::bufread::GzDecoder;
 
 use crate::{
-    reader::{heaptrack::HeaptrackFormat, nextjs::NextJsFormat, turbopack::TurbopackFormat},
+    reader::{
+        chrome::ChromeTraceFormat, heaptrack::HeaptrackFormat, nextjs::NextJsFormat,
+        turbopack::TurbopackFormat,
+    },
     store_container::StoreContainer,
 };
 
@@ -123,14 +127,34 @@ impl TraceFile {
 pub struct TraceReader {
     store: Arc<StoreContainer>,
     path: PathBuf,
+    /// Keep reading when the trace file grows or is replaced.
+    follow: bool,
 }
 
 impl TraceReader {
     pub fn spawn(store: Arc<StoreContainer>, path: PathBuf) -> JoinHandle<()> {
-        let mut reader = Self { store, path };
+        let mut reader = Self {
+            store,
+            path,
+            follow: true,
+        };
         std::thread::spawn(move || reader.run())
     }
 
+    /// Reads the trace file once until the end into a new store without waiting for more data.
+    pub fn read_to_end(path: PathBuf) -> Result<Arc<StoreContainer>> {
+        let store = Arc::new(StoreContainer::new());
+        let mut reader = Self {
+            store: store.clone(),
+            path,
+            follow: false,
+        };
+        if !reader.try_read() {
+            bail!("Unable to read trace file at {}", reader.path.display());
+        }
+        Ok(store)
+    }
+
     pub fn run(&mut self) {
         let mut file_warning_printed = false;
         loop {
@@ -158,12 +182,14 @@ impl TraceReader {
         let Ok(mut file) = File::open(&self.path) else {
             return false;
         };
-        println!("Trace file opened");
+        if self.follow {
+            println!("Trace file opened");
+        }
         let stop_at = env::var("STOP_AT")
             .unwrap_or_default()
             .parse()
             .map_or(u64::MAX, |v: u64| v * 1024 * 1024);
-        if stop_at != u64::MAX {
+        if self.follow && stop_at != u64::MAX {
             println!("Will stop reading file at {} MB", stop_at / 1024 / 1024)
         }
 
@@ -175,9 +201,12 @@ impl TraceReader {
         let mut format: Option<(ErasedTraceFormat, ErasedReused)> = None;
 
         let mut current_read = 0;
+        // Progress is only reported when following the file, otherwise the output is reserved for
+        // the caller.
         let mut initial_read = file
             .seek(SeekFrom::End(0))
             .ok()
+            .filter(|_| self.follow)
             .map(|total| (total, Instant::now()));
         if file.seek(SeekFrom::Start(0)).is_err() {
             return false;
@@ -219,6 +248,10 @@ impl TraceReader {
                                 ErasedTraceFormat(Box::new(TurbopackFormat::new(
                                     self.store.clone(),
                                 )))
+                            } else if ChromeTraceFormat::is_chrome_trace(&buffer) {
+                                ErasedTraceFormat(Box::new(ChromeTraceFormat::new(
+                                    self.store.clone(),
+                                )))
                             } else if buffer.starts_with(b"[{\"name\"") {
                                 ErasedTraceFormat(Box::new(NextJsFormat::new(self.store.clone())))
                             } else if buffer.starts_with(b"v ") {
@@ -282,6 +315,9 @@ impl TraceReader {
                                 }
                             }
                             if current_read >= stop_at {
+                                if !self.follow {
+                                    return true;
+                                }
                                 println!(
                                     "Stopped reading file as requested by STOP_AT env var. \
                                      Waiting for new file..."
@@ -333,6 +369,9 @@ impl TraceReader {
                 );
             }
         }
+        if !self.follow {
+            return Some(true);
+        }
         loop {
             // No more data to read, sleep for a while to wait for more data
             thread::sleep(Duration::from_millis(100));
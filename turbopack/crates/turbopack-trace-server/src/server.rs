@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 use tungstenite::{accept, Message};
 
 use crate::{
+    diff::{diff_stores, SpanDiff},
     store::SpanId,
     store_container::StoreContainer,
     u64_string,
@@ -43,6 +44,13 @@ pub enum ServerToClientMessage {
         args: Vec<(String, String)>,
         path: Vec<String>,
     },
+    /// The result of a [`ClientToServerMessage::Diff`]. `spans` is empty when no baseline trace
+    /// was loaded.
+    #[serde(rename_all = "camelCase")]
+    DiffResult {
+        has_baseline: bool,
+        spans: Vec<SpanDiff>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -67,6 +75,11 @@ pub enum ClientToServerMessage {
         #[serde(with = "u64_string")]
         id: SpanId,
     },
+    /// Compares the trace against the baseline trace and reports the `limit` span paths with the
+    /// largest change.
+    Diff {
+        limit: usize,
+    },
     Ack,
     CheckForMoreData,
 }
@@ -337,6 +350,25 @@ fn handle_connection(
 
                         continue;
                     }
+                    ClientToServerMessage::Diff { limit } => {
+                        let message = {
+                            let store = state.store.read();
+                            if let Some(baseline) = state.store.baseline() {
+                                let baseline = baseline.read();
+                                ServerToClientMessage::DiffResult {
+                                    has_baseline: true,
+                                    spans: diff_stores(&baseline, &store, limit),
+                                }
+                            } else {
+                                ServerToClientMessage::DiffResult {
+                                    has_baseline: false,
+                                    spans: Vec::new(),
+                                }
+                            }
+                        };
+                        let message = serde_json::to_string(&message).unwrap();
+                        websocket.send(Message::Text(message))?;
+                    }
                     ClientToServerMessage::Ack => {
                         ready_for_update = true;
                         if update_skipped {
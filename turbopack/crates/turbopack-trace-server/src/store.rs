@@ -366,6 +366,19 @@ impl Store {
         }
     }
 
+    /// Iterates over all spans except the root span.
+    pub fn spans(&self) -> impl Iterator<Item = SpanRef<'_>> {
+        self.spans
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, span)| SpanRef {
+                span,
+                store: self,
+                index,
+            })
+    }
+
     pub fn span(&self, id: SpanId) -> Option<(SpanRef<'_>, bool)> {
         let id = id.get();
         let is_graph = id & 1 == 1;
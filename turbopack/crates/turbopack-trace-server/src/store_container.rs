@@ -2,7 +2,7 @@ use std::{
     ops::{Deref, DerefMut},
     sync::{
         atomic::{AtomicBool, Ordering},
-        RwLock, RwLockReadGuard, RwLockWriteGuard,
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
     },
 };
 
@@ -11,6 +11,7 @@ use crate::store::Store;
 pub struct StoreContainer {
     store: RwLock<StoreWithGeneration>,
     want_to_read: AtomicBool,
+    baseline: Option<Arc<StoreContainer>>,
 }
 
 struct StoreWithGeneration {
@@ -26,9 +27,22 @@ impl StoreContainer {
                 generation: 0,
             }),
             want_to_read: AtomicBool::new(false),
+            baseline: None,
         }
     }
 
+    /// Creates a store that is compared against the trace loaded into `baseline`.
+    pub fn with_baseline(baseline: Arc<StoreContainer>) -> Self {
+        Self {
+            baseline: Some(baseline),
+            ..Self::new()
+        }
+    }
+
+    pub fn baseline(&self) -> Option<&StoreContainer> {
+        self.baseline.as_deref()
+    }
+
     pub fn read(&self) -> StoreReadGuard<'_> {
         if let Ok(guard) = self.store.try_read() {
             return StoreReadGuard { guard };
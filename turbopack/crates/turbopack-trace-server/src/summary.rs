@@ -0,0 +1,139 @@
+use std::{cmp::Reverse, path::PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{reader::TraceReader, store::Store, FxIndexMap};
+
+/// The aggregated values of all spans with the same group name.
+#[derive(Serialize, Default, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpanSummary {
+    pub name: String,
+    pub count: u64,
+    /// The time spent in the spans themselves, excluding their children, in microseconds.
+    pub self_time: u64,
+    pub allocations: u64,
+    pub persistent_allocations: u64,
+}
+
+/// The top spans of a trace, ranked by different values.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub by_self_time: Vec<SpanSummary>,
+    pub by_allocations: Vec<SpanSummary>,
+    pub by_persistent_allocations: Vec<SpanSummary>,
+}
+
+impl Summary {
+    /// Aggregates all spans in `store` by their group name and keeps the `top` entries of every
+    /// ranking. Only values of the spans themselves are added up, so nested spans with the same
+    /// name are not counted twice.
+    pub fn new(store: &Store, top: usize) -> Self {
+        let mut groups: FxIndexMap<&str, SpanSummary> = FxIndexMap::default();
+        for span in store.spans() {
+            let summary = groups.entry(span.group_name()).or_default();
+            summary.count += 1;
+            summary.self_time += span.self_time();
+            summary.allocations += span.self_allocations();
+            summary.persistent_allocations += span.self_persistent_allocations();
+        }
+        let groups = groups
+            .into_iter()
+            .map(|(name, summary)| SpanSummary {
+                name: name.to_string(),
+                ..summary
+            })
+            .collect::<Vec<_>>();
+        let top_by = |key: fn(&SpanSummary) -> u64| {
+            let mut groups = groups.clone();
+            groups.sort_by_key(|summary| Reverse(key(summary)));
+            groups.truncate(top);
+            groups
+        };
+        Self {
+            by_self_time: top_by(|summary| summary.self_time),
+            by_allocations: top_by(|summary| summary.allocations),
+            by_persistent_allocations: top_by(|summary| summary.persistent_allocations),
+        }
+    }
+
+    pub fn print_table(&self) {
+        for (title, spans) in [
+            ("Top spans by self time", &self.by_self_time),
+            ("Top spans by allocations", &self.by_allocations),
+            (
+                "Top spans by persistent allocations",
+                &self.by_persistent_allocations,
+            ),
+        ] {
+            println!("{title}");
+            println!(
+                "{:>12} {:>12} {:>12} {:>8}  name",
+                "self time", "allocations", "persistent", "count"
+            );
+            for span in spans {
+                println!(
+                    "{:>10}ms {:>10}MB {:>10}MB {:>8}  {}",
+                    span.self_time / 1000,
+                    span.allocations / 1024 / 1024,
+                    span.persistent_allocations / 1024 / 1024,
+                    span.count,
+                    span.name
+                );
+            }
+            println!();
+        }
+    }
+}
+
+/// Reads the trace file at `path` once and prints the `top` spans by self time, allocations and
+/// persistent allocations, either as JSON or as a table.
+pub fn print_summary(path: PathBuf, top: usize, json: bool) -> Result<()> {
+    let store = TraceReader::read_to_end(path)?;
+    let summary = Summary::new(&store.read(), top);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        summary.print_table();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn aggregates_spans_by_name() {
+        let mut store = Store::new();
+        let mut outdated_spans = HashSet::new();
+        for (name, start, self_time, allocations) in [
+            ("parse", 0, 30, 1000),
+            ("resolve", 30, 20, 5000),
+            ("parse", 50, 30, 1000),
+        ] {
+            let span = store.add_span(
+                None,
+                start,
+                "".to_string(),
+                name.to_string(),
+                vec![],
+                &mut outdated_spans,
+            );
+            store.add_self_time(span, start, start + self_time, &mut outdated_spans);
+            store.add_allocation(span, allocations, 1, &mut outdated_spans);
+        }
+        store.invalidate_outdated_spans(&outdated_spans);
+
+        let summary = Summary::new(&store, 1);
+        assert_eq!(summary.by_self_time.len(), 1);
+        assert_eq!(summary.by_self_time[0].name, "parse");
+        assert_eq!(summary.by_self_time[0].count, 2);
+        assert_eq!(summary.by_self_time[0].self_time, 60);
+        assert_eq!(summary.by_allocations[0].name, "resolve");
+    }
+}
@@ -665,7 +665,10 @@ async fn externals_tracing_module_context(ty: ExternalType) -> Result<Vc<ModuleA
         custom_conditions: match ty {
             ExternalType::CommonJs => vec!["require".into()],
             ExternalType::EcmaScriptModule => vec!["import".into()],
-            ExternalType::Url => vec![],
+            ExternalType::Url
+            | ExternalType::Global
+            | ExternalType::Umd
+            | ExternalType::SystemJs => vec![],
         },
         ..Default::default()
     };
@@ -1037,6 +1040,9 @@ pub async fn replace_external(
                 CachedExternalType::EcmaScriptViaRequire
             }
         }
+        ExternalType::Global => CachedExternalType::Global,
+        ExternalType::Umd => CachedExternalType::Umd,
+        ExternalType::SystemJs => CachedExternalType::SystemJs,
         ExternalType::Url => {
             // we don't want to wrap url externals.
             return Ok(None);